@@ -13,14 +13,54 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use std::path::PathBuf;
+use std::{fs, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant}};
 
-use crate::messages::{audio::{AudioDevice, AudioMessage}, camera::{CameraCommand, CameraMessage}, recorder::{ClipInfo, RecorderCommand, RecorderStatus}, video::VideoConfig};
-use crate::recorder::types::{EncoderPreset, EncodingQuality, EncodingSpeed};
+use crate::messages::{audio::{AudioApp, AudioDevice, AudioMessage}, camera::{CameraCommand, CameraMessage}, recorder::{ClipInfo, ProgressStage, RecorderCommand, RecorderStatus}, video::VideoConfig};
+use crate::recorder::{ffmpeg, naming, types::{AnimationFormat, AnimationOptions, AudioCodec, AudioEncoding, CaptionPosition, Downmix, EncoderPreset, EncodingQuality, EncodingSpeed, ExportJob, ExportOptions, ExportPreset, ExportRecord, FrameFormat, GpuVendor, Lane, LowerThird, RateControl, RecorderConfig, Transition, TransitionKind, VerticalFit, VerticalFrame, VisualizerStyle, Watermark, DEFAULT_TRANSITION_SECS}};
+use crate::{budget::{self, DropPolicy, FrameBudget, FrameQueue}, crash, estimate::{self, Throughput}, global_hotkey::GlobalHotkey, gpu::{self, GpuDevice}, hls_server::{self, HlsServer}, instance::{MonitorServer, MonitorStatus}, keymap::{Action, KeyMap}, latency::LatencyTest, recovery::{self, CrashedSession}, review::{self, ReviewFlag}, settings::{PinHash, Settings}, status_feed::{FeedState, StatusFeed, StatusReport, StatusTarget}, storage, tuning::{self, ThreadTuning}};
 use crossbeam_channel::{Receiver, Sender};
 use eframe::{egui, App, Frame};
 use chrono::Local;
 use egui_extras::install_image_loaders;
+use serde::{Deserialize, Serialize};
+
+const PENDING_EXPORTS: &str = "pending_exports.json";
+const EXPORT_HISTORY: &str = "export_history.json";
+const EXPORT_HISTORY_LEN: usize = 20;
+const DEVICE_CACHE: &str = "device_cache.json";
+const IDLE_REPAINT: Duration = Duration::from_millis(250);
+const ROOM_TONE_SECS: u64 = 10;
+/// How long the attract screen thanks a guest before inviting the next one.
+const GUESTBOOK_THANKS: Duration = Duration::from_secs(5);
+const CAMERA_BUSY_HINT: &str = "Close it there (Zoom, Teams, a browser tab…) and the picture comes up by itself. Clipper tries again every few seconds.";
+
+/// Devices found on the previous run, offered while this run is still probing.
+#[derive(Default, Serialize, Deserialize)]
+struct DeviceCache {
+    video: Vec<VideoConfig>,
+    audio: Vec<AudioDevice>
+}
+
+/// The "Export animation" window: what it's made from and how.
+struct AnimationDraft {
+    source: PathBuf,
+    duration: f64,
+    options: AnimationOptions
+}
+
+/// What kiosk mode holds back until the PIN is entered.
+enum Guarded {
+    Settings,
+    RemoveClip(PathBuf),
+    Undo,
+    Exit
+}
+
+struct PinPrompt {
+    action: Guarded,
+    entry: String,
+    wrong: bool
+}
 
 #[derive(PartialEq)]
 enum AppState {
@@ -38,120 +78,829 @@ pub struct ClipperApp {
     state: AppState,
     video_configs: Vec<VideoConfig>,
     selected_video_config: Option<VideoConfig>,
+    /// The lists still come from the device cache; the probes haven't reported yet.
+    video_last_known: bool,
+    audio_last_known: bool,
     audio_devices: Vec<AudioDevice>,
     selected_audio_device: Option<AudioDevice>,
+    audio_apps: Vec<AudioApp>,
+    selected_audio_app: Option<AudioApp>,
     selected_encoder: EncoderPreset,
+    available_encoders: Vec<EncoderPreset>,
+    ffmpeg_found: bool,
     selected_quality: EncodingQuality,
     selected_speed: EncodingSpeed,
+    selected_rate_control: RateControl,
+    video_bitrate_kbps: u32,
+    export_options: ExportOptions,
+    two_pass_kbps: u32,
+    /// Crop or pad for vertical exports, kept while the option is off.
+    vertical_frame: VerticalFrame,
+    progress: Option<(ProgressStage, f32)>,
+    duration_mismatch: Option<(PathBuf, f64, f64)>,
+    /// The export that came out the wrong length, redone from its own clips and options.
+    mismatched_job: Option<ExportJob>,
+    quarantined: Option<(PathBuf, bool)>,
+    review_flags: Vec<ReviewFlag>,
+    flag_draft: Option<ReviewFlag>,
+    export_queue: Vec<ExportJob>,
+    /// Where the export being worked on ended up, once it has.
+    finished_export: Option<PathBuf>,
+    resume_prompt: bool,
+    /// Left behind by a crash and not answered yet.
+    crashed_session: Option<CrashedSession>,
+    /// Restored once the camera is confirmed, when the recorder has a config to rebuild clips with.
+    restore_session: Option<CrashedSession>,
+    live_hls: bool,
+    record_proxy: bool,
+    replay_enabled: bool,
+    live: bool,
+    hls_server: Option<HlsServer>,
+    workspace_root: PathBuf,
+    project: String,
+    clip_template: String,
+    export_template: String,
+    export_index: u32,
+    workspace: PathBuf,
+    replay_secs: u32,
+    audio_encoding: AudioEncoding,
+    measured_offset_ms: Option<f64>,
+    dry_run: bool,
+    dry_run_log: Vec<String>,
+    show_safe_guides: bool,
+    preview_enabled: bool,
+    capture_tuning: ThreadTuning,
+    recorder_tuning: ThreadTuning,
+    frame_budget: Arc<FrameBudget>,
+    frame_queue: Arc<FrameQueue>,
+    stream_config: Option<VideoConfig>,
     texture: Option<egui::TextureHandle>,
+    ghost: Option<egui::TextureHandle>,
+    onion_skin: bool,
+    interval_secs: u32,
+    interval_timelapse: bool,
+    interval_stills: Option<u32>,
+    ghost_opacity: f32,
     is_recording: bool,
+    take_started: Option<Instant>,
+    /// Frames the current or last take lost to an overloaded encoder.
+    take_dropped: u64,
+    /// The hardware encoder the recorder gave up on this session.
+    encoder_fallback: Option<EncoderPreset>,
+    shot_name: String,
+    show_operator: bool,
     playlist: Vec<ClipInfo>,
     last_error: Option<String>,
+    /// Another app is holding the camera; cleared once it comes through.
+    camera_busy: bool,
+    /// Format the settings screen is previewing before any stream is started.
+    config_preview: Option<VideoConfig>,
+    /// The latency test flashing the window, or its results until they're closed.
+    latency_test: Option<LatencyTest>,
     final_file: Option<String>,
+    /// Finished exports, newest first.
+    export_history: Vec<ExportRecord>,
+    animation_draft: Option<AnimationDraft>,
+    /// Format, size and loop settings carried over to the next animation.
+    animation_options: AnimationOptions,
+    /// Style used for the next generated visualizer clip.
+    visualizer_style: VisualizerStyle,
+    export_note: Option<String>,
+    /// Format of the timeline's first take, which takes in other formats are conformed to on export.
+    timeline_format: Option<VideoConfig>,
+    room_tone: Option<PathBuf>,
+    room_tone_until: Option<Instant>,
+    split_secs: f64,
+    /// Thumbnail time being scrubbed in a clip's context menu.
+    thumb_secs: f64,
+    /// Where the dragged clip would land, counted among the other clips.
+    drop_target: Option<usize>,
+    throughput: Throughput,
+    /// Detected the first time the advanced panel is opened.
+    gpu_devices: Option<Vec<(GpuVendor, Vec<GpuDevice>)>>,
     dragged_item: Option<usize>,
+    voiceover_clip: Option<PathBuf>,
+    crash_report: Option<PathBuf>,
+    keymap: KeyMap,
+    settings: Settings,
+    show_help: bool,
+    /// Feeds read-only monitor windows opened by later instances.
+    monitor: Option<MonitorServer>,
+    status_feed: Option<StatusFeed>,
+    /// The timeline as last written to the session folder.
+    saved_timeline: Vec<ClipInfo>,
+    /// The status feed was picked on the command line, which outranks the settings.
+    status_from_args: bool,
+    pin_prompt: Option<PinPrompt>,
+    /// When the last guestbook take was saved, for the thank-you on the attract screen.
+    guest_saved: Option<Instant>,
+    /// Which guestbook question is up, counted over every take so far.
+    prompt_index: usize,
+    /// The PIN was given to open settings this session.
+    settings_unlocked: bool,
+    /// A kiosk PIN being typed in settings, hashed once it's set.
+    pin_draft: String,
+    /// The hotkey waiting in settings for its new key.
+    rebinding: Option<Action>,
+    /// The save-replay key grabbed system-wide while it's wanted.
+    global_replay_key: Option<GlobalHotkey>,
+    /// The PIN was given to quit, so the next close goes through.
+    exit_unlocked: bool,
 }
 
 impl ClipperApp {
-    pub fn new(_cc: &eframe::CreationContext, camera_rx: Receiver<CameraMessage>, camera_tx: Sender<CameraCommand>, rec_tx: Sender<RecorderCommand>, rec_status: Receiver<RecorderStatus>, audio_rx: Receiver<AudioMessage>) -> Self {
+    pub fn new(_cc: &eframe::CreationContext, camera_rx: Receiver<CameraMessage>, camera_tx: Sender<CameraCommand>, rec_tx: Sender<RecorderCommand>, rec_status: Receiver<RecorderStatus>, audio_rx: Receiver<AudioMessage>, frame_queue: Arc<FrameQueue>) -> Self {
         egui_extras::install_image_loaders(&_cc.egui_ctx);
-        Self {
+        let export_queue: Vec<ExportJob> = storage::load_json(PENDING_EXPORTS).unwrap_or_default();
+        let probed_encoders = ffmpeg::available_encoders();
+        let cache: DeviceCache = storage::load_json(DEVICE_CACHE).unwrap_or_default();
+        let crashed_session = recovery::find_crashed();
+        if crashed_session.is_none() {
+            storage::clean_stale_temp();
+        }
+        let mut app = Self {
             camera_rx,
             camera_tx,
             rec_tx,
             rec_status,
             audio_rx,
-            state: AppState::Loading,
-            video_configs: Vec::new(),
-            selected_video_config: None,
-            audio_devices: Vec::new(),
-            selected_audio_device: None,
+            state: if cache.video.is_empty() { AppState::Loading } else { AppState::Configuring },
+            selected_video_config: cache.video.first().cloned(),
+            video_last_known: !cache.video.is_empty(),
+            video_configs: cache.video,
+            selected_audio_device: cache.audio.first().cloned(),
+            audio_last_known: !cache.audio.is_empty(),
+            audio_devices: cache.audio,
+            audio_apps: Vec::new(),
+            selected_audio_app: None,
             selected_encoder: EncoderPreset::CPU,
+            ffmpeg_found: probed_encoders.is_some(),
+            available_encoders: probed_encoders.unwrap_or_else(|| EncoderPreset::ALL.to_vec()),
             selected_quality: EncodingQuality::Med,
             selected_speed: EncodingSpeed::Balanced,
+            selected_rate_control: RateControl::Crf,
+            video_bitrate_kbps: 8000,
+            export_options: ExportOptions::default(),
+            two_pass_kbps: 6000,
+            vertical_frame: VerticalFrame::default(),
+            progress: None,
+            duration_mismatch: None,
+            mismatched_job: None,
+            quarantined: None,
+            review_flags: Vec::new(),
+            flag_draft: None,
+            resume_prompt: !export_queue.is_empty(),
+            crashed_session,
+            restore_session: None,
+            export_queue,
+            finished_export: None,
+            live_hls: false,
+            record_proxy: false,
+            replay_enabled: false,
+            live: false,
+            hls_server: None,
+            workspace_root: storage::default_workspace_root(),
+            project: String::from("clipper"),
+            clip_template: String::from(naming::DEFAULT_CLIP_TEMPLATE),
+            export_template: String::from(naming::DEFAULT_EXPORT_TEMPLATE),
+            export_index: 0,
+            workspace: PathBuf::from("."),
+            replay_secs: 30,
+            audio_encoding: AudioEncoding::default(),
+            measured_offset_ms: None,
+            dry_run: false,
+            dry_run_log: Vec::new(),
+            show_safe_guides: false,
+            preview_enabled: true,
+            capture_tuning: ThreadTuning::default(),
+            recorder_tuning: ThreadTuning::default(),
+            frame_budget: frame_queue.budget().clone(),
+            frame_queue,
+            stream_config: None,
             texture: None,
+            ghost: None,
+            onion_skin: false,
+            interval_secs: 10,
+            interval_timelapse: true,
+            interval_stills: None,
+            ghost_opacity: 0.35,
             is_recording: false,
+            take_started: None,
+            take_dropped: 0,
+            encoder_fallback: None,
+            shot_name: String::new(),
+            show_operator: false,
             playlist: Vec::new(),
             final_file: None,
+            export_history: storage::load_json(EXPORT_HISTORY).unwrap_or_default(),
+            animation_draft: None,
+            animation_options: AnimationOptions::default(),
+            visualizer_style: VisualizerStyle::default(),
+            export_note: None,
+            timeline_format: None,
+            room_tone: None,
+            room_tone_until: None,
+            split_secs: 1.0,
+            thumb_secs: 0.0,
+            drop_target: None,
+            throughput: storage::load_json(estimate::THROUGHPUT_FILE).unwrap_or_default(),
+            gpu_devices: None,
             dragged_item: None,
-            last_error: None
+            voiceover_clip: None,
+            crash_report: crash::take_pending_report(),
+            keymap: KeyMap::default(),
+            settings: Settings::load(),
+            show_help: false,
+            monitor: None,
+            status_feed: None,
+            saved_timeline: Vec::new(),
+            status_from_args: false,
+            pin_prompt: None,
+            guest_saved: None,
+            prompt_index: 0,
+            settings_unlocked: false,
+            pin_draft: String::new(),
+            rebinding: None,
+            global_replay_key: None,
+            exit_unlocked: false,
+            last_error: None,
+            camera_busy: false,
+            config_preview: None,
+            latency_test: None
+        };
+        app.keymap = KeyMap::with_overrides(&app.settings.hotkeys);
+        if let Some(root) = app.settings.workspace_root.clone() {
+            app.workspace_root = root;
+        }
+        if let Some(format) = app.settings.video_format.clone().filter(|f| app.video_configs.contains(f)) {
+            app.selected_video_config = Some(format);
+        }
+        app.apply_pipeline();
+        app.update_status_feed();
+        app
+    }
+
+    /// Hands the saved pipeline tuning to the frame queue and the camera. Both take it live.
+    fn apply_pipeline(&self) {
+        self.frame_budget.set_cap_mb(self.settings.frame_cap_mb);
+        self.frame_queue.set_capacity(self.settings.frame_queue_len);
+        self.frame_queue.set_policy(self.settings.drop_policy);
+        let _ = self.camera_tx.send(CameraCommand::SetPreviewEvery(self.settings.preview_every));
+    }
+
+    pub fn with_monitor(mut self, monitor: MonitorServer) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    pub fn with_status_feed(mut self, target: Option<StatusTarget>) -> Self {
+        if let Some(target) = target {
+            self.status_feed = Some(StatusFeed::new(target));
+            self.status_from_args = true;
         }
+        self
     }
 }
 
 impl App for ClipperApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        if ctx.input(|i| i.viewport().close_requested()) && self.kiosk_locked() && !self.exit_unlocked {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.guard(ctx, Guarded::Exit);
+        }
+        let mut got_message = false;
         while let Ok(msg) = self.camera_rx.try_recv() {
+            got_message = true;
             match msg {
-                CameraMessage::Capabilities(c) => { self.video_configs = c; self.selected_video_config = self.video_configs.first().cloned(); self.state = AppState::Configuring; },
+                CameraMessage::Capabilities(c) => {
+                    // Confirming the last known format early queues the stream start behind the probe,
+                    // so the probe finishing then mustn't pull the app back to configuration.
+                    let confirmed_early = self.video_last_known && self.state == AppState::Running;
+                    self.video_configs = c;
+                    self.video_last_known = false;
+                    self.camera_busy = false;
+                    self.config_preview = None;
+                    if !self.selected_video_config.as_ref().is_some_and(|s| self.video_configs.contains(s)) {
+                        self.selected_video_config = self.settings.video_format.clone().filter(|f| self.video_configs.contains(f))
+                            .or_else(|| self.video_configs.first().cloned());
+                    }
+                    self.save_device_cache();
+                    if !confirmed_early {
+                        self.state = AppState::Configuring;
+                    }
+                },
                 CameraMessage::StreamStarted(w, h, fps) => {
+                    self.camera_busy = false;
                     if let Some(cfg) = &self.selected_video_config {
-                        let _ = self.rec_tx.send(RecorderCommand::UpdateConfig {
-                            width: w, height: h, fps, format: cfg.fmt.clone(),
-                            encoder: self.selected_encoder, quality: self.selected_quality, speed: self.selected_speed
-                        });
+                        self.stream_config = Some(VideoConfig { width: w, height: h, fps, fmt: cfg.fmt.clone() });
+                        self.push_recorder_config();
                     }
                 },
                 CameraMessage::Frame { raw: _, preview, p_width, p_height } => {
+                    self.frame_budget.release(preview.len());
+                    if let Some(monitor) = &mut self.monitor {
+                        monitor.offer_frame(p_width, p_height, &preview);
+                    }
+                    if let Some(test) = &mut self.latency_test {
+                        test.offer_frame(&preview, Instant::now());
+                    }
                     let img = egui::ColorImage::from_rgb([p_width as usize, p_height as usize], &preview);
                     self.texture = Some(ctx.load_texture("cam", img, Default::default()));
                 },
-                CameraMessage::Error(e) => self.last_error = Some(format!("Cam: {}", e)),
+                CameraMessage::Busy => {
+                    if !self.camera_busy {
+                        crash::record(String::from("Camera busy, retrying"));
+                    }
+                    self.camera_busy = true;
+                },
+                CameraMessage::Error(e) => { let msg = format!("Cam: {}", e); crash::record(msg.clone()); self.last_error = Some(msg); },
             }
         }
 
         while let Ok(msg) = self.audio_rx.try_recv() {
+            got_message = true;
             match msg {
-                AudioMessage::DeviceList(l) => { self.audio_devices = l; self.selected_audio_device = self.audio_devices.first().cloned(); },
-                AudioMessage::Error(e) => self.last_error = Some(format!("Audio: {}", e)),
+                AudioMessage::DeviceList(l) => {
+                    self.audio_devices = l;
+                    self.audio_last_known = false;
+                    if !self.selected_audio_device.as_ref().is_some_and(|s| self.audio_devices.contains(s)) {
+                        self.selected_audio_device = self.audio_devices.first().cloned();
+                    }
+                    self.save_device_cache();
+                },
+                AudioMessage::AppList(l) => self.audio_apps = l,
+                AudioMessage::Error(e) => { let msg = format!("Audio: {}", e); crash::record(msg.clone()); self.last_error = Some(msg); },
             }
         }
 
         while let Ok(stat) = self.rec_status.try_recv() {
+            got_message = true;
             match stat {
-                RecorderStatus::SegmentSaved(p) => self.playlist.push(p),
+                RecorderStatus::SegmentSaved(p) => {
+                    if self.settings.guestbook {
+                        self.guest_saved = Some(Instant::now());
+                        self.prompt_index += 1;
+                        self.push_recorder_config();
+                    } else {
+                        if self.playlist.is_empty() {
+                            self.timeline_format = self.stream_config.clone();
+                        }
+                        self.playlist.push(*p);
+                    }
+                    self.progress = None;
+                },
                 RecorderStatus::SegmentDeleted => { self.playlist.pop(); },
-                RecorderStatus::VideoFinalized(p) => { self.playlist.clear(); self.final_file = Some(p.to_string_lossy().to_string()); },
-                RecorderStatus::Error(e) => self.last_error = Some(format!("Rec: {}", e)),
+                RecorderStatus::VideoFinalized(p) => {
+                    self.playlist.clear();
+                    self.final_file = Some(match self.export_note.take() {
+                        Some(note) => format!("{} ({})", p.to_string_lossy(), note),
+                        None => p.to_string_lossy().to_string()
+                    });
+                    self.finished_export = Some(p);
+                },
+                RecorderStatus::ConcatReencoded(note) => self.export_note = Some(match self.export_note.take() {
+                    Some(earlier) => format!("{}; {}", earlier, note),
+                    None => note
+                }),
+                RecorderStatus::ExportFailed(e) => self.last_error = Some(format!("Export: {}", e)),
+                RecorderStatus::ExportCancelled => {},
+                RecorderStatus::ExportEnded(output) => self.complete_export(&output),
+                RecorderStatus::SyncMeasured(Some(ms)) => self.measured_offset_ms = Some(ms),
+                RecorderStatus::SyncMeasured(None) => self.last_error = Some(String::from("Sync: no clap detected in the last clip")),
+                RecorderStatus::DryRun(line) => self.dry_run_log.push(line),
+                RecorderStatus::Progress { stage, percent } => self.progress = Some((stage, percent)),
+                RecorderStatus::Throughput { encode, speed } => {
+                    self.throughput.record(encode, speed);
+                    storage::save_json(estimate::THROUGHPUT_FILE, &self.throughput);
+                },
+                RecorderStatus::DurationMismatch(p, expected, actual) => self.duration_mismatch = Some((p, expected, actual)),
+                RecorderStatus::SegmentQuarantined(p, recovered) => { self.quarantined = Some((p, recovered)); self.progress = None; },
+                RecorderStatus::ClipSplit(original, at, first, second) => self.apply_split(&original, at, *first, *second),
+                RecorderStatus::AnimationFinished(p) => {
+                    self.progress = None;
+                    if let Some(p) = p {
+                        self.final_file = Some(p.to_string_lossy().to_string());
+                    }
+                },
+                RecorderStatus::ClipAssetsReady { clip, black_secs, frozen_secs } => {
+                    if let Some(c) = self.playlist.iter_mut().find(|c| c.video_path == clip) {
+                        c.black_secs = black_secs;
+                        c.frozen_secs = frozen_secs;
+                        // The card may have asked for these before they existed, so drop the failed loads.
+                        for path in [&c.thumb_path, &c.preview_path, &c.waveform_path] {
+                            ctx.forget_image(&format!("file://{}", path.to_string_lossy()));
+                        }
+                    }
+                },
+                RecorderStatus::ThumbnailSet(clip, at) => {
+                    if let Some(c) = self.playlist.iter_mut().find(|c| c.video_path == clip) {
+                        c.thumb_secs = Some(at);
+                        // Same file, new picture: drop the cached decode so the card picks it up.
+                        ctx.forget_image(&format!("file://{}", c.thumb_path.to_string_lossy()));
+                    }
+                },
+                RecorderStatus::LowDiskSpace { free_gb, refused } => {
+                    if refused {
+                        self.is_recording = false;
+                        self.take_started = None;
+                        let _ = self.camera_tx.send(CameraCommand::LockExposure(false));
+                        self.last_error = Some(format!("Disk: only {:.1} GB free in the workspace, take not started", free_gb));
+                    } else {
+                        self.last_error = Some(format!("Disk: {:.1} GB left, finish this take soon", free_gb));
+                    }
+                },
+                RecorderStatus::SessionRecovered { restored, lost } => {
+                    if lost > 0 {
+                        self.last_error = Some(format!("Restored {} clip(s), {} couldn't be salvaged", restored, lost));
+                    }
+                },
+                RecorderStatus::FramesDropped(n) => self.take_dropped = n,
+                RecorderStatus::BackupFailed(clip, e) => {
+                    let name = clip.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    self.last_error = Some(format!("Backup: couldn't copy {}: {}", name, e));
+                },
+                RecorderStatus::LiveStreamEnded => {
+                    if self.live {
+                        self.last_error = Some(String::from("The live stream stopped. Check the stream address and the connection."));
+                    }
+                    self.live = false;
+                },
+                RecorderStatus::AudioMissing => {
+                    let reason = if self.audio_devices.is_empty() { "no microphone was found" } else { "the audio source gave nothing" };
+                    self.last_error = Some(format!("Takes are being saved without sound, {}", reason));
+                },
+                RecorderStatus::EncoderFallback(encoder) => {
+                    crash::record(format!("{} failed to start, fell back to libx264", encoder.ffmpeg_name()));
+                    self.encoder_fallback = Some(encoder);
+                },
+                RecorderStatus::RoomToneRecorded(p) => { self.room_tone = Some(p); self.export_options.room_tone = true; },
+                RecorderStatus::StillCaptured(n) => self.interval_stills = Some(n),
+                RecorderStatus::IntervalFinished(p) => self.final_file = Some(p.to_string_lossy().to_string()),
+                RecorderStatus::VoiceoverReplaced(p) => self.final_file = Some(format!("{} (new voiceover)", p.to_string_lossy())),
+                RecorderStatus::Error(e) => { let msg = format!("Rec: {}", e); crash::record(msg.clone()); self.last_error = Some(msg); self.progress = None; },
             }
         }
-        if ctx.input(|i| i.key_pressed(egui::Key::Space)) && !self.is_recording {
+        // A key pressed while a hotkey is being rebound is its new key, not a command.
+        let hotkeys_live = self.rebinding.is_none();
+        if hotkeys_live && self.keymap.pressed(ctx, Action::Record) && !self.is_recording && self.voiceover_clip.is_none() && self.room_tone_until.is_none() {
             self.is_recording = true; self.final_file = None; self.last_error = None;
+            self.take_started = Some(Instant::now());
+            self.take_dropped = 0;
+            if self.settings.lock_exposure {
+                let _ = self.camera_tx.send(CameraCommand::LockExposure(true));
+            }
             let _ = self.rec_tx.send(RecorderCommand::StartSegment);
         }
-        if ctx.input(|i| i.key_released(egui::Key::Space)) && self.is_recording {
+        let take_limit_hit = self.settings.max_take_secs.zip(self.take_started).is_some_and(|(max, started)| started.elapsed().as_secs() >= max as u64);
+        if (self.keymap.released(ctx, Action::Record) || take_limit_hit) && self.is_recording {
+            if take_limit_hit {
+                self.last_error = Some(format!("Take stopped at the {} limit", review::timestamp(self.settings.max_take_secs.unwrap_or(0) as f64)));
+            }
             self.is_recording = false;
-            let _ = self.rec_tx.send(RecorderCommand::EndSegment);
+            self.take_started = None;
+            // The last preview frame of a take is its end framing, which is what the next take picks up from.
+            self.ghost = self.texture.clone();
+            let _ = self.rec_tx.send(RecorderCommand::EndSegment(Instant::now()));
+            // Unlocking is a no-op when nothing was locked, and covers the option being switched off mid-take.
+            let _ = self.camera_tx.send(CameraCommand::LockExposure(false));
+        }
+        if let Some(monitor) = &mut self.monitor {
+            monitor.offer_status(MonitorStatus {
+                recording: self.is_recording,
+                take: self.playlist.len() + 1,
+                elapsed_secs: self.take_started.map(|t| t.elapsed().as_secs()).unwrap_or(0),
+                shot: self.shot_name.clone(),
+                note: self.last_error.clone()
+            });
+        }
+        // The session folder keeps the current timeline for `clipper merge`.
+        if self.state == AppState::Running && !self.settings.guestbook && self.playlist != self.saved_timeline {
+            if let Err(e) = recovery::save_timeline(&self.workspace, &self.playlist) {
+                self.last_error = Some(format!("Timeline: {}", e));
+            }
+            self.saved_timeline = self.playlist.clone();
+        }
+        if let Some(feed) = &mut self.status_feed {
+            let state = if self.state != AppState::Running {
+                FeedState::Configuring
+            } else if self.is_recording {
+                FeedState::Recording
+            } else if self.progress.is_some() {
+                FeedState::Exporting
+            } else {
+                FeedState::Idle
+            };
+            feed.offer(&StatusReport {
+                state,
+                elapsed_secs: self.take_started.filter(|_| self.is_recording).map(|t| t.elapsed().as_secs()).unwrap_or(0),
+                clips: self.playlist.len(),
+                last_error: self.last_error.clone()
+            });
         }
-        if ctx.input(|i| i.key_pressed(egui::Key::Backspace)) && !self.is_recording {
-             let _ = self.rec_tx.send(RecorderCommand::Undo);
+        self.update_global_replay_key(ctx);
+        let global_save = self.global_replay_key.as_ref().is_some_and(GlobalHotkey::pressed);
+        if (global_save || hotkeys_live && self.keymap.pressed(ctx, Action::SaveReplay)) && self.replay_enabled && self.state == AppState::Running {
+            let _ = self.rec_tx.send(RecorderCommand::SaveReplay);
         }
-        if ctx.input(|i| i.key_pressed(egui::Key::Enter)) && !self.is_recording && !self.playlist.is_empty() {
+        if hotkeys_live && self.keymap.pressed(ctx, Action::RevealLastExport)
+            && let Some(record) = self.export_history.first() {
+            reveal(&record.path);
+        }
+        if hotkeys_live && self.keymap.pressed(ctx, Action::Undo) && !self.is_recording && !self.settings.guestbook {
+            self.guard(ctx, Guarded::Undo);
+        }
+        if hotkeys_live && self.keymap.pressed(ctx, Action::Export) && !self.is_recording && self.playlist.iter().any(|c| !c.excluded) {
             let file_choice = rfd::FileDialog::new()
-                .add_filter("video", &["mp4"])
-                .set_file_name("vid.mp4")
-                .set_directory("~")
+                .add_filter("video", &[self.export_extension()])
+                .set_file_name(self.export_file_name())
+                .set_directory(&self.workspace)
                 .save_file();
 
             if let Some(path) = file_choice {
                 let output_path_string = path.to_string_lossy().to_string();
-                let clip_paths: Vec<std::path::PathBuf> = self.playlist.iter()
-                    .map(|c| c.video_path.clone())
-                    .collect();
-                let _ = self.rec_tx.send(RecorderCommand::FinalizeVideo(clip_paths, output_path_string));
+                self.queue_export(self.export_clips(), output_path_string, self.current_export_options());
+            }
+        }
+
+        if hotkeys_live && self.keymap.pressed(ctx, Action::Help) {
+            self.show_help = !self.show_help;
+        }
+        if self.show_help {
+            self.show_help_overlay(ctx);
+        }
+
+        if let Some(session) = &self.crashed_session {
+            let mut answered = None;
+            egui::Window::new("Restore last session").collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO).show(ctx, |ui| {
+                ui.label("Clipper didn't close properly last time.");
+                ui.label(egui::RichText::new(session.workspace.to_string_lossy()).monospace());
+                ui.label(format!("{} clip(s) are still there", session.clips));
+                if !session.orphaned_takes.is_empty() {
+                    ui.label(format!("{} take(s) were still recording and may be salvageable", session.orphaned_takes.len()));
+                }
+                ui.label("Restoring puts them back on the timeline once the camera is confirmed, and keeps recording into that folder.");
+                ui.horizontal(|ui| {
+                    if ui.button("Restore").clicked() {
+                        answered = Some(true);
+                    }
+                    if ui.button("Start fresh").on_hover_text("Leaves the clips where they are and deletes the unfinished takes").clicked() {
+                        answered = Some(false);
+                    }
+                });
+            });
+            match answered {
+                Some(true) => self.restore_session = self.crashed_session.take(),
+                Some(false) => {
+                    if let Some(session) = self.crashed_session.take() {
+                        recovery::discard_unfinished(&session);
+                    }
+                    storage::clean_stale_temp();
+                },
+                None => {}
+            }
+        }
+
+        if self.resume_prompt {
+            egui::Window::new("Unfinished exports").collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO).show(ctx, |ui| {
+                ui.label(format!("{} export(s) were still queued when Clipper last closed.", self.export_queue.len()));
+                for job in &self.export_queue {
+                    ui.label(format!("{} ({} clips)", job.output, job.clips.len()));
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Resume").clicked() {
+                        for job in &self.export_queue {
+                            let _ = self.rec_tx.send(RecorderCommand::FinalizeVideo(job.clips.clone(), job.output.clone(), job.options.clone()));
+                        }
+                        self.resume_prompt = false;
+                    }
+                    if ui.button("Discard").clicked() {
+                        self.export_queue.clear();
+                        storage::save_json(PENDING_EXPORTS, &self.export_queue);
+                        self.resume_prompt = false;
+                    }
+                });
+            });
+        }
+
+        if let Some(report) = self.crash_report.clone() {
+            egui::Window::new("Clipper crashed last time").collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO).show(ctx, |ui| {
+                ui.label("A crash report was saved. Attaching it to a bug report helps a lot.");
+                ui.label(egui::RichText::new(report.to_string_lossy()).monospace());
+                ui.horizontal(|ui| {
+                    if ui.button("Open").clicked() {
+                        crash::open_path(&report);
+                    }
+                    if ui.button("Copy").clicked() {
+                        ui.ctx().copy_text(fs::read_to_string(&report).unwrap_or_default());
+                    }
+                    if ui.button("Dismiss").clicked() {
+                        self.crash_report = None;
+                    }
+                });
+            });
+        }
+
+        if let Some((path, expected, actual)) = self.duration_mismatch.clone() {
+            egui::Window::new("Export length mismatch").collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO).show(ctx, |ui| {
+                ui.label(format!("{} is {:.2} s long, but the clips add up to {:.2} s.", path.to_string_lossy(), actual, expected));
+                ui.label("This usually means the clips' timestamps didn't join cleanly. Re-encoding rebuilds them.");
+                ui.horizontal(|ui| {
+                    if ui.button("Re-export with re-encode").clicked() {
+                        let job = self.mismatched_job.take().unwrap_or_else(|| ExportJob {
+                            clips: self.export_clips(),
+                            output: path.to_string_lossy().to_string(),
+                            options: self.current_export_options()
+                        });
+                        self.queue_export(job.clips, job.output, ExportOptions { reencode: true, ..job.options });
+                        self.duration_mismatch = None;
+                    }
+                    if ui.button("Keep it").clicked() {
+                        let _ = self.rec_tx.send(RecorderCommand::ClearSegments);
+                        self.playlist.clear();
+                        self.final_file = Some(path.to_string_lossy().to_string());
+                        self.duration_mismatch = None;
+                        self.mismatched_job = None;
+                    }
+                });
+            });
+        }
+
+        if let Some((path, recovered)) = self.quarantined.clone() {
+            egui::Window::new("Damaged clip").collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO).show(ctx, |ui| {
+                ui.label("The last clip didn't pass validation and was moved to quarantine:");
+                ui.label(egui::RichText::new(path.to_string_lossy()).monospace());
+                if recovered {
+                    ui.label("A salvaged copy was added to the timeline. Check it before exporting; damaged frames may have been dropped.");
+                } else {
+                    ui.colored_label(egui::Color32::from_rgb(255, 120, 120), "It couldn't be recovered, so nothing was added to the timeline.");
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Open quarantine folder").clicked()
+                        && let Some(dir) = path.parent() {
+                        crash::open_path(dir);
+                    }
+                    if ui.button("OK").clicked() {
+                        self.quarantined = None;
+                    }
+                });
+            });
+        }
+
+        if let Some(mut draft) = self.flag_draft.take() {
+            let mut keep = true;
+            let clip = self.playlist.iter().position(|c| c.video_path == draft.clip);
+            let duration = clip.map(|i| self.playlist[i].duration).unwrap_or(0.0);
+            egui::Window::new("Flag a moment").collapsible(false).resizable(false).show(ctx, |ui| {
+                ui.label(format!("Clip {}", clip.map(|i| i + 1).unwrap_or(0)));
+                ui.horizontal(|ui| {
+                    ui.label("At");
+                    ui.add(egui::DragValue::new(&mut draft.at_secs).range(0.0..=duration).speed(0.1).custom_formatter(|v, _| review::timestamp(v)));
+                });
+                ui.add(egui::TextEdit::singleline(&mut draft.note).hint_text("What to check"));
+                ui.horizontal(|ui| {
+                    if ui.button("Add").clicked() {
+                        self.review_flags.push(draft.clone());
+                        self.save_review_notes();
+                        keep = false;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        keep = false;
+                    }
+                });
+            });
+            if keep {
+                self.flag_draft = Some(draft);
+            }
+        }
+
+        if let Some(mut prompt) = self.pin_prompt.take() {
+            let (mut keep, mut unlocked) = (true, false);
+            egui::Window::new("Enter PIN").collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO).show(ctx, |ui| {
+                ui.label(match &prompt.action {
+                    Guarded::Settings => "Settings are locked.",
+                    Guarded::RemoveClip(_) | Guarded::Undo => "Removing clips is locked.",
+                    Guarded::Exit => "Quitting is locked."
+                });
+                let field = ui.add(egui::TextEdit::singleline(&mut prompt.entry).password(true).desired_width(120.0));
+                field.request_focus();
+                if prompt.wrong {
+                    ui.colored_label(egui::Color32::RED, "Wrong PIN");
+                }
+                ui.horizontal(|ui| {
+                    let entered = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Unlock").clicked() || entered {
+                        if self.settings.kiosk_pin.as_ref().is_some_and(|pin| pin.matches(&prompt.entry)) {
+                            unlocked = true;
+                        } else {
+                            prompt.wrong = true;
+                            prompt.entry.clear();
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        keep = false;
+                    }
+                });
+            });
+            if unlocked {
+                self.perform(ctx, prompt.action);
+            } else if keep {
+                self.pin_prompt = Some(prompt);
+            }
+        }
+
+        if let Some(mut draft) = self.animation_draft.take() {
+            let mut keep = true;
+            egui::Window::new("Export animation").collapsible(false).resizable(false).show(ctx, |ui| {
+                ui.label(draft.source.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default());
+                let options = &mut draft.options;
+                egui::Grid::new("animation_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Format:");
+                    ui.horizontal(|ui| {
+                        for format in [AnimationFormat::Gif, AnimationFormat::WebP] {
+                            ui.radio_value(&mut options.format, format, format.to_string());
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("Width:");
+                    ui.add(egui::DragValue::new(&mut options.width).range(64..=1920).speed(8).suffix(" px"));
+                    ui.end_row();
+                    ui.label("Frame rate:");
+                    ui.add(egui::DragValue::new(&mut options.fps).range(1..=30).suffix(" fps"));
+                    ui.end_row();
+                    ui.label("Range:");
+                    ui.horizontal(|ui| {
+                        let mut end = options.end.unwrap_or(draft.duration);
+                        ui.add(egui::DragValue::new(&mut options.start).range(0.0..=end).speed(0.05).max_decimals(2).suffix(" s"));
+                        ui.label("to");
+                        ui.add(egui::DragValue::new(&mut end).range(options.start..=draft.duration).speed(0.05).max_decimals(2).suffix(" s"));
+                        options.end = (end < draft.duration).then_some(end);
+                    });
+                    ui.end_row();
+                    ui.label("");
+                    ui.checkbox(&mut options.loop_forever, "Loop forever");
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Export…").clicked() {
+                        let ext = options.format.extension();
+                        let stem = draft.source.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+                        let file_choice = rfd::FileDialog::new().add_filter(ext, &[ext]).set_file_name(format!("{}.{}", stem, ext)).save_file();
+                        if let Some(path) = file_choice {
+                            let _ = self.rec_tx.send(RecorderCommand::ExportAnimation(draft.source.clone(), path.to_string_lossy().to_string(), *options));
+                            self.animation_options = AnimationOptions { start: 0.0, end: None, ..*options };
+                            keep = false;
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        keep = false;
+                    }
+                });
+            });
+            if keep {
+                self.animation_draft = Some(draft);
+            }
+        }
+
+        if !self.dry_run_log.is_empty() {
+            let mut open = true;
+            egui::Window::new("ffmpeg commands (dry run)").open(&mut open).default_width(600.0).show(ctx, |ui| {
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for line in &self.dry_run_log {
+                        ui.horizontal(|ui| {
+                            if ui.small_button("Copy").clicked() {
+                                ui.ctx().copy_text(line.clone());
+                            }
+                            ui.add(egui::Label::new(egui::RichText::new(line).monospace()).wrap());
+                        });
+                    }
+                });
+            });
+            if !open {
+                self.dry_run_log.clear();
             }
         }
 
+        if self.show_operator {
+            self.show_operator_display(ctx);
+        }
+        self.show_latency_test(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             match self.state {
                 AppState::Loading => {
                     ui.centered_and_justified(|ui| {
                         ui.vertical_centered(|ui| {
-                            if let Some(err) = &self.last_error {
+                            if self.camera_busy {
+                                ui.heading("The camera is in use by another app");
+                                ui.label(CAMERA_BUSY_HINT);
+                                ui.spinner();
+                            } else if let Some(err) = &self.last_error {
                                 ui.heading(egui::RichText::new("Camera initialization failed").color(egui::Color32::RED));
                                 ui.label(err);
                                 ui.add_space(10.0);
                                 if ui.button("Retry").clicked() {
                                     self.last_error = None;
+                                    let _ = self.camera_tx.send(CameraCommand::Retry);
                                 }
                             } else {
                                 ui.spinner();
@@ -160,157 +909,1479 @@ impl App for ClipperApp {
                         });
                     });
                 },
+                AppState::Configuring if self.kiosk_locked() && !self.settings_unlocked => self.show_locked_config(ui),
                 AppState::Configuring => self.show_config(ui),
                 AppState::Running => self.show_running(ui),
             }
         });
-        
-        ctx.request_repaint();
+
+        self.schedule_repaint(ctx, got_message);
     }
 }
 
 impl ClipperApp {
-    fn show_config(&mut self, ui: &mut egui::Ui) {
-        ui.heading("Configure");
-        ui.separator();
-        egui::Grid::new("cfg_grid").show(ui, |ui| {
-            ui.label("Video:");
-            if let Some(sel) = &mut self.selected_video_config {
-                egui::ComboBox::from_id_salt("vid").selected_text(sel.to_string()).show_ui(ui, |ui| {
-                    for config in &self.video_configs { ui.selectable_value(sel, config.clone(), config.to_string()); }
-                });
-            }
-            ui.end_row();
+    /// Channels can't wake egui, so they are polled: right away after a message (handling one often
+    /// leads to more), at the camera rate while something live is on screen, and slowly otherwise.
+    fn schedule_repaint(&self, ctx: &egui::Context, got_message: bool) {
+        let live = self.state == AppState::Running && (self.preview_enabled || self.is_recording || self.show_operator);
+        if got_message {
+            ctx.request_repaint();
+        } else if live {
+            let fps = self.stream_config.as_ref().map(|c| c.fps).unwrap_or(30).max(1);
+            ctx.request_repaint_after(Duration::from_secs_f64(1.0 / fps as f64));
+        } else {
+            ctx.request_repaint_after(IDLE_REPAINT);
+        }
+    }
 
-            ui.label("Audio:");
-            if let Some(sel) = &mut self.selected_audio_device {
-                egui::ComboBox::from_id_salt("aud").selected_text(&sel.name).show_ui(ui, |ui| {
-                    for device in &self.audio_devices {
-                        if ui.selectable_value(sel, device.clone(), &device.name).clicked() {
-                            let _ = self.rec_tx.send(RecorderCommand::SetAudioDevice(device.index));
-                        }
+    /// A second window meant for a monitor facing the talent or crew: take number, elapsed time and
+    /// shot name in large type, on red while recording.
+    fn show_operator_display(&mut self, ctx: &egui::Context) {
+        let viewport = egui::ViewportBuilder::default().with_title("Clipper - Operator").with_inner_size([640.0, 360.0]);
+        ctx.show_viewport_immediate(egui::ViewportId::from_hash_of("operator"), viewport, |ctx, _class| {
+            if ctx.input(|i| i.viewport().close_requested()) {
+                self.show_operator = false;
+            }
+            let background = if self.is_recording { egui::Color32::from_rgb(150, 0, 0) } else { egui::Color32::from_gray(20) };
+            egui::CentralPanel::default().frame(egui::Frame::NONE.fill(background)).show(ctx, |ui| {
+                let height = ui.available_height();
+                let elapsed = self.take_started.map(|t| t.elapsed().as_secs()).unwrap_or(0);
+                ui.vertical_centered(|ui| {
+                    ui.add_space(height * 0.08);
+                    ui.label(egui::RichText::new(format!("TAKE {}", self.playlist.len() + 1)).size(height * 0.25).strong().color(egui::Color32::WHITE));
+                    ui.label(egui::RichText::new(format!("{:02}:{:02}", elapsed / 60, elapsed % 60)).size(height * 0.2).monospace().color(egui::Color32::WHITE));
+                    if !self.shot_name.is_empty() {
+                        ui.label(egui::RichText::new(&self.shot_name).size(height * 0.12).color(egui::Color32::LIGHT_GRAY));
                     }
                 });
-            }
-            ui.end_row();
-
-            ui.label("Encoder:");
-            egui::ComboBox::from_id_salt("enc").selected_text(self.selected_encoder.to_string()).show_ui(ui, |ui| {
-                ui.selectable_value(&mut self.selected_encoder, EncoderPreset::CPU, "CPU");
-                ui.selectable_value(&mut self.selected_encoder, EncoderPreset::NVIDIA, "NVIDIA");
-                ui.selectable_value(&mut self.selected_encoder, EncoderPreset::AMD, "AMD");
-                ui.selectable_value(&mut self.selected_encoder, EncoderPreset::INTEL, "Intel");
             });
-            ui.end_row();
+        });
+    }
 
-            ui.label("Encoding Quality:");
-            egui::ComboBox::from_id_salt("qty").selected_text(self.selected_quality.to_string()).show_ui(ui, |ui| {
-                ui.selectable_value(&mut self.selected_quality, EncodingQuality::High, format!("{}", EncodingQuality::High));
-                ui.selectable_value(&mut self.selected_quality, EncodingQuality::Med, format!("{}", EncodingQuality::Med));
-                ui.selectable_value(&mut self.selected_quality, EncodingQuality::Low, format!("{}", EncodingQuality::Low));
+    /// Paints the whole window black or white for the latency test, then shows what it measured.
+    fn show_latency_test(&mut self, ctx: &egui::Context) {
+        let frame_ms = self.stream_config.as_ref().map_or(0, |c| 1000 / c.fps.max(1)) as u128;
+        let Some(test) = &mut self.latency_test else { return };
+        if !test.is_done() {
+            test.tick(Instant::now());
+            let fill = if test.flashing() { egui::Color32::WHITE } else { egui::Color32::BLACK };
+            let (round, rounds) = test.progress();
+            let screen = ctx.content_rect();
+            egui::Area::new(egui::Id::new("latency_flash")).order(egui::Order::Foreground).fixed_pos(screen.min).show(ctx, |ui| {
+                ui.painter().rect_filled(screen, 0.0, fill);
+                ui.painter().text(screen.left_bottom() + egui::vec2(10.0, -10.0), egui::Align2::LEFT_BOTTOM,
+                    format!("Latency test {}/{}: point the camera at this window. Esc cancels.", round + 1, rounds),
+                    egui::FontId::proportional(14.0), egui::Color32::GRAY);
             });
-            ui.end_row();
+            if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.latency_test = None;
+            }
+            ctx.request_repaint();
+            return;
+        }
 
-            ui.label("Encoding Speed:");
-            egui::ComboBox::from_id_salt("spd").selected_text(self.selected_speed.to_string()).show_ui(ui, |ui| {
-                ui.selectable_value(&mut self.selected_speed, EncodingSpeed::Fastest, format!("{}", EncodingSpeed::Fastest));
-                ui.selectable_value(&mut self.selected_speed, EncodingSpeed::Balanced, format!("{}", EncodingSpeed::Balanced));
-                ui.selectable_value(&mut self.selected_speed, EncodingSpeed::Compact, format!("{}", EncodingSpeed::Compact));
+        let mut open = true;
+        egui::Window::new("Latency").open(&mut open).collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO).show(ctx, |ui| {
+            let Some(result) = test.result() else {
+                ui.label("The camera never saw the window flash. Point it at the window so the window fills most of the picture, and try again.");
+                return;
+            };
+            let median = result.median.as_millis();
+            ui.label(format!("Glass to preview: {} ms (fastest {} ms, slowest {} ms)", median, result.min.as_millis(), result.max.as_millis()));
+            ui.label(format!("Glass to file: about {} ms", median + frame_ms))
+                .on_hover_text("Frames are handed to the encoder on the capture clock, up to one frame after the preview gets them");
+            if result.misses > 0 {
+                ui.weak(format!("{} of the flashes weren't seen", result.misses));
+            }
+            ui.add_space(6.0);
+            ui.label(match median {
+                0..=40 => "Quick enough to play along to the preview.",
+                41..=90 => "Noticeable when playing along; fine for checking framing and posture.",
+                _ => "Too late to play along to; listen to the instrument itself and use the preview for framing only."
             });
-            ui.end_row();
+            ui.weak("Includes the display's own lag, so another monitor may measure differently.");
         });
-
-        ui.add_space(20.0);
-        if ui.button("Confirm").clicked() {
-            if let Some(cfg) = &self.selected_video_config {
-                let _ = self.camera_tx.send(CameraCommand::StartStream(cfg.clone()));
-                let _ = self.rec_tx.send(RecorderCommand::UpdateConfig {
-                    width: cfg.width, height: cfg.height, fps: cfg.fps, format: cfg.fmt.clone(), encoder: self.selected_encoder, quality: self.selected_quality, speed: self.selected_speed
-                });
-                self.state = AppState::Running;
-            }
+        if !open {
+            self.latency_test = None;
         }
     }
 
-    fn show_running(&mut self, ui: &mut egui::Ui) {
-        ui.horizontal(|ui| {
-            if self.is_recording {
-                ui.colored_label(egui::Color32::RED, "RECORDING");
-            } else {
-                ui.label("Idle");
-            }
-
-            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                if !self.playlist.is_empty() && !self.is_recording {
-                    if ui.button("Merge").clicked() {
-                        let file_choice = rfd::FileDialog::new().add_filter("video", &["mp4"]).set_file_name("vid.mp4").set_directory(".").save_file();
-                        if let Some(path) = file_choice {
-                            let output_path_string = path.to_string_lossy().to_string();
-                            let clip_paths : Vec<PathBuf> = self.playlist.iter().map(|c| c.video_path.clone()).collect();
-                            let _ = self.rec_tx.send(RecorderCommand::FinalizeVideo(clip_paths, output_path_string));
-                        }
-                    }
+    fn show_help_overlay(&mut self, ctx: &egui::Context) {
+        let key = |action| self.keymap.key_name(action);
+        egui::Window::new("Hotkeys").open(&mut self.show_help).collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO).show(ctx, |ui| {
+            egui::Grid::new("help_grid").striped(true).show(ui, |ui| {
+                for (action, k) in self.keymap.bindings() {
+                    ui.label(egui::RichText::new(k.name()).monospace().strong());
+                    ui.label(action.description());
+                    ui.end_row();
                 }
             });
+            ui.separator();
+            ui.label(format!("1. Record: hold {} for each take; {} throws away a take you don't like.", key(Action::Record), key(Action::Undo)));
+            ui.label("2. Review: hover a clip to preview it, drag to reorder, right-click to change its lane or trim it.");
+            ui.label(format!("3. Finalize: press {} or Merge to export the timeline as one video.", key(Action::Export)));
         });
+    }
 
-        ui.separator();
-        let total_height = ui.available_height();
-        let timeline_height = 150.0;
-        let camera_height = total_height - timeline_height;
-        let camera_rect = ui.allocate_ui(egui::vec2(ui.available_width(), camera_height), |ui| {
-            if let Some(texture) = &self.texture {
-                let size = texture.size_vec2();
-                let aspect = size.x / size.y;
-                let available_w = ui.available_width();
-                let available_h = ui.available_height();
+    fn export_file_name(&self) -> String {
+        format!("{}.{}", naming::expand(&self.export_template, &self.project, self.export_index + 1), self.export_extension())
+    }
 
-                let (w, h) = if available_w / aspect <= available_h {
-                    (available_w, available_w / aspect)
-                } else {
-                    (available_h * aspect, available_h)
-                };
+    /// The export options as set, plus the watermark when it's burned in at export and the format
+    /// the timeline started in.
+    fn current_export_options(&self) -> ExportOptions {
+        ExportOptions {
+            watermark: self.settings.watermark.clone().filter(|w| w.at_export),
+            frame_format: self.timeline_format.as_ref().map(|f| FrameFormat { width: f.width, height: f.height, fps: f.fps }),
+            ..self.export_options.clone()
+        }
+    }
 
-                ui.centered_and_justified(|ui| {
-                    ui.add(egui::Image::new(texture).fit_to_exact_size(egui::vec2(w, h)));
-                });
-            }  
-        }).response.rect;
+    fn export_extension(&self) -> &'static str {
+        self.export_options.preset.map_or("mp4", ExportPreset::container)
+    }
 
-        if self.is_recording {
-            ui.put(egui::Rect::from_min_size(camera_rect.min + egui::vec2(20.0, 20.0), egui::vec2(200.0, 50.0)),
-    |ui: &mut egui::Ui| ui.heading(egui::RichText::new("REC").color(egui::Color32::RED).strong())
-            );
+    fn queue_export(&mut self, clips: Vec<ClipInfo>, output: String, options: ExportOptions) {
+        self.export_index += 1;
+        if self.dry_run {
+            let _ = self.rec_tx.send(RecorderCommand::FinalizeVideo(clips, output, options));
+            return;
         }
 
-        ui.put(
-            egui::Rect::from_min_size(camera_rect.left_bottom() + egui::vec2(20.0, -50.0), egui::vec2(300.0, 50.0)),
-            |ui: &mut egui::Ui| ui.colored_label(egui::Color32::WHITE, format!("Clips: {}", self.playlist.len()))
-        );
-        
-        if let Some(f) = &self.final_file {
-            ui.put(
-                egui::Rect::from_center_size(camera_rect.center(), egui::vec2(400.0, 50.0)),
-                |ui: &mut egui::Ui| ui.colored_label(egui::Color32::GREEN, format!("Saved: {}", f))
-            );
-        }
+        let job = ExportJob { clips, output, options };
+        let _ = self.rec_tx.send(RecorderCommand::FinalizeVideo(job.clips.clone(), job.output.clone(), job.options.clone()));
+        self.export_queue.push(job);
+        storage::save_json(PENDING_EXPORTS, &self.export_queue);
+    }
 
-        if let Some(e) = &self.last_error {
-            ui.put(
-                egui::Rect::from_min_size(camera_rect.left_bottom() + egui::vec2(20.0, -100.0), egui::vec2(400.0, 40.0)),
-                |ui: &mut egui::Ui| ui.colored_label(egui::Color32::RED, format!("{}", e))
-            );
+    /// The guestbook question for the next take, if any are set.
+    fn guest_prompt(&self) -> Option<&str> {
+        if !self.settings.guestbook {
+            return None;
         }
+        let prompts: Vec<&str> = self.settings.guestbook_prompts.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        (!prompts.is_empty()).then(|| prompts[self.prompt_index % prompts.len()])
+    }
 
-        ui.separator();
-        ui.label("Timeline");
-        egui::ScrollArea::horizontal().min_scrolled_height(120.0).show(ui, |ui| {
+    fn kiosk_locked(&self) -> bool {
+        self.settings.kiosk_pin.is_some()
+    }
+
+    /// Runs the action now, or asks for the kiosk PIN first.
+    fn guard(&mut self, ctx: &egui::Context, action: Guarded) {
+        // Guests share the booth, so no one can take back a message the last guest left.
+        if self.settings.guestbook && matches!(action, Guarded::RemoveClip(_) | Guarded::Undo) {
+            return;
+        }
+        if self.kiosk_locked() {
+            if self.pin_prompt.is_none() {
+                self.pin_prompt = Some(PinPrompt { action, entry: String::new(), wrong: false });
+            }
+        } else {
+            self.perform(ctx, action);
+        }
+    }
+
+    fn perform(&mut self, ctx: &egui::Context, action: Guarded) {
+        match action {
+            Guarded::Settings => self.settings_unlocked = true,
+            Guarded::RemoveClip(path) => {
+                self.playlist.retain(|c| c.video_path != path);
+                // A queued export still reads it, and deletes it once done.
+                if !self.export_queue.iter().any(|job| job.clips.iter().any(|c| c.video_path == path)) {
+                    let _ = self.rec_tx.send(RecorderCommand::DeleteClips(vec![path]));
+                }
+            },
+            Guarded::Undo => { let _ = self.rec_tx.send(RecorderCommand::Undo); },
+            Guarded::Exit => {
+                self.exit_unlocked = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        }
+    }
+
+    /// The timeline as it gets exported, without the clips switched off for export.
+    fn export_clips(&self) -> Vec<ClipInfo> {
+        self.playlist.iter().filter(|c| !c.excluded).cloned().collect()
+    }
+
+    /// Swaps a split clip for its halves, which keep its lane. Its trim points, outgoing
+    /// transition and review flags move to whichever half they fall in.
+    fn apply_split(&mut self, original: &Path, at: f64, mut first: ClipInfo, mut second: ClipInfo) {
+        let Some(index) = self.playlist.iter().position(|c| c.video_path == original) else { return; };
+        let old = self.playlist.remove(index);
+        first.lane = old.lane;
+        second.lane = old.lane;
+        first.label = old.label.clone();
+        second.label = old.label.clone();
+        first.excluded = old.excluded;
+        second.excluded = old.excluded;
+        if old.trim_in < at {
+            first.trim_in = old.trim_in;
+        } else {
+            second.trim_in = old.trim_in - at;
+        }
+        match old.trim_out {
+            Some(out) if out <= at => first.trim_out = Some(out),
+            Some(out) => second.trim_out = Some(out - at),
+            None => {}
+        }
+        second.transition = old.transition;
+        // The caption only covers the take's first seconds.
+        first.burned_caption = old.burned_caption.clone();
+        first.burned_timestamp = old.burned_timestamp;
+        second.burned_timestamp = old.burned_timestamp;
+        for flag in self.review_flags.iter_mut().filter(|f| f.clip == original) {
+            if flag.at_secs < at {
+                flag.clip = first.video_path.clone();
+            } else {
+                flag.clip = second.video_path.clone();
+                flag.at_secs -= at;
+            }
+        }
+        self.playlist.insert(index, second);
+        self.playlist.insert(index, first);
+        if !self.review_flags.is_empty() {
+            self.save_review_notes();
+        }
+    }
+
+    fn save_device_cache(&self) {
+        if !self.video_configs.is_empty() || !self.audio_devices.is_empty() {
+            storage::save_json(DEVICE_CACHE, &DeviceCache { video: self.video_configs.clone(), audio: self.audio_devices.clone() });
+        }
+    }
+
+    fn save_review_notes(&mut self) {
+        if let Err(e) = review::write_notes(&self.workspace, &self.review_flags, &self.playlist) {
+            self.last_error = Some(format!("Review notes: {}", e));
+        }
+    }
+
+    /// Takes the export to `output` off the queue. A successful one goes into the history, and its
+    /// clips are deleted unless an export still queued uses them too.
+    fn complete_export(&mut self, output: &str) {
+        self.progress = None;
+        self.export_note = None;
+        let finished = self.finished_export.take();
+        let Some(i) = self.export_queue.iter().position(|job| job.output == output) else { return; };
+        let job = self.export_queue.remove(i);
+        storage::save_json(PENDING_EXPORTS, &self.export_queue);
+        let Some(path) = finished else {
+            if self.duration_mismatch.is_some() {
+                self.mismatched_job = Some(job);
+            }
+            return;
+        };
+
+        let release: Vec<PathBuf> = job.clips.iter().map(|c| c.video_path.clone())
+            .filter(|p| !self.export_queue.iter().any(|queued| queued.clips.iter().any(|c| &c.video_path == p)))
+            .collect();
+        let _ = self.rec_tx.send(RecorderCommand::DeleteClips(release));
+        self.export_history.insert(0, ExportRecord { job, path, finished_at: Local::now().format("%Y-%m-%d %H:%M").to_string() });
+        self.export_history.truncate(EXPORT_HISTORY_LEN);
+        storage::save_json(EXPORT_HISTORY, &self.export_history);
+    }
+
+    /// The "Recent" menu: each finished export with open and reveal actions. Re-exporting needs the
+    /// original clips, which a successful export cleans up, so it's only offered while they're all
+    /// still there.
+    fn show_export_history(&mut self, ui: &mut egui::Ui) {
+        let mut requeue = None;
+        ui.menu_button("Recent", |ui| {
+            for (i, record) in self.export_history.iter().enumerate() {
+                let name = record.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                ui.menu_button(format!("{}  {}", record.finished_at, name), |ui| {
+                    ui.label(egui::RichText::new(record.path.to_string_lossy()).monospace());
+                    ui.weak(format!("{} clips, {}", record.job.clips.len(), export_summary(&record.job.options)));
+                    let exists = record.path.exists();
+                    if ui.add_enabled(exists, egui::Button::new("Open")).clicked() {
+                        crash::open_path(&record.path);
+                        ui.close();
+                    }
+                    if ui.button("Show in folder").clicked() {
+                        reveal(&record.path);
+                        ui.close();
+                    }
+                    let clips_left = record.job.clips.iter().all(|c| c.video_path.exists());
+                    if clips_left && ui.add_enabled(!self.is_recording, egui::Button::new("Export again")).clicked() {
+                        requeue = Some(i);
+                        ui.close();
+                    }
+                });
+            }
+        }).response.on_hover_text(format!("Recent exports ({} shows the last one)", self.keymap.key_name(Action::RevealLastExport)));
+        if let Some(i) = requeue {
+            let job = self.export_history[i].job.clone();
+            self.queue_export(job.clips, job.output, job.options);
+        }
+    }
+
+    /// Grabs the save-replay key from the desktop while the replay buffer runs, and lets it go
+    /// otherwise or when it's rebound. A key that can't be grabbed turns the option off.
+    fn update_global_replay_key(&mut self, ctx: &egui::Context) {
+        let key = self.keymap.key(Action::SaveReplay);
+        if !(self.settings.global_replay_hotkey && self.replay_enabled && self.state == AppState::Running) {
+            self.global_replay_key = None;
+        } else if self.global_replay_key.as_ref().is_none_or(|grabbed| grabbed.key() != key) {
+            self.global_replay_key = None;
+            match GlobalHotkey::register(key, ctx.clone()) {
+                Ok(grabbed) => self.global_replay_key = Some(grabbed),
+                Err(e) => {
+                    self.last_error = Some(format!("{}; {} only saves the replay while Clipper has focus", e, key.name()));
+                    self.settings.global_replay_hotkey = false;
+                }
+            }
+        }
+    }
+
+    /// Performance-first defaults for recording games: keep the CPU free for the game itself.
+    fn apply_game_profile(&mut self) {
+        let hardware = self.selected_encoder.hardware_equivalent();
+        if self.available_encoders.contains(&hardware) {
+            self.selected_encoder = hardware;
+        }
+        self.selected_speed = EncodingSpeed::Fastest;
+        self.live_hls = false;
+        self.replay_enabled = true;
+        // The game has focus while it's played, so the save key has to work from outside Clipper.
+        self.settings.global_replay_hotkey = true;
+        self.capture_tuning.raise_priority = true;
+        self.recorder_tuning.raise_priority = true;
+        self.set_preview(false);
+    }
+
+    fn set_preview(&mut self, enabled: bool) {
+        self.preview_enabled = enabled;
+        if !enabled {
+            self.texture = None;
+        }
+        let _ = self.camera_tx.send(CameraCommand::SetPreview(enabled));
+    }
+
+    fn push_recorder_config(&self) {
+        if let Some(cfg) = &self.stream_config {
+            let config = RecorderConfig {
+                width: cfg.width, height: cfg.height, fps: cfg.fps, format: cfg.fmt.clone(),
+                encoder: self.selected_encoder,
+                quality: self.selected_quality,
+                speed: self.selected_speed,
+                rate_control: self.selected_rate_control,
+                bitrate_kbps: self.video_bitrate_kbps,
+                live_hls: self.live_hls,
+                proxy: self.record_proxy,
+                audio_offset_ms: self.settings.audio_offset_ms,
+                audio: self.audio_encoding,
+                dry_run: self.dry_run,
+                workspace: self.workspace.clone(),
+                project: self.project.clone(),
+                clip_template: if self.settings.guestbook { naming::GUESTBOOK_CLIP_TEMPLATE.to_string() } else { self.clip_template.clone() },
+                extra_args: ffmpeg::split_args(&self.settings.extra_ffmpeg_args),
+                encoder_device: self.selected_encoder.vendor().and_then(|v| self.settings.encoder_devices.get(&v).cloned()),
+                min_free_gb: self.settings.min_free_gb,
+                burn_caption: self.guest_prompt().filter(|_| self.settings.burn_prompts).map(String::from),
+                burn_timestamp: self.settings.burn_timestamp,
+                record_audio: self.settings.record_audio,
+                overflow_dir: self.settings.overflow_dir.clone(),
+                backup_dir: self.settings.backup_dir.clone(),
+                watermark: self.settings.watermark.clone().filter(|w| !w.at_export),
+                face_model: self.settings.face_model.clone()
+            };
+            // Device names are left out on purpose, they often contain people's names.
+            crash::set_settings(format!("{:#?}\npreview: {}\nreplay: {:?}\ncapture thread: {:?}\nrecorder thread: {:?}\nframe cap: {} MB",
+                config, self.preview_enabled, self.replay_enabled.then_some(self.replay_secs), self.capture_tuning, self.recorder_tuning, self.settings.frame_cap_mb));
+            let _ = self.rec_tx.send(RecorderCommand::UpdateConfig(config));
+        }
+    }
+
+    fn show_config(&mut self, ui: &mut egui::Ui) {
+        // Until a stream is started, the highlighted format streams to a preview here to check
+        // framing and that the camera takes it.
+        if self.stream_config.is_none() && self.selected_video_config != self.config_preview
+            && let Some(sel) = &self.selected_video_config {
+            let _ = self.camera_tx.send(CameraCommand::PreviewFormat(sel.clone()));
+            self.texture = None;
+            self.config_preview = Some(sel.clone());
+        }
+        ui.heading("Configure");
+        if let Some(texture) = &self.texture {
+            ui.add(egui::Image::new(texture).max_height(180.0).corner_radius(4.0));
+        } else if self.config_preview.is_some() {
+            ui.weak(self.last_error.as_deref().unwrap_or("Starting the preview…"));
+        }
+        ui.horizontal(|ui| {
+            ui.label("Profile:");
+            if ui.button("Game capture").on_hover_text("Hardware encoder, fastest preset, replay buffer with a system-wide save key, raised thread priority and no preview decoding").clicked() {
+                self.apply_game_profile();
+            }
+        });
+        ui.separator();
+        egui::Grid::new("cfg_grid").show(ui, |ui| {
+            ui.label("Video:");
+            if let Some(sel) = &mut self.selected_video_config {
+                egui::ComboBox::from_id_salt("vid").selected_text(sel.to_string()).show_ui(ui, |ui| {
+                    for config in &self.video_configs { ui.selectable_value(sel, config.clone(), config.to_string()); }
+                });
+            }
+            if self.video_last_known {
+                ui.weak("last known, still probing the camera…");
+            } else if ui.small_button("Refresh").on_hover_text("Ask the camera for its formats again instead of using the cached list").clicked() {
+                self.video_last_known = true;
+                let _ = self.camera_tx.send(CameraCommand::RefreshFormats);
+            }
+            ui.end_row();
+
+            ui.label("Sound:");
+            ui.checkbox(&mut self.settings.record_audio, "Record an audio track")
+                .on_hover_text("Turn off for silent screen captures. Takes are then saved with no audio track, and exports give them a silent one only to join them with other clips.");
+            ui.end_row();
+
+            ui.label("Audio:");
+            if let Some(sel) = &mut self.selected_audio_device {
+                egui::ComboBox::from_id_salt("aud").selected_text(&sel.name).show_ui(ui, |ui| {
+                    for device in &self.audio_devices {
+                        if ui.selectable_value(sel, device.clone(), &device.name).clicked() {
+                            let _ = self.rec_tx.send(RecorderCommand::SetAudioDevice(device.index));
+                        }
+                    }
+                });
+            }
+            if self.audio_devices.is_empty() {
+                ui.weak("No microphone found");
+            } else if self.audio_last_known {
+                ui.weak("last known");
+            }
+            ui.end_row();
+
+            ui.label("Channels:");
+            ui.horizontal(|ui| {
+                let channels = self.selected_audio_device.as_ref().map(|d| d.channels).unwrap_or(2).min(32);
+                for c in 0..channels {
+                    let bit = 1u32 << c;
+                    let mut on = self.audio_encoding.channel_mask == 0 || self.audio_encoding.channel_mask & bit != 0;
+                    if ui.checkbox(&mut on, format!("{}", c + 1)).changed() {
+                        let all = if channels >= 32 { u32::MAX } else { (1u32 << channels) - 1 };
+                        let current = if self.audio_encoding.channel_mask == 0 { all } else { self.audio_encoding.channel_mask };
+                        let next = if on { current | bit } else { current & !bit };
+                        // Never leave zero channels picked; an all-set mask is the same as "everything".
+                        if next != 0 {
+                            self.audio_encoding.channel_mask = if next == all { 0 } else { next };
+                        }
+                    }
+                }
+                egui::ComboBox::from_id_salt("downmix").selected_text(self.audio_encoding.downmix.to_string()).show_ui(ui, |ui| {
+                    for mix in [Downmix::Keep, Downmix::Mono, Downmix::Stereo] {
+                        ui.selectable_value(&mut self.audio_encoding.downmix, mix, mix.to_string());
+                    }
+                });
+            });
+            ui.end_row();
+
+            ui.label("Audio Source:");
+            ui.horizontal(|ui| {
+                let selected = self.selected_audio_app.as_ref().map(|a| a.name.clone()).unwrap_or_else(|| String::from("Microphone"));
+                egui::ComboBox::from_id_salt("aud_app").selected_text(selected).show_ui(ui, |ui| {
+                    if ui.selectable_value(&mut self.selected_audio_app, None, "Microphone").clicked() {
+                        let _ = self.rec_tx.send(RecorderCommand::SetAudioApp(None));
+                    }
+                    for app in &self.audio_apps {
+                        if ui.selectable_value(&mut self.selected_audio_app, Some(app.clone()), &app.name).clicked() {
+                            let _ = self.rec_tx.send(RecorderCommand::SetAudioApp(Some(app.clone())));
+                        }
+                    }
+                });
+                if ui.button("Refresh").on_hover_text("Look for applications playing audio (PipeWire only)").clicked() {
+                    let _ = self.rec_tx.send(RecorderCommand::RefreshAudioApps);
+                }
+            });
+            ui.end_row();
+
+            ui.label("Encoder:");
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("enc").selected_text(self.selected_encoder.to_string()).show_ui(ui, |ui| {
+                    for preset in &self.available_encoders {
+                        ui.selectable_value(&mut self.selected_encoder, *preset, preset.to_string());
+                    }
+                });
+                if !self.ffmpeg_found {
+                    ui.colored_label(egui::Color32::RED, "ffmpeg not found on PATH");
+                } else if !self.available_encoders.iter().any(|p| p.is_hardware()) {
+                    ui.colored_label(egui::Color32::YELLOW, "No hardware encoders in this ffmpeg build")
+                        .on_hover_text("NVENC, AMF and QuickSync need an ffmpeg built with them; encoding will use the CPU");
+                }
+            });
+            ui.end_row();
+
+            let lossless = self.selected_encoder == EncoderPreset::MASTERING;
+            ui.label("Rate Control:");
+            ui.add_enabled_ui(!lossless, |ui| ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("rc").selected_text(self.selected_rate_control.to_string()).show_ui(ui, |ui| {
+                    for rc in [RateControl::Crf, RateControl::Vbr, RateControl::Cbr] {
+                        ui.selectable_value(&mut self.selected_rate_control, rc, rc.to_string());
+                    }
+                });
+                ui.add_enabled(self.selected_rate_control != RateControl::Crf,
+                    egui::DragValue::new(&mut self.video_bitrate_kbps).range(500..=100_000).speed(100).suffix(" kbps"));
+            })).response.on_disabled_hover_text("Lossless recording keeps every bit, so there is no rate to set");
+            ui.end_row();
+
+            ui.label("Encoding Quality:");
+            ui.add_enabled_ui(self.selected_rate_control == RateControl::Crf && !lossless, |ui| egui::ComboBox::from_id_salt("qty").selected_text(self.selected_quality.to_string()).show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.selected_quality, EncodingQuality::High, format!("{}", EncodingQuality::High));
+                ui.selectable_value(&mut self.selected_quality, EncodingQuality::Med, format!("{}", EncodingQuality::Med));
+                ui.selectable_value(&mut self.selected_quality, EncodingQuality::Low, format!("{}", EncodingQuality::Low));
+            }));
+            ui.end_row();
+
+            ui.label("Encoding Speed:");
+            egui::ComboBox::from_id_salt("spd").selected_text(self.selected_speed.to_string()).show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.selected_speed, EncodingSpeed::Fastest, format!("{}", EncodingSpeed::Fastest));
+                ui.selectable_value(&mut self.selected_speed, EncodingSpeed::Balanced, format!("{}", EncodingSpeed::Balanced));
+                ui.selectable_value(&mut self.selected_speed, EncodingSpeed::Compact, format!("{}", EncodingSpeed::Compact));
+            });
+            ui.end_row();
+
+            ui.label("Live HLS:");
             ui.horizontal(|ui| {
-                let mut move_from = None;
-                let mut move_to = None;
+                ui.checkbox(&mut self.live_hls, format!("Also write {} while recording (picture only)", crate::recorder::LIVE_HLS_PLAYLIST))
+                    .on_hover_text("Watch takes in a browser a few seconds behind real time. The playlist has no sound, takes are recorded with it as usual.");
+                ui.add_enabled(self.live_hls, egui::Checkbox::new(&mut self.settings.serve_hls, "Serve on the network"))
+                    .on_hover_ui(|ui| {
+                        ui.label(match hls_server::lan_address() {
+                            Some(addr) => format!("Open http://{} in a browser on another machine to watch takes as they're recorded", addr),
+                            None => format!("Open this machine's address on port {} in a browser on another machine", hls_server::HLS_PORT)
+                        });
+                    });
+            });
+            ui.end_row();
+
+            ui.label("Proxy:");
+            ui.checkbox(&mut self.record_proxy, "Also record a 480p proxy of each take")
+                .on_hover_text("Thumbnails, previews and checks are made from the proxy to keep weak machines responsive. Exports still use the full-quality clips.");
+            ui.end_row();
+
+            ui.label("Timestamp:");
+            ui.checkbox(&mut self.settings.burn_timestamp, "Burn the date and time into every frame")
+                .on_hover_text("Drawn in the bottom-right corner as each frame is encoded, so it can't be removed later. Applies to takes and the replay buffer.");
+            ui.end_row();
+
+            ui.label("Max take length:");
+            ui.horizontal(|ui| {
+                let mut limited = self.settings.max_take_secs.is_some();
+                ui.checkbox(&mut limited, "Stop takes after")
+                    .on_hover_text("End a take by itself, as if the record key were let go, in case it was held down by mistake");
+                let mut secs = self.settings.max_take_secs.unwrap_or(600);
+                ui.add_enabled(limited, egui::DragValue::new(&mut secs).range(5..=14_400).speed(5).custom_formatter(|v, _| review::timestamp(v)));
+                self.settings.max_take_secs = limited.then_some(secs);
+            });
+            ui.end_row();
+
+            ui.label("Guestbook:");
+            ui.checkbox(&mut self.settings.guestbook, "Save each take on its own, behind an attract screen")
+                .on_hover_text("For events where nobody edits a timeline: every take is merged into its own file named by the time it was recorded, and guests see large recording instructions");
+            ui.end_row();
+
+            if self.settings.guestbook {
+                ui.label("Questions:");
+                ui.vertical(|ui| {
+                    ui.add(egui::TextEdit::multiline(&mut self.settings.guestbook_prompts).hint_text("One per line, e.g. What's your favourite memory of the couple?").desired_rows(3).desired_width(320.0))
+                        .on_hover_text("Shown to guests on the attract screen, moving to the next question after each take");
+                    ui.checkbox(&mut self.settings.burn_prompts, "Burn the question into the first seconds of the take")
+                        .on_hover_text("Re-encodes each take's video as it's saved, so saving takes a little longer");
+                });
+                ui.end_row();
+            }
+
+            ui.label("Exposure:");
+            ui.checkbox(&mut self.settings.lock_exposure, "Lock white balance and exposure while recording")
+                .on_hover_text("Auto white balance and exposure settle during preview and are frozen for each take, so the picture doesn't pump mid-take");
+            ui.end_row();
+
+            ui.label("Keep free:");
+            ui.add(egui::DragValue::new(&mut self.settings.min_free_gb).range(0.0..=500.0).speed(0.5).max_decimals(1).suffix(" GB"))
+                .on_hover_text("Takes won't start with less free space than this in the workspace, and a running take warns when it gets there");
+            ui.end_row();
+
+            ui.label("Workspace:");
+            ui.horizontal(|ui| {
+                ui.label(self.workspace_root.to_string_lossy())
+                    .on_hover_text("Each session records into its own dated folder in here");
+                if ui.button("Browse…").clicked()
+                    && let Some(dir) = rfd::FileDialog::new().set_directory(&self.workspace_root).pick_folder() {
+                    self.workspace_root = dir;
+                }
+            });
+            ui.end_row();
+
+            ui.label("Status file:");
+            ui.horizontal(|ui| {
+                match &self.settings.status_file {
+                    Some(path) => ui.label(path.to_string_lossy()),
+                    None => ui.weak("None")
+                }.on_hover_text("Rewrite the recording state as JSON here once a second, for dashboards and OBS text sources. Start Clipper with --status-json - to print it to the console instead.");
+                if ui.button("Choose…").clicked()
+                    && let Some(path) = rfd::FileDialog::new().add_filter("JSON", &["json"]).set_file_name("clipper-status.json").save_file() {
+                    self.settings.status_file = Some(path);
+                }
+                if self.settings.status_file.is_some() && ui.small_button("✖").on_hover_text("Stop writing a status file").clicked() {
+                    self.settings.status_file = None;
+                }
+            });
+            ui.end_row();
+
+            ui.label("Overflow:");
+            ui.horizontal(|ui| {
+                match &self.settings.overflow_dir {
+                    Some(dir) => ui.label(dir.to_string_lossy()),
+                    None => ui.weak("None")
+                }.on_hover_text("A take that would fill the workspace volume, or outgrow a FAT32 drive's 4 GB file limit, is split into parts and carries on in here");
+                if ui.button("Choose…").clicked()
+                    && let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                    self.settings.overflow_dir = Some(dir);
+                }
+                if self.settings.overflow_dir.is_some() && ui.small_button("✖").on_hover_text("Stop using an overflow folder").clicked() {
+                    self.settings.overflow_dir = None;
+                }
+            });
+            ui.end_row();
+
+            ui.label("Watermark:");
+            ui.vertical(|ui| {
+                ui.horizontal(|ui| {
+                    match &self.settings.watermark {
+                        Some(mark) => ui.label(mark.image.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()),
+                        None => ui.weak("None")
+                    }.on_hover_text("A logo composited over the picture. Its placement shows on the camera preview.");
+                    if ui.button("Choose…").clicked()
+                        && let Some(image) = rfd::FileDialog::new().add_filter("image", &["png"]).pick_file() {
+                        self.settings.watermark = Some(match self.settings.watermark.take() {
+                            Some(mark) => Watermark { image, ..mark },
+                            None => Watermark::new(image)
+                        });
+                    }
+                    if self.settings.watermark.is_some() && ui.small_button("✖").on_hover_text("Stop adding a watermark").clicked() {
+                        self.settings.watermark = None;
+                    }
+                });
+                if let Some(mark) = &mut self.settings.watermark {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Slider::new(&mut mark.x, 0.0..=1.0).text("Across").show_value(false));
+                        ui.add(egui::Slider::new(&mut mark.y, 0.0..=1.0).text("Down").show_value(false));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Slider::new(&mut mark.height, 0.02..=0.5).text("Size").show_value(false));
+                        ui.add(egui::Slider::new(&mut mark.opacity, 0.1..=1.0).text("Opacity").show_value(false));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut mark.at_export, true, "At export")
+                            .on_hover_text("Burned into the finished export once; the clips stay clean");
+                        ui.radio_value(&mut mark.at_export, false, "Into every take")
+                            .on_hover_text("Each take is re-encoded with the logo as it's saved, so saving takes a little longer");
+                    });
+                }
+            });
+            ui.end_row();
+
+            ui.label("Backup:");
+            ui.horizontal(|ui| {
+                match &self.settings.backup_dir {
+                    Some(dir) => ui.label(dir.to_string_lossy()),
+                    None => ui.weak("None")
+                }.on_hover_text("Each clip is also copied here once it's saved, and the copy checked against it. Best on another drive.");
+                if ui.button("Choose…").clicked()
+                    && let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                    self.settings.backup_dir = Some(dir);
+                }
+                if self.settings.backup_dir.is_some() && ui.small_button("✖").on_hover_text("Stop backing up clips").clicked() {
+                    self.settings.backup_dir = None;
+                }
+            });
+            ui.end_row();
+
+            ui.label("Face model:");
+            ui.horizontal(|ui| {
+                match &self.settings.face_model {
+                    Some(model) => ui.label(model.to_string_lossy()),
+                    None => ui.weak("None")
+                }.on_hover_text("An OpenVINO face detection model, such as face-detection-adas-0001.xml. Vertical exports use it to follow the subject, and need an ffmpeg built with OpenVINO.");
+                if ui.button("Choose…").clicked()
+                    && let Some(model) = rfd::FileDialog::new().add_filter("OpenVINO model", &["xml"]).pick_file() {
+                    self.settings.face_model = Some(model);
+                }
+                if self.settings.face_model.is_some() && ui.small_button("✖").on_hover_text("Keep vertical crops centred").clicked() {
+                    self.settings.face_model = None;
+                }
+            });
+            ui.end_row();
+
+            ui.label("Project:");
+            ui.text_edit_singleline(&mut self.project);
+            ui.end_row();
+
+            ui.label("File Names:");
+            ui.horizontal(|ui| {
+                ui.label("Clips");
+                ui.add(egui::TextEdit::singleline(&mut self.clip_template).desired_width(140.0))
+                    .on_hover_text(format!("Placeholders: {}. The clip number is appended if {{index}} is missing.", naming::VARIABLES));
+                ui.label("Exports");
+                ui.add(egui::TextEdit::singleline(&mut self.export_template).desired_width(140.0))
+                    .on_hover_text(format!("Placeholders: {}. {{index}} counts exports this session.", naming::VARIABLES));
+            });
+            ui.end_row();
+
+            ui.label("Replay Buffer:");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.replay_enabled, "Keep the last")
+                    .on_hover_text(format!("Always encode the most recent seconds; press {} to save them as a clip", self.keymap.key_name(Action::SaveReplay)));
+                ui.add_enabled(self.replay_enabled, egui::DragValue::new(&mut self.replay_secs).range(5..=300).suffix(" s"));
+                ui.weak("picture only").on_hover_text("The buffer doesn't record sound, so replays are saved with a silent track");
+                ui.add_enabled(self.replay_enabled, egui::Checkbox::new(&mut self.settings.global_replay_hotkey, "from any app"))
+                    .on_hover_text(format!("Grab {} system-wide while recording, so it saves the replay while a game has focus. Not available on Wayland.", self.keymap.key_name(Action::SaveReplay)));
+            });
+            ui.end_row();
+
+            ui.label("Live stream:");
+            ui.add(egui::TextEdit::singleline(&mut self.settings.live_url).password(true).hint_text("rtmp://server/app/stream-key").desired_width(220.0))
+                .on_hover_text("RTMP address including the stream key. Go live from the recording screen; takes keep recording locally while streaming.");
+            ui.end_row();
+
+            ui.label("Audio Codec:");
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("acodec").selected_text(self.audio_encoding.codec.to_string()).show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.audio_encoding.codec, AudioCodec::Aac, AudioCodec::Aac.to_string());
+                    ui.selectable_value(&mut self.audio_encoding.codec, AudioCodec::Opus, AudioCodec::Opus.to_string());
+                });
+                egui::ComboBox::from_id_salt("abitrate").selected_text(format!("{} kbps", self.audio_encoding.bitrate_kbps)).show_ui(ui, |ui| {
+                    for kbps in [96, 128, 160, 192, 256, 320] {
+                        ui.selectable_value(&mut self.audio_encoding.bitrate_kbps, kbps, format!("{} kbps", kbps));
+                    }
+                });
+            });
+            ui.end_row();
+
+            ui.label("Audio Lag:");
+            ui.add(egui::DragValue::new(&mut self.settings.audio_offset_ms).range(-1000..=1000).suffix(" ms"))
+                .on_hover_text("How late the microphone arrives relative to the camera");
+            ui.end_row();
+
+            ui.label("Debug:");
+            ui.checkbox(&mut self.dry_run, "Dry run")
+                .on_hover_text("Show the ffmpeg command lines instead of running them");
+            ui.end_row();
+        });
+
+        ui.add_space(10.0);
+        egui::CollapsingHeader::new("Advanced").show(ui, |ui| {
+            let cores = tuning::core_count();
+            egui::Grid::new("tuning_grid").show(ui, |ui| {
+                for (label, salt, tuning) in [("Capture thread:", "cap_core", &mut self.capture_tuning), ("Recorder thread:", "rec_core", &mut self.recorder_tuning)] {
+                    ui.label(label);
+                    ui.checkbox(&mut tuning.raise_priority, "Raise priority");
+                    let core_text = tuning.core.map(|c| format!("Core {}", c)).unwrap_or_else(|| String::from("Any core"));
+                    egui::ComboBox::from_id_salt(salt).selected_text(core_text).show_ui(ui, |ui| {
+                        ui.selectable_value(&mut tuning.core, None, "Any core");
+                        for c in 0..cores {
+                            ui.selectable_value(&mut tuning.core, Some(c), format!("Core {}", c));
+                        }
+                    });
+                    ui.end_row();
+                }
+                ui.label("Encoder device:");
+                match self.selected_encoder.vendor() {
+                    Some(vendor) => {
+                        let detected = self.gpu_devices.get_or_insert_with(|| {
+                            [GpuVendor::Nvidia, GpuVendor::Intel, GpuVendor::Amd].into_iter().map(|v| (v, gpu::detect(v))).collect()
+                        });
+                        let devices = detected.iter().find(|(v, _)| *v == vendor).map(|(_, d)| d.as_slice()).unwrap_or_default();
+                        let mut chosen = self.settings.encoder_devices.get(&vendor).cloned();
+                        let chosen_text = match &chosen {
+                            Some(id) => devices.iter().find(|d| &d.id == id).map(|d| d.name.clone()).unwrap_or_else(|| id.clone()),
+                            None => String::from("Driver default")
+                        };
+                        ui.add_enabled_ui(!devices.is_empty() || chosen.is_some(), |ui| {
+                            egui::ComboBox::from_id_salt("encoder_device").selected_text(chosen_text).show_ui(ui, |ui| {
+                                ui.selectable_value(&mut chosen, None, "Driver default");
+                                for device in devices {
+                                    ui.selectable_value(&mut chosen, Some(device.id.clone()), &device.name);
+                                }
+                            });
+                        }).response.on_hover_text(format!("GPU the {} encoder runs on", vendor));
+                        match chosen {
+                            Some(id) => { self.settings.encoder_devices.insert(vendor, id); },
+                            None => { self.settings.encoder_devices.remove(&vendor); }
+                        }
+                    },
+                    None => { ui.weak("Software encoder"); }
+                }
+                ui.end_row();
+
+                ui.label("Extra ffmpeg args:");
+                ui.add(egui::TextEdit::singleline(&mut self.settings.extra_ffmpeg_args).hint_text("-g 60 -bf 0").desired_width(200.0))
+                    .on_hover_text("Appended to the video encoder arguments. Use dry run to check the result.");
+                ui.end_row();
+
+                ui.label("Timeline:");
+                ui.checkbox(&mut self.settings.animate_timeline, "Animate reordering");
+                ui.end_row();
+
+                ui.label("Kiosk PIN:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.pin_draft).password(true).hint_text("New PIN").desired_width(80.0));
+                    let label = if self.settings.kiosk_pin.is_some() { "Change" } else { "Lock" };
+                    if ui.add_enabled(!self.pin_draft.is_empty(), egui::Button::new(label))
+                        .on_hover_text("Ask for this PIN before opening settings, removing clips or quitting, for unattended setups like an event guestbook")
+                        .clicked() {
+                        self.settings.kiosk_pin = Some(PinHash::new(&self.pin_draft));
+                        self.pin_draft.clear();
+                    }
+                    if self.settings.kiosk_pin.is_some() && ui.button("Unlock").clicked() {
+                        self.settings.kiosk_pin = None;
+                    }
+                });
+                ui.end_row();
+            });
+        });
+
+        egui::CollapsingHeader::new("Pipeline").show(ui, |ui| {
+            let mut changed = false;
+            egui::Grid::new("pipeline_grid").show(ui, |ui| {
+                ui.label("Frame buffer cap:");
+                changed |= ui.add(egui::DragValue::new(&mut self.settings.frame_cap_mb).range(64..=8192).suffix(" MB"))
+                    .on_hover_text("Frames beyond this much queued memory are dropped instead of buffered")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Frame queue:");
+                changed |= ui.add(egui::DragValue::new(&mut self.settings.frame_queue_len).range(1..=budget::MAX_FRAME_QUEUE_LEN).suffix(" frames"))
+                    .on_hover_text("How many frames can wait for the encoder. Longer rides out hiccups, shorter keeps takes closer to live.")
+                    .changed();
+                ui.end_row();
+
+                ui.label("When full:");
+                egui::ComboBox::from_id_salt("drop_policy").selected_text(self.settings.drop_policy.to_string()).show_ui(ui, |ui| {
+                    for policy in DropPolicy::ALL {
+                        changed |= ui.selectable_value(&mut self.settings.drop_policy, policy, policy.to_string()).changed();
+                    }
+                }).response.on_hover_text("Block slows capture to the encoder's pace rather than skipping frames, up to half a second per frame");
+                ui.end_row();
+
+                ui.label("Preview:");
+                changed |= ui.add(egui::DragValue::new(&mut self.settings.preview_every).range(1..=10).prefix("every ").suffix(" frames"))
+                    .on_hover_text("Decode the preview from fewer frames to leave more CPU for capture. Recording is unaffected.")
+                    .changed();
+                ui.end_row();
+            });
+            if ui.button("Reset").clicked() {
+                let defaults = Settings::default();
+                (self.settings.frame_cap_mb, self.settings.frame_queue_len, self.settings.drop_policy, self.settings.preview_every) =
+                    (defaults.frame_cap_mb, defaults.frame_queue_len, defaults.drop_policy, defaults.preview_every);
+                changed = true;
+            }
+            if changed {
+                self.apply_pipeline();
+            }
+        });
+
+        egui::CollapsingHeader::new("Hotkeys").show(ui, |ui| {
+            if let Some(action) = self.rebinding {
+                let pressed = ui.input(|i| i.events.iter().find_map(|e| match e {
+                    egui::Event::Key { key, pressed: true, .. } => Some(*key),
+                    _ => None
+                }));
+                match pressed {
+                    Some(egui::Key::Escape) => self.rebinding = None,
+                    Some(key) => {
+                        self.keymap.rebind(action, key);
+                        self.settings.hotkeys = self.keymap.overrides();
+                        self.settings.save();
+                        self.rebinding = None;
+                    },
+                    None => {}
+                }
+            }
+            egui::Grid::new("hotkey_grid").show(ui, |ui| {
+                for (action, key) in self.keymap.bindings().to_vec() {
+                    ui.label(action.description());
+                    let text = if self.rebinding == Some(action) { "Press a key…" } else { key.name() };
+                    if ui.button(text).on_hover_text("Click, then press the new key. Escape cancels.").clicked() {
+                        self.rebinding = Some(action);
+                    }
+                    ui.end_row();
+                }
+            });
+            if ui.button("Reset").clicked() {
+                self.keymap = KeyMap::default();
+                self.settings.hotkeys.clear();
+                self.settings.save();
+                self.rebinding = None;
+            }
+        });
+
+        ui.add_space(20.0);
+        if ui.button("Confirm").clicked() {
+            self.confirm_config();
+        }
+    }
+
+    /// The settings screen of a locked kiosk: it can only go on to recording until the PIN is given.
+    fn show_locked_config(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Configure");
+        ui.label("Settings are locked.");
+        ui.add_space(10.0);
+        ui.horizontal(|ui| {
+            if ui.button("Start").clicked() {
+                self.confirm_config();
+            }
+            if ui.button("Unlock settings…").clicked() {
+                let ctx = ui.ctx().clone();
+                self.guard(&ctx, Guarded::Settings);
+            }
+        });
+    }
+
+    fn confirm_config(&mut self) {
+        if let Some(cfg) = &self.selected_video_config {
+            let _ = self.camera_tx.send(CameraCommand::SetTuning(self.capture_tuning));
+            let _ = self.rec_tx.send(RecorderCommand::SetTuning(self.recorder_tuning));
+            let _ = self.camera_tx.send(CameraCommand::StartStream(cfg.clone()));
+            // Kept for the next launch and for `clipper doctor`.
+            self.settings.workspace_root = Some(self.workspace_root.clone());
+            self.settings.video_format = Some(cfg.clone());
+            self.settings.save();
+            self.stream_config = Some(cfg.clone());
+            self.workspace = match &self.restore_session {
+                Some(session) => session.workspace.clone(),
+                None => self.workspace_root.join(Local::now().format("%Y-%m-%d_%H%M").to_string())
+            };
+            recovery::mark_active(&self.workspace);
+            self.review_flags = review::load_flags(&self.workspace);
+            self.timeline_format = None;
+            self.room_tone = None;
+            self.push_recorder_config();
+            if let Some(session) = self.restore_session.take() {
+                let room_tone = self.workspace.join(ffmpeg::ROOM_TONE);
+                self.room_tone = room_tone.exists().then_some(room_tone);
+                let _ = self.rec_tx.send(RecorderCommand::RecoverSession(session.orphaned_takes));
+            }
+            let _ = self.rec_tx.send(RecorderCommand::SetReplay(self.replay_enabled.then_some(self.replay_secs)));
+            self.update_hls_server();
+            self.update_status_feed();
+            self.state = AppState::Running;
+            // Settings lock again for the next visit.
+            self.settings_unlocked = false;
+        }
+    }
+
+    /// Switches the camera to another format between takes. The session carries on, and takes in
+    /// the new format are conformed to the first take's on export.
+    fn show_format_switch(&mut self, ui: &mut egui::Ui) {
+        let Some(current) = self.stream_config.clone() else { return };
+        let mut selected = current.clone();
+        ui.add_enabled_ui(!self.is_recording && self.progress.is_none(), |ui| {
+            egui::ComboBox::from_id_salt("running_format").selected_text(current.to_string()).show_ui(ui, |ui| {
+                for config in &self.video_configs { ui.selectable_value(&mut selected, config.clone(), config.to_string()); }
+            }).response.on_hover_text("Record the next takes in another format. Exports conform every take to the size and frame rate the timeline started in.");
+        });
+        if selected != current {
+            let _ = self.camera_tx.send(CameraCommand::StartStream(selected.clone()));
+            self.texture = None;
+            self.selected_video_config = Some(selected.clone());
+            self.stream_config = Some(selected);
+            self.push_recorder_config();
+        }
+        if let Some(first) = &self.timeline_format
+            && !self.playlist.is_empty()
+            && (first.width, first.height, first.fps) != (current.width, current.height, current.fps) {
+            let softer = current.width > first.width || current.height > first.height || current.fps > first.fps;
+            ui.colored_label(egui::Color32::YELLOW, "Mixed formats").on_hover_text(format!(
+                "The timeline started in {}x{} at {} fps. New takes will be re-encoded to match on export{}.",
+                first.width, first.height, first.fps,
+                if softer { ", losing the extra resolution or frame rate" } else { ", and smaller takes scaled up look softer" }));
+        }
+    }
+
+    /// Follows the status file setting, unless the command line picked where the feed goes.
+    fn update_status_feed(&mut self) {
+        if self.status_from_args { return; }
+        let target = self.settings.status_file.clone().map(StatusTarget::File);
+        if self.status_feed.as_ref().map(StatusFeed::target) != target.as_ref() {
+            self.status_feed = target.map(StatusFeed::new);
+        }
+    }
+
+    /// Points the network preview at this session's live folder, starting the server when it's
+    /// wanted and closing its port when it no longer is.
+    fn update_hls_server(&mut self) {
+        let serve = self.live_hls && self.settings.serve_hls;
+        if serve && self.hls_server.is_none() {
+            match HlsServer::start() {
+                Ok(server) => self.hls_server = Some(server),
+                Err(e) => self.last_error = Some(format!("Couldn't serve the live preview on port {}: {}", hls_server::HLS_PORT, e))
+            }
+        }
+        if !serve {
+            self.hls_server = None;
+        }
+        if let Some(server) = &self.hls_server {
+            server.set_root(Some(self.workspace.join(crate::recorder::LIVE_HLS_DIR)));
+        }
+    }
+
+    /// What guests see in guestbook mode: the camera with one line of large instructions. Takes
+    /// stand alone, so there is no timeline to show.
+    fn show_guestbook(&mut self, ui: &mut egui::Ui) {
+        let rect = ui.available_rect_before_wrap();
+        let painter = ui.painter();
+        painter.rect_filled(rect, 0.0, egui::Color32::BLACK);
+        if let Some(texture) = &self.texture {
+            let size = texture.size_vec2();
+            let scale = (rect.width() / size.x).min(rect.height() / size.y);
+            let image_rect = egui::Rect::from_center_size(rect.center(), size * scale);
+            let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+            painter.image(texture.id(), image_rect, uv, egui::Color32::WHITE);
+        }
+
+        let key = self.keymap.key_name(Action::Record);
+        let thanked = self.guest_saved.is_some_and(|t| t.elapsed() < GUESTBOOK_THANKS);
+        let (headline, detail, color) = if self.is_recording {
+            let elapsed = self.take_started.map(|t| t.elapsed().as_secs()).unwrap_or(0);
+            (format!("● {:02}:{:02}", elapsed / 60, elapsed % 60), format!("Let go of {} when you're done", key), egui::Color32::from_rgb(255, 70, 70))
+        } else if self.progress.is_some() {
+            (String::from("Saving…"), String::from("One moment"), egui::Color32::WHITE)
+        } else if thanked {
+            (String::from("Thank you!"), String::from("Your message was saved"), egui::Color32::from_rgb(120, 230, 120))
+        } else {
+            (String::from("Leave a message"), format!("Hold {} to record", key), egui::Color32::WHITE)
+        };
+        let height = rect.height();
+        if !thanked && let Some(prompt) = self.guest_prompt() {
+            let galley = painter.layout(prompt.to_string(), egui::FontId::proportional(height * 0.07), egui::Color32::WHITE, rect.width() * 0.9);
+            let top = egui::Rect::from_min_max(rect.min, egui::pos2(rect.max.x, rect.min.y + galley.size().y + height * 0.08));
+            painter.rect_filled(top, 0.0, egui::Color32::from_black_alpha(170));
+            painter.galley(egui::pos2(rect.center().x - galley.size().x / 2.0, rect.min.y + height * 0.04), galley, egui::Color32::WHITE);
+        }
+        let band = egui::Rect::from_min_max(egui::pos2(rect.min.x, rect.max.y - height * 0.3), rect.max);
+        painter.rect_filled(band, 0.0, egui::Color32::from_black_alpha(170));
+        painter.text(band.center() - egui::vec2(0.0, height * 0.05), egui::Align2::CENTER_CENTER, headline, egui::FontId::proportional(height * 0.1), color);
+        painter.text(band.center() + egui::vec2(0.0, height * 0.07), egui::Align2::CENTER_CENTER, detail, egui::FontId::proportional(height * 0.05), egui::Color32::LIGHT_GRAY);
+        ui.allocate_rect(rect, egui::Sense::hover());
+    }
+
+    fn show_running(&mut self, ui: &mut egui::Ui) {
+        if self.settings.guestbook {
+            self.show_guestbook(ui);
+            return;
+        }
+        ui.horizontal(|ui| {
+            if self.is_recording {
+                ui.colored_label(egui::Color32::RED, "RECORDING");
+            } else if self.voiceover_clip.is_some() {
+                ui.colored_label(egui::Color32::RED, "VOICEOVER");
+                if ui.button("Stop voiceover").clicked() {
+                    self.voiceover_clip = None;
+                    let _ = self.rec_tx.send(RecorderCommand::StopVoiceover);
+                }
+            } else if let Some(until) = self.room_tone_until {
+                let left = until.saturating_duration_since(Instant::now());
+                ui.colored_label(egui::Color32::RED, format!("ROOM TONE {}s, stay quiet", left.as_secs() + 1));
+                if left.is_zero() {
+                    self.room_tone_until = None;
+                    let _ = self.rec_tx.send(RecorderCommand::StopRoomTone);
+                }
+            } else {
+                ui.label("Idle");
+                let label = if self.room_tone.is_some() { "Re-record room tone" } else { "Record room tone" };
+                if ui.button(label).on_hover_text(format!("Capture {} s of the room in silence to fill audio gaps on export", ROOM_TONE_SECS)).clicked() {
+                    self.room_tone_until = Some(Instant::now() + Duration::from_secs(ROOM_TONE_SECS));
+                    let _ = self.rec_tx.send(RecorderCommand::StartRoomTone);
+                }
+            }
+
+            ui.separator();
+            self.show_format_switch(ui);
+            let mut preview = self.preview_enabled;
+            if ui.checkbox(&mut preview, "Preview").on_hover_text("Decoding the preview costs CPU on every frame").changed() {
+                self.set_preview(preview);
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.shot_name).hint_text("Shot name").desired_width(100.0));
+            ui.checkbox(&mut self.show_operator, "Operator display").on_hover_text("Open a second window with the take number and timer in large type");
+            if ui.add_enabled(!self.is_recording, egui::Button::new("Latency test")).on_hover_text("Flash the window at the camera and time how late the preview and recordings are").clicked() {
+                self.set_preview(true);
+                self.latency_test = Some(LatencyTest::start());
+            }
+            ui.checkbox(&mut self.show_safe_guides, "Safe guides").on_hover_text("Action-safe (93%) and title-safe (90%) frames");
+            ui.add_enabled(self.ghost.is_some(), egui::Checkbox::new(&mut self.onion_skin, "Onion skin"))
+                .on_hover_text("Overlay the last frame of the previous take to match framing between takes");
+            if self.onion_skin {
+                ui.add(egui::Slider::new(&mut self.ghost_opacity, 0.1..=0.8).show_value(false));
+            }
+            ui.separator();
+            if let Some(stills) = self.interval_stills {
+                ui.label(format!("Stills: {}", stills));
+                if ui.button("Stop interval").clicked() {
+                    self.interval_stills = None;
+                    let _ = self.rec_tx.send(RecorderCommand::StopInterval);
+                }
+            } else {
+                ui.add(egui::DragValue::new(&mut self.interval_secs).range(1..=3600).prefix("every ").suffix(" s"));
+                ui.checkbox(&mut self.interval_timelapse, "Time-lapse")
+                    .on_hover_text("Assemble the stills into a video when the interval stops");
+                if ui.button("Start interval").on_hover_text("Save a full-resolution still at a fixed interval, separate from clips").clicked() {
+                    self.interval_stills = Some(0);
+                    let _ = self.rec_tx.send(RecorderCommand::StartInterval(self.interval_secs, self.interval_timelapse));
+                }
+            }
+            ui.separator();
+            let mb = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+            ui.label(format!("Buffer {:.0}/{:.0} MB", mb(self.frame_budget.used()), mb(self.frame_budget.cap())))
+                .on_hover_text("Frame memory queued between the camera, recorder and preview");
+            let dropped = self.frame_queue.dropped();
+            if dropped > 0 {
+                ui.colored_label(egui::Color32::YELLOW, format!("Dropped {}", dropped)).on_hover_text("Frames the recorder lost since startup");
+            }
+            let missed = self.frame_queue.tap_missed();
+            if missed > 0 {
+                ui.colored_label(egui::Color32::YELLOW, format!("Live missed {}", missed)).on_hover_text("Frames the live stream went without since startup; takes keep them");
+            }
+            if self.take_dropped > 0 {
+                ui.colored_label(egui::Color32::from_rgb(255, 120, 120), format!("Encoder overloaded: {} frames lost this take", self.take_dropped))
+                    .on_hover_text("The encoder can't keep up with the camera. Try a faster speed preset, a hardware encoder or a lower resolution");
+            }
+            if self.encoder_fallback == Some(self.selected_encoder) {
+                ui.colored_label(egui::Color32::YELLOW, format!("{} didn't start, recording with libx264", self.selected_encoder.ffmpeg_name()))
+                    .on_hover_text("The hardware encoder failed to initialize (missing driver or busy GPU), so takes are encoded on the CPU. Switch to another encoder and back to try it again.");
+            }
+            ui.separator();
+            ui.label("A/V lag:");
+            if ui.add(egui::DragValue::new(&mut self.settings.audio_offset_ms).range(-1000..=1000).suffix(" ms")).changed() {
+                self.settings.save();
+                self.push_recorder_config();
+            }
+            if let Some(clip) = self.playlist.last()
+                && ui.button("Clap sync").on_hover_text("Record one clip of a single clap in view, then measure it").clicked() {
+                let _ = self.rec_tx.send(RecorderCommand::MeasureSync(clip.video_path.clone()));
+            }
+            if let Some(ms) = self.measured_offset_ms {
+                ui.label(format!("Measured {:+.0} ms", ms));
+                if ui.button("Apply").clicked() {
+                    self.settings.audio_offset_ms += ms.round() as i32;
+                    self.settings.save();
+                    self.measured_offset_ms = None;
+                    self.push_recorder_config();
+                }
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if !self.export_history.is_empty() {
+                    self.show_export_history(ui);
+                }
+                if let Some(record) = self.export_history.first()
+                    && !self.is_recording && ui.button("GIF/WebP…").on_hover_text("Make an animation of the last export").clicked() {
+                    self.animation_draft = Some(AnimationDraft { source: record.path.clone(), duration: ffmpeg::get_video_duration(&record.path), options: self.animation_options });
+                }
+                if !self.is_recording {
+                    ui.menu_button("Visualizer", |ui| {
+                        for style in VisualizerStyle::ALL {
+                            ui.radio_value(&mut self.visualizer_style, style, style.to_string());
+                        }
+                        ui.separator();
+                        if ui.button("From a song…").on_hover_text("Render an audio file as a visualizer clip, added to the timeline as B-roll").clicked() {
+                            ui.close();
+                            if let Some(song) = rfd::FileDialog::new().add_filter("audio", &["mp3", "wav", "m4a", "aac", "flac", "ogg", "opus"]).pick_file() {
+                                let _ = self.rec_tx.send(RecorderCommand::GenerateVisualizer(song, self.visualizer_style, 0.0, None));
+                            }
+                        }
+                    });
+                }
+                if !self.settings.live_url.trim().is_empty() {
+                    let label = if self.live { "End stream" } else { "Go live" };
+                    if ui.button(label).on_hover_text("Stream the camera and microphone to the live stream address set up in settings").clicked() {
+                        self.live = !self.live;
+                        let _ = self.rec_tx.send(RecorderCommand::SetLiveStream(self.live.then(|| self.settings.live_url.trim().to_string())));
+                    }
+                    if self.live {
+                        ui.colored_label(egui::Color32::RED, "● LIVE");
+                    }
+                }
+                if self.replay_enabled && ui.button("Clip that!").on_hover_text(format!("Save the last {} s as a clip, without sound ({})", self.replay_secs, self.keymap.key_name(Action::SaveReplay))).clicked() {
+                    let _ = self.rec_tx.send(RecorderCommand::SaveReplay);
+                }
+                if !self.playlist.is_empty() && !self.is_recording {
+                    let included = self.export_clips();
+                    let merge = ui.add_enabled(!included.is_empty(), egui::Button::new("Merge")).on_hover_ui(|ui| {
+                        let guess = estimate::estimate(&included, &self.export_options, &self.throughput);
+                        ui.label(format!("About {} to export, roughly {}", estimate::format_duration(guess.secs), estimate::format_size(guess.bytes)));
+                        if !guess.measured {
+                            ui.weak("Guessed from defaults until an export like this has been timed");
+                        }
+                    }).on_disabled_hover_text("Every clip is excluded from export");
+                    if merge.clicked() {
+                        let file_choice = rfd::FileDialog::new().add_filter("video", &[self.export_extension()]).set_file_name(self.export_file_name()).set_directory(&self.workspace).save_file();
+                        if let Some(path) = file_choice {
+                            let output_path_string = path.to_string_lossy().to_string();
+                            self.queue_export(self.export_clips(), output_path_string, self.current_export_options());
+                        }
+                    }
+                    let append = ui.add_enabled(!included.is_empty(), egui::Button::new("Append to…"))
+                        .on_hover_text("Add the clips onto the end of an earlier export. Its content is copied as it is; the clips are only encoded if they don't match it.");
+                    if append.clicked()
+                        && let Some(path) = rfd::FileDialog::new().add_filter("video", &["mp4"]).set_directory(&self.workspace).pick_file() {
+                        let options = ExportOptions { append_to: Some(path.clone()), ..self.current_export_options() };
+                        self.queue_export(self.export_clips(), path.to_string_lossy().to_string(), options);
+                    }
+                    ui.menu_button("Tags", |ui| {
+                        egui::Grid::new("export_metadata").num_columns(2).show(ui, |ui| {
+                            let metadata = &mut self.export_options.metadata;
+                            ui.label("Title:");
+                            ui.add(egui::TextEdit::singleline(&mut metadata.title).desired_width(220.0));
+                            ui.end_row();
+                            ui.label("Author:");
+                            ui.add(egui::TextEdit::singleline(&mut metadata.author).desired_width(220.0));
+                            ui.end_row();
+                            ui.label("Comment:");
+                            ui.add(egui::TextEdit::multiline(&mut metadata.comment).desired_rows(2).desired_width(220.0));
+                            ui.end_row();
+                        });
+                    }).response.on_hover_text("Title, author and comment written into the exported file, along with today's date");
+                    if self.playlist.iter().any(|c| !c.lower_thirds.is_empty()) {
+                        ui.menu_button("Lower thirds", |ui| {
+                            let style = &mut self.export_options.lower_third_style;
+                            ui.horizontal(|ui| {
+                                ui.label("Accent");
+                                ui.color_edit_button_srgb(&mut style.accent);
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Slide");
+                                ui.add(egui::DragValue::new(&mut style.slide_secs).range(0.0..=2.0).speed(0.05).max_decimals(2).suffix(" s"));
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Position");
+                                egui::ComboBox::from_id_salt("lower_third_position").selected_text(style.position.to_string()).show_ui(ui, |ui| {
+                                    for position in CaptionPosition::ALL {
+                                        ui.selectable_value(&mut style.position, position, position.to_string());
+                                    }
+                                });
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Font");
+                                let font = style.font.as_deref().and_then(|path| path.file_name()).map_or(String::from("Default"), |name| name.to_string_lossy().to_string());
+                                ui.label(font);
+                                if ui.button("Choose…").clicked()
+                                    && let Some(path) = rfd::FileDialog::new().add_filter("font", &["ttf", "otf"]).pick_file() {
+                                    style.font = Some(path);
+                                }
+                                if style.font.is_some() && ui.small_button("✖").on_hover_text("Use ffmpeg's default font").clicked() {
+                                    style.font = None;
+                                }
+                            });
+                        }).response.on_hover_text("How the clips' name and title captions look. Add them from a clip's menu.");
+                    }
+                    let has_cutaways = self.playlist.iter().any(|c| c.lane != Lane::ARoll);
+                    ui.add_enabled(has_cutaways, egui::Checkbox::new(&mut self.export_options.cutaways, "Cutaways"))
+                        .on_hover_text("Lay B-roll and cutaway clips over the A-roll clip before them, keeping the A-roll audio");
+                    ui.add_enabled(self.export_options.ladder, egui::Checkbox::new(&mut self.export_options.hls, "HLS"))
+                        .on_hover_text("Package the renditions as an HLS stream with a master playlist");
+                    ui.checkbox(&mut self.export_options.ladder, "1080p/720p/480p")
+                        .on_hover_text("Export every rendition in one pass");
+                    ui.add_enabled_ui(!self.export_options.ladder && !self.export_options.cutaways, |ui| {
+                        let selected = self.export_options.preset.map_or(String::from("Custom"), |p| p.to_string());
+                        egui::ComboBox::from_id_salt("export_preset").selected_text(selected).show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.export_options.preset, None, "Custom");
+                            for preset in ExportPreset::ALL {
+                                ui.selectable_value(&mut self.export_options.preset, Some(preset), preset.to_string());
+                            }
+                        }).response.on_hover_text("Size, frame rate and codecs for a destination, picked before choosing where to save. Custom uses the options beside it.");
+                    });
+                    let plain_concat = !self.export_options.ladder && !self.export_options.cutaways && self.export_options.preset.is_none();
+                    if self.export_options.two_pass_kbps.is_some() {
+                        ui.add_enabled(plain_concat, egui::DragValue::new(&mut self.two_pass_kbps).range(500..=100_000).speed(100).suffix(" kbps"));
+                    }
+                    let mut two_pass = self.export_options.two_pass_kbps.is_some();
+                    ui.add_enabled(plain_concat && !self.export_options.dual, egui::Checkbox::new(&mut two_pass, "Two-pass"))
+                        .on_hover_text("Re-encode the merged video in two H.264 passes at a target bitrate for a predictable file size");
+                    self.export_options.two_pass_kbps = two_pass.then_some(self.two_pass_kbps);
+                    ui.add_enabled(self.room_tone.is_some() && !self.export_options.ladder, egui::Checkbox::new(&mut self.export_options.room_tone, "Room tone"))
+                        .on_hover_text("Fill gaps in the audio with the recorded room tone instead of digital silence");
+                    let can_track = self.settings.face_model.is_some();
+                    ui.add_enabled(plain_concat && self.export_options.dual && can_track, egui::Checkbox::new(&mut self.export_options.reframe, "Follow subject"))
+                        .on_hover_text("Pan the vertical crop to keep the person in frame, found by the face model in sampled frames")
+                        .on_disabled_hover_text("Needs a face model, set in settings. Without one the vertical crop stays centred.");
+                    ui.add_enabled(plain_concat && self.export_options.vertical.is_none(), egui::Checkbox::new(&mut self.export_options.dual, "16:9 + 9:16"))
+                        .on_hover_text("Also write a vertical copy next to the export as <name>_vertical.mp4");
+                    let mut vertical = self.export_options.vertical.is_some();
+                    ui.add_enabled(plain_concat && !self.export_options.dual, egui::Checkbox::new(&mut vertical, "9:16"))
+                        .on_hover_text("Export the timeline as one 1080x1920 video for Shorts and Reels. Transitions and lower thirds are left out.");
+                    if vertical {
+                        ui.menu_button(self.vertical_frame.fit.to_string(), |ui| {
+                            ui.horizontal(|ui| {
+                                for fit in [VerticalFit::Crop, VerticalFit::Pad] {
+                                    ui.radio_value(&mut self.vertical_frame.fit, fit, fit.to_string());
+                                }
+                            });
+                            let axis = if self.vertical_frame.fit == VerticalFit::Crop { "Left to right" } else { "Top to bottom" };
+                            ui.add(egui::Slider::new(&mut self.vertical_frame.anchor, 0.0..=1.0).text(axis).fixed_decimals(2))
+                                .on_hover_text("Where the window sits, shown on the camera preview");
+                        }).response.on_hover_text("Crop a 9:16 window out of the picture, or fit the whole picture with bars above and below");
+                    }
+                    self.export_options.vertical = vertical.then_some(self.vertical_frame);
+                }
+                if let Some((stage, percent)) = self.progress {
+                    ui.add(egui::ProgressBar::new(percent / 100.0).desired_width(160.0).text(format!("{} {:.0}%", stage, percent)));
+                    if stage != ProgressStage::Merge && ui.button("Cancel").on_hover_text("Stop the export and delete the partial output").clicked() {
+                        let _ = self.rec_tx.send(RecorderCommand::CancelExport);
+                    }
+                }
+            });
+        });
+
+        ui.separator();
+        let total_height = ui.available_height();
+        let timeline_height = 180.0;
+        let camera_height = total_height - timeline_height;
+        let camera_rect = ui.allocate_ui(egui::vec2(ui.available_width(), camera_height), |ui| {
+            if let Some(texture) = &self.texture {
+                let size = texture.size_vec2();
+                let aspect = size.x / size.y;
+                let available_w = ui.available_width();
+                let available_h = ui.available_height();
+
+                let (w, h) = if available_w / aspect <= available_h {
+                    (available_w, available_w / aspect)
+                } else {
+                    (available_h * aspect, available_h)
+                };
+
+                ui.centered_and_justified(|ui| {
+                    let image_rect = ui.add(egui::Image::new(texture).fit_to_exact_size(egui::vec2(w, h))).rect;
+                    if self.onion_skin && !self.is_recording
+                        && let Some(ghost) = &self.ghost {
+                        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                        ui.painter().image(ghost.id(), image_rect, uv, egui::Color32::WHITE.gamma_multiply(self.ghost_opacity));
+                    }
+                    if self.show_safe_guides {
+                        paint_safe_guides(ui.painter(), image_rect);
+                    }
+                    if let Some(mark) = &self.settings.watermark {
+                        paint_watermark(ui, image_rect, mark);
+                    }
+                    if let Some(frame) = self.export_options.vertical {
+                        paint_vertical_frame(ui.painter(), image_rect, texture.id(), frame);
+                    }
+                });
+            } else if !self.preview_enabled {
+                ui.centered_and_justified(|ui| {
+                    ui.label("Preview off (still recording)");
+                });
+            }
+        }).response.rect;
+
+        if self.is_recording {
+            ui.put(egui::Rect::from_min_size(camera_rect.min + egui::vec2(20.0, 20.0), egui::vec2(200.0, 50.0)),
+    |ui: &mut egui::Ui| ui.heading(egui::RichText::new("REC").color(egui::Color32::RED).strong())
+            );
+        }
+
+        ui.put(
+            egui::Rect::from_min_size(camera_rect.left_bottom() + egui::vec2(20.0, -50.0), egui::vec2(300.0, 50.0)),
+            |ui: &mut egui::Ui| ui.colored_label(egui::Color32::WHITE, format!("Clips: {}", self.playlist.len()))
+        );
+        
+        if let Some(f) = &self.final_file {
+            ui.put(
+                egui::Rect::from_center_size(camera_rect.center(), egui::vec2(400.0, 50.0)),
+                |ui: &mut egui::Ui| ui.colored_label(egui::Color32::GREEN, format!("Saved: {}", f))
+            );
+        }
+
+        if self.camera_busy {
+            ui.put(
+                egui::Rect::from_center_size(camera_rect.center(), egui::vec2(420.0, 80.0)),
+                |ui: &mut egui::Ui| ui.vertical_centered(|ui| {
+                    ui.heading("The camera is in use by another app");
+                    ui.label(CAMERA_BUSY_HINT);
+                }).response
+            );
+        }
+
+        if let Some(e) = &self.last_error {
+            ui.put(
+                egui::Rect::from_min_size(camera_rect.left_bottom() + egui::vec2(20.0, -100.0), egui::vec2(400.0, 40.0)),
+                |ui: &mut egui::Ui| ui.colored_label(egui::Color32::RED, format!("{}", e))
+            );
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Timeline");
+            if ui.checkbox(&mut self.settings.timeline_wrap, "Wrap").on_hover_text("Show clips in rows instead of one scrolling strip").changed() {
+                self.settings.save();
+            }
+        });
+        let wrap = self.settings.timeline_wrap;
+        egui::ScrollArea::new([!wrap, wrap]).min_scrolled_height(150.0).max_height(if wrap { 340.0 } else { f32::INFINITY }).show(ui, |ui| {
+            // Dragging near either edge scrolls, faster the closer the pointer gets.
+            if self.dragged_item.is_some()
+                && let Some(pointer) = ui.ctx().pointer_hover_pos() {
+                let visible = ui.clip_rect();
+                let edge = 48.0;
+                let (pos, start, end) = if wrap {
+                    (pointer.y, visible.top(), visible.bottom())
+                } else {
+                    (pointer.x, visible.left(), visible.right())
+                };
+                let delta = if pos < start + edge {
+                    (start + edge - pos) / edge * 12.0
+                } else if pos > end - edge {
+                    -(pos - (end - edge)) / edge * 12.0
+                } else {
+                    0.0
+                };
+                if delta != 0.0 {
+                    ui.scroll_with_delta(if wrap { egui::vec2(0.0, delta) } else { egui::vec2(delta, 0.0) });
+                    ui.ctx().request_repaint();
+                }
+            }
+
+            ui.with_layout(egui::Layout::left_to_right(egui::Align::Center).with_main_wrap(wrap), |ui| {
+                let card_size = egui::vec2(120.0, 90.0);
+                let gap_secs = if self.settings.animate_timeline { 0.12 } else { 0.0 };
+                let drop_target = self.dragged_item.and(self.drop_target);
+                // Opens a card-wide gap with a caret where the dragged clip would be dropped.
+                let drop_gap = |ui: &mut egui::Ui, slot: usize| {
+                    let open = drop_target == Some(slot);
+                    let width = ui.ctx().animate_value_with_time(ui.id().with(("drop_gap", slot)), if open { card_size.x } else { 0.0 }, gap_secs);
+                    if width > 0.5 {
+                        let (rect, _) = ui.allocate_exact_size(egui::vec2(width, card_size.y), egui::Sense::hover());
+                        if open {
+                            let x = rect.center().x;
+                            ui.painter().line_segment([egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())], egui::Stroke::new(3.0, ui.visuals().selection.bg_fill));
+                        }
+                    }
+                };
+                let mut card_rects: Vec<egui::Rect> = Vec::new();
                 let mut delete_index: Option<usize> = None;
+                let mut voiceover_index: Option<usize> = None;
+                let mut lane_change: Option<(usize, Lane)> = None;
+                let mut flag_index: Option<usize> = None;
+                let mut resolved_flag: Option<usize> = None;
+                let mut transition_change: Option<(usize, Option<Transition>)> = None;
+                let mut trim_change: Option<(usize, f64, Option<f64>)> = None;
+                let mut split_request: Option<(usize, f64)> = None;
+                let mut label_change: Option<(usize, String)> = None;
+                let mut lower_thirds_change: Option<(usize, Vec<LowerThird>)> = None;
+                let mut exclude_toggle: Option<usize> = None;
+                let mut thumb_request: Option<(usize, f64)> = None;
+                let mut animation_index: Option<usize> = None;
+                let mut visualizer_index: Option<usize> = None;
                 for (index, clip) in self.playlist.iter().enumerate() {
-                    let size = egui::vec2(120.0, 90.0);
+                    let others_index = match self.dragged_item {
+                        Some(from) if index > from => index - 1,
+                        _ => index
+                    };
+                    if self.dragged_item != Some(index) {
+                        drop_gap(ui, others_index);
+                    }
+                    let size = card_size;
                     let item_id = ui.make_persistent_id(index);
                     let is_being_dragged = self.dragged_item == Some(index);
                     let response = ui.group(|ui| {
@@ -324,6 +2395,15 @@ impl ClipperApp {
 
                         let img_resp = ui.add(egui::Image::new(img_source).fit_to_exact_size(size).rounding(4.0));
                         let rect = img_resp.rect;
+                        if clip.excluded {
+                            ui.painter().rect_filled(rect, 4.0, egui::Color32::from_black_alpha(170));
+                        }
+                        if let Some(problem) = &clip.damaged {
+                            ui.painter().rect_stroke(rect, 4.0, egui::Stroke::new(2.0, egui::Color32::from_rgb(230, 60, 60)), egui::StrokeKind::Inside);
+                            let badge_rect = egui::Rect::from_min_size(egui::pos2(rect.max.x - 50.0, rect.min.y + 5.0), egui::vec2(20.0, 20.0));
+                            ui.put(badge_rect, egui::Label::new(egui::RichText::new("✖").color(egui::Color32::from_rgb(230, 60, 60)).strong()))
+                                .on_hover_text(format!("Damaged: {}. Check this take before exporting.", problem));
+                        }
                         ui.painter().text(
                             rect.min + egui::vec2(5.0, 5.0),
                             egui::Align2::LEFT_TOP,
@@ -331,42 +2411,397 @@ impl ClipperApp {
                             egui::FontId::proportional(20.0),
                             egui::Color32::WHITE
                         );
+                        if !clip.label.is_empty() {
+                            ui.painter().text(
+                                rect.min + egui::vec2(5.0, 30.0),
+                                egui::Align2::LEFT_TOP,
+                                &clip.label,
+                                egui::FontId::proportional(12.0),
+                                egui::Color32::WHITE
+                            );
+                        }
+                        let lane_strip = egui::Rect::from_min_max(egui::pos2(rect.min.x, rect.max.y - 5.0), rect.max);
+                        ui.painter().rect_filled(lane_strip, 0.0, lane_color(clip.lane));
+                        if clip.is_trimmed() && clip.duration > 0.0 {
+                            let shade = egui::Color32::from_black_alpha(160);
+                            let in_x = rect.min.x + rect.width() * (clip.trim_in / clip.duration) as f32;
+                            let out_x = rect.min.x + rect.width() * (clip.trim_out.unwrap_or(clip.duration) / clip.duration) as f32;
+                            ui.painter().rect_filled(egui::Rect::from_min_max(rect.min, egui::pos2(in_x, rect.max.y)), 0.0, shade);
+                            ui.painter().rect_filled(egui::Rect::from_min_max(egui::pos2(out_x, rect.min.y), rect.max), 0.0, shade);
+                        }
+                        for (flag_i, flag) in self.review_flags.iter().enumerate().filter(|(_, f)| f.clip == clip.video_path) {
+                            let x = rect.min.x + rect.width() * (flag.at_secs / clip.duration.max(0.001)).clamp(0.0, 1.0) as f32;
+                            let marker = egui::Rect::from_center_size(egui::pos2(x, rect.max.y - 8.0), egui::vec2(6.0, 12.0));
+                            ui.painter().rect_filled(marker, 1.0, egui::Color32::from_rgb(230, 60, 60));
+                            ui.interact(marker, item_id.with(flag_i), egui::Sense::hover())
+                                .on_hover_text(format!("{}: {}", review::timestamp(flag.at_secs), flag.note));
+                        }
+                        if clip.black_secs > 0.0 || clip.frozen_secs > 0.0 {
+                            let badge_rect = egui::Rect::from_min_size(egui::pos2(rect.max.x - 25.0, rect.min.y + 5.0), egui::vec2(20.0, 20.0));
+                            let mut problems = Vec::new();
+                            if clip.black_secs > 0.0 {
+                                problems.push(format!("{:.1}s of black picture", clip.black_secs));
+                            }
+                            if clip.frozen_secs > 0.0 {
+                                problems.push(format!("{:.1}s of frozen picture", clip.frozen_secs));
+                            }
+                            ui.put(badge_rect, egui::Label::new(egui::RichText::new("⚠").color(egui::Color32::from_rgb(255, 200, 0)).strong()))
+                                .on_hover_text(problems.join("\n"));
+                        }
+                        if !clip.waveform_path.as_os_str().is_empty() {
+                            let wave_source = format!("file://{}", clip.waveform_path.to_string_lossy());
+                            ui.add(egui::Image::new(wave_source).fit_to_exact_size(egui::vec2(size.x, 24.0)));
+                        }
+
+                        let include_rect = egui::Rect::from_min_size(egui::pos2(rect.min.x + 5.0, rect.max.y - 30.0), egui::vec2(20.0, 20.0));
+                        let mut included = !clip.excluded;
+                        if ui.put(include_rect, egui::Checkbox::without_text(&mut included)).on_hover_text("Include in export").changed() {
+                            exclude_toggle = Some(index);
+                        }
 
                         if hover_state {
                             let delete_btn_rect = egui::Rect::from_min_size(rect.max - egui::vec2(25.0, 25.0), egui::vec2(20.0, 20.0));
                             if ui.put(delete_btn_rect, egui::Button::new("X").small()).clicked() {
                                 delete_index = Some(index);
                             }
+
+                            let voiceover_btn_rect = egui::Rect::from_min_size(rect.max - egui::vec2(55.0, 25.0), egui::vec2(25.0, 20.0));
+                            let can_voiceover = !self.is_recording && self.voiceover_clip.is_none();
+                            if ui.put(voiceover_btn_rect, egui::Button::new("VO").small()).on_hover_text("Re-record narration while the clip plays").clicked() && can_voiceover {
+                                voiceover_index = Some(index);
+                            }
                         }
                     }).response;
 
-                    let response = response.interact(egui::Sense::drag());
+                    let response = response.interact(egui::Sense::click_and_drag());
+                    response.context_menu(|ui| {
+                        let mut label = clip.label.clone();
+                        ui.horizontal(|ui| {
+                            ui.label("Label");
+                            if ui.add(egui::TextEdit::singleline(&mut label).hint_text(format!("Clip {}", index + 1)).desired_width(140.0))
+                                .on_hover_text("Chapter title in the exported video").changed() {
+                                label_change = Some((index, label));
+                            }
+                        });
+                        ui.separator();
+                        let mut captions = clip.lower_thirds.clone();
+                        let mut captions_changed = false;
+                        let mut removed = None;
+                        let end = clip.trim_out.unwrap_or(clip.duration);
+                        for (i, caption) in captions.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                captions_changed |= ui.add(egui::TextEdit::singleline(&mut caption.name).hint_text("Name").desired_width(90.0)).changed();
+                                captions_changed |= ui.add(egui::TextEdit::singleline(&mut caption.title).hint_text("Title").desired_width(90.0)).changed();
+                                captions_changed |= ui.add(egui::DragValue::new(&mut caption.start).range(0.0..=caption.end).speed(0.05).max_decimals(2).suffix(" s")).changed();
+                                captions_changed |= ui.add(egui::DragValue::new(&mut caption.end).range(caption.start..=clip.duration).speed(0.05).max_decimals(2).suffix(" s")).changed();
+                                if ui.small_button("✖").on_hover_text("Remove this lower third").clicked() {
+                                    removed = Some(i);
+                                }
+                            });
+                        }
+                        if let Some(i) = removed {
+                            captions.remove(i);
+                            captions_changed = true;
+                        }
+                        if ui.button("Add lower third").on_hover_text("Name and title caption over part of this clip, drawn when the export re-encodes").clicked() {
+                            captions.push(LowerThird { name: String::new(), title: String::new(), start: clip.trim_in, end: (clip.trim_in + 5.0).min(end) });
+                            captions_changed = true;
+                        }
+                        if captions_changed {
+                            lower_thirds_change = Some((index, captions));
+                        }
+                        ui.separator();
+                        for lane in [Lane::ARoll, Lane::BRoll, Lane::Cutaway] {
+                            if ui.radio(clip.lane == lane, egui::RichText::new(lane.to_string()).color(lane_color(lane))).clicked() {
+                                lane_change = Some((index, lane));
+                                ui.close();
+                            }
+                        }
+                        ui.separator();
+                        let mut trim_in = clip.trim_in;
+                        let mut trim_out = clip.trim_out.unwrap_or(clip.duration);
+                        ui.horizontal(|ui| {
+                            ui.label("In");
+                            let in_changed = ui.add(egui::DragValue::new(&mut trim_in).range(0.0..=trim_out).speed(0.05).max_decimals(2).suffix(" s")).changed();
+                            ui.label("Out");
+                            let out_changed = ui.add(egui::DragValue::new(&mut trim_out).range(trim_in..=clip.duration).speed(0.05).max_decimals(2).suffix(" s")).changed();
+                            if in_changed || out_changed {
+                                trim_change = Some((index, trim_in, (trim_out < clip.duration).then_some(trim_out)));
+                            }
+                        });
+                        if clip.is_trimmed() && ui.button("Clear trim").clicked() {
+                            trim_change = Some((index, 0.0, None));
+                            ui.close();
+                        }
+                        ui.horizontal(|ui| {
+                            let max = (clip.duration - 0.1).max(0.1);
+                            self.split_secs = self.split_secs.clamp(0.1, max);
+                            ui.add(egui::DragValue::new(&mut self.split_secs).range(0.1..=max).speed(0.05).max_decimals(2).suffix(" s"));
+                            if ui.add_enabled(!self.is_recording, egui::Button::new("Split here")).on_hover_text("Cut the clip in two at this point").clicked() {
+                                split_request = Some((index, self.split_secs));
+                                ui.close();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            let max = (clip.duration - 0.05).max(0.0);
+                            self.thumb_secs = self.thumb_secs.clamp(0.0, max);
+                            ui.add(egui::Slider::new(&mut self.thumb_secs, 0.0..=max).max_decimals(2).suffix(" s"));
+                            if ui.button("Set thumbnail").on_hover_text("Regrab the thumbnail from this point. The first clip with a picked thumbnail is also the export's cover art.").clicked() {
+                                thumb_request = Some((index, self.thumb_secs));
+                                ui.close();
+                            }
+                        });
+                        if ui.button("Export as GIF/WebP…").clicked() {
+                            animation_index = Some(index);
+                            ui.close();
+                        }
+                        if ui.add_enabled(!self.is_recording, egui::Button::new(format!("Visualizer B-roll ({})", self.visualizer_style)))
+                            .on_hover_text("Render this clip's audio as a waveform or spectrum clip, added to the timeline as B-roll").clicked() {
+                            visualizer_index = Some(index);
+                            ui.close();
+                        }
+                        ui.separator();
+                        if ui.button("Flag a moment…").clicked() {
+                            flag_index = Some(index);
+                            ui.close();
+                        }
+                        for (flag_i, flag) in self.review_flags.iter().enumerate().filter(|(_, f)| f.clip == clip.video_path) {
+                            if ui.button(format!("✔ {} {}", review::timestamp(flag.at_secs), flag.note)).on_hover_text("Mark as checked").clicked() {
+                                resolved_flag = Some(flag_i);
+                                ui.close();
+                            }
+                        }
+                    });
                     if response.drag_started() {
                         self.dragged_item = Some(index);
                     }
 
                     if is_being_dragged {
+                        ui.painter().rect_filled(response.rect, 2.0, egui::Color32::from_black_alpha(120));
                         ui.painter().rect_stroke(response.rect, 2.0, egui::Stroke::new(2.0, egui::Color32::YELLOW), egui::StrokeKind::Middle);
+                    } else {
+                        card_rects.push(response.rect);
                     }
 
-                    if let Some(dragged_index) = self.dragged_item {
-                        if dragged_index != index && response.hovered() {
-                            move_from = Some(dragged_index);
-                            move_to = Some(index)
-                        }
+                    if index + 1 < self.playlist.len() {
+                        let label = match clip.transition {
+                            Some(t) => format!("⇢ {:.1}s", t.secs),
+                            None => String::from("|")
+                        };
+                        ui.menu_button(label, |ui| {
+                            if ui.radio(clip.transition.is_none(), "Cut").clicked() {
+                                transition_change = Some((index, None));
+                                ui.close();
+                            }
+                            let secs = clip.transition.map(|t| t.secs).unwrap_or(DEFAULT_TRANSITION_SECS);
+                            for kind in TransitionKind::ALL {
+                                if ui.radio(clip.transition.map(|t| t.kind) == Some(kind), kind.to_string()).clicked() {
+                                    transition_change = Some((index, Some(Transition { kind, secs })));
+                                }
+                            }
+                            if let Some(mut t) = clip.transition
+                                && ui.add(egui::DragValue::new(&mut t.secs).range(0.1..=3.0).speed(0.05).suffix(" s")).changed() {
+                                transition_change = Some((index, Some(t)));
+                            }
+                        }).response.on_hover_text("Transition into the next clip. Any transition makes the export re-encode.");
                     }
                 }
+                drop_gap(ui, card_rects.len());
+
+                // The drop slot is the number of other cards ahead of the pointer in reading order, so
+                // it only changes once the pointer crosses a card's middle. Wrapped rows count whole
+                // rows above the pointer.
+                if self.dragged_item.is_some()
+                    && let Some(pointer) = ui.ctx().pointer_hover_pos() {
+                    let ahead = |r: &&egui::Rect| if wrap {
+                        r.bottom() < pointer.y || (r.top() <= pointer.y && r.center().x < pointer.x)
+                    } else {
+                        r.center().x < pointer.x
+                    };
+                    self.drop_target = Some(card_rects.iter().filter(ahead).count());
+                }
+
+                if let Some((index, lane)) = lane_change {
+                    self.playlist[index].lane = lane;
+                }
+
+                if let Some(index) = animation_index {
+                    let clip = &self.playlist[index];
+                    let options = AnimationOptions { start: clip.trim_in, end: clip.trim_out, ..self.animation_options };
+                    self.animation_draft = Some(AnimationDraft { source: clip.video_path.clone(), duration: clip.duration, options });
+                }
+
+                if let Some(index) = visualizer_index {
+                    let clip = &self.playlist[index];
+                    let _ = self.rec_tx.send(RecorderCommand::GenerateVisualizer(clip.video_path.clone(), self.visualizer_style, clip.trim_in, clip.trim_out));
+                }
+
+                if let Some((index, at)) = thumb_request {
+                    let clip = &self.playlist[index];
+                    let _ = self.rec_tx.send(RecorderCommand::SetThumbnail(clip.video_path.clone(), clip.thumb_path.clone(), at));
+                }
+
+                if let Some((index, label)) = label_change {
+                    self.playlist[index].label = label;
+                }
+
+                if let Some(index) = delete_index {
+                    let path = self.playlist[index].video_path.clone();
+                    self.guard(ui.ctx(), Guarded::RemoveClip(path));
+                }
+
+                if let Some((index, captions)) = lower_thirds_change {
+                    self.playlist[index].lower_thirds = captions;
+                }
+
+                if let Some(index) = exclude_toggle {
+                    self.playlist[index].excluded = !self.playlist[index].excluded;
+                }
+
+                if let Some((index, transition)) = transition_change {
+                    self.playlist[index].transition = transition;
+                }
+
+                if let Some((index, at)) = split_request {
+                    let _ = self.rec_tx.send(RecorderCommand::SplitClip(self.playlist[index].video_path.clone(), at));
+                }
+
+                if let Some((index, trim_in, trim_out)) = trim_change {
+                    self.playlist[index].trim_in = trim_in;
+                    self.playlist[index].trim_out = trim_out;
+                }
+
+                if let Some(index) = flag_index {
+                    self.flag_draft = Some(ReviewFlag { clip: self.playlist[index].video_path.clone(), at_secs: 0.0, note: String::new() });
+                }
 
-                if let (Some(from), Some(to)) = (move_from, move_to) {
-                    let item = self.playlist.remove(from);
-                    self.playlist.insert(to, item);
-                    self.dragged_item = Some(to);
+                if let Some(flag_i) = resolved_flag {
+                    self.review_flags.remove(flag_i);
+                    self.save_review_notes();
+                }
+
+                if let Some(index) = voiceover_index {
+                    let clip = self.playlist[index].clone();
+                    self.voiceover_clip = Some(clip.video_path.clone());
+                    let _ = self.rec_tx.send(RecorderCommand::StartVoiceover(Box::new(clip)));
                 }
 
                 if ui.input(|i| i.pointer.any_released()) {
+                    if let (Some(from), Some(to)) = (self.dragged_item, self.drop_target)
+                        && from != to && from < self.playlist.len() {
+                        let item = self.playlist.remove(from);
+                        self.playlist.insert(to.min(self.playlist.len()), item);
+                        if !self.review_flags.is_empty() {
+                            self.save_review_notes();
+                        }
+                    }
                     self.dragged_item = None;
+                    self.drop_target = None;
                 }
             })
         });
     }
-}
\ No newline at end of file
+}
+
+fn lane_color(lane: Lane) -> egui::Color32 {
+    match lane {
+        Lane::ARoll => egui::Color32::from_rgb(70, 130, 230),
+        Lane::BRoll => egui::Color32::from_rgb(80, 190, 90),
+        Lane::Cutaway => egui::Color32::from_rgb(230, 150, 40)
+    }
+}
+
+/// Action-safe and title-safe areas as fractions of the frame, per SMPTE ST 2046-1.
+const ACTION_SAFE: f32 = 0.93;
+const TITLE_SAFE: f32 = ffmpeg::TITLE_SAFE as f32;
+
+/// Opens the folder holding `path`.
+fn reveal(path: &Path) {
+    if let Some(dir) = path.parent() {
+        crash::open_path(dir);
+    }
+}
+
+/// The options that shaped an export, e.g. "two-pass 8000 kbps, 16:9 + 9:16".
+fn export_summary(options: &ExportOptions) -> String {
+    let mut parts = Vec::new();
+    if options.ladder {
+        parts.push(String::from(if options.hls { "HLS ladder" } else { "1080p/720p/480p" }));
+    }
+    if options.cutaways {
+        parts.push(String::from("cutaways"));
+    }
+    if let Some(kbps) = options.two_pass_kbps {
+        parts.push(format!("two-pass {} kbps", kbps));
+    }
+    if options.reencode {
+        parts.push(String::from("re-encoded"));
+    }
+    if options.dual {
+        parts.push(String::from(if options.reframe { "16:9 + 9:16, following subject" } else { "16:9 + 9:16" }));
+    }
+    if let Some(frame) = options.vertical {
+        parts.push(format!("9:16 {}", frame.fit.to_string().to_lowercase()));
+    }
+    if let Some(preset) = options.preset {
+        parts.push(preset.to_string());
+    }
+    if options.room_tone {
+        parts.push(String::from("room tone"));
+    }
+    if parts.is_empty() {
+        String::from("plain join")
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// Draws the watermark over the preview where and how it will be burned in.
+fn paint_watermark(ui: &egui::Ui, picture: egui::Rect, mark: &Watermark) {
+    let logo = egui::Image::new(format!("file://{}", mark.image.to_string_lossy()));
+    let Ok(egui::load::TexturePoll::Ready { texture }) = logo.load_for_size(ui.ctx(), picture.size()) else { return };
+    let height = picture.height() * mark.height;
+    let size = egui::vec2(height * texture.size.x / texture.size.y.max(1.0), height);
+    let min = picture.min + (picture.size() - size) * egui::vec2(mark.x, mark.y);
+    let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+    ui.painter().image(texture.id, egui::Rect::from_min_size(min, size), uv, egui::Color32::WHITE.gamma_multiply(mark.opacity));
+}
+
+/// Shades what a vertical export crops out of the picture, or for a padded one draws the 9:16
+/// frame over it with the picture shrunk into place.
+fn paint_vertical_frame(painter: &egui::Painter, picture: egui::Rect, texture: egui::TextureId, frame: VerticalFrame) {
+    let outline = egui::Stroke::new(1.5, egui::Color32::from_rgb(0, 200, 255));
+    match frame.fit {
+        VerticalFit::Crop => {
+            let width = (picture.height() * 9.0 / 16.0).min(picture.width());
+            let left = picture.left() + (picture.width() - width) * frame.anchor;
+            let window = egui::Rect::from_min_size(egui::pos2(left, picture.top()), egui::vec2(width, picture.height()));
+            let shade = egui::Color32::from_black_alpha(150);
+            painter.rect_filled(egui::Rect::from_min_max(picture.min, window.left_bottom()), 0.0, shade);
+            painter.rect_filled(egui::Rect::from_min_max(window.right_top(), picture.max), 0.0, shade);
+            painter.rect_stroke(window, 0.0, outline, egui::StrokeKind::Middle);
+        },
+        VerticalFit::Pad => {
+            let frame_size = egui::vec2(picture.height() * 9.0 / 16.0, picture.height());
+            let target = egui::Rect::from_center_size(picture.center(), frame_size);
+            let scaled = egui::vec2(frame_size.x, frame_size.x * picture.height() / picture.width());
+            let top = target.top() + (frame_size.y - scaled.y) * frame.anchor;
+            let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+            painter.rect_filled(target, 0.0, egui::Color32::BLACK);
+            painter.image(texture, egui::Rect::from_min_size(egui::pos2(target.left(), top), scaled), uv, egui::Color32::WHITE);
+            painter.rect_stroke(target, 0.0, outline, egui::StrokeKind::Middle);
+        }
+    }
+}
+
+fn paint_safe_guides(painter: &egui::Painter, frame: egui::Rect) {
+    let action = egui::Rect::from_center_size(frame.center(), frame.size() * ACTION_SAFE);
+    let title = egui::Rect::from_center_size(frame.center(), frame.size() * TITLE_SAFE);
+    painter.rect_stroke(action, 0.0, egui::Stroke::new(1.0, egui::Color32::from_white_alpha(160)), egui::StrokeKind::Middle);
+    painter.rect_stroke(title, 0.0, egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(255, 220, 0, 160)), egui::StrokeKind::Middle);
+
+    let c = frame.center();
+    let cross = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(120));
+    painter.line_segment([c - egui::vec2(10.0, 0.0), c + egui::vec2(10.0, 0.0)], cross);
+    painter.line_segment([c - egui::vec2(0.0, 10.0), c + egui::vec2(0.0, 10.0)], cross);
+}
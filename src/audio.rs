@@ -13,45 +13,79 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{messages::audio::{AudioCommand, AudioDevice, AudioMessage}};
+use crate::{messages::audio::{AudioApp, AudioClock, AudioCommand, AudioDevice, AudioMessage, LiveAudio}, recorder::{ffmpeg, types::AudioEncoding}};
 use crossbeam_channel::{Receiver, Sender};
 use cpal::{StreamError, traits::{DeviceTrait, HostTrait, StreamTrait}};
-use std::{thread, sync::{Arc, Mutex}};
+use std::{io::{ErrorKind, Write}, net::{Ipv4Addr, TcpListener}, process::{Child, Command, Stdio}, thread::{self, Thread}, sync::{Arc, Mutex, atomic::{AtomicU32, AtomicUsize, Ordering}}, time::{Duration, Instant}};
 
 pub fn start_thread(msg_tx: Sender<AudioMessage>, cmd_rx: Receiver<AudioCommand>) {
     thread::spawn(move || {
         let host: cpal::Host = cpal::default_host();
         let devices = match host.input_devices() {
             Ok(devs) => devs.collect::<Vec<_>>(),
+            // Application capture and video-only takes still work without the host's devices.
             Err(e) => {
                 let _ = msg_tx.send(AudioMessage::Error(format!("Audio host error: {}", e)));
-                return;
+                Vec::new()
             }
         };
 
         let device_list: Vec<AudioDevice> = devices.iter().enumerate().map(|(i, d)| {
             AudioDevice {
                 name: d.name().unwrap_or_else(|_| format!("Unknown device: {}", i)),
-                index: i
+                index: i,
+                channels: d.default_input_config().map(|c| c.channels()).unwrap_or(2)
             }
         }).collect();
         
         if msg_tx.send(AudioMessage::DeviceList(device_list)).is_err() {
             return;
         }
+        let _ = msg_tx.send(AudioMessage::AppList(list_app_streams()));
 
         let mut active_stream: Option<cpal::Stream> = None;
         let mut selected_device_index = 0;
-        let writer_handle = Arc::new(Mutex::new(None));
+        let mut selected_app: Option<AudioApp> = None;
+        let mut app_capture: Option<Child> = None;
+        let writer_handle: Arc<Mutex<Option<Child>>> = Arc::new(Mutex::new(None));
+        let sample_clock: Arc<Mutex<SampleClock>> = Arc::new(Mutex::new(SampleClock::default()));
+        // Microphone audio on its way to a live stream's encoder, alongside whatever take is recording.
+        let live_audio: Arc<Mutex<Option<Sender<Vec<u8>>>>> = Arc::new(Mutex::new(None));
+        let ring = Arc::new(SampleRing::new(RING_SAMPLES));
+        let pump = start_pump(ring.clone(), writer_handle.clone(), sample_clock.clone(), live_audio.clone());
         while let Ok(cmd) = cmd_rx.recv() {
             match cmd {
                 AudioCommand::SelectDevice(index) => {
                     selected_device_index = index;
                     active_stream = None;
                 },
-                AudioCommand::StartRecording(filename) => {
+                AudioCommand::SelectApp(app) => {
+                    selected_app = app;
+                    active_stream = None;
+                },
+                AudioCommand::RefreshApps => {
+                    let _ = msg_tx.send(AudioMessage::AppList(list_app_streams()));
+                },
+                AudioCommand::StartRecording(filename, encoding) if selected_app.is_some() => {
+                    stop_app_capture(&mut app_capture);
+                    finish_encoder(&writer_handle, &msg_tx);
+                    if let Some(app) = &selected_app {
+                        match start_app_capture(app, encoding, &filename) {
+                            Ok((capture, encoder)) => {
+                                app_capture = Some(capture);
+                                if let Ok(mut guard) = writer_handle.lock() {
+                                    *guard = Some(encoder);
+                                }
+                            },
+                            Err(e) => { let _ = msg_tx.send(AudioMessage::Error(format!("Failed to capture {}: {}", app.name, e))); }
+                        }
+                    }
+                },
+                AudioCommand::StartRecording(filename, encoding) | AudioCommand::StartMicRecording(filename, encoding) => {
                     let device = match devices.get(selected_device_index) {
                         Some(d) => d,
+                        // With no input devices at all the recorder saves takes without sound and says so itself
+                        None if devices.is_empty() => continue,
                         None => {
                             let _ = msg_tx.send(AudioMessage::Error(String::from("Invalid audio device index")));
                             continue;
@@ -66,69 +100,313 @@ pub fn start_thread(msg_tx: Sender<AudioMessage>, cmd_rx: Receiver<AudioCommand>
                         }
                     };
 
-                    let spec = hound::WavSpec {
-                        channels: config.channels(),
-                        sample_rate: config.sample_rate().0,
-                        bits_per_sample: 32,
-                        sample_format: hound::SampleFormat::Float
-                    };
-
-                    match hound::WavWriter::create(&filename, spec) {
-                        Ok(writer) => {
+                    finish_encoder(&writer_handle, &msg_tx);
+                    if let Ok(mut clock) = sample_clock.lock() {
+                        *clock = SampleClock { nominal: config.sample_rate().0, ..SampleClock::default() };
+                    }
+                    let args = ffmpeg::build_audio_cmd(config.sample_rate().0, config.channels(), encoding, &filename);
+                    match Command::new("ffmpeg").args(&args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn() {
+                        Ok(encoder) => {
                             if let Ok(mut guard) = writer_handle.lock() {
-                                *guard = Some(writer);
+                                *guard = Some(encoder);
                             } else {
                                 let _ = msg_tx.send(AudioMessage::Error(String::from("Audio mutex poisoned")));
                                 continue;
                             }
 
                             if active_stream.is_none() {
-                                let writer_clone = writer_handle.clone();
-                                let error_tx = msg_tx.clone();
-                                let err_fn = move |err: StreamError| { let _ = error_tx.send(AudioMessage::Error(format!("Stream lost: {}", err))); };
-                                let data_fn = move |data: &[f32], _: &_| {
-                                    if let Ok(mut guard) = writer_clone.lock() {
-                                        if let Some(writer) = guard.as_mut() {
-                                            for &sample in data {
-                                                let _ = writer.write_sample(sample);
-                                            }
-                                        }
-                                    }
-                                };
-
-                                let stream_result = device.build_input_stream(&config.into(), data_fn, err_fn, None);
-                                match stream_result {
-                                    Ok(s) => {
-                                        if let Err(e) = s.play() {
-                                            let _ = msg_tx.send(AudioMessage::Error(format!("Failed to play stream: {}", e)));
-                                        } else {
-                                            active_stream = Some(s);
-                                        }
-                                    },
-                                    Err(e) => {
-                                        let _ = msg_tx.send(AudioMessage::Error(format!("Failed to build stream: {}", e)));
-                                    }
-                                }
+                                active_stream = open_input(device, config, ring.clone(), pump.clone(), &msg_tx);
                             }
                         },
                         Err(e) => {
-                            let _ = msg_tx.send(AudioMessage::Error(format!("Could not create WAV file: {}", e)));
+                            let _ = msg_tx.send(AudioMessage::Error(format!("Failed to spawn audio encoder: {}", e)));
                         }
                     }
                 },
 
-                AudioCommand::StopRecording(ack_tx) => {
-                    if let Ok(mut guard) = writer_handle.lock() {
-                        if let Some(mut writer) = guard.take() { 
-                            if let Err(e) = writer.flush() { 
-                                let _ = msg_tx.send(AudioMessage::Error(format!("Failed to flush audio to disk: {}", e))); 
-                            } 
+                AudioCommand::StartLiveAudio(reply_tx) => {
+                    stop_live_audio(&live_audio);
+                    // Application capture runs through its own encoder, so a live stream only gets the microphone
+                    let Some(device) = devices.get(selected_device_index).filter(|_| selected_app.is_none()) else {
+                        let _ = reply_tx.send(None);
+                        continue;
+                    };
+                    let config = match device.default_input_config() {
+                        Ok(c) => c,
+                        Err(e) => {
+                            let _ = msg_tx.send(AudioMessage::Error(format!("Failed to get microphone config: {}", e)));
+                            let _ = reply_tx.send(None);
+                            continue;
                         }
+                    };
+                    let live = LiveAudio { port: 0, sample_rate: config.sample_rate().0, channels: config.channels() };
+                    match serve_live_audio(&live_audio) {
+                        Ok(port) => {
+                            if active_stream.is_none() {
+                                active_stream = open_input(device, config, ring.clone(), pump.clone(), &msg_tx);
+                            }
+                            let _ = reply_tx.send(Some(LiveAudio { port, ..live }));
+                        },
+                        Err(e) => {
+                            let _ = msg_tx.send(AudioMessage::Error(format!("Couldn't pass audio to the live stream: {}", e)));
+                            let _ = reply_tx.send(None);
+                        }
+                    }
+                },
+                AudioCommand::StopLiveAudio => stop_live_audio(&live_audio),
+                AudioCommand::StopRecording(ack_tx) => {
+                    // Application capture is paced by the sound server, so only the microphone is timed
+                    // The microphone's last buffers are still on their way to the encoder.
+                    ring.wait_drained();
+                    let measured = app_capture.is_none().then(|| sample_clock.lock().ok().and_then(|clock| clock.measure())).flatten();
+                    stop_app_capture(&mut app_capture);
+                    finish_encoder(&writer_handle, &msg_tx);
+                    if let Ok(mut clock) = sample_clock.lock() {
+                        *clock = SampleClock::default();
                     }
+                    let _ = ack_tx.send(measured);
+                }
+            }
+        }
+    });
+}
+
+/// Starts the microphone at its default format. The callback only copies each buffer into the
+/// ring and wakes the pump, which does the writing.
+fn open_input(device: &cpal::Device, config: cpal::SupportedStreamConfig, ring: Arc<SampleRing>, pump: Thread, msg_tx: &Sender<AudioMessage>) -> Option<cpal::Stream> {
+    ring.channels.store(config.channels().max(1) as usize, Ordering::Relaxed);
+    let error_tx = msg_tx.clone();
+    let err_fn = move |err: StreamError| { let _ = error_tx.send(AudioMessage::Error(format!("Stream lost: {}", err))); };
+    let data_fn = move |data: &[f32], _: &_| {
+        ring.push(data);
+        pump.unpark();
+    };
+
+    match device.build_input_stream(&config.into(), data_fn, err_fn, None) {
+        Ok(s) => {
+            if let Err(e) = s.play() {
+                let _ = msg_tx.send(AudioMessage::Error(format!("Failed to play stream: {}", e)));
+                None
+            } else {
+                Some(s)
+            }
+        },
+        Err(e) => {
+            let _ = msg_tx.send(AudioMessage::Error(format!("Failed to build stream: {}", e)));
+            None
+        }
+    }
+}
+
+/// Samples the ring holds, about two seconds of 48 kHz stereo, for when the pump falls behind.
+const RING_SAMPLES: usize = 1 << 18;
 
-                    let _ = ack_tx.send(());
+/// Microphone samples on their way from the audio callback to the pump thread. The callback is the
+/// only writer and the pump the only reader, so neither ever waits on the other, and the callback
+/// never allocates.
+struct SampleRing {
+    slots: Box<[AtomicU32]>,
+    /// Samples ever pushed and ever taken; what lies between is waiting for the pump.
+    written: AtomicUsize,
+    read: AtomicUsize,
+    /// Of the stream currently feeding the ring.
+    channels: AtomicUsize
+}
+
+impl SampleRing {
+    fn new(capacity: usize) -> Self {
+        Self { slots: (0..capacity).map(|_| AtomicU32::new(0)).collect(), written: AtomicUsize::new(0), read: AtomicUsize::new(0), channels: AtomicUsize::new(1) }
+    }
+
+    /// A buffer that doesn't fit is dropped whole rather than waited on.
+    fn push(&self, data: &[f32]) {
+        let written = self.written.load(Ordering::Relaxed);
+        if written - self.read.load(Ordering::Acquire) + data.len() > self.slots.len() {
+            return;
+        }
+        for (i, sample) in data.iter().enumerate() {
+            self.slots[(written + i) % self.slots.len()].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.written.store(written + data.len(), Ordering::Release);
+    }
+
+    /// Appends every waiting sample to `out` as little-endian bytes and returns how many there were.
+    fn take_into(&self, out: &mut Vec<u8>) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        let written = self.written.load(Ordering::Acquire);
+        for i in read..written {
+            out.extend_from_slice(&self.slots[i % self.slots.len()].load(Ordering::Relaxed).to_le_bytes());
+        }
+        self.read.store(written, Ordering::Release);
+        written - read
+    }
+
+    /// Waits a moment for the pump to take what's been pushed so far.
+    fn wait_drained(&self) {
+        let target = self.written.load(Ordering::Acquire);
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while self.read.load(Ordering::Acquire) < target && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// Moves the microphone's samples from the ring to the take's encoder while one is running, and to
+/// the live stream while there is one. Runs until the audio thread is gone.
+fn start_pump(ring: Arc<SampleRing>, writer: Arc<Mutex<Option<Child>>>, clock: Arc<Mutex<SampleClock>>, live: Arc<Mutex<Option<Sender<Vec<u8>>>>>) -> Thread {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        while Arc::strong_count(&ring) > 1 {
+            thread::park_timeout(Duration::from_millis(50));
+            bytes.clear();
+            let samples = ring.take_into(&mut bytes);
+            if samples == 0 { continue; }
+            if let Ok(guard) = live.lock()
+                && let Some(tx) = guard.as_ref() {
+                let _ = tx.try_send(bytes.clone());
+            }
+            if let Ok(mut guard) = writer.lock()
+                && let Some(stdin) = guard.as_mut().and_then(|encoder| encoder.stdin.as_mut()) {
+                let _ = stdin.write_all(&bytes);
+                if let Ok(mut clock) = clock.lock() {
+                    clock.count(samples / ring.channels.load(Ordering::Relaxed));
                 }
             }
         }
+    }).thread().clone()
+}
+
+/// Buffers of microphone audio held for the live stream while its encoder catches up; any more are dropped.
+const LIVE_AUDIO_QUEUE: usize = 64;
+/// How long the live stream's encoder gets to connect for its audio.
+const LIVE_AUDIO_CONNECT: Duration = Duration::from_secs(10);
+
+/// Listens on a local port for the live stream's encoder and, once it connects, writes it the
+/// microphone's raw samples. Audio captured before then is thrown away so it starts in step with
+/// the picture.
+fn serve_live_audio(live: &Arc<Mutex<Option<Sender<Vec<u8>>>>>) -> std::io::Result<u16> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    listener.set_nonblocking(true)?;
+    let port = listener.local_addr()?.port();
+    let (tx, rx) = crossbeam_channel::bounded::<Vec<u8>>(LIVE_AUDIO_QUEUE);
+    thread::spawn(move || {
+        let deadline = Instant::now() + LIVE_AUDIO_CONNECT;
+        let mut socket = loop {
+            match listener.accept() {
+                Ok((socket, _)) => break socket,
+                Err(e) if e.kind() == ErrorKind::WouldBlock && Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+                Err(_) => return
+            }
+        };
+        let _ = socket.set_nonblocking(false);
+        while rx.try_recv().is_ok() {}
+        for bytes in rx {
+            if socket.write_all(&bytes).is_err() {
+                break;
+            }
+        }
     });
-}
\ No newline at end of file
+    if let Ok(mut guard) = live.lock() {
+        *guard = Some(tx);
+    }
+    Ok(port)
+}
+
+fn stop_live_audio(live: &Arc<Mutex<Option<Sender<Vec<u8>>>>>) {
+    if let Ok(mut guard) = live.lock() {
+        *guard = None;
+    }
+}
+
+/// Shortest recording whose sample count is trusted to say how fast the device's clock runs.
+const MIN_CLOCK_SECS: f64 = 60.0;
+
+/// Counts the frames the microphone delivers after its first callback of a recording, so the
+/// rate it really runs at can be compared with the one it reports.
+#[derive(Default)]
+struct SampleClock {
+    nominal: u32,
+    first: Option<Instant>,
+    last: Option<Instant>,
+    frames: u64
+}
+
+impl SampleClock {
+    fn count(&mut self, frames: usize) {
+        let now = Instant::now();
+        // The first buffer was captured before its callback, so timing starts after it
+        if self.first.is_none() {
+            self.first = Some(now);
+        } else {
+            self.frames += frames as u64;
+        }
+        self.last = Some(now);
+    }
+
+    fn measure(&self) -> Option<AudioClock> {
+        let secs = self.last?.duration_since(self.first?).as_secs_f64();
+        (self.nominal > 0 && secs >= MIN_CLOCK_SECS).then(|| AudioClock { nominal: self.nominal, measured: self.frames as f64 / secs })
+    }
+}
+
+/// Closes the encoder's stdin so ffmpeg finalizes the file, then waits for it to exit.
+fn finish_encoder(writer_handle: &Arc<Mutex<Option<Child>>>, msg_tx: &Sender<AudioMessage>) {
+    let encoder = writer_handle.lock().ok().and_then(|mut guard| guard.take());
+    if let Some(mut encoder) = encoder {
+        drop(encoder.stdin.take());
+        match encoder.wait() {
+            Ok(status) if status.success() => {},
+            Ok(status) => { let _ = msg_tx.send(AudioMessage::Error(format!("Audio encoder exited with {}", status))); },
+            Err(e) => { let _ = msg_tx.send(AudioMessage::Error(format!("Failed to flush audio to disk: {}", e))); }
+        }
+    }
+}
+
+const APP_CAPTURE_RATE: u32 = 48000;
+const APP_CAPTURE_CHANNELS: u16 = 2;
+
+/// Lists applications currently playing audio. Only PipeWire exposes per-application streams we
+/// can record from, so this is empty everywhere else.
+fn list_app_streams() -> Vec<AudioApp> {
+    let Ok(output) = Command::new("pw-dump").stderr(Stdio::null()).output() else { return Vec::new(); };
+    let Ok(objects) = serde_json::from_slice::<Vec<serde_json::Value>>(&output.stdout) else { return Vec::new(); };
+
+    objects.iter().filter_map(|obj| {
+        if obj["type"] != "PipeWire:Interface:Node" { return None; }
+        let props = &obj["info"]["props"];
+        if props["media.class"] != "Stream/Output/Audio" { return None; }
+
+        let name = props["application.name"].as_str().or(props["node.name"].as_str()).unwrap_or("Unknown app");
+        let target = props["object.serial"].as_u64().or(obj["id"].as_u64())?;
+        Some(AudioApp { name: name.to_string(), target: target.to_string() })
+    }).collect()
+}
+
+/// Records one application's output with `pw-record` and pipes it straight into the audio encoder.
+fn start_app_capture(app: &AudioApp, encoding: AudioEncoding, filename: &str) -> std::io::Result<(Child, Child)> {
+    let mut capture = Command::new("pw-record")
+        .args(["--target", &app.target, "--rate", &APP_CAPTURE_RATE.to_string(), "--channels", &APP_CAPTURE_CHANNELS.to_string(), "--format", "f32", "--raw", "-"])
+        .stdout(Stdio::piped()).stderr(Stdio::null()).spawn()?;
+    let Some(pcm) = capture.stdout.take() else {
+        let _ = capture.kill();
+        return Err(std::io::Error::other("pw-record has no stdout"));
+    };
+
+    let args = ffmpeg::build_audio_cmd(APP_CAPTURE_RATE, APP_CAPTURE_CHANNELS, encoding, filename);
+    match Command::new("ffmpeg").args(&args).stdin(Stdio::from(pcm)).stdout(Stdio::null()).stderr(Stdio::null()).spawn() {
+        Ok(encoder) => Ok((capture, encoder)),
+        Err(e) => {
+            let _ = capture.kill();
+            let _ = capture.wait();
+            Err(e)
+        }
+    }
+}
+
+/// Stopping `pw-record` closes the encoder's input, which lets `finish_encoder` finalize the file.
+fn stop_app_capture(app_capture: &mut Option<Child>) {
+    if let Some(mut capture) = app_capture.take() {
+        let _ = capture.kill();
+        let _ = capture.wait();
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use crossbeam_channel::{Receiver, Sender, bounded};
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::{Arc, Mutex, atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering}}, thread, time::{Duration, Instant}};
+
+pub const DEFAULT_CAP_MB: usize = 512;
+/// Frames queued for the recorder, a few seconds' worth at common frame rates.
+pub const FRAME_QUEUE_LEN: usize = 120;
+/// Most frames the queue can be set to hold. The channel is made this big up front so the
+/// length can change while frames are flowing.
+pub const MAX_FRAME_QUEUE_LEN: usize = 1200;
+/// Longest a blocking queue holds up the camera before giving up on a frame, so a recorder that
+/// has stopped reading can't freeze capture.
+const BLOCK_LIMIT: Duration = Duration::from_millis(500);
+
+pub type QueuedFrame = (Arc<Vec<u8>>, Instant);
+
+/// Bytes of frame data in flight between threads. Producers reserve before
+/// sending and consumers release once a frame is written or shown, so a
+/// stalled encoder makes the producer drop frames instead of growing a queue.
+pub struct FrameBudget {
+    cap: AtomicUsize,
+    used: AtomicUsize,
+}
+
+impl FrameBudget {
+    pub fn new(cap_mb: usize) -> Arc<Self> {
+        Arc::new(Self {
+            cap: AtomicUsize::new(cap_mb * 1024 * 1024),
+            used: AtomicUsize::new(0),
+        })
+    }
+
+    /// Refusals aren't counted here: the queue counts the recorder's and the tap's apart, and a
+    /// skipped preview frame costs nothing.
+    pub fn try_reserve(&self, bytes: usize) -> bool {
+        let cap = self.cap.load(Ordering::Relaxed);
+        self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            (used + bytes <= cap).then_some(used + bytes)
+        }).is_ok()
+    }
+
+    pub fn fits(&self, bytes: usize) -> bool {
+        self.used.load(Ordering::Relaxed) + bytes <= self.cap.load(Ordering::Relaxed)
+    }
+
+    pub fn release(&self, bytes: usize) {
+        let _ = self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| Some(used.saturating_sub(bytes)));
+    }
+
+    pub fn set_cap_mb(&self, cap_mb: usize) {
+        self.cap.store(cap_mb * 1024 * 1024, Ordering::Relaxed);
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn cap(&self) -> usize {
+        self.cap.load(Ordering::Relaxed)
+    }
+}
+
+/// What the queue does with a new frame once it is full, by count or by the memory budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DropPolicy {
+    /// The oldest queued frame makes way, keeping recordings closest to live.
+    DropOldest,
+    /// The new frame is dropped, keeping what's queued intact.
+    DropNewest,
+    /// The camera waits for room, so capture slows to the encoder's pace instead of skipping.
+    Block
+}
+
+impl DropPolicy {
+    pub const ALL: [DropPolicy; 3] = [DropPolicy::DropOldest, DropPolicy::DropNewest, DropPolicy::Block];
+}
+
+impl fmt::Display for DropPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DropPolicy::DropOldest => write!(f, "Drop oldest"),
+            DropPolicy::DropNewest => write!(f, "Drop newest"),
+            DropPolicy::Block => write!(f, "Block")
+        }
+    }
+}
+
+/// The camera's frames on their way to the recorder. A stalled encoder costs frames rather than
+/// memory: when the queue is full, by count or by the memory budget, the drop policy decides
+/// which frame goes. Length and policy can be changed while frames are flowing.
+pub struct FrameQueue {
+    tx: Sender<QueuedFrame>,
+    rx: Receiver<QueuedFrame>,
+    budget: Arc<FrameBudget>,
+    dropped: AtomicU64,
+    /// Frames the tap went without, kept apart from the recorder's drops.
+    tap_missed: AtomicU64,
+    capacity: AtomicUsize,
+    policy: AtomicU8,
+    /// Second consumer that gets its own copy of every frame, like the live stream.
+    tap: Mutex<Option<Sender<QueuedFrame>>>,
+}
+
+impl FrameQueue {
+    pub fn new(capacity: usize, budget: Arc<FrameBudget>) -> Arc<Self> {
+        let (tx, rx) = bounded(MAX_FRAME_QUEUE_LEN);
+        Arc::new(Self {
+            tx,
+            rx,
+            budget,
+            dropped: AtomicU64::new(0),
+            tap_missed: AtomicU64::new(0),
+            capacity: AtomicUsize::new(capacity.clamp(1, MAX_FRAME_QUEUE_LEN)),
+            policy: AtomicU8::new(DropPolicy::DropOldest as u8),
+            tap: Mutex::new(None),
+        })
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity.clamp(1, MAX_FRAME_QUEUE_LEN), Ordering::Relaxed);
+    }
+
+    pub fn set_policy(&self, policy: DropPolicy) {
+        self.policy.store(policy as u8, Ordering::Relaxed);
+    }
+
+    /// Sends every frame to `tap` as well, with its own budget reservation for the receiver to
+    /// release. A full tap misses frames without affecting the recorder's queue.
+    pub fn set_tap(&self, tap: Option<Sender<QueuedFrame>>) {
+        if let Ok(mut current) = self.tap.lock() {
+            *current = tap;
+        }
+    }
+
+    fn policy(&self) -> DropPolicy {
+        DropPolicy::ALL.get(self.policy.load(Ordering::Relaxed) as usize).copied().unwrap_or(DropPolicy::DropOldest)
+    }
+
+    fn is_full(&self, bytes: usize) -> bool {
+        self.rx.len() >= self.capacity.load(Ordering::Relaxed) || !self.budget.fits(bytes)
+    }
+
+    pub fn push(&self, frame: Arc<Vec<u8>>, captured: Instant) {
+        if let Ok(tap) = self.tap.lock()
+            && let Some(tap) = tap.as_ref() {
+            let sent = self.budget.try_reserve(frame.len()) && match tap.try_send((frame.clone(), captured)) {
+                Ok(()) => true,
+                Err(_) => { self.budget.release(frame.len()); false }
+            };
+            if !sent {
+                self.tap_missed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        match self.policy() {
+            DropPolicy::DropOldest => {
+                while self.is_full(frame.len()) {
+                    match self.rx.try_recv() {
+                        Ok((oldest, _)) => {
+                            self.budget.release(oldest.len());
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                        },
+                        Err(_) => break
+                    }
+                }
+            },
+            DropPolicy::DropNewest => {
+                if self.is_full(frame.len()) {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            },
+            DropPolicy::Block => {
+                let deadline = Instant::now() + BLOCK_LIMIT;
+                while self.is_full(frame.len()) {
+                    if Instant::now() >= deadline {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }
+        if !self.budget.try_reserve(frame.len()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let len = frame.len();
+        if self.tx.try_send((frame, captured)).is_err() {
+            self.budget.release(len);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The memory budget the queue reserves against, shared with the preview.
+    pub fn budget(&self) -> &Arc<FrameBudget> {
+        &self.budget
+    }
+
+    pub fn receiver(&self) -> &Receiver<QueuedFrame> {
+        &self.rx
+    }
+
+    /// Frames dropped since startup, for the recorder to turn into per-take counts.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Frames the tap missed since startup.
+    pub fn tap_missed(&self) -> u64 {
+        self.tap_missed.load(Ordering::Relaxed)
+    }
+}
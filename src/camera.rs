@@ -13,90 +13,164 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::messages::{camera::{CameraCommand, CameraMessage}, recorder::RecorderCommand, video::VideoConfig};
-use crossbeam_channel::{Sender, Receiver};
+use crate::{budget::{FrameBudget, FrameQueue}, crash::log_line, messages::{camera::{CameraCommand, CameraMessage}, video::VideoConfig}, storage, tuning::{self, ThreadTuning}};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TryRecvError};
 use image::imageops::FilterType;
-use nokhwa::{Camera, pixel_format::RgbFormat, utils::{CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType}};
-use std::{sync::{Arc, Mutex}, thread, time::{Duration, Instant}};
+use nokhwa::{Buffer, Camera, NokhwaError, pixel_format::RgbFormat, utils::{ApiBackend, CameraFormat, CameraIndex, ControlValueSetter, FrameFormat, KnownCameraControl, RequestedFormat, RequestedFormatType}};
+use std::{collections::HashMap, sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU32, Ordering}}, thread, time::{Duration, Instant}};
 
 const MJPEG: &str = "MJPEG";
 const YUYV: &str = "YUYV";
 const NV12: &str = "NV12";
 const GRAY: &str = "GRAY";
+const CAPABILITY_CACHE: &str = "camera_formats.json";
+/// How often a camera another app is holding is tried again.
+const BUSY_RETRY: Duration = Duration::from_secs(3);
 const W480p: u32 = 854;
 const H480p: u32 = 480;
+/// The V4L2 switches for automatic white balance and exposure, with the value that turns each
+/// off: auto white balance off, manual exposure mode. Turning them off keeps the values they
+/// settled on.
+#[cfg(target_os = "linux")]
+const AUTO_CONTROLS: [(u128, i64); 2] = [(0x0098_090c, 0), (0x009a_0901, 1)];
 
-pub fn start_thread(tx: Sender<CameraMessage>, rec_tx: Sender<RecorderCommand>, cmd_rx: Receiver<CameraCommand>) {
+pub fn start_thread(tx: Sender<CameraMessage>, frames: Arc<FrameQueue>, cmd_rx: Receiver<CameraCommand>, frame_budget: Arc<FrameBudget>) {
     thread::spawn(move || {
+        let preview_enabled = Arc::new(AtomicBool::new(true));
+        let preview_every = Arc::new(AtomicU32::new(1));
+        let mut thread_tuning = ThreadTuning::default();
+        // Format lists per camera; some capture cards take seconds to enumerate them.
+        let mut format_cache: HashMap<String, Vec<VideoConfig>> = storage::load_json(CAPABILITY_CACHE).unwrap_or_default();
+        let mut force_probe = false;
+        // A stream asked for while the camera was busy, started as soon as it's free.
+        let mut pending: Option<VideoConfig> = None;
         loop {
             let index: CameraIndex = CameraIndex::Index(0);
-            let requested = RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestFrameRate);
-            let query_camera_result = Camera::new(index.clone(), requested);
-            match query_camera_result {
-                Ok(mut camera) => {
-                    match camera.compatible_camera_formats() {
-                        Ok(formats) => {
-                            let mut configs = Vec::new();
-                            for fmt in formats {
-                                let c = VideoConfig {
-                                    width: fmt.resolution().width(),
-                                    height: fmt.resolution().height(),
-                                    fps: fmt.frame_rate(),
-                                    fmt: fmt.format().to_string()
-                                };
-                                if !configs.contains(&c) { configs.push(c); }
+            let key = camera_key(&index);
+            let cached = key.as_ref().filter(|_| !force_probe).and_then(|k| format_cache.get(k).cloned());
+            force_probe = false;
+            if let Some(configs) = cached {
+                log_line!("Using cached formats for {}", key.as_deref().unwrap_or_default());
+                let _ = tx.send(CameraMessage::Capabilities(configs));
+            } else {
+                let requested = RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestFrameRate);
+                let query_camera_result = Camera::new(index.clone(), requested);
+                match query_camera_result {
+                    Ok(mut camera) => {
+                        match camera.compatible_camera_formats() {
+                            Ok(formats) => {
+                                let mut configs = Vec::new();
+                                for fmt in formats {
+                                    let c = VideoConfig {
+                                        width: fmt.resolution().width(),
+                                        height: fmt.resolution().height(),
+                                        fps: fmt.frame_rate(),
+                                        fmt: fmt.format().to_string()
+                                    };
+                                    if !configs.contains(&c) { configs.push(c); }
+                                }
+                                configs.sort_by(|a, b| b.width.cmp(&a.width).then(b.fps.cmp(&a.fps)));
+                                if let Some(key) = &key {
+                                    format_cache.insert(key.clone(), configs.clone());
+                                    storage::save_json(CAPABILITY_CACHE, &format_cache);
+                                }
+                                let _ = tx.send(CameraMessage::Capabilities(configs));
+                            },
+                            Err(e) if is_busy(&e) => {
+                                let _ = tx.send(CameraMessage::Busy);
+                                if wait_while_busy(&cmd_rx, &preview_enabled, &mut pending) { continue; } else { break; }
+                            },
+                            Err(e) => {
+                                let _ = tx.send(CameraMessage::Error(format!("Query failed: {}", e)));
+                                if wait_for_retry(&cmd_rx) { continue; } else { break; }
                             }
-                            configs.sort_by(|a, b| b.width.cmp(&a.width).then(b.fps.cmp(&a.fps)));
-                            let _ = tx.send(CameraMessage::Capabilities(configs));
-                        },
-                        Err(e) => {
-                            let _ = tx.send(CameraMessage::Error(format!("Query failed: {}", e)));
-                            if wait_for_retry(&cmd_rx) { continue; } else { break; }
                         }
-                    }
                     
-                    drop(camera);
-                },
-                Err(e) => {
-                    let _= tx.send(CameraMessage::Error(format!("Camera initialization failed: {}", e)));
-                    if wait_for_retry(&cmd_rx) { continue; } else { break; }
-                }
-            };
+                        drop(camera);
+                    },
+                    Err(e) if is_busy(&e) => {
+                        let _ = tx.send(CameraMessage::Busy);
+                        if wait_while_busy(&cmd_rx, &preview_enabled, &mut pending) { continue; } else { break; }
+                    },
+                    Err(e) => {
+                        let _= tx.send(CameraMessage::Error(format!("Camera initialization failed: {}", e)));
+                        if wait_for_retry(&cmd_rx) { continue; } else { break; }
+                    }
+                };
+            }
 
-            let cfg = match cmd_rx.recv() {
-                Ok(CameraCommand::StartStream(c)) => c,
-                Ok(CameraCommand::Retry) => continue,
-                Err(_) => break
-            };
+            // A command that ended a settings preview, handled as if it had just arrived.
+            let mut next: Option<CameraCommand> = None;
+            let cfg = if let Some(c) = pending.take() { Some(c) } else { loop {
+                let cmd = match next.take() {
+                    Some(cmd) => Ok(cmd),
+                    None => cmd_rx.recv().map_err(|_| ())
+                };
+                match cmd {
+                    Ok(CameraCommand::StartStream(c)) => break Some(c),
+                    Ok(CameraCommand::SetPreview(enabled)) => preview_enabled.store(enabled, Ordering::Relaxed),
+                    Ok(CameraCommand::SetPreviewEvery(n)) => preview_every.store(n.max(1), Ordering::Relaxed),
+                    Ok(CameraCommand::SetTuning(t)) => thread_tuning = t,
+                    Ok(CameraCommand::Retry) => break None,
+                    Ok(CameraCommand::LockExposure(_)) => {},
+                    Ok(CameraCommand::RefreshFormats) => {
+                        force_probe = true;
+                        break None;
+                    },
+                    Ok(CameraCommand::PreviewFormat(c)) => match preview_format(&index, &c, &tx, &cmd_rx, &frame_budget) {
+                        Some(cmd) => next = Some(cmd),
+                        None => return
+                    },
+                    Err(_) => return
+                }
+            } };
+            let Some(mut cfg) = cfg else { continue; };
 
-            let frame_format = match cfg.fmt.as_str() {
-                MJPEG => FrameFormat::MJPEG,
-                YUYV => FrameFormat::YUYV,
-                _ => FrameFormat::MJPEG
-            };
-            let exact = CameraFormat::new_from(cfg.width, cfg.height, frame_format, cfg.fps);
-            println!("camera line 76) cfg.fps: {}", cfg.fps.to_string());
-            let req = RequestedFormat::new::<RgbFormat>(RequestedFormatType::Exact(exact));
-            let mut camera = match Camera::new(index, req) {
-                Ok(c) => c,
-                Err(e) => {
-                    let _ = tx.send(CameraMessage::Error(format!("Re-init failed: {}", e)));
-                    if wait_for_retry(&cmd_rx) { continue; } else { break; }
+            log_line!("camera line 76) cfg.fps: {}", cfg.fps.to_string());
+            // Another app holding the camera is waited out here rather than through a re-probe,
+            // which would send the app back to configuration.
+            let opened = loop {
+                match open_stream(&index, &cfg) {
+                    Ok(camera) => break Some(camera),
+                    Err((_, e)) if is_busy(&e) => {
+                        log_line!("Camera busy: {}", e);
+                        let _ = tx.send(CameraMessage::Busy);
+                        let mut requested = None;
+                        if !wait_while_busy(&cmd_rx, &preview_enabled, &mut requested) { return; }
+                        if let Some(c) = requested { cfg = c; }
+                    },
+                    Err((step, e)) => {
+                        let _ = tx.send(CameraMessage::Error(format!("{} failed: {}", step, e)));
+                        break None;
+                    }
                 }
             };
-
-            if let Err(e) = camera.open_stream() {
-                let _ = tx.send(CameraMessage::Error(format!("Open stream failed: {}", e)));
+            let Some(mut camera) = opened else {
                 if wait_for_retry(&cmd_rx) { continue; } else { break; }
-            }
+            };
 
             let _ = tx.send(CameraMessage::StreamStarted(cfg.width, cfg.height, cfg.fps));
             let latest_frame: Arc<Mutex<Option<Arc<Vec<u8>>>>> = Arc::new(Mutex::new(None));
             let cap_frame_storage = latest_frame.clone();
             let ui_tx = tx.clone();
+            let cap_preview_enabled = preview_enabled.clone();
+            let cap_preview_every = preview_every.clone();
+            let cap_budget = frame_budget.clone();
+            // Controls have to be set from the thread that owns the camera.
+            let (lock_tx, lock_rx) = crossbeam_channel::unbounded::<bool>();
 
             thread::spawn(move || {
+                if let Err(e) = tuning::apply(thread_tuning) {
+                    let _ = ui_tx.send(CameraMessage::Error(e));
+                }
+                let mut auto_values: Vec<(KnownCameraControl, ControlValueSetter)> = Vec::new();
+                let mut captured: u32 = 0;
                 loop {
+                    while let Ok(lock) = lock_rx.try_recv() {
+                        if let Err(e) = set_exposure_lock(&mut camera, lock, &mut auto_values) {
+                            let _ = ui_tx.send(CameraMessage::Error(e));
+                        }
+                    }
                     match camera.frame() {
                         Ok(frame) => {
                             let raw_data = frame.buffer().to_vec();
@@ -105,18 +179,9 @@ pub fn start_thread(tx: Sender<CameraMessage>, rec_tx: Sender<RecorderCommand>,
                                 *guard = Some(raw_arc.clone());
                             }
 
-                            if let Ok(decoded) = frame.decode_image::<RgbFormat>() {
-                                let preview = image::imageops::resize(&decoded, W480p, H480p, FilterType::Nearest);
-                                let p_width = preview.width();
-                                let p_height = preview.height();
-                                let preview = preview.into_raw();
-                                let raw: Arc<Vec<u8>> = Arc::new(vec![]);
-                                let _ = ui_tx.send(CameraMessage::Frame {
-                                    raw,
-                                    preview,
-                                    p_width,
-                                    p_height
-                                });
+                            captured = captured.wrapping_add(1);
+                            if cap_preview_enabled.load(Ordering::Relaxed) && captured.is_multiple_of(cap_preview_every.load(Ordering::Relaxed).max(1)) {
+                                send_preview(&frame, &ui_tx, &cap_budget);
                             }
                         },
                         Err(_) => {
@@ -126,18 +191,29 @@ pub fn start_thread(tx: Sender<CameraMessage>, rec_tx: Sender<RecorderCommand>,
                 }
             });
 
+            if let Err(e) = tuning::apply(thread_tuning) {
+                let _ = tx.send(CameraMessage::Error(e));
+            }
             let target_interval = Duration::from_secs_f64(1.0/cfg.fps as f64);
             let mut next_tick = Instant::now();
 
             loop {
+                while let Ok(cmd) = cmd_rx.try_recv() {
+                    match cmd {
+                        CameraCommand::SetPreview(enabled) => preview_enabled.store(enabled, Ordering::Relaxed),
+                        CameraCommand::SetPreviewEvery(n) => preview_every.store(n.max(1), Ordering::Relaxed),
+                        CameraCommand::LockExposure(lock) => { let _ = lock_tx.send(lock); },
+                        _ => {}
+                    }
+                }
+
                 let frame_to_send = {
                     let guard = latest_frame.lock().unwrap();
                     guard.clone()
                 };
 
                 if let Some(data) = frame_to_send {
-                    let capture_time = Instant::now();
-                    let _ = rec_tx.send(RecorderCommand::WriteFrame(data, capture_time));
+                    frames.push(data, Instant::now());
                 }
 
                 next_tick += target_interval;
@@ -152,6 +228,124 @@ pub fn start_thread(tx: Sender<CameraMessage>, rec_tx: Sender<RecorderCommand>,
     });
 }
 
+/// Identifies the camera at `index` by name and backend details (bus path, or the device path
+/// with USB VID:PID on Windows) without opening it, which is the slow part.
+fn camera_key(index: &CameraIndex) -> Option<String> {
+    let cameras = nokhwa::query(ApiBackend::Auto).ok()?;
+    let info = cameras.iter().find(|c| c.index() == index)?;
+    Some(format!("{} {}", info.human_name(), info.misc()))
+}
+
+/// Locking switches the automatic controls off and remembers how they were; unlocking puts them
+/// back. Controls the camera doesn't have are skipped.
+#[cfg(target_os = "linux")]
+fn set_exposure_lock(camera: &mut Camera, lock: bool, auto_values: &mut Vec<(KnownCameraControl, ControlValueSetter)>) -> Result<(), String> {
+    if !lock {
+        for (control, value) in auto_values.drain(..) {
+            camera.set_camera_control(control, value).map_err(|e| format!("Unlocking {} failed: {}", control, e))?;
+        }
+        return Ok(());
+    }
+    if !auto_values.is_empty() { return Ok(()); }
+    for (id, off) in AUTO_CONTROLS {
+        let control = KnownCameraControl::Other(id);
+        let Ok(current) = camera.camera_control(control).map(|c| c.value()) else { continue };
+        let off = match current {
+            ControlValueSetter::Boolean(_) => ControlValueSetter::Boolean(off != 0),
+            _ => ControlValueSetter::Integer(off)
+        };
+        camera.set_camera_control(control, off).map_err(|e| format!("Locking {} failed: {}", control, e))?;
+        auto_values.push((control, current));
+    }
+    log_line!("Locked {} automatic camera controls", auto_values.len());
+    Ok(())
+}
+
+/// Media Foundation only lets nokhwa change a control's value, not whether it's automatic.
+#[cfg(not(target_os = "linux"))]
+fn set_exposure_lock(_camera: &mut Camera, lock: bool, _auto_values: &mut Vec<(KnownCameraControl, ControlValueSetter)>) -> Result<(), String> {
+    if lock { Err(String::from("Locking white balance and exposure isn't supported on this platform")) } else { Ok(()) }
+}
+
+/// Decodes a frame at preview size and hands it to the UI, unless the frame budget is spent.
+fn send_preview(frame: &Buffer, tx: &Sender<CameraMessage>, budget: &FrameBudget) {
+    let Ok(decoded) = frame.decode_image::<RgbFormat>() else { return };
+    let preview = image::imageops::resize(&decoded, W480p, H480p, FilterType::Nearest);
+    let p_width = preview.width();
+    let p_height = preview.height();
+    let preview = preview.into_raw();
+    if !budget.try_reserve(preview.len()) {
+        return;
+    }
+    let raw: Arc<Vec<u8>> = Arc::new(vec![]);
+    let _ = tx.send(CameraMessage::Frame {
+        raw,
+        preview,
+        p_width,
+        p_height
+    });
+}
+
+/// Streams `cfg` to the settings screen as preview frames only, nothing reaching the recorder,
+/// until the next command arrives, which is handed back. `None` once the app has gone.
+fn preview_format(index: &CameraIndex, cfg: &VideoConfig, tx: &Sender<CameraMessage>, cmd_rx: &Receiver<CameraCommand>, budget: &FrameBudget) -> Option<CameraCommand> {
+    let mut camera = match open_stream(index, cfg) {
+        Ok(c) => c,
+        Err((step, e)) => {
+            let _ = tx.send(CameraMessage::Error(format!("Preview of {}: {} failed: {}", cfg, step, e)));
+            return cmd_rx.recv().ok();
+        }
+    };
+    loop {
+        match cmd_rx.try_recv() {
+            Ok(cmd) => return Some(cmd),
+            Err(TryRecvError::Disconnected) => return None,
+            Err(TryRecvError::Empty) => {}
+        }
+        match camera.frame() {
+            Ok(frame) => send_preview(&frame, tx, budget),
+            Err(_) => thread::sleep(Duration::from_millis(10))
+        }
+    }
+}
+
+/// Opens the camera in exactly this format and starts it streaming. Errors say which step failed.
+pub fn open_stream(index: &CameraIndex, cfg: &VideoConfig) -> Result<Camera, (&'static str, NokhwaError)> {
+    let frame_format = match cfg.fmt.as_str() {
+        MJPEG => FrameFormat::MJPEG,
+        YUYV => FrameFormat::YUYV,
+        _ => FrameFormat::MJPEG
+    };
+    let exact = CameraFormat::new_from(cfg.width, cfg.height, frame_format, cfg.fps);
+    let req = RequestedFormat::new::<RgbFormat>(RequestedFormatType::Exact(exact));
+    let mut camera = Camera::new(index.clone(), req).map_err(|e| ("Re-init", e))?;
+    camera.open_stream().map_err(|e| ("Open stream", e))?;
+    Ok(camera)
+}
+
+/// Whether the camera failed to open because another app has it, rather than being missing or
+/// broken. nokhwa only passes the platform error on as text: EBUSY from V4L2, and on Windows
+/// Media Foundation's failed-to-start-streaming (0xC00D3704) or a sharing violation.
+fn is_busy(error: &NokhwaError) -> bool {
+    let text = error.to_string().to_lowercase();
+    ["busy", "os error 16", "0xc00d3704", "-1072875772", "0x80070020", "being used by another"].iter().any(|s| text.contains(s))
+}
+
+/// Sits out `BUSY_RETRY` while the camera is busy, keeping up with preview changes and taking
+/// note of a stream asked for meanwhile. Returns false once the app has gone.
+fn wait_while_busy(rx: &Receiver<CameraCommand>, preview_enabled: &AtomicBool, requested: &mut Option<VideoConfig>) -> bool {
+    let deadline = Instant::now() + BUSY_RETRY;
+    loop {
+        match rx.recv_deadline(deadline) {
+            Ok(CameraCommand::Retry) | Err(RecvTimeoutError::Timeout) => return true,
+            Ok(CameraCommand::StartStream(c)) => *requested = Some(c),
+            Ok(CameraCommand::SetPreview(enabled)) => preview_enabled.store(enabled, Ordering::Relaxed),
+            Ok(_) => {},
+            Err(RecvTimeoutError::Disconnected) => return false
+        }
+    }
+}
+
 fn wait_for_retry(rx: &Receiver<CameraCommand>) -> bool {
     loop {
         match rx.recv() {
@@ -0,0 +1,116 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use crate::storage;
+use chrono::Local;
+use once_cell::sync::Lazy;
+use std::{backtrace::Backtrace, collections::VecDeque, fs, io::{BufReader, Read}, panic, path::{Path, PathBuf}, process::{Child, Command}, sync::Mutex, thread};
+
+const LOG_LINES: usize = 200;
+const PENDING_CRASH: &str = "pending_crash.json";
+
+static LOG: Lazy<Mutex<VecDeque<String>>> = Lazy::new(|| Mutex::new(VecDeque::with_capacity(LOG_LINES)));
+static SETTINGS: Lazy<Mutex<String>> = Lazy::new(|| Mutex::new(String::from("(not configured yet)")));
+
+/// Prints a line to stderr and keeps it for the next crash report.
+macro_rules! log_line {
+    ($($arg:tt)*) => { $crate::crash::record(format!($($arg)*)) };
+}
+pub(crate) use log_line;
+
+pub fn record(line: String) {
+    eprintln!("{}", line);
+    if let Ok(mut log) = LOG.lock() {
+        if log.len() == LOG_LINES {
+            log.pop_front();
+        }
+        log.push_back(format!("{} {}", Local::now().format("%H:%M:%S%.3f"), line));
+    }
+}
+
+/// Passes a child's stderr through `record` line by line on a thread of its own, so ffmpeg's
+/// complaints end up in crash reports. The child has to be spawned with a piped stderr.
+pub fn log_stderr(mut child: Child) -> Child {
+    if let Some(stderr) = child.stderr.take() {
+        thread::spawn(move || {
+            let mut line = Vec::new();
+            for byte in BufReader::new(stderr).bytes().map_while(Result::ok) {
+                match byte {
+                    b'\n' => { record(String::from_utf8_lossy(&line).trim_end().to_string()); line.clear(); },
+                    // ffmpeg redraws its running stats over themselves, which would crowd out the log.
+                    b'\r' => { eprint!("{}\r", String::from_utf8_lossy(&line)); line.clear(); },
+                    _ => line.push(byte)
+                }
+            }
+        });
+    }
+    child
+}
+
+/// Replaces the settings section of any future crash report. The home directory is masked so
+/// reports can be pasted into a public issue.
+pub fn set_settings(snapshot: String) {
+    let snapshot = match dirs::home_dir() {
+        Some(home) if !home.as_os_str().is_empty() => snapshot.replace(&*home.to_string_lossy(), "~"),
+        _ => snapshot
+    };
+    if let Ok(mut settings) = SETTINGS.lock() {
+        *settings = snapshot;
+    }
+}
+
+/// Writes a report into the data dir on any panic and remembers it so the next launch can offer it.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let thread = std::thread::current().name().unwrap_or("unnamed").to_string();
+        let mut report = format!("Clipper {} crash report, {}\n\n{} (thread {})\n\nBacktrace:\n{}\n",
+            env!("CARGO_PKG_VERSION"), Local::now().format("%Y-%m-%d %H:%M:%S"), info, thread, Backtrace::force_capture());
+        // A panic while one of these is held would deadlock the hook, so never block on them.
+        if let Ok(settings) = SETTINGS.try_lock() {
+            report.push_str(&format!("\nSettings:\n{}\n", settings));
+        }
+        if let Ok(log) = LOG.try_lock() {
+            report.push_str(&format!("\nLast {} log lines:\n", log.len()));
+            for line in log.iter() {
+                report.push_str(line);
+                report.push('\n');
+            }
+        }
+
+        let dir = storage::data_dir().join("crash_reports");
+        let path = dir.join(format!("crash_{}.txt", Local::now().format("%Y%m%d_%H%M%S")));
+        if fs::create_dir_all(&dir).is_ok() && fs::write(&path, report).is_ok() {
+            storage::save_json(PENDING_CRASH, &path);
+            eprintln!("Crash report written to {}", path.display());
+        }
+    }));
+}
+
+/// The report left by the previous run, if any. Only offered once.
+pub fn take_pending_report() -> Option<PathBuf> {
+    let path: PathBuf = storage::load_json(PENDING_CRASH)?;
+    let _ = fs::remove_file(storage::data_dir().join(PENDING_CRASH));
+    path.exists().then_some(path)
+}
+
+pub fn open_path(path: &Path) {
+    let opener = if cfg!(windows) { "explorer" } else if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    if let Err(e) = Command::new(opener).arg(path).spawn() {
+        log_line!("Failed to open {}: {}", path.display(), e);
+    }
+}
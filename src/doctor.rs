@@ -0,0 +1,137 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use crate::{camera, recorder::types::EncoderPreset, settings::Settings, storage};
+use cpal::traits::{DeviceTrait, HostTrait};
+use nokhwa::{Camera, pixel_format::RgbFormat, utils::{CameraIndex, RequestedFormat, RequestedFormatType}};
+use std::{fs, path::Path, process::{Command, Stdio}};
+
+const AUDIO_ENCODERS: [&str; 2] = ["aac", "libopus"];
+const LOW_DISK_GB: f64 = 5.0;
+
+enum Level {
+    Ok,
+    Warn,
+    Fail
+}
+
+/// `clipper doctor`: checks everything a recording session depends on and prints a report.
+/// Returns false when something required is missing.
+pub fn run() -> bool {
+    println!("Clipper {} doctor", env!("CARGO_PKG_VERSION"));
+    let mut healthy = true;
+    let mut report = |level: Level, what: &str, detail: String| {
+        let tag = match level {
+            Level::Ok => "  ok",
+            Level::Warn => "warn",
+            Level::Fail => { healthy = false; "FAIL" }
+        };
+        println!("[{}] {}: {}", tag, what, detail);
+    };
+
+    for tool in ["ffmpeg", "ffprobe", "ffplay"] {
+        match tool_version(tool) {
+            Some(v) => report(Level::Ok, tool, v),
+            None if tool == "ffplay" => report(Level::Warn, tool, String::from("not found, voiceover recording is unavailable")),
+            None => report(Level::Fail, tool, String::from("not found on PATH"))
+        }
+    }
+
+    // Mastering runs on libx264, which is already checked.
+    let video_encoders: Vec<&str> = EncoderPreset::ALL.into_iter().filter(|p| *p != EncoderPreset::MASTERING).map(EncoderPreset::ffmpeg_name).collect();
+    for encoder in video_encoders.iter().chain(AUDIO_ENCODERS.iter()) {
+        let is_audio = AUDIO_ENCODERS.contains(encoder);
+        if encoder_works(encoder, is_audio) {
+            report(Level::Ok, encoder, String::from("usable"));
+        } else if *encoder == "libx264" || *encoder == "aac" {
+            report(Level::Fail, encoder, String::from("not usable, ffmpeg build is missing it"));
+        } else {
+            report(Level::Warn, encoder, String::from("not usable on this machine"));
+        }
+    }
+
+    let settings = Settings::load();
+    let requested = RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestFrameRate);
+    match Camera::new(CameraIndex::Index(0), requested) {
+        Ok(mut camera) => match camera.compatible_camera_formats() {
+            Ok(formats) => report(Level::Ok, "camera", format!("opened, {} formats", formats.len())),
+            Err(e) => report(Level::Fail, "camera", format!("opened but format query failed: {}", e))
+        },
+        Err(e) => report(Level::Fail, "camera", e.to_string())
+    }
+    // The format the app last started is the one the next session will ask for.
+    if let Some(format) = &settings.video_format {
+        match camera::open_stream(&CameraIndex::Index(0), format) {
+            Ok(_) => report(Level::Ok, "camera format", format!("{} streams", format)),
+            Err((stage, e)) => report(Level::Fail, "camera format", format!("{}: {} failed: {}", format, stage, e))
+        }
+    }
+
+    let host = cpal::default_host();
+    match host.default_input_device() {
+        Some(device) => match device.default_input_config() {
+            Ok(cfg) => report(Level::Ok, "microphone", format!("{} ({} ch @ {} Hz)", device.name().unwrap_or_default(), cfg.channels(), cfg.sample_rate().0)),
+            Err(e) => report(Level::Fail, "microphone", format!("{}: {}", device.name().unwrap_or_default(), e))
+        },
+        None => report(Level::Fail, "microphone", String::from("no input device"))
+    }
+
+    let root = settings.workspace_root.clone().unwrap_or_else(storage::default_workspace_root);
+    let folders = [("workspace", Some(root)), ("overflow folder", settings.overflow_dir.clone()), ("backup folder", settings.backup_dir.clone())];
+    for (what, dir) in folders.into_iter().filter_map(|(what, dir)| Some((what, dir?))) {
+        if !dir.is_dir() {
+            // The workspace is made on the first take; the others are usually a drive that's been unplugged.
+            let level = if what == "workspace" { Level::Warn } else { Level::Fail };
+            report(level, what, format!("{} does not exist", dir.display()));
+            continue;
+        }
+        if let Err(e) = writable(&dir) {
+            report(Level::Fail, what, format!("cannot write to {}: {}", dir.display(), e));
+            continue;
+        }
+        match storage::free_space_gb(&dir) {
+            Some(gb) if gb < LOW_DISK_GB => report(Level::Warn, what, format!("{:.1} GB free in {}", gb, dir.display())),
+            Some(gb) => report(Level::Ok, what, format!("{:.1} GB free in {}", gb, dir.display())),
+            None => report(Level::Warn, what, format!("could not read free space for {}", dir.display()))
+        }
+    }
+
+    println!("{}", if healthy { "All required checks passed." } else { "Some required checks failed." });
+    healthy
+}
+
+/// Writes and removes an empty file, since permissions alone don't show a read-only mount.
+fn writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(".clipper_doctor");
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+fn tool_version(tool: &str) -> Option<String> {
+    let output = Command::new(tool).args(["-hide_banner", "-version"]).stderr(Stdio::null()).output().ok()?;
+    if !output.status.success() { return None; }
+    String::from_utf8_lossy(&output.stdout).lines().next().map(str::to_string)
+}
+
+/// Encodes a single test frame or a short tone, since hardware encoders are listed by ffmpeg builds
+/// even when the GPU or driver is not there.
+fn encoder_works(encoder: &str, audio: bool) -> bool {
+    let (source, codec_flag) = if audio { ("sine=duration=0.1", "-c:a") } else { ("color=size=256x256:duration=0.1", "-c:v") };
+    Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-f", "lavfi", "-i", source, codec_flag, encoder, "-f", "null", "-"])
+        .stdout(Stdio::null()).stderr(Stdio::null())
+        .status().map(|s| s.success()).unwrap_or(false)
+}
@@ -0,0 +1,120 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::messages::recorder::ClipInfo;
+use crate::recorder::{ffmpeg, types::{ExportOptions, Lane}};
+
+pub const THROUGHPUT_FILE: &str = "throughput.json";
+
+/// Used until a pass of that kind has been timed on this machine.
+const DEFAULT_COPY_SPEED: f64 = 40.0;
+const DEFAULT_ENCODE_SPEED: f64 = 1.0;
+const AUDIO_KBPS: f64 = 192.0;
+
+/// How many seconds of media ffmpeg gets through per second of wall time, for passes that copy
+/// the video and passes that encode it. Each new measurement is blended into the last.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Throughput {
+    pub copy: Option<f64>,
+    pub encode: Option<f64>
+}
+
+impl Throughput {
+    pub fn record(&mut self, encode: bool, speed: f64) {
+        let slot = if encode { &mut self.encode } else { &mut self.copy };
+        *slot = Some(match *slot {
+            Some(old) => old * 0.7 + speed * 0.3,
+            None => speed
+        });
+    }
+}
+
+pub struct Estimate {
+    pub secs: f64,
+    pub bytes: u64,
+    /// Whether every kind of pass the export needs has been timed before.
+    pub measured: bool
+}
+
+/// Predicts how long finalize will take and how big the result will be, following the same
+/// choice of passes the recorder makes. Stream-copied sizes come from the clip files themselves.
+pub fn estimate(clips: &[ClipInfo], options: &ExportOptions, throughput: &Throughput) -> Estimate {
+    let timeline: Vec<&ClipInfo> = clips.iter().filter(|c| !options.cutaways || c.lane == Lane::ARoll).collect();
+    let media_secs: f64 = timeline.iter().map(|c| c.trimmed_duration()).sum();
+    let source_bytes: f64 = timeline.iter().map(|c| {
+        let size = fs::metadata(&c.video_path).map(|m| m.len()).unwrap_or(0) as f64;
+        if c.duration > 0.0 { size * c.trimmed_duration() / c.duration } else { size }
+    }).sum();
+
+    // Encoding passes, weighted by how many outputs each one writes.
+    let encode_work = if options.ladder {
+        3.0
+    } else if options.dual || options.two_pass_kbps.is_some() {
+        2.0
+    } else if options.cutaways || options.reencode || options.vertical.is_some() || options.preset.is_some() || ffmpeg::has_transitions(clips) || clips.iter().any(ClipInfo::is_trimmed) {
+        1.0
+    } else {
+        0.0
+    };
+    // The stream-copy concat, then the room tone mix and the tag remux.
+    let copy_passes = [encode_work == 0.0, options.room_tone && !options.ladder, !options.ladder];
+    let copy_work = copy_passes.iter().filter(|p| **p).count() as f64;
+
+    let encode_speed = throughput.encode.unwrap_or(DEFAULT_ENCODE_SPEED);
+    let copy_speed = throughput.copy.unwrap_or(DEFAULT_COPY_SPEED);
+    let secs = media_secs * (encode_work / encode_speed + copy_work / copy_speed);
+    let measured = (encode_work == 0.0 || throughput.encode.is_some()) && throughput.copy.is_some();
+
+    let bytes = if options.ladder {
+        ffmpeg::ladder_kbps() as f64 * 1000.0 / 8.0 * media_secs
+    } else if let Some(kbps) = options.two_pass_kbps.filter(|_| !options.dual) {
+        (kbps as f64 + AUDIO_KBPS) * 1000.0 / 8.0 * media_secs
+    } else if let Some(target) = options.preset.and_then(|p| p.target_bytes()) {
+        target as f64
+    } else if options.dual {
+        // The vertical crop keeps about a third of the 16:9 picture.
+        source_bytes * (1.0 + 0.32)
+    } else {
+        source_bytes
+    };
+
+    Estimate { secs, bytes: bytes as u64, measured }
+}
+
+/// "45 s", "3 min 20 s" or "1 h 05 min".
+pub fn format_duration(secs: f64) -> String {
+    let whole = secs.max(1.0).round() as u64;
+    if whole < 60 {
+        format!("{} s", whole)
+    } else if whole < 3600 {
+        format!("{} min {:02} s", whole / 60, whole % 60)
+    } else {
+        format!("{} h {:02} min", whole / 3600, whole % 3600 / 60)
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    if mb >= 1024.0 {
+        format!("{:.1} GB", mb / 1024.0)
+    } else {
+        format!("{:.0} MB", mb.max(1.0))
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use eframe::egui::{Context, Key};
+use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, thread, time::Duration};
+
+/// How often the listener looks for presses and for being dropped.
+const POLL: Duration = Duration::from_millis(30);
+
+/// A key grabbed from the whole desktop, so it works while another app, like a game, has focus.
+/// Presses go to the grab and not to whichever window is focused. Released when dropped.
+pub struct GlobalHotkey {
+    key: Key,
+    presses: Receiver<()>,
+    stop: Arc<AtomicBool>
+}
+
+impl GlobalHotkey {
+    /// Grabs `key` with no modifiers and wakes `ctx` on each press. Fails when the platform has no
+    /// such thing (Wayland, macOS), the key isn't one that can be grabbed, or another app holds it.
+    pub fn register(key: Key, ctx: Context) -> Result<Self, String> {
+        let code = KeyCode::of(key).ok_or_else(|| format!("{} can't be a system-wide hotkey", key.name()))?;
+        let (tx, presses) = unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = crossbeam_channel::bounded(1);
+        let thread_stop = stop.clone();
+        thread::spawn(move || listen(code, Press { tx, ctx }, thread_stop, ready_tx));
+        ready_rx.recv().unwrap_or_else(|_| Err(String::from("The hotkey listener stopped")))?;
+        Ok(Self { key, presses, stop })
+    }
+
+    pub fn key(&self) -> Key {
+        self.key
+    }
+
+    /// Whether the key has been pressed since the last call.
+    pub fn pressed(&self) -> bool {
+        self.presses.try_iter().count() > 0
+    }
+}
+
+impl Drop for GlobalHotkey {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Press {
+    tx: Sender<()>,
+    ctx: Context
+}
+
+impl Press {
+    fn send(&self) {
+        let _ = self.tx.send(());
+        self.ctx.request_repaint();
+    }
+}
+
+/// The keys that can be grabbed: F1 to F24, letters, digits and space.
+#[derive(Clone, Copy)]
+enum KeyCode {
+    Function(u32),
+    Char(char),
+    Space
+}
+
+impl KeyCode {
+    fn of(key: Key) -> Option<Self> {
+        let name = key.name();
+        if name == "Space" {
+            return Some(KeyCode::Space);
+        }
+        if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+            return (1..=24).contains(&n).then_some(KeyCode::Function(n));
+        }
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphanumeric() => Some(KeyCode::Char(c.to_ascii_uppercase())),
+            _ => None
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn listen(code: KeyCode, press: Press, stop: Arc<AtomicBool>, ready: Sender<Result<(), String>>) {
+    use x11rb::{connection::Connection, protocol::{Event, xproto::{ConnectionExt, GrabMode, ModMask}}};
+
+    let keysym = match code {
+        KeyCode::Function(n) => 0xffbe + n - 1,
+        KeyCode::Char(c) => c.to_ascii_lowercase() as u32,
+        KeyCode::Space => 0x20
+    };
+    let grab = || -> Result<_, String> {
+        let (conn, screen) = x11rb::connect(None).map_err(|e| format!("No X11 display for a system-wide hotkey: {}", e))?;
+        let root = conn.setup().roots[screen].root;
+        let (min, max) = (conn.setup().min_keycode, conn.setup().max_keycode);
+        let mapping = conn.get_keyboard_mapping(min, max - min + 1).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?;
+        let per_code = mapping.keysyms_per_keycode.max(1) as usize;
+        let keycode = mapping.keysyms.chunks(per_code).position(|syms| syms.contains(&keysym))
+            .map(|i| min + i as u8).ok_or_else(|| String::from("The keyboard has no such key"))?;
+        // Caps Lock and Num Lock count as modifiers, so the key is grabbed with each of them too.
+        let masks = [ModMask::from(0u16), ModMask::LOCK, ModMask::M2, ModMask::LOCK | ModMask::M2];
+        for mask in masks {
+            conn.grab_key(true, root, mask, keycode, GrabMode::ASYNC, GrabMode::ASYNC).map_err(|e| e.to_string())?
+                .check().map_err(|_| String::from("Another app already uses that key system-wide"))?;
+        }
+        Ok((conn, root, keycode, masks))
+    };
+    let (conn, root, keycode, masks) = match grab() {
+        Ok(grabbed) => { let _ = ready.send(Ok(())); grabbed },
+        Err(e) => { let _ = ready.send(Err(e)); return; }
+    };
+    while !stop.load(Ordering::Relaxed) {
+        match conn.poll_for_event() {
+            Ok(Some(Event::KeyPress(event))) if event.detail == keycode => press.send(),
+            Ok(Some(_)) => {},
+            Ok(None) => thread::sleep(POLL),
+            Err(_) => return
+        }
+    }
+    for mask in masks {
+        let _ = conn.ungrab_key(keycode, root, mask);
+    }
+    let _ = conn.flush();
+}
+
+#[cfg(windows)]
+fn listen(code: KeyCode, press: Press, stop: Arc<AtomicBool>, ready: Sender<Result<(), String>>) {
+    use windows_sys::Win32::UI::{Input::KeyboardAndMouse::{MOD_NOREPEAT, RegisterHotKey, UnregisterHotKey}, WindowsAndMessaging::{MSG, PM_REMOVE, PeekMessageW, WM_HOTKEY}};
+
+    const ID: i32 = 1;
+    let vk = match code {
+        KeyCode::Function(n) => 0x70 + n - 1,
+        KeyCode::Char(c) => c as u32,
+        KeyCode::Space => 0x20
+    };
+    // The hotkey belongs to this thread, whose message queue its presses arrive in.
+    if unsafe { RegisterHotKey(std::ptr::null_mut(), ID, MOD_NOREPEAT, vk) } == 0 {
+        let _ = ready.send(Err(format!("Another app already uses that key system-wide: {}", std::io::Error::last_os_error())));
+        return;
+    }
+    let _ = ready.send(Ok(()));
+    let mut msg: MSG = unsafe { std::mem::zeroed() };
+    while !stop.load(Ordering::Relaxed) {
+        while unsafe { PeekMessageW(&mut msg, std::ptr::null_mut(), WM_HOTKEY, WM_HOTKEY, PM_REMOVE) } != 0 {
+            press.send();
+        }
+        thread::sleep(POLL);
+    }
+    unsafe { UnregisterHotKey(std::ptr::null_mut(), ID) };
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn listen(_code: KeyCode, _press: Press, _stop: Arc<AtomicBool>, ready: Sender<Result<(), String>>) {
+    let _ = ready.send(Err(String::from("System-wide hotkeys are not supported on this platform")));
+}
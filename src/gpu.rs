@@ -0,0 +1,96 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::process::Command;
+
+use crate::recorder::types::GpuVendor;
+
+/// A device a hardware encoder can be pointed at. `id` is what the encoder's device option takes:
+/// a CUDA index for NVENC, a render node or adapter index for QSV, an adapter index for AMF.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuDevice {
+    pub id: String,
+    pub name: String
+}
+
+/// Lists the devices the given vendor's encoder could run on. Empty when detection isn't
+/// possible here, in which case the encoder keeps its own default.
+pub fn detect(vendor: GpuVendor) -> Vec<GpuDevice> {
+    match vendor {
+        GpuVendor::Nvidia => nvidia_devices(),
+        GpuVendor::Intel | GpuVendor::Amd => platform_devices(vendor)
+    }
+}
+
+/// NVENC's `-gpu` counts CUDA devices, which `nvidia-smi` lists in PCI bus order. Setting
+/// `CUDA_DEVICE_ORDER=PCI_BUS_ID` makes the two agree on machines where they wouldn't.
+fn nvidia_devices() -> Vec<GpuDevice> {
+    let Ok(output) = Command::new("nvidia-smi").args(["--query-gpu=index,name", "--format=csv,noheader"]).output() else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout).lines().filter_map(|line| {
+        let (index, name) = line.split_once(',')?;
+        Some(GpuDevice { id: index.trim().to_string(), name: name.trim().to_string() })
+    }).collect()
+}
+
+#[cfg(target_os = "linux")]
+fn platform_devices(vendor: GpuVendor) -> Vec<GpuDevice> {
+    // QSV takes the DRM render node; AMF has no device option on Linux.
+    if vendor != GpuVendor::Intel {
+        return Vec::new();
+    }
+    let Ok(entries) = std::fs::read_dir("/sys/class/drm") else { return Vec::new(); };
+    let mut devices: Vec<GpuDevice> = entries.filter_map(Result::ok).filter_map(|entry| {
+        let node = entry.file_name().to_string_lossy().to_string();
+        if !node.starts_with("renderD") {
+            return None;
+        }
+        let id = std::fs::read_to_string(entry.path().join("device/vendor")).ok()?;
+        if id.trim() != "0x8086" {
+            return None;
+        }
+        let slot = std::fs::read_link(entry.path().join("device")).ok()
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+            .unwrap_or_default();
+        Some(GpuDevice { id: format!("/dev/dri/{}", node), name: format!("Intel {} ({})", node, slot) })
+    }).collect();
+    devices.sort_by(|a, b| a.id.cmp(&b.id));
+    devices
+}
+
+/// Adapters in the order Windows enumerates them, which is the index QSV and D3D11 expect.
+#[cfg(windows)]
+fn platform_devices(vendor: GpuVendor) -> Vec<GpuDevice> {
+    let Ok(output) = Command::new("powershell")
+        .args(["-NoProfile", "-Command", "(Get-CimInstance Win32_VideoController).Name"]).output() else {
+        return Vec::new();
+    };
+    let matches = |name: &str| match vendor {
+        GpuVendor::Intel => name.contains("Intel"),
+        GpuVendor::Amd => name.contains("AMD") || name.contains("Radeon"),
+        GpuVendor::Nvidia => name.contains("NVIDIA")
+    };
+    String::from_utf8_lossy(&output.stdout).lines().map(str::trim).filter(|l| !l.is_empty()).enumerate()
+        .filter(|(_, name)| matches(name))
+        .map(|(index, name)| GpuDevice { id: index.to_string(), name: name.to_string() })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn platform_devices(_vendor: GpuVendor) -> Vec<GpuDevice> {
+    Vec::new()
+}
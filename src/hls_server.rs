@@ -0,0 +1,210 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! A tiny HTTP server for watching the live HLS playlist from a browser on another machine. It
+//! only ever serves a page with a player and the files of the current session's live folder.
+
+use crate::crash::log_line;
+use std::{fs, io::{self, Read, Write}, net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket}, path::PathBuf, sync::{Arc, Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}}, thread, time::Duration};
+
+pub const HLS_PORT: u16 = 8086;
+/// Longest request head read before giving up on a client.
+const MAX_REQUEST: usize = 8 * 1024;
+/// Requests handled at once. A few browsers polling the playlist need far fewer; anyone else on
+/// the network opening more is turned away rather than given a thread each.
+const MAX_HANDLERS: usize = 16;
+/// How often the listener checks for new connections and for being dropped.
+const POLL: Duration = Duration::from_millis(50);
+
+/// Plays the playlist natively where the browser can (Safari, mobile Chrome) and elsewhere feeds
+/// its fMP4 segments to Media Source Extensions itself, so nothing is loaded from outside the LAN.
+const INDEX_HTML: &str = r#"<!doctype html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1"><title>Clipper live</title>
+<style>body{margin:0;background:#000;color:#aaa;font:14px sans-serif}video{width:100vw;height:100vh;object-fit:contain}p{position:fixed;top:8px;left:12px}</style></head>
+<body><p id="note">Waiting for a take… (picture only, no sound)</p><video id="v" controls autoplay muted playsinline></video>
+<script>
+const v = document.getElementById('v'), note = document.getElementById('note');
+// Picked by the config box in the init segment; browsers only hold the family to it.
+const CODECS = [['avcC', 'avc1.640028'], ['hvcC', 'hvc1.1.6.L120.90'], ['av1C', 'av01.0.08M.08']];
+const sleep = ms => new Promise(r => setTimeout(r, ms));
+async function get(name) {
+  const r = await fetch('live/' + name, { cache: 'no-store' });
+  if (!r.ok) throw new Error(name + ': ' + r.status);
+  return r;
+}
+function mime(init) {
+  const text = Array.from(init.subarray(0, 4096), b => String.fromCharCode(b)).join('');
+  const found = CODECS.find(([box]) => text.includes(box));
+  if (!found) throw new Error('unknown codec');
+  return 'video/mp4; codecs="' + found[1] + '"';
+}
+async function play() {
+  if (v.canPlayType('application/vnd.apple.mpegurl')) { v.src = 'live/stream.m3u8'; return; }
+  if (!window.MediaSource) { note.textContent = 'This browser cannot play the preview.'; return; }
+  const ms = new MediaSource();
+  v.src = URL.createObjectURL(ms);
+  await new Promise(r => ms.addEventListener('sourceopen', r, { once: true }));
+  let sb = null, last = null;
+  const append = data => new Promise((ok, fail) => {
+    sb.addEventListener('updateend', ok, { once: true });
+    sb.addEventListener('error', fail, { once: true });
+    sb.appendBuffer(data);
+  });
+  for (;;) {
+    try {
+      const segments = [];
+      let map = null, cut = false;
+      for (const line of (await (await get('stream.m3u8')).text()).split('\n').map(l => l.trim())) {
+        if (line.startsWith('#EXT-X-MAP:')) map = /URI="([^"]+)"/.exec(line)[1];
+        else if (line.startsWith('#EXT-X-DISCONTINUITY')) cut = true;
+        else if (line && !line.startsWith('#')) { segments.push({ name: line, map, cut }); cut = false; }
+      }
+      // Start near the live edge, then take every segment after the last one appended.
+      const from = last === null ? Math.max(0, segments.length - 2) : segments.findIndex(s => s.name === last) + 1;
+      for (const s of segments.slice(from)) {
+        // Each take starts with a new header, appended again before its first segment.
+        if (!sb || s.cut) {
+          const init = new Uint8Array(await (await get(s.map)).arrayBuffer());
+          if (!sb) { sb = ms.addSourceBuffer(mime(init)); sb.mode = 'sequence'; }
+          await append(init);
+        }
+        await append(await (await get(s.name)).arrayBuffer());
+        last = s.name;
+      }
+      if (sb && sb.buffered.length) {
+        const end = sb.buffered.end(sb.buffered.length - 1);
+        if (end - v.currentTime > 6) v.currentTime = end - 1;
+      }
+    } catch (e) {
+      if (ms.readyState !== 'open') { setTimeout(() => location.reload(), 3000); return; }
+    }
+    await sleep(1000);
+  }
+}
+v.addEventListener('playing', () => note.textContent = '');
+play();
+</script></body></html>
+"#;
+
+/// Serves on every interface so other machines on the network can connect. The port is closed
+/// again once this is dropped; pointing it at no folder turns the playlist off meanwhile.
+pub struct HlsServer {
+    root: Arc<Mutex<Option<PathBuf>>>,
+    stop: Arc<AtomicBool>
+}
+
+impl HlsServer {
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, HLS_PORT))?;
+        // Polled, so the thread notices it's been dropped without waiting for a connection.
+        listener.set_nonblocking(true)?;
+        let root: Arc<Mutex<Option<PathBuf>>> = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (serving, stopped) = (root.clone(), stop.clone());
+        thread::spawn(move || {
+            let handlers = Arc::new(AtomicUsize::new(0));
+            while !stopped.load(Ordering::Relaxed) {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => { thread::sleep(POLL); continue; },
+                    Err(_) => continue
+                };
+                if handlers.fetch_add(1, Ordering::Relaxed) >= MAX_HANDLERS {
+                    handlers.fetch_sub(1, Ordering::Relaxed);
+                    continue;
+                }
+                let root = serving.lock().ok().and_then(|r| r.clone());
+                let handlers = handlers.clone();
+                thread::spawn(move || {
+                    if let Err(e) = stream.set_nonblocking(false).and_then(|_| handle(stream, root)) {
+                        log_line!("HLS preview request failed: {}", e);
+                    }
+                    handlers.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+            log_line!("Stopped serving the live preview");
+        });
+        log_line!("Serving the live preview on port {}", HLS_PORT);
+        Ok(Self { root, stop })
+    }
+
+    /// The folder the playlist and segments are read from, such as the session's live folder.
+    pub fn set_root(&self, root: Option<PathBuf>) {
+        if let Ok(mut guard) = self.root.lock() {
+            *guard = root;
+        }
+    }
+}
+
+impl Drop for HlsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn handle(mut stream: TcpStream, root: Option<PathBuf>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST {
+        let n = stream.read(&mut buf)?;
+        if n == 0 { break; }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request = head.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (request.next().unwrap_or_default(), request.next().unwrap_or_default());
+    if method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"GET only");
+    }
+    let path = path.split('?').next().unwrap_or_default();
+    if path == "/" {
+        return respond(&mut stream, "200 OK", "text/html; charset=utf-8", INDEX_HTML.as_bytes());
+    }
+    // Only plain file names straight inside the live folder, so nothing else on disk is reachable.
+    let file = path.strip_prefix("/live/").filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c)) && !name.starts_with('.'));
+    let content_type = file.and_then(|name| name.rsplit_once('.')).and_then(|(_, ext)| match ext {
+        "m3u8" => Some("application/vnd.apple.mpegurl"),
+        "ts" => Some("video/mp2t"),
+        "m4s" | "mp4" => Some("video/mp4"),
+        _ => None
+    });
+    match (root, file, content_type) {
+        (Some(root), Some(name), Some(content_type)) => match fs::read(root.join(name)) {
+            Ok(body) => respond(&mut stream, "200 OK", content_type, &body),
+            Err(_) => respond(&mut stream, "404 Not Found", "text/plain", b"Not recording")
+        },
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"Not found")
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    let head = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n", status, content_type, body.len());
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)
+}
+
+/// This machine's address on the network it reaches the internet through, for showing the
+/// preview's URL. Connecting a UDP socket sends nothing.
+pub fn lan_address() -> Option<SocketAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    let mut addr = socket.local_addr().ok()?;
+    addr.set_port(HLS_PORT);
+    Some(addr)
+}
@@ -0,0 +1,246 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+//! Single-instance detection. The first Clipper to start writes a lock file, readable only by
+//! this user, naming its localhost port and a random token, and streams its preview and recording
+//! state to any later instance that presents the token. That instance opens as a read-only monitor
+//! instead of fighting over the camera and the workspace's file names.
+
+use crate::{crash::log_line, storage};
+use crossbeam_channel::{Receiver, Sender, bounded, unbounded};
+use eframe::egui::ColorImage;
+use image::{ExtendedColorType, ImageFormat, codecs::jpeg::JpegEncoder};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::{self, Read, Write}, net::{Ipv4Addr, TcpListener, TcpStream}, sync::{Arc, Mutex}, thread, time::{Duration, Instant}};
+
+/// Lock file in the data dir holding the running instance's port and token.
+const INSTANCE_FILE: &str = "instance.json";
+/// Hex characters in the token a monitor sends first.
+const TOKEN_LEN: usize = 32;
+/// Sent back once the token checks out, so a monitor can tell Clipper apart from whatever else
+/// might have taken the port since.
+const HANDSHAKE: &[u8; 8] = b"CLIPMON1";
+/// How long either end waits on the other during the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Largest packet a monitor accepts; a 4K preview frame as JPEG is well under this.
+const MAX_PACKET_BYTES: usize = 32 * 1024 * 1024;
+/// Monitors get at most this many preview frames per second.
+const MONITOR_FPS: u32 = 10;
+const JPEG_QUALITY: u8 = 70;
+/// Status is resent this often even when unchanged, so a monitor that just connected catches up.
+const STATUS_EVERY: Duration = Duration::from_secs(1);
+
+const PACKET_STATUS: u8 = 0;
+const PACKET_FRAME: u8 = 1;
+
+/// What a monitor shows next to the preview.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MonitorStatus {
+    pub recording: bool,
+    /// Number of the take being recorded, or of the next one.
+    pub take: usize,
+    pub elapsed_secs: u64,
+    pub shot: String,
+    pub note: Option<String>
+}
+
+pub enum MonitorMessage {
+    Status(MonitorStatus),
+    Frame(ColorImage),
+    /// The recording instance closed or the connection dropped.
+    Closed
+}
+
+/// Where the running instance can be reached.
+#[derive(Serialize, Deserialize)]
+struct InstanceLock {
+    port: u16,
+    token: String
+}
+
+/// Takes the single-instance lock. `None` means another Clipper answered on the port in the lock
+/// file; a lock left by one that crashed is taken over.
+pub fn claim() -> Option<MonitorServer> {
+    if let Some(lock) = read_lock() && open_session(&lock).is_ok() {
+        log_line!("Another instance answers on port {}", lock.port);
+        return None;
+    }
+    let token = new_token();
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, 0)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log_line!("Couldn't open a port for monitors: {}", e);
+            return Some(MonitorServer::start(None, token));
+        }
+    };
+    if let Ok(addr) = listener.local_addr()
+        && let Err(e) = write_lock(&InstanceLock { port: addr.port(), token: token.clone() }) {
+        log_line!("Couldn't write the instance lock: {}", e);
+    }
+    Some(MonitorServer::start(Some(listener), token))
+}
+
+/// 128 random bits from the OS, as hex.
+fn new_token() -> String {
+    let mut bytes = [0u8; TOKEN_LEN / 2];
+    getrandom::fill(&mut bytes).expect("the OS has no random source");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn read_lock() -> Option<InstanceLock> {
+    let data = fs::read_to_string(storage::data_dir().join(INSTANCE_FILE)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+/// Written readable by this user only, since the token is all that guards the camera feed.
+fn write_lock(lock: &InstanceLock) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(storage::data_dir().join(INSTANCE_FILE))?.write_all(&serde_json::to_vec(lock)?)
+}
+
+/// Connects to the instance in `lock` and presents its token, returning the stream once it
+/// answers with the handshake.
+fn open_session(lock: &InstanceLock) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect_timeout(&(Ipv4Addr::LOCALHOST, lock.port).into(), HANDSHAKE_TIMEOUT)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    stream.write_all(lock.token.as_bytes())?;
+    let mut handshake = [0u8; 8];
+    stream.read_exact(&mut handshake)?;
+    if &handshake != HANDSHAKE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("port {} is used by something other than Clipper", lock.port)));
+    }
+    stream.set_read_timeout(None)?;
+    Ok(stream)
+}
+
+enum Packet {
+    Status(Vec<u8>),
+    Frame(u32, u32, Vec<u8>)
+}
+
+/// Checks a new connection's token and adds it to the monitors once it answers with the handshake.
+fn admit(mut stream: TcpStream, token: &str, clients: &Mutex<Vec<TcpStream>>) {
+    let _ = stream.set_nodelay(true);
+    let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+    // A monitor that stops reading must not stall the sender for everyone else.
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+    let mut presented = [0u8; TOKEN_LEN];
+    if stream.read_exact(&mut presented).is_err() || presented != token.as_bytes() {
+        log_line!("Refused a monitor connection without the instance token");
+        return;
+    }
+    if stream.write_all(HANDSHAKE).is_ok() && let Ok(mut clients) = clients.lock() {
+        log_line!("Monitor connected from {:?}", stream.peer_addr().ok());
+        clients.push(stream);
+    }
+}
+
+/// The recording instance's end: accepts monitors and fans preview frames and status out to them.
+pub struct MonitorServer {
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    packets: Sender<Packet>,
+    last_frame: Instant,
+    last_status: Option<(MonitorStatus, Instant)>
+}
+
+impl MonitorServer {
+    fn start(listener: Option<TcpListener>, token: String) -> Self {
+        let clients: Arc<Mutex<Vec<TcpStream>>> = Arc::new(Mutex::new(Vec::new()));
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.iter().flat_map(TcpListener::incoming) {
+                let Ok(stream) = stream else { continue };
+                // Each handshake waits on its own thread, so a silent connection can't hold up the next.
+                let (token, accepted) = (token.clone(), accepted.clone());
+                thread::spawn(move || admit(stream, &token, &accepted));
+            }
+        });
+
+        // Small queue: a frame that can't be sent right away is stale by the time it could be.
+        let (packets, packet_rx) = bounded::<Packet>(2);
+        let sending = clients.clone();
+        thread::spawn(move || {
+            for packet in packet_rx {
+                let (kind, payload) = match packet {
+                    Packet::Status(json) => (PACKET_STATUS, json),
+                    Packet::Frame(w, h, rgb) => {
+                        let mut jpeg = Vec::new();
+                        if JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode(&rgb, w, h, ExtendedColorType::Rgb8).is_err() { continue; }
+                        (PACKET_FRAME, jpeg)
+                    }
+                };
+                let mut header = vec![kind];
+                header.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+                if let Ok(mut clients) = sending.lock() {
+                    clients.retain_mut(|c| c.write_all(&header).and_then(|_| c.write_all(&payload)).is_ok());
+                }
+            }
+        });
+
+        Self { clients, packets, last_frame: Instant::now(), last_status: None }
+    }
+
+    fn has_clients(&self) -> bool {
+        self.clients.lock().is_ok_and(|c| !c.is_empty())
+    }
+
+    /// Passes a preview frame on to monitors, throttled to `MONITOR_FPS`.
+    pub fn offer_frame(&mut self, width: u32, height: u32, rgb: &[u8]) {
+        if self.last_frame.elapsed() < Duration::from_secs(1) / MONITOR_FPS || !self.has_clients() { return; }
+        self.last_frame = Instant::now();
+        let _ = self.packets.try_send(Packet::Frame(width, height, rgb.to_vec()));
+    }
+
+    /// Sends the status when it changed, or as a keep-alive every `STATUS_EVERY`.
+    pub fn offer_status(&mut self, status: MonitorStatus) {
+        if self.last_status.as_ref().is_some_and(|(last, at)| *last == status && at.elapsed() < STATUS_EVERY) || !self.has_clients() { return; }
+        if let Ok(json) = serde_json::to_vec(&status) {
+            let _ = self.packets.try_send(Packet::Status(json));
+        }
+        self.last_status = Some((status, Instant::now()));
+    }
+}
+
+/// Connects to the recording instance. Frames are decoded on the reader thread.
+pub fn connect() -> io::Result<Receiver<MonitorMessage>> {
+    let lock = read_lock().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no recording instance is running"))?;
+    let mut stream = open_session(&lock)?;
+
+    let (tx, rx) = unbounded();
+    thread::spawn(move || {
+        let mut header = [0u8; 5];
+        while stream.read_exact(&mut header).is_ok() {
+            let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+            if len > MAX_PACKET_BYTES { break; }
+            let mut payload = vec![0u8; len];
+            if stream.read_exact(&mut payload).is_err() { break; }
+            let message = match header[0] {
+                PACKET_STATUS => serde_json::from_slice(&payload).ok().map(MonitorMessage::Status),
+                PACKET_FRAME => image::load_from_memory_with_format(&payload, ImageFormat::Jpeg).ok().map(|img| {
+                    let rgb = img.to_rgb8();
+                    MonitorMessage::Frame(ColorImage::from_rgb([rgb.width() as usize, rgb.height() as usize], rgb.as_raw()))
+                }),
+                _ => None
+            };
+            if let Some(message) = message && tx.send(message).is_err() { return; }
+        }
+        let _ = tx.send(MonitorMessage::Closed);
+    });
+    Ok(rx)
+}
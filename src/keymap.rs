@@ -0,0 +1,116 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use eframe::egui::{Context, Key};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    Record,
+    Undo,
+    Export,
+    SaveReplay,
+    RevealLastExport,
+    Help
+}
+
+impl Action {
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Record => "Hold to record a take",
+            Action::Undo => "Delete the last take",
+            Action::Export => "Export the timeline",
+            Action::SaveReplay => "Save the replay buffer as a clip",
+            Action::RevealLastExport => "Show the last export in its folder",
+            Action::Help => "Show or hide this help"
+        }
+    }
+}
+
+/// Every hotkey the app reacts to. Input handling and the help overlay both read from here.
+pub struct KeyMap {
+    bindings: Vec<(Action, Key)>
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (Action::Record, Key::Space),
+                (Action::Undo, Key::Backspace),
+                (Action::Export, Key::Enter),
+                (Action::SaveReplay, Key::F9),
+                (Action::RevealLastExport, Key::F10),
+                (Action::Help, Key::Questionmark)
+            ]
+        }
+    }
+}
+
+impl KeyMap {
+    /// The defaults with `overrides`, keyed by egui's key names, laid over them. Names egui doesn't
+    /// know are ignored.
+    pub fn with_overrides(overrides: &BTreeMap<Action, String>) -> Self {
+        let mut keymap = Self::default();
+        for (action, name) in overrides {
+            if let Some(key) = Key::from_name(name) {
+                keymap.rebind(*action, key);
+            }
+        }
+        keymap
+    }
+
+    /// Bindings that differ from the defaults, for saving in the settings.
+    pub fn overrides(&self) -> BTreeMap<Action, String> {
+        let defaults = Self::default();
+        self.bindings.iter().filter(|(action, key)| defaults.key(*action) != *key).map(|(action, key)| (*action, key.name().to_string())).collect()
+    }
+
+    /// Moves `action` to `key`. An action already on `key` takes the one `action` leaves, so no two
+    /// ever share a key.
+    pub fn rebind(&mut self, action: Action, key: Key) {
+        let old = self.key(action);
+        for (a, k) in &mut self.bindings {
+            if *a == action {
+                *k = key;
+            } else if *k == key {
+                *k = old;
+            }
+        }
+    }
+
+    pub fn bindings(&self) -> &[(Action, Key)] {
+        &self.bindings
+    }
+
+    pub fn key(&self, action: Action) -> Key {
+        self.bindings.iter().find(|(a, _)| *a == action).map(|(_, k)| *k).unwrap_or(Key::Escape)
+    }
+
+    pub fn key_name(&self, action: Action) -> &'static str {
+        self.key(action).name()
+    }
+
+    /// Hotkeys are ignored while a text field has focus, so typing a project name can't start a take.
+    pub fn pressed(&self, ctx: &Context, action: Action) -> bool {
+        !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(self.key(action)))
+    }
+
+    pub fn released(&self, ctx: &Context, action: Action) -> bool {
+        ctx.input(|i| i.key_released(self.key(action)))
+    }
+}
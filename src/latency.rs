@@ -0,0 +1,120 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! Glass-to-glass latency test: the window flashes white in front of the camera, and the time
+//! until a preview frame shows the flash is how far the picture runs behind reality.
+
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 5;
+/// Dark time between flashes. The second half of it sets the brightness a flash is judged against.
+const SETTLE: Duration = Duration::from_millis(800);
+/// A flash the camera hasn't seen by now counts as missed.
+const FLASH_TIMEOUT: Duration = Duration::from_millis(1500);
+/// How much brighter than the dark baseline (mean luma, 0-255) a frame has to be to have seen the flash.
+const BRIGHTER_BY: f32 = 25.0;
+
+enum Phase {
+    /// Dark until this time.
+    Dark(Instant),
+    /// White since this time.
+    Flash(Instant),
+    Done
+}
+
+pub struct LatencyTest {
+    phase: Phase,
+    baseline: Option<f32>,
+    samples: Vec<Duration>,
+    misses: usize
+}
+
+/// The median time for the flash to reach the preview, and the fastest and slowest round.
+pub struct LatencyResult {
+    pub median: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub misses: usize
+}
+
+impl LatencyTest {
+    pub fn start() -> Self {
+        Self { phase: Phase::Dark(Instant::now() + SETTLE), baseline: None, samples: Vec::new(), misses: 0 }
+    }
+
+    /// Whether the window should be painted white.
+    pub fn flashing(&self) -> bool {
+        matches!(self.phase, Phase::Flash(_))
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.phase, Phase::Done)
+    }
+
+    /// Rounds finished so far, out of how many.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.samples.len() + self.misses, ROUNDS)
+    }
+
+    /// Moves on to the next flash once the dark spell is over, or gives up on one nobody saw.
+    /// Call before painting, so a flash is timed from the frame it's first drawn in.
+    pub fn tick(&mut self, now: Instant) {
+        match self.phase {
+            Phase::Dark(until) if now >= until && self.baseline.is_some() => self.phase = Phase::Flash(now),
+            Phase::Flash(shown) if now - shown > FLASH_TIMEOUT => {
+                self.misses += 1;
+                self.next_round(now);
+            },
+            _ => {}
+        }
+    }
+
+    /// Looks at a preview frame (RGB) that arrived at `now`.
+    pub fn offer_frame(&mut self, rgb: &[u8], now: Instant) {
+        let luma = mean_luma(rgb);
+        match self.phase {
+            // Frames early in the dark spell may still show the last flash.
+            Phase::Dark(until) if until.saturating_duration_since(now) < SETTLE / 2 => self.baseline = Some(luma),
+            Phase::Flash(shown) if self.baseline.is_some_and(|b| luma > b + BRIGHTER_BY) => {
+                self.samples.push(now - shown);
+                self.next_round(now);
+            },
+            _ => {}
+        }
+    }
+
+    fn next_round(&mut self, now: Instant) {
+        self.phase = if self.samples.len() + self.misses >= ROUNDS { Phase::Done } else { Phase::Dark(now + SETTLE) };
+    }
+
+    /// `None` until the test is over, or if the camera never saw a flash.
+    pub fn result(&self) -> Option<LatencyResult> {
+        if !self.is_done() || self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort();
+        Some(LatencyResult { median: sorted[sorted.len() / 2], min: sorted[0], max: sorted[sorted.len() - 1], misses: self.misses })
+    }
+}
+
+/// Rec. 601 luma averaged over every 16th pixel, plenty for a full-frame flash.
+fn mean_luma(rgb: &[u8]) -> f32 {
+    let (sum, count) = rgb.chunks_exact(3).step_by(16)
+        .fold((0.0, 0u32), |(sum, count), p| (sum + 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32, count + 1));
+    if count == 0 { 0.0 } else { sum / count as f32 }
+}
@@ -18,10 +18,46 @@ mod camera;
 mod recorder;
 mod app;
 mod audio;
+mod storage;
+mod tuning;
+mod budget;
+mod doctor;
+mod crash;
+mod keymap;
+mod global_hotkey;
+mod settings;
+mod review;
+mod estimate;
+mod gpu;
+mod hls_server;
+mod instance;
+mod monitor;
+mod recovery;
+mod latency;
+mod merge;
+mod status_feed;
 
 use crossbeam_channel::unbounded;
 use eframe::NativeOptions;
 fn main() -> eframe::Result<()> {
+    if std::env::args().nth(1).as_deref() == Some("doctor") {
+        std::process::exit(if doctor::run() { 0 } else { 1 });
+    }
+    if std::env::args().nth(1).as_deref() == Some("merge") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        std::process::exit(if merge::run(&args) { 0 } else { 1 });
+    }
+    crash::install_hook();
+
+    let monitor_only = std::env::args().nth(1).as_deref() == Some("monitor");
+    let Some(monitor_server) = (!monitor_only).then(instance::claim).flatten() else {
+        let options = NativeOptions {
+            viewport: eframe::egui::ViewportBuilder::default().with_inner_size([640.0, 480.0]),
+            ..Default::default()
+        };
+        return eframe::run_native("Clipper - Monitor", options, Box::new(move |_cc| Ok(Box::new(monitor::MonitorApp::new(!monitor_only)))));
+    };
+
     let (cam_tx, cam_rx) = unbounded();
     let (cam_command_tx, cam_command_rx) = unbounded();
     let (rec_command_tx, rec_command_rx) = unbounded();
@@ -29,8 +65,12 @@ fn main() -> eframe::Result<()> {
     let (rec_status_tx, rec_status_rx) = unbounded();
     let (audio_message_tx, audio_message_rx) = unbounded();
 
-    camera::start_thread(cam_tx, rec_command_tx.clone(),  cam_command_rx);
-    recorder::start_thread(rec_command_rx, rec_status_tx, audio_command_tx);
+    let frame_budget = budget::FrameBudget::new(budget::DEFAULT_CAP_MB);
+
+    let frame_queue = budget::FrameQueue::new(budget::FRAME_QUEUE_LEN, frame_budget.clone());
+
+    camera::start_thread(cam_tx, frame_queue.clone(), cam_command_rx, frame_budget.clone());
+    recorder::start_thread(rec_command_rx, frame_queue.clone(), rec_status_tx, audio_command_tx, frame_budget.clone());
     audio::start_thread(audio_message_tx, audio_command_rx);
 
     let options = NativeOptions {
@@ -38,7 +78,10 @@ fn main() -> eframe::Result<()> {
         ..Default::default()
     };
 
-    eframe::run_native("Clipper", options, Box::new(|cc| {
-        Ok(Box::new(app::ClipperApp::new(cc, cam_rx, cam_command_tx, rec_command_tx, rec_status_rx, audio_message_rx)))
-    }))
+    let result = eframe::run_native("Clipper", options, Box::new(|cc| {
+        Ok(Box::new(app::ClipperApp::new(cc, cam_rx, cam_command_tx, rec_command_tx, rec_status_rx, audio_message_rx, frame_queue).with_monitor(monitor_server).with_status_feed(status_feed::StatusTarget::from_args())))
+    }));
+    storage::remove_session_temp();
+    recovery::mark_clean_exit();
+    result
 }
@@ -0,0 +1,125 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! `clipper merge <session-dir> -o out.mp4`: exports a session folder's timeline without the GUI.
+//! The timeline the app kept in the folder, with its order, trims, lanes and exclusions, goes to
+//! the same recorder thread the app exports through, so it gets the same mismatch checks,
+//! re-encodes and length validation.
+
+use crate::{budget::{self, FrameBudget, FrameQueue}, messages::recorder::{RecorderCommand, RecorderStatus}, recorder::{self, types::{ExportOptions, RecorderConfig}}, recovery, storage};
+use crossbeam_channel::unbounded;
+use std::{fs, io::Write, path::{Path, PathBuf}};
+
+const USAGE: &str = "usage: clipper merge <session-dir> -o <out.mp4> [--reencode]";
+
+/// Runs the merge with the arguments after `merge`. Returns false when nothing was written.
+pub fn run(args: &[String]) -> bool {
+    let mut session = None;
+    let mut output = None;
+    let mut options = ExportOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = args.next().cloned(),
+            "--reencode" => options.reencode = true,
+            _ if session.is_none() => session = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("unexpected argument {}\n{}", arg, USAGE);
+                return false;
+            }
+        }
+    }
+    let (Some(session), Some(output)) = (session, output) else {
+        eprintln!("{}", USAGE);
+        return false;
+    };
+
+    let clips: Vec<_> = match recovery::load_timeline(&session) {
+        Some(timeline) => timeline.into_iter().filter(|c| !c.excluded).collect(),
+        None => {
+            // Sessions from before the timeline was kept only have their files to go by.
+            eprintln!("No timeline in {}, taking its clips in recording order", session.display());
+            recovery::session_clips(&session).into_iter().map(|path| recorder::plain_clip_info(&path)).collect()
+        }
+    };
+    if clips.is_empty() {
+        eprintln!("No clips in {}", session.display());
+        return false;
+    }
+    if let Some(missing) = clips.iter().find(|c| !c.video_path.exists()) {
+        eprintln!("{} is on the timeline but no longer exists", missing.video_path.display());
+        return false;
+    }
+    let target = absolute(Path::new(&output));
+    if clips.iter().any(|c| fs::canonicalize(&c.video_path).ok() == target) {
+        eprintln!("{} is one of the clips being merged, pick another output", output);
+        return false;
+    }
+    // Keeps a Clipper started meanwhile from clearing out this run's scratch folder.
+    storage::hold_session_temp();
+    println!("Merging {} clips from {}", clips.len(), session.display());
+
+    let (cmd_tx, cmd_rx) = unbounded();
+    let (status_tx, status_rx) = unbounded();
+    let (audio_tx, _audio_rx) = unbounded();
+    let frame_budget = FrameBudget::new(budget::DEFAULT_CAP_MB);
+    let frame_queue = FrameQueue::new(budget::FRAME_QUEUE_LEN, frame_budget.clone());
+    recorder::start_thread(cmd_rx, frame_queue, status_tx, audio_tx, frame_budget);
+    let _ = cmd_tx.send(RecorderCommand::UpdateConfig(RecorderConfig { workspace: session, ..RecorderConfig::default() }));
+    let _ = cmd_tx.send(RecorderCommand::FinalizeVideo(clips.clone(), output.clone(), options.clone()));
+
+    let merged = loop {
+        let Ok(status) = status_rx.recv() else { break false };
+        match status {
+            RecorderStatus::Progress { stage, percent } => {
+                eprint!("\r{} {:.0}%", stage, percent);
+                let _ = std::io::stderr().flush();
+            },
+            RecorderStatus::ConcatReencoded(note) => eprintln!("\r{}", note),
+            RecorderStatus::VideoFinalized(path) => {
+                println!("\rSaved {}", path.display());
+                break true;
+            },
+            // The app asks before re-encoding; here it's tried once right away.
+            RecorderStatus::DurationMismatch(path, expected, actual) if !options.reencode => {
+                eprintln!("\r{} came out {:.1}s long instead of {:.1}s, re-encoding", path.display(), actual, expected);
+                options.reencode = true;
+                let _ = cmd_tx.send(RecorderCommand::FinalizeVideo(clips.clone(), output.clone(), options.clone()));
+            },
+            RecorderStatus::DurationMismatch(path, expected, actual) => {
+                eprintln!("\r{} came out {:.1}s long instead of {:.1}s even re-encoded, it's kept for a look", path.display(), actual, expected);
+                break false;
+            },
+            RecorderStatus::ExportFailed(e) | RecorderStatus::Error(e) => {
+                eprintln!("\r{}", e);
+                break false;
+            },
+            _ => {}
+        }
+    };
+    storage::remove_session_temp();
+    merged
+}
+
+/// Where `path` points once resolved, for a file that may not exist yet.
+fn absolute(path: &Path) -> Option<PathBuf> {
+    if let Ok(path) = fs::canonicalize(path) {
+        return Some(path);
+    }
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    Some(fs::canonicalize(dir).ok()?.join(path.file_name()?))
+}
@@ -1,19 +1,62 @@
 
+use crate::recorder::types::AudioEncoding;
 use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AudioDevice {
     pub name: String,
-    pub index: usize
+    pub index: usize,
+    pub channels: u16
+}
+
+/// Another application's audio output stream that can be recorded on its own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioApp {
+    pub name: String,
+    pub target: String
+}
+
+/// How fast the microphone really delivered samples over a recording, timed against the wall clock.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioClock {
+    pub nominal: u32,
+    pub measured: f64
+}
+
+impl AudioClock {
+    /// Parts per million the device ran fast (positive) or slow against its nominal rate.
+    pub fn drift_ppm(&self) -> f64 {
+        (self.measured / self.nominal as f64 - 1.0) * 1e6
+    }
+}
+
+/// Where a live stream's encoder reads the microphone from: raw little-endian f32 samples served
+/// on a local port.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LiveAudio {
+    pub port: u16,
+    pub sample_rate: u32,
+    pub channels: u16
 }
 
 pub enum AudioMessage {
     DeviceList(Vec<AudioDevice>),
+    AppList(Vec<AudioApp>),
     Error(String)
 }
 
 pub enum AudioCommand {
     SelectDevice(usize),
-    StartRecording(String),
-    StopRecording(Sender<()>)
+    SelectApp(Option<AudioApp>),
+    RefreshApps,
+    StartRecording(String, AudioEncoding),
+    /// Records the microphone even while an app is selected, for voiceovers.
+    StartMicRecording(String, AudioEncoding),
+    /// Also serve the microphone to a live stream, independently of takes. Answered with where to
+    /// read it, or `None` when there's no microphone to give.
+    StartLiveAudio(Sender<Option<LiveAudio>>),
+    StopLiveAudio,
+    /// Acknowledged once the file is finalized, with the measured clock when the take was long enough to tell.
+    StopRecording(Sender<Option<AudioClock>>)
 }
\ No newline at end of file
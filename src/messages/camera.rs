@@ -1,4 +1,4 @@
-use crate::messages::video::VideoConfig;
+use crate::{messages::video::VideoConfig, tuning::ThreadTuning};
 use std::sync::Arc;
 pub enum CameraMessage {
     Capabilities(Vec<VideoConfig>),
@@ -9,10 +9,23 @@ pub enum CameraMessage {
         p_height: u32
     },
     StreamStarted(u32, u32, u32),
+    /// Another app has the camera. It's tried again every few seconds and streams as soon as it's free.
+    Busy,
     Error(String)
 }
 
 pub enum CameraCommand {
     StartStream(VideoConfig),
+    SetPreview(bool),
+    /// Decode a preview from only every nth captured frame, sparing the CPU on slow machines.
+    SetPreviewEvery(u32),
+    SetTuning(ThreadTuning),
+    /// Enumerate the camera's formats again instead of using the cached list.
+    RefreshFormats,
+    /// Freezes automatic white balance and exposure where they've settled, or hands them back
+    /// to the camera.
+    LockExposure(bool),
+    /// Streams this format to the settings screen's preview, without recording, until the next command.
+    PreviewFormat(VideoConfig),
     Retry
 }
\ No newline at end of file
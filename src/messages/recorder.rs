@@ -1,29 +1,189 @@
 use eframe::epaint::tessellator::path;
 
-use crate::recorder::types::{EncoderPreset, EncodingQuality, EncodingSpeed};
-use std::{path::PathBuf, sync::Arc, time::Instant};
+use crate::messages::audio::AudioApp;
+use crate::recorder::types::{AnimationOptions, EncoderPreset, ExportOptions, Lane, LowerThird, RecorderConfig, Transition, VisualizerStyle};
+use crate::tuning::ThreadTuning;
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf, sync::Arc, time::Instant};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ClipInfo {
     pub video_path: PathBuf,
     pub thumb_path: PathBuf,
     pub preview_path: PathBuf,
-    pub duration: f64
+    #[serde(default)]
+    pub waveform_path: PathBuf,
+    pub duration: f64,
+    #[serde(default)]
+    pub lane: Lane,
+    /// Seconds of black picture found when the clip was saved.
+    #[serde(default)]
+    pub black_secs: f64,
+    /// Seconds of frozen picture found when the clip was saved.
+    #[serde(default)]
+    pub frozen_secs: f64,
+    /// Transition into the next clip on export; `None` is a hard cut.
+    #[serde(default)]
+    pub transition: Option<Transition>,
+    /// Export starts this many seconds into the clip.
+    #[serde(default)]
+    pub trim_in: f64,
+    /// Export stops at this point in the clip instead of its end.
+    #[serde(default)]
+    pub trim_out: Option<f64>,
+    /// Name shown on the timeline and used as the clip's chapter title on export.
+    #[serde(default)]
+    pub label: String,
+    /// Where the thumbnail was taken from when picked by hand; `None` is the first frame.
+    #[serde(default)]
+    pub thumb_secs: Option<f64>,
+    /// Captions drawn over the clip when the export re-encodes.
+    #[serde(default)]
+    pub lower_thirds: Vec<LowerThird>,
+    /// Kept on the timeline for reference but left out of exports.
+    #[serde(default)]
+    pub excluded: bool,
+    /// Low-res copy the thumbnail, preview and defect scan are made from. Exports always use
+    /// `video_path`.
+    #[serde(default)]
+    pub proxy_path: Option<PathBuf>,
+    /// What the check after saving found wrong with the file; `None` if it passed or wasn't checked.
+    #[serde(default)]
+    pub damaged: Option<String>,
+    /// Guestbook question burned into the start of the take as it was saved.
+    #[serde(default)]
+    pub burned_caption: Option<String>,
+    /// The date and time are burned into every frame.
+    #[serde(default)]
+    pub burned_timestamp: bool
+}
+
+impl ClipInfo {
+    pub fn is_trimmed(&self) -> bool {
+        self.trim_in > 0.0 || self.trim_out.is_some()
+    }
+
+    /// Length of the clip once its in and out points are applied.
+    pub fn trimmed_duration(&self) -> f64 {
+        (self.trim_out.unwrap_or(self.duration).min(self.duration) - self.trim_in).max(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProgressStage {
+    Merge,
+    /// Re-encoding takes in another format, or without audio, so the timeline joins up.
+    Conform,
+    /// Following the subject for a vertical export, before its encode.
+    Reframe,
+    Export
+}
+
+impl fmt::Display for ProgressStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgressStage::Merge => write!(f, "Saving clip"),
+            ProgressStage::Conform => write!(f, "Conforming takes"),
+            ProgressStage::Reframe => write!(f, "Finding the subject"),
+            ProgressStage::Export => write!(f, "Exporting")
+        }
+    }
 }
 
 pub enum RecorderCommand {
     StartSegment,
+    /// A camera frame, taken off the frame queue by the recorder thread itself.
     WriteFrame(Arc<Vec<u8>>, Instant),
-    EndSegment,
+    /// Ends the take at the instant the stop was asked for. Frames captured before it are still
+    /// written, even when they're queued behind this command.
+    EndSegment(Instant),
     Undo,
-    UpdateConfig { width: u32, height: u32, fps: u32, format: String, encoder: EncoderPreset, quality: EncodingQuality, speed: EncodingSpeed },
+    UpdateConfig(RecorderConfig),
     SetAudioDevice(usize),
-    FinalizeVideo(Vec<PathBuf>, String)
+    SetAudioApp(Option<AudioApp>),
+    RefreshAudioApps,
+    SetReplay(Option<u32>),
+    /// Streams to this RTMP address alongside takes, or stops streaming.
+    SetLiveStream(Option<String>),
+    SaveReplay,
+    StartInterval(u32, bool),
+    StopInterval,
+    ClearSegments,
+    /// Puts a crashed session's clips back on the timeline, salvaging the takes left in these
+    /// scratch folders first. Expects the config to already point at the old workspace.
+    RecoverSession(Vec<PathBuf>),
+    CancelExport,
+    SetTuning(ThreadTuning),
+    FinalizeVideo(Vec<ClipInfo>, String, ExportOptions),
+    /// Deletes clips taken off the timeline, or exported with no queued export still needing them.
+    DeleteClips(Vec<PathBuf>),
+    /// Records the microphone over a clip, whatever app's audio takes are recording, and redraws
+    /// its assets once the new audio is in.
+    StartVoiceover(Box<ClipInfo>),
+    StopVoiceover,
+    /// Cut a clip in two at this many seconds in.
+    SplitClip(PathBuf, f64),
+    /// Turn a clip or an export into an animated GIF/WebP at the given path.
+    ExportAnimation(PathBuf, String, AnimationOptions),
+    /// Regrab a clip's thumbnail from this many seconds in: clip, thumbnail path and time.
+    SetThumbnail(PathBuf, PathBuf, f64),
+    /// Render a visualizer of a clip's or a song's audio as a new B-roll clip: source, style and
+    /// the stretch of it to use.
+    GenerateVisualizer(PathBuf, VisualizerStyle, f64, Option<f64>),
+    StartRoomTone,
+    StopRoomTone,
+    MeasureSync(PathBuf)
 }
 
 pub enum RecorderStatus {
-    SegmentSaved(ClipInfo),
+    /// A clip is ready to use. Its thumbnail, preview and waveform follow in `ClipAssetsReady`.
+    SegmentSaved(Box<ClipInfo>),
+    /// A clip's thumbnail, preview and waveform are written, along with the seconds of black and
+    /// frozen picture the defect scan found.
+    ClipAssetsReady { clip: PathBuf, black_secs: f64, frozen_secs: f64 },
     SegmentDeleted,
     VideoFinalized(PathBuf),
+    ExportFailed(String),
+    ExportCancelled,
+    /// Sent last for every export however it went, with the output it was queued for.
+    ExportEnded(String),
+    /// The export couldn't stream-copy the clips and re-encoded them instead, and why.
+    ConcatReencoded(String),
+    VoiceoverReplaced(PathBuf),
+    SyncMeasured(Option<f64>),
+    DryRun(String),
+    /// How far a long-running ffmpeg job has got, from 0 to 100.
+    Progress { stage: ProgressStage, percent: f32 },
+    /// A finished pass got through `speed` seconds of media per second, encoding the video or copying it.
+    Throughput { encode: bool, speed: f64 },
+    /// The export finished but its length is off: output path, expected and actual seconds.
+    DurationMismatch(PathBuf, f64, f64),
+    /// A clip failed validation and was moved aside: quarantined path and whether a salvaged
+    /// copy took its place.
+    SegmentQuarantined(PathBuf, bool),
+    RoomToneRecorded(PathBuf),
+    /// Frames the take in progress has lost so far because the encoder couldn't keep up.
+    FramesDropped(u64),
+    /// This hardware encoder failed to start, so takes are recorded with libx264 until another
+    /// encoder is chosen.
+    EncoderFallback(EncoderPreset),
+    /// A crashed session was restored: clips put back, and clips or takes that couldn't be saved.
+    SessionRecovered { restored: usize, lost: usize },
+    /// The workspace volume is below the free-space threshold. `refused` means a take was not
+    /// started because of it; otherwise the take in progress is running out of room.
+    LowDiskSpace { free_gb: f64, refused: bool },
+    /// A clip was split: the original path, the split point and the two new halves.
+    ClipSplit(PathBuf, f64, Box<ClipInfo>, Box<ClipInfo>),
+    ThumbnailSet(PathBuf, f64),
+    /// An animation export ended: its path, or `None` if it was cancelled.
+    AnimationFinished(Option<PathBuf>),
+    StillCaptured(u32),
+    IntervalFinished(PathBuf),
+    /// A clip couldn't be copied to the backup location, and why.
+    BackupFailed(PathBuf, String),
+    /// A take had no audio to merge, so it was saved video-only. Sent once per session.
+    AudioMissing,
+    /// The live stream stopped by itself, such as when the server refused or dropped it.
+    LiveStreamEnded,
     Error(String)
 }
\ No newline at end of file
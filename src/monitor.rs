@@ -0,0 +1,130 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+//! The window a second Clipper opens while another instance is recording: its preview and take
+//! state, read-only.
+
+use crate::instance::{self, MonitorMessage, MonitorStatus};
+use crossbeam_channel::Receiver;
+use eframe::{App, Frame, egui};
+use std::time::{Duration, Instant};
+
+const RECONNECT_EVERY: Duration = Duration::from_secs(2);
+
+pub struct MonitorApp {
+    /// Launched without `monitor`, so ask before turning into one.
+    asking: bool,
+    connection: Option<Receiver<MonitorMessage>>,
+    last_attempt: Option<Instant>,
+    connect_error: Option<String>,
+    status: Option<MonitorStatus>,
+    texture: Option<egui::TextureHandle>
+}
+
+impl MonitorApp {
+    pub fn new(asking: bool) -> Self {
+        Self { asking, connection: None, last_attempt: None, connect_error: None, status: None, texture: None }
+    }
+
+    fn poll(&mut self, ctx: &egui::Context) {
+        if self.connection.is_none() && self.last_attempt.is_none_or(|t| t.elapsed() >= RECONNECT_EVERY) {
+            self.last_attempt = Some(Instant::now());
+            match instance::connect() {
+                Ok(rx) => { self.connection = Some(rx); self.connect_error = None; },
+                Err(e) => self.connect_error = Some(e.to_string())
+            }
+        }
+        let Some(rx) = &self.connection else { return };
+        while let Ok(msg) = rx.try_recv() {
+            match msg {
+                MonitorMessage::Status(s) => self.status = Some(s),
+                MonitorMessage::Frame(img) => self.texture = Some(ctx.load_texture("monitor", img, Default::default())),
+                MonitorMessage::Closed => {
+                    self.connection = None;
+                    self.status = None;
+                    self.connect_error = Some(String::from("The recording instance closed"));
+                    break;
+                }
+            }
+        }
+    }
+
+    fn show_prompt(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(ui.available_height() * 0.3);
+                ui.heading("Clipper is already running");
+                ui.label("Only one instance can use the camera and workspace. This window can follow it read-only instead.");
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Open monitor").clicked() {
+                        self.asking = false;
+                    }
+                    if ui.button("Quit").clicked() {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                });
+            });
+        });
+    }
+}
+
+impl App for MonitorApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        if self.asking {
+            self.show_prompt(ctx);
+            return;
+        }
+        self.poll(ctx);
+
+        egui::TopBottomPanel::top("monitor_status").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                match &self.status {
+                    Some(s) if s.recording => {
+                        ui.label(egui::RichText::new("● REC").color(egui::Color32::RED).strong());
+                        ui.label(format!("Take {}  {:02}:{:02}", s.take, s.elapsed_secs / 60, s.elapsed_secs % 60));
+                    },
+                    Some(s) => { ui.label(format!("Idle, next take {}", s.take)); },
+                    None => { ui.label(self.connect_error.as_deref().unwrap_or("Connecting…")); }
+                }
+                if let Some(s) = &self.status {
+                    if !s.shot.is_empty() {
+                        ui.separator();
+                        ui.label(&s.shot);
+                    }
+                    if let Some(note) = &s.note {
+                        ui.separator();
+                        ui.colored_label(egui::Color32::YELLOW, note);
+                    }
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| ui.weak("Read-only monitor"));
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            match &self.texture {
+                Some(texture) => {
+                    let available = ui.available_size();
+                    let size = texture.size_vec2();
+                    let scale = (available.x / size.x).min(available.y / size.y);
+                    ui.centered_and_justified(|ui| ui.add(egui::Image::new(texture).fit_to_exact_size(size * scale)));
+                },
+                None => { ui.centered_and_justified(|ui| ui.weak("No preview yet")); }
+            }
+        });
+        ctx.request_repaint_after(Duration::from_millis(50));
+    }
+}
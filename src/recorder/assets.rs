@@ -0,0 +1,79 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+//! Thumbnails, hover previews, waveforms and the defect scan for finished clips, made on worker
+//! threads so a clip lands on the timeline as soon as it's merged.
+
+use super::ffmpeg;
+use crate::{crash::log_line, messages::recorder::RecorderStatus};
+use crossbeam_channel::{Sender, unbounded};
+use std::{path::PathBuf, process::Command, thread};
+
+/// Enough to keep up with back-to-back takes without starving the encoder of cores.
+const WORKERS: usize = 2;
+
+struct AssetJob {
+    clip: PathBuf,
+    /// The clip's proxy when it has one, otherwise the clip itself.
+    picture: PathBuf,
+    thumb: PathBuf,
+    /// Seconds into the clip the thumbnail is grabbed from.
+    thumb_secs: f64,
+    preview: PathBuf,
+    waveform: PathBuf
+}
+
+pub struct AssetPool {
+    jobs: Sender<AssetJob>
+}
+
+impl AssetPool {
+    pub fn start(status_tx: Sender<RecorderStatus>) -> Self {
+        let (jobs, job_rx) = unbounded::<AssetJob>();
+        for _ in 0..WORKERS {
+            let job_rx = job_rx.clone();
+            let status_tx = status_tx.clone();
+            thread::spawn(move || {
+                for job in job_rx {
+                    let (black_secs, frozen_secs) = build_assets(&job);
+                    let _ = status_tx.send(RecorderStatus::ClipAssetsReady { clip: job.clip, black_secs, frozen_secs });
+                }
+            });
+        }
+        Self { jobs }
+    }
+
+    pub fn queue(&self, clip: PathBuf, picture: PathBuf, (thumb, thumb_secs): (PathBuf, f64), preview: PathBuf, waveform: PathBuf) {
+        let _ = self.jobs.send(AssetJob { clip, picture, thumb, thumb_secs, preview, waveform });
+    }
+}
+
+/// Returns the seconds of black and of frozen picture found in the clip.
+fn build_assets(job: &AssetJob) -> (f64, f64) {
+    let clip = job.clip.to_string_lossy();
+    let picture = job.picture.to_string_lossy();
+    let _ = Command::new("ffmpeg").args(ffmpeg::build_thumb_cmd(&picture, job.thumb_secs, &job.thumb)).output();
+    let _ = Command::new("ffmpeg").args(ffmpeg::build_preview_cmd(&picture, &job.preview)).output();
+    let _ = Command::new("ffmpeg").args(ffmpeg::build_waveform_cmd(&clip, &job.waveform)).output();
+    let (black_secs, frozen_secs) = match Command::new("ffmpeg").args(ffmpeg::build_defect_scan_cmd(&picture)).output() {
+        Ok(o) => ffmpeg::parse_defect_scan(&String::from_utf8_lossy(&o.stderr)),
+        Err(_) => (0.0, 0.0)
+    };
+    if black_secs > 0.0 || frozen_secs > 0.0 {
+        log_line!("{}: {:.1}s black, {:.1}s frozen", clip, black_secs, frozen_secs);
+    }
+    (black_secs, frozen_secs)
+}
@@ -0,0 +1,106 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! Copies finished clips to a second location on a worker thread, verifying each copy against
+//! the original so one failed disk doesn't take the shoot with it.
+
+use crate::{crash::log_line, messages::recorder::RecorderStatus};
+use crossbeam_channel::{Sender, unbounded};
+use std::{fs::{self, File}, io::{self, Read}, path::{Path, PathBuf}, thread};
+
+const CHUNK: usize = 1 << 20;
+
+struct BackupJob {
+    clip: PathBuf,
+    /// Folder the copy goes in.
+    dir: PathBuf
+}
+
+pub struct BackupPool {
+    jobs: Sender<BackupJob>
+}
+
+impl BackupPool {
+    /// One worker, so copies land in the order clips were saved and don't fight over the disk.
+    pub fn start(status_tx: Sender<RecorderStatus>) -> Self {
+        let (jobs, job_rx) = unbounded::<BackupJob>();
+        thread::spawn(move || {
+            for job in job_rx {
+                if let Err(e) = back_up(&job) {
+                    log_line!("Backing up {} failed: {}", job.clip.display(), e);
+                    let _ = status_tx.send(RecorderStatus::BackupFailed(job.clip, e.to_string()));
+                }
+            }
+        });
+        Self { jobs }
+    }
+
+    pub fn queue(&self, clip: PathBuf, dir: PathBuf) {
+        let _ = self.jobs.send(BackupJob { clip, dir });
+    }
+}
+
+/// Copies under a temporary name, flushes it to the disk, reads both files back to compare them
+/// and only then gives the copy the clip's name, so a file with that name in the backup is always a
+/// good one. A clip replaced since its last backup is copied again over the old one.
+fn back_up(job: &BackupJob) -> io::Result<()> {
+    let name = job.clip.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let target = job.dir.join(name);
+    if target.exists() && same_contents(&job.clip, &target)? {
+        return Ok(());
+    }
+    fs::create_dir_all(&job.dir)?;
+    let partial = target.with_extension("partial");
+    fs::copy(&job.clip, &partial)?;
+    // Opened for writing, which Windows needs to flush a file.
+    fs::OpenOptions::new().write(true).open(&partial)?.sync_all()?;
+    if !same_contents(&job.clip, &partial)? {
+        let _ = fs::remove_file(&partial);
+        return Err(io::Error::other("the copy doesn't match the original"));
+    }
+    fs::rename(&partial, &target)?;
+    sync_dir(&job.dir)?;
+    log_line!("Backed up {} to {}", job.clip.display(), target.display());
+    Ok(())
+}
+
+/// Makes the rename itself durable. Windows has no way to open a folder for this and doesn't need it.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let (mut buf_a, mut buf_b) = (vec![0u8; CHUNK], vec![0u8; CHUNK]);
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
@@ -13,11 +13,463 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use std::{path::PathBuf, process::Command};
+use std::{path::{Path, PathBuf}, process::Command};
 
-use super::types::{EncoderPreset, EncodingQuality, EncodingSpeed};
+use super::types::{AnimationFormat, AnimationOptions, AudioEncoding, Downmix, EncoderPreset, EncodingQuality, EncodingSpeed, ExportMetadata, FrameFormat, GpuVendor, Lane, LowerThirdStyle, ExportPreset, RateControl, RecorderConfig, VerticalFit, VerticalFrame, VisualizerStyle, Watermark};
+use crate::messages::{audio::{AudioClock, LiveAudio}, recorder::ClipInfo};
 
-pub fn build_cmd(width: u32, height: u32, fps: u32, format: &str, encoder: EncoderPreset, quality: EncodingQuality, speed: EncodingSpeed, filename: &str) -> Vec<String> {
+pub const LIVE_HLS_DIR: &str = "live";
+pub const LIVE_HLS_PLAYLIST: &str = "live/stream.m3u8";
+pub const REPLAY_DIR: &str = "replay";
+pub const REPLAY_LIST: &str = "replay/replay.ffconcat";
+pub const QUARANTINE_DIR: &str = "quarantine";
+pub const ROOM_TONE: &str = "room_tone.mp4";
+pub const PROXY_DIR: &str = "proxies";
+/// Hidden folder in the session folder a take's raw files are recorded into until it's merged.
+pub const RECORDING_DIR: &str = ".recording";
+const PROXY_HEIGHT: u32 = 480;
+/// How long a take's burned-in caption stays up.
+const CAPTION_SECS: u32 = 4;
+/// Title-safe area as a fraction of the frame, per SMPTE ST 2046-1. Burned-in text is placed
+/// inside it, and exports note any that isn't.
+pub const TITLE_SAFE: f64 = 0.90;
+/// Rough advance of a glyph as a share of its font size, for guessing how wide drawtext's text is.
+const GLYPH_WIDTH: f64 = 0.55;
+/// Local date and time in the bottom-right corner of title-safe, in drawtext's default
+/// `%Y-%m-%d %H:%M:%S`. `%{localtime}` is the wall clock when the encoder draws the frame, not
+/// when the camera took it.
+const TIMESTAMP_FILTER: &str = "drawtext=text=%{localtime}:fontcolor=white:fontsize=h/30:box=1:boxcolor=black@0.5:boxborderw=6:x=w*0.95-tw-6:y=h*0.95-th-6";
+/// Audio clock drift below this is left alone: 20 ppm is about two frames over an hour.
+const DRIFT_TOLERANCE_PPM: f64 = 20.0;
+const REPLAY_SEGMENT_SECS: u32 = 2;
+const STILL_PATTERN: &str = "still_%05d.jpg";
+
+/// The take encoder. With `proxy`, the same frames also go to a small, quick libx264 encode
+/// as a second output.
+pub fn build_cmd(config: &RecorderConfig, filename: &str, proxy: Option<&str>) -> Vec<String> {
+    let mut args = build_encode_args(config);
+    if config.live_hls {
+        // HLS can only cut on keyframes, so force one per segment to keep the live edge close.
+        args.extend(["-force_key_frames", "expr:gte(t,n_forced*2)", "-map", "0:v", "-f", "tee"].into_iter().map(String::from));
+        args.push(String::from("-y"));
+        // fMP4 segments, which the preview page can hand straight to the browser without hls.js.
+        args.push(format!(
+            "[f=mp4]{}|[f=hls:hls_time=2:hls_list_size=10:hls_segment_type=fmp4:hls_flags=append_list+discont_start+delete_segments+omit_endlist:hls_segment_filename={}/seg_%05d.m4s]{}",
+            filename, config.workspace.join(LIVE_HLS_DIR).to_string_lossy(), config.workspace.join(LIVE_HLS_PLAYLIST).to_string_lossy()
+        ));
+    } else {
+        args.push(String::from("-y"));
+        args.push(filename.to_string());
+    }
+    if let Some(proxy) = proxy {
+        args.extend([
+            String::from("-map"), String::from("0:v"),
+            String::from("-vf"), format!("scale=-2:{},format=yuv420p", PROXY_HEIGHT),
+            String::from("-c:v"), String::from("libx264"), String::from("-preset"), String::from("ultrafast"), String::from("-crf"), String::from("28"),
+            String::from("-y"), proxy.to_string()
+        ]);
+    }
+    args
+}
+
+/// Where a clip's proxy lives. Kept out of the workspace's top level so it's never taken for a clip.
+pub fn proxy_path(clip: &Path) -> PathBuf {
+    let name = clip.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    clip.parent().map(Path::to_path_buf).unwrap_or_default().join(PROXY_DIR).join(name)
+}
+
+/// Encodes into a ring of short segments under `REPLAY_DIR`. The ffconcat list only ever names the
+/// newest `seconds` worth of finished segments, and the ring has one spare slot for the segment
+/// being written, so the list never points at a file that is being overwritten.
+pub fn build_replay_cmd(config: &RecorderConfig, seconds: u32) -> Vec<String> {
+    let listed = seconds.div_ceil(REPLAY_SEGMENT_SECS).max(1);
+    let ext = if config.encoder.is_av1() { "mkv" } else { "ts" };
+    let mut args = build_encode_args(config);
+    args.extend([
+        String::from("-force_key_frames"), format!("expr:gte(t,n_forced*{})", REPLAY_SEGMENT_SECS),
+        String::from("-f"), String::from("segment"),
+        String::from("-segment_time"), REPLAY_SEGMENT_SECS.to_string(),
+        String::from("-segment_wrap"), (listed + 1).to_string(),
+        String::from("-segment_list"), config.workspace.join(REPLAY_LIST).to_string_lossy().to_string(),
+        String::from("-segment_list_type"), String::from("ffconcat"),
+        String::from("-segment_list_size"), listed.to_string(),
+        String::from("-reset_timestamps"), String::from("1"),
+        String::from("-y"), config.workspace.join(REPLAY_DIR).join(format!("replay_%03d.{}", ext)).to_string_lossy().to_string()
+    ]);
+    args
+}
+
+/// Whether the file has an audio stream. Unreadable files count as having one, so they're left
+/// for the export itself to fail on.
+pub fn has_audio(path: &Path) -> bool {
+    Command::new("ffprobe").args(["-v", "error", "-select_streams", "a", "-show_entries", "stream=index", "-of", "csv=p=0"]).arg(path).output()
+        .map_or(true, |o| !o.status.success() || !o.stdout.iter().all(u8::is_ascii_whitespace))
+}
+
+/// Copies a clip recorded without audio, adding a silent track in the session codec so it can be
+/// joined with clips that have sound.
+pub fn build_silent_track_cmd(input: &Path, encoding: AudioEncoding, output: &str) -> Vec<String> {
+    let mut args = vec![
+        String::from("-i"), input.to_string_lossy().to_string(),
+        String::from("-f"), String::from("lavfi"), String::from("-i"), String::from("anullsrc=r=48000:cl=stereo"),
+        String::from("-map"), String::from("0:v"), String::from("-map"), String::from("1:a"),
+        String::from("-c:v"), String::from("copy")
+    ];
+    args.extend(audio_codec_args(encoding));
+    args.extend([String::from("-shortest"), String::from("-y"), output.to_string()]);
+    args
+}
+
+/// Streams the camera to an RTMP server with the microphone served by the audio thread, or a
+/// silent track without one. Always libx264 at a constant bitrate with a keyframe every two
+/// seconds, which is what streaming services ask for, so takes keep the chosen encoder to themselves.
+pub fn build_live_cmd(config: &RecorderConfig, audio: Option<LiveAudio>, url: &str) -> Vec<String> {
+    let mut args = build_input_args(config);
+    match audio {
+        Some(audio) => args.extend([
+            String::from("-f"), String::from("f32le"),
+            String::from("-ar"), audio.sample_rate.to_string(),
+            String::from("-ac"), audio.channels.to_string(),
+            String::from("-i"), format!("tcp://127.0.0.1:{}", audio.port)
+        ]),
+        None => args.extend(["-f", "lavfi", "-i", "anullsrc=r=48000:cl=stereo"].into_iter().map(String::from))
+    }
+    let kbps = config.bitrate_kbps;
+    args.extend(["-map", "0:v", "-map", "1:a", "-c:v", "libx264", "-preset", "veryfast", "-tune", "zerolatency", "-pix_fmt", "yuv420p"].into_iter().map(String::from));
+    args.extend([
+        String::from("-b:v"), format!("{}k", kbps), String::from("-maxrate"), format!("{}k", kbps), String::from("-bufsize"), format!("{}k", kbps * 2),
+        String::from("-g"), (config.fps * 2).to_string()
+    ]);
+    args.extend(["-c:a", "aac", "-b:a", "160k", "-ac", "2", "-f", "flv"].into_iter().map(String::from));
+    args.push(url.to_string());
+    args
+}
+
+/// Joins the buffered replay segments into a clip. The replay has no audio of its own, so a silent
+/// track in the session codec is added to keep the clip concat-compatible with recorded ones.
+pub fn build_replay_save_cmd(config: &RecorderConfig, output: &str) -> Vec<String> {
+    let mut args = vec![
+        String::from("-f"), String::from("concat"), String::from("-safe"), String::from("0"), String::from("-i"), config.workspace.join(REPLAY_LIST).to_string_lossy().to_string(),
+        String::from("-f"), String::from("lavfi"), String::from("-i"), String::from("anullsrc=r=48000:cl=stereo"),
+        String::from("-map"), String::from("0:v"), String::from("-map"), String::from("1:a"),
+        String::from("-c:v"), String::from("copy")
+    ];
+    args.extend(audio_codec_args(config.audio));
+    args.extend([String::from("-shortest"), String::from("-y"), output.to_string()]);
+    args
+}
+
+/// The session's audio codec and bitrate, for every clip the recorder makes. Clips that share
+/// them can be joined without re-encoding.
+fn audio_codec_args(encoding: AudioEncoding) -> Vec<String> {
+    vec![String::from("-c:a"), encoding.codec.ffmpeg_name().to_string(), String::from("-b:a"), format!("{}k", encoding.bitrate_kbps)]
+}
+
+/// Converts one raw camera frame from stdin into a full-resolution JPEG.
+pub fn build_still_cmd(config: &RecorderConfig, output: &Path) -> Vec<String> {
+    let mut args = build_input_args(config);
+    args.extend([
+        String::from("-frames:v"), String::from("1"),
+        String::from("-q:v"), String::from("2"),
+        String::from("-y"), output.to_string_lossy().to_string()
+    ]);
+    args
+}
+
+/// Assembles an interval still folder into a video, one still per frame.
+pub fn build_timelapse_cmd(dir: &Path, fps: u32, output: &Path) -> Vec<String> {
+    vec![
+        String::from("-framerate"), fps.to_string(),
+        String::from("-i"), dir.join(STILL_PATTERN).to_string_lossy().to_string(),
+        String::from("-c:v"), String::from("libx264"),
+        String::from("-vf"), String::from("scale=trunc(iw/2)*2:trunc(ih/2)*2,format=yuv420p"),
+        String::from("-y"), output.to_string_lossy().to_string()
+    ]
+}
+
+pub fn still_path(dir: &Path, index: u32) -> PathBuf {
+    dir.join(format!("still_{:05}.jpg", index))
+}
+
+fn build_encode_args(config: &RecorderConfig) -> Vec<String> {
+    let device = config.encoder_device.as_deref();
+    // QSV and AMF pick their device before the input is opened.
+    let mut args: Vec<String> = match (config.encoder.vendor(), device) {
+        (Some(GpuVendor::Intel), Some(d)) => vec![String::from("-qsv_device"), d.to_string()],
+        (Some(GpuVendor::Amd), Some(d)) => vec![
+            String::from("-init_hw_device"), format!("d3d11va=gpu:{}", d),
+            String::from("-filter_hw_device"), String::from("gpu")
+        ],
+        _ => Vec::new()
+    };
+    args.extend(build_input_args(config));
+    let mut codec = video_codec_args(config);
+    match (config.encoder.vendor(), device) {
+        (Some(GpuVendor::Nvidia), Some(d)) => codec.extend([String::from("-gpu"), d.to_string()]),
+        // AMF only encodes on another adapter when handed frames that already live there.
+        (Some(GpuVendor::Amd), Some(_)) => {
+            if let Some(vf) = codec.iter().position(|a| a == "-vf") {
+                codec[vf + 1] = String::from("format=nv12,hwupload");
+            }
+        },
+        _ => {}
+    }
+    // Drawn as the encoder receives each frame, which trails capture only by the frame queue.
+    if config.burn_timestamp {
+        match codec.iter().position(|a| a == "-vf") {
+            Some(vf) => codec[vf + 1] = format!("{},{}", TIMESTAMP_FILTER, codec[vf + 1]),
+            // Mastering keeps the camera's own pixel format, so it has no filter to join.
+            None => codec.extend([String::from("-vf"), String::from(TIMESTAMP_FILTER)])
+        }
+    }
+    args.extend(codec);
+    args.extend(config.extra_args.iter().cloned());
+    args
+}
+
+/// The session's video encoder at its quality, speed and rate control, with the pixel format
+/// filter it needs as `-vf`. Re-encodes made from clips use it so they match the takes.
+fn video_codec_args(config: &RecorderConfig) -> Vec<String> {
+    let (encoder, quality, speed) = (config.encoder, config.quality, config.speed);
+    let (enc_args, quality_args) = match encoder {
+        EncoderPreset::CPU => {
+            let preset = match speed {
+                EncodingSpeed::Fastest => "ultrafast",
+                EncodingSpeed::Balanced => "veryfast",
+                EncodingSpeed::Compact => "medium"
+            };
+
+            let crf = match quality {
+                EncodingQuality::High => "18",
+                EncodingQuality::Med => "23",
+                EncodingQuality::Low => "28"
+            };
+
+            (vec!["-c:v", "libx264", "-vf", "format=yuv420p",
+                "-preset", preset, "-tune", "zerolatency"], vec!["-crf", crf])
+        },
+        
+        EncoderPreset::NVIDIA => {
+            let preset = match speed {
+                EncodingSpeed::Fastest => "p1",
+                EncodingSpeed::Balanced => "p4",
+                EncodingSpeed::Compact => "p7"
+            };
+
+            let cq = match quality {
+                EncodingQuality::High => "19",
+                EncodingQuality::Med => "23",
+                EncodingQuality::Low => "28"
+            };
+
+            (vec!["-c:v", "h264_nvenc", "-vf", "format=yuv420p",
+                "-preset", preset], vec!["-rc:v", "vbr", "-cq", cq])
+        },
+        EncoderPreset::AMD => (vec!["-c:v", "h264_amf", "-vf", "format=yuv420p", "-usage", "transcoding"], vec![]),
+        EncoderPreset::INTEL => (vec!["-c:v", "h264_qsv", "-vf", "format=nv12", "-preset", "medium"], vec![]),
+        // HEVC reaches the same visual quality at higher CRF/QP values than H.264.
+        EncoderPreset::CPUHEVC => {
+            let preset = match speed {
+                EncodingSpeed::Fastest => "ultrafast",
+                EncodingSpeed::Balanced => "veryfast",
+                EncodingSpeed::Compact => "medium"
+            };
+
+            let crf = match quality {
+                EncodingQuality::High => "22",
+                EncodingQuality::Med => "26",
+                EncodingQuality::Low => "30"
+            };
+
+            (vec!["-c:v", "libx265", "-vf", "format=yuv420p",
+                "-preset", preset, "-tune", "zerolatency", "-x265-params", "log-level=error"], vec!["-crf", crf])
+        },
+        EncoderPreset::NVIDIAHEVC => {
+            let preset = match speed {
+                EncodingSpeed::Fastest => "p1",
+                EncodingSpeed::Balanced => "p4",
+                EncodingSpeed::Compact => "p7"
+            };
+
+            let cq = match quality {
+                EncodingQuality::High => "21",
+                EncodingQuality::Med => "25",
+                EncodingQuality::Low => "30"
+            };
+
+            (vec!["-c:v", "hevc_nvenc", "-vf", "format=yuv420p",
+                "-preset", preset], vec!["-rc:v", "vbr", "-cq", cq])
+        },
+        EncoderPreset::AMDHEVC => {
+            let amf_quality = match speed {
+                EncodingSpeed::Fastest => "speed",
+                EncodingSpeed::Balanced => "balanced",
+                EncodingSpeed::Compact => "quality"
+            };
+
+            let qp = match quality {
+                EncodingQuality::High => "22",
+                EncodingQuality::Med => "26",
+                EncodingQuality::Low => "30"
+            };
+
+            (vec!["-c:v", "hevc_amf", "-vf", "format=yuv420p", "-usage", "transcoding",
+                "-quality", amf_quality], vec!["-rc", "cqp", "-qp_i", qp, "-qp_p", qp])
+        },
+        EncoderPreset::INTELHEVC => {
+            let preset = match speed {
+                EncodingSpeed::Fastest => "veryfast",
+                EncodingSpeed::Balanced => "medium",
+                EncodingSpeed::Compact => "veryslow"
+            };
+
+            let global_quality = match quality {
+                EncodingQuality::High => "22",
+                EncodingQuality::Med => "26",
+                EncodingQuality::Low => "30"
+            };
+
+            (vec!["-c:v", "hevc_qsv", "-vf", "format=nv12", "-preset", preset], vec!["-global_quality", global_quality])
+        },
+        EncoderPreset::CPUAV1 => {
+            // SVT-AV1 presets run 0 (slowest) to 13; below 6 is too slow for live capture.
+            let preset = match speed {
+                EncodingSpeed::Fastest => "12",
+                EncodingSpeed::Balanced => "10",
+                EncodingSpeed::Compact => "7"
+            };
+
+            let crf = match quality {
+                EncodingQuality::High => "28",
+                EncodingQuality::Med => "35",
+                EncodingQuality::Low => "42"
+            };
+
+            (vec!["-c:v", "libsvtav1", "-vf", "format=yuv420p", "-preset", preset], vec!["-crf", crf])
+        },
+        EncoderPreset::NVIDIAAV1 => {
+            let preset = match speed {
+                EncodingSpeed::Fastest => "p1",
+                EncodingSpeed::Balanced => "p4",
+                EncodingSpeed::Compact => "p7"
+            };
+
+            let cq = match quality {
+                EncodingQuality::High => "28",
+                EncodingQuality::Med => "34",
+                EncodingQuality::Low => "40"
+            };
+
+            (vec!["-c:v", "av1_nvenc", "-vf", "format=yuv420p",
+                "-preset", preset], vec!["-rc:v", "vbr", "-cq", cq])
+        },
+        EncoderPreset::INTELAV1 => {
+            let preset = match speed {
+                EncodingSpeed::Fastest => "veryfast",
+                EncodingSpeed::Balanced => "medium",
+                EncodingSpeed::Compact => "veryslow"
+            };
+
+            let global_quality = match quality {
+                EncodingQuality::High => "25",
+                EncodingQuality::Med => "30",
+                EncodingQuality::Low => "35"
+            };
+
+            (vec!["-c:v", "av1_qsv", "-vf", "format=nv12", "-preset", preset], vec!["-global_quality", global_quality])
+        },
+        // Every frame a lossless keyframe, in the camera's own chroma layout, so an editor can cut
+        // anywhere without decoding a GOP and nothing is lost before the final render.
+        EncoderPreset::MASTERING => {
+            let preset = match speed {
+                EncodingSpeed::Fastest => "ultrafast",
+                EncodingSpeed::Balanced => "superfast",
+                EncodingSpeed::Compact => "veryfast"
+            };
+
+            (vec!["-c:v", "libx264", "-preset", preset, "-g", "1"], vec!["-qp", "0"])
+        }
+    };
+
+    let mut args: Vec<String> = enc_args.into_iter().map(String::from).collect();
+    match config.rate_control {
+        // Lossless has no rate to control.
+        _ if encoder == EncoderPreset::MASTERING => args.extend(quality_args.into_iter().map(String::from)),
+        RateControl::Crf => args.extend(quality_args.into_iter().map(String::from)),
+        rc => args.extend(build_bitrate_args(encoder, rc, config.bitrate_kbps))
+    }
+    if encoder.is_hevc() {
+        // Apple players only accept HEVC in mp4 with the hvc1 tag.
+        args.extend([String::from("-tag:v"), String::from("hvc1")]);
+    }
+    args
+}
+
+/// Splits a user-typed argument string on whitespace, keeping single- or double-quoted runs together.
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => { quote = Some(c); in_arg = true; },
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            },
+            (None, c) => { current.push(c); in_arg = true; }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+/// Bitrate-targeted rate control. VBR peaks are allowed 1.5x the target.
+fn build_bitrate_args(encoder: EncoderPreset, rc: RateControl, kbps: u32) -> Vec<String> {
+    let cbr = rc == RateControl::Cbr;
+    let target = format!("{}k", kbps);
+    let peak = if cbr { target.clone() } else { format!("{}k", kbps * 3 / 2) };
+    let bufsize = format!("{}k", kbps * 2);
+    let mut args = vec![String::from("-b:v"), target.clone(), String::from("-maxrate"), peak];
+    match encoder {
+        EncoderPreset::CPU | EncoderPreset::CPUHEVC | EncoderPreset::MASTERING => {
+            args.extend([String::from("-bufsize"), bufsize]);
+            if cbr {
+                args.extend([String::from("-minrate"), target]);
+                if encoder == EncoderPreset::CPU {
+                    args.extend([String::from("-x264-params"), String::from("nal-hrd=cbr")]);
+                }
+            }
+        },
+        EncoderPreset::CPUAV1 => {
+            // SVT-AV1 only does true CBR with its low-delay prediction structure.
+            if cbr {
+                args.extend([String::from("-svtav1-params"), String::from("rc=2:pred-struct=1")]);
+            }
+        },
+        EncoderPreset::NVIDIA | EncoderPreset::NVIDIAHEVC | EncoderPreset::NVIDIAAV1 => {
+            args.extend([String::from("-rc:v"), String::from(if cbr { "cbr" } else { "vbr" }), String::from("-bufsize"), bufsize]);
+        },
+        EncoderPreset::AMD | EncoderPreset::AMDHEVC => {
+            args.extend([String::from("-rc"), String::from(if cbr { "cbr" } else { "vbr_peak" })]);
+        },
+        EncoderPreset::INTEL | EncoderPreset::INTELHEVC | EncoderPreset::INTELAV1 => {
+            // QSV picks CBR when maxrate equals the target and VBR when it is higher.
+            args.extend([String::from("-bufsize"), bufsize]);
+        }
+    }
+    args
+}
+
+fn build_input_args(config: &RecorderConfig) -> Vec<String> {
+    let (width, height, fps) = (config.width, config.height, config.fps);
     let f = String::from("-f");
     let framerate = String::from("-framerate");
     let pxformat = String::from("-pixel_format");
@@ -26,7 +478,7 @@ pub fn build_cmd(width: u32, height: u32, fps: u32, format: &str, encoder: Encod
     let i = String::from("-i");
     let dash = String::from("-");
 
-    let mut args = match format {
+    match config.format.as_str() {
         "MJPEG" => vec![
             f, String::from("mjpeg"),
             framerate, fpstr,
@@ -53,52 +505,1137 @@ pub fn build_cmd(width: u32, height: u32, fps: u32, format: &str, encoder: Encod
             framerate, fpstr,
             i, dash
         ]
-    };
+    }
+}
 
-    let enc_args = match encoder {
-        EncoderPreset::CPU => {
-            let preset = match speed {
-                EncodingSpeed::Fastest => "ultrafast",
-                EncodingSpeed::Balanced => "veryfast",
-                EncodingSpeed::Compact => "medium"
-            };
+const LADDER: [(&str, u32, &str); 3] = [
+    ("1080p", 1080, "5000k"),
+    ("720p", 720, "2800k"),
+    ("480p", 480, "1400k")
+];
 
-            let crf = match quality {
-                EncodingQuality::High => "18",
-                EncodingQuality::Med => "23",
-                EncodingQuality::Low => "28"
-            };
+/// Combined bitrate of every rung, audio included.
+pub fn ladder_kbps() -> u32 {
+    LADDER.iter().map(|(_, _, bitrate)| bitrate.trim_end_matches('k').parse::<u32>().unwrap_or(0) + 128).sum()
+}
+
+/// Whether a pass leaves the video stream as it is.
+pub fn copies_video(args: &[String]) -> bool {
+    args.windows(2).any(|w| (w[0] == "-c" || w[0] == "-c:v") && w[1] == "copy")
+}
+
+/// Builds a single ffmpeg pass that decodes the concat list once and encodes every rung of the ladder.
+/// Without `hls` each rendition lands next to `output` as `<stem>_<name>.mp4`; with `hls` the renditions
+/// are packaged into `<stem>_hls/` with a `master.m3u8` referencing them. The HLS variants only get an
+/// audio rendition when the timeline has `audio`, as `-var_stream_map` can't name a missing stream.
+pub fn build_ladder_cmd(list_file: &str, output: &Path, hls: bool, audio: bool) -> Vec<String> {
+    let mut args: Vec<String> = vec!["-f", "concat", "-safe", "0", "-i", list_file].into_iter().map(String::from).collect();
+
+    let mut graph = format!("[0:v]split={}", LADDER.len());
+    for i in 0..LADDER.len() { graph.push_str(&format!("[s{}]", i)); }
+    for (i, (_, height, _)) in LADDER.iter().enumerate() {
+        graph.push_str(&format!(";[s{}]scale=-2:{}[v{}]", i, height, i));
+    }
+    args.push(String::from("-filter_complex"));
+    args.push(graph);
+
+    if hls {
+        let mut var_map = Vec::new();
+        for (i, (_, _, bitrate)) in LADDER.iter().enumerate() {
+            args.extend([String::from("-map"), format!("[v{}]", i)]);
+            if audio {
+                args.extend([String::from("-map"), String::from("0:a:0?")]);
+            }
+            args.extend([format!("-b:v:{}", i), bitrate.to_string()]);
+            var_map.push(if audio { format!("v:{},a:{},name:{}", i, i, LADDER[i].0) } else { format!("v:{},name:{}", i, LADDER[i].0) });
+        }
+
+        let hls_dir = hls_dir(output);
+        args.extend(["-c:v", "libx264", "-preset", "veryfast", "-c:a", "aac", "-b:a", "128k",
+            "-f", "hls", "-hls_time", "6", "-hls_playlist_type", "vod",
+            "-master_pl_name", "master.m3u8"].into_iter().map(String::from));
+        args.push(String::from("-var_stream_map"));
+        args.push(var_map.join(" "));
+        args.push(String::from("-hls_segment_filename"));
+        args.push(hls_dir.join("%v").join("seg_%03d.ts").to_string_lossy().to_string());
+        args.push(String::from("-y"));
+        args.push(hls_dir.join("%v").join("index.m3u8").to_string_lossy().to_string());
+    } else {
+        for (i, ((_, _, bitrate), path)) in LADDER.iter().zip(ladder_outputs(output)).enumerate() {
+            args.extend([String::from("-map"), format!("[v{}]", i), String::from("-map"), String::from("0:a?")]);
+            args.extend(["-c:v", "libx264", "-preset", "veryfast", "-b:v", bitrate, "-c:a", "aac", "-b:a", "128k", "-y"].into_iter().map(String::from));
+            args.push(path.to_string_lossy().to_string());
+        }
+    }
+
+    args
+}
+
+/// Two-track assembly: A-roll clips are concatenated as the programme (video and audio), and every
+/// B-roll/cutaway clip is laid over the video of the A-roll clip before it, back to back from that
+/// clip's start, while the A-roll audio keeps playing. B-roll recorded before the first A-roll clip
+/// covers the start of the programme. Returns `None` without any A-roll.
+pub fn build_cutaway_cmd(clips: &[ClipInfo], output: &str) -> Option<Vec<String>> {
+    let first_a = clips.iter().position(|c| c.lane == Lane::ARoll)?;
+    let (width, height) = get_video_resolution(&clips[first_a].video_path).unwrap_or((1920, 1080));
+
+    let mut args = Vec::new();
+    for clip in clips {
+        args.extend(clip_input(clip));
+    }
+
+    let mut audio = String::new();
+    let mut concat_inputs = String::new();
+    let mut a_count = 0;
+    let mut overlays: Vec<(usize, f64, f64)> = Vec::new();
+    let mut programme_time = 0.0;
+    let mut cover_time = 0.0;
+    for (i, clip) in clips.iter().enumerate() {
+        if clip.lane == Lane::ARoll {
+            // A-roll without a sound track is padded with silence so the concat still lines up.
+            audio.push_str(&audio_chain(i, clip));
+            concat_inputs.push_str(&format!("[{}:v][a{}]", i, i));
+            cover_time = programme_time;
+            programme_time += clip.trimmed_duration();
+            a_count += 1;
+        } else {
+            overlays.push((i, cover_time, clip.trimmed_duration()));
+            cover_time += clip.trimmed_duration();
+        }
+    }
+
+    let mut graph = format!("{}{}concat=n={}:v=1:a=1[base0][aout]", audio, concat_inputs, a_count);
+    for (n, (input, start, duration)) in overlays.iter().enumerate() {
+        graph.push_str(&format!(
+            ";[{}:v]scale={}:{},setsar=1,setpts=PTS-STARTPTS+{:.3}/TB[b{}]",
+            input, width, height, start, n
+        ));
+        graph.push_str(&format!(
+            ";[base{}][b{}]overlay=enable='between(t,{:.3},{:.3})':eof_action=pass[base{}]",
+            n, n, start, start + duration, n + 1
+        ));
+    }
 
-            vec!["-c:v", "libx264", "-vf", "format=yuv420p",
-                "-preset", preset, "-crf", crf, "-tune", "zerolatency"]
+    args.push(String::from("-filter_complex"));
+    args.push(graph);
+    args.extend([String::from("-map"), format!("[base{}]", overlays.len()), String::from("-map"), String::from("[aout]")]);
+    args.extend(["-c:v", "libx264", "-preset", "veryfast", "-crf", "20", "-pix_fmt", "yuv420p", "-c:a", "aac", "-b:a", "192k", "-y", output].into_iter().map(String::from));
+    Some(args)
+}
+
+/// `-i` for a clip, preceded by input seeking to its in and out points when it is trimmed. Input
+/// seeking is frame-accurate whenever the clip is decoded.
+pub fn clip_input(clip: &ClipInfo) -> Vec<String> {
+    let mut args = Vec::new();
+    if clip.trim_in > 0.0 {
+        args.extend([String::from("-ss"), format!("{:.3}", clip.trim_in)]);
+    }
+    if let Some(out) = clip.trim_out {
+        args.extend([String::from("-to"), format!("{:.3}", out)]);
+    }
+    args.extend([String::from("-i"), clip.video_path.to_string_lossy().to_string()]);
+    args
+}
+
+/// One pass of a two-pass libx264 encode of a concat list. Pass 1 only writes the rate statistics to
+/// `passlog`; pass 2 writes `output`. Both report progress on stdout.
+pub fn build_two_pass_cmd(list_file: &str, output: &str, kbps: u32, pass: u8, passlog: &str) -> Vec<String> {
+    let mut args: Vec<String> = vec!["-f", "concat", "-safe", "0", "-i", list_file,
+        "-c:v", "libx264", "-preset", "medium", "-vf", "format=yuv420p"].into_iter().map(String::from).collect();
+    args.extend([String::from("-b:v"), format!("{}k", kbps), String::from("-pass"), pass.to_string(), String::from("-passlogfile"), passlog.to_string()]);
+    if pass == 1 {
+        args.extend(["-an", "-f", "null", "-"].into_iter().map(String::from));
+    } else {
+        args.extend([String::from("-c:a"), String::from("copy"), String::from("-y"), output.to_string()]);
+    }
+    args
+}
+
+/// Audio bitrate of a size-targeted preset, left out of the video's share of the budget.
+const TARGET_AUDIO_KBPS: u64 = 96;
+/// Size-targeted presets aim this far under the limit, for the container and rate control overshoot.
+const TARGET_MARGIN: f64 = 0.92;
+/// Below this a size-targeted preset's picture isn't worth sending, so longer timelines are refused.
+const MIN_TARGET_KBPS: u64 = 100;
+
+/// The video bitrate that keeps `secs` of a size-targeted preset under its limit, or `None` when
+/// that would be below `MIN_TARGET_KBPS`.
+pub fn target_video_kbps(preset: ExportPreset, secs: f64) -> Option<u64> {
+    let budget_kbps = preset.target_bytes()? as f64 * TARGET_MARGIN * 8.0 / 1000.0 / secs.max(1.0);
+    Some((budget_kbps as u64).saturating_sub(TARGET_AUDIO_KBPS)).filter(|kbps| *kbps >= MIN_TARGET_KBPS)
+}
+
+/// Encodes a concat list with a preset's size, frame rate and codecs. `secs` is the timeline's
+/// length, which a size-targeted preset divides its budget by.
+pub fn build_preset_cmd(list_file: &str, preset: ExportPreset, secs: f64, output: &str) -> Vec<String> {
+    let mut args: Vec<String> = vec!["-f", "concat", "-safe", "0", "-i", list_file].into_iter().map(String::from).collect();
+    let mut filters = Vec::new();
+    if let Some(height) = preset.height() {
+        filters.push(format!("scale=-2:min(ih\\,{height})"));
+    }
+    if let Some(fps) = preset.fps() {
+        filters.push(format!("fps={fps}"));
+    }
+    if preset != ExportPreset::ArchiveLossless {
+        filters.push(String::from("format=yuv420p"));
+    }
+    if !filters.is_empty() {
+        args.extend([String::from("-vf"), filters.join(",")]);
+    }
+    match preset {
+        ExportPreset::YouTube1080p60 => {
+            args.extend(["-c:v", "libx264", "-preset", "slow", "-crf", "18", "-c:a", "aac", "-b:a", "192k", "-movflags", "+faststart"].into_iter().map(String::from));
         },
-        
-        EncoderPreset::NVIDIA => {
-            let preset = match speed {
-                EncodingSpeed::Fastest => "p1",
-                EncodingSpeed::Balanced => "p4",
-                EncodingSpeed::Compact => "p7"
-            };
+        ExportPreset::Discord25Mb => {
+            let kbps = target_video_kbps(preset, secs).unwrap_or(MIN_TARGET_KBPS);
+            args.extend(["-c:v", "libx264", "-preset", "medium"].into_iter().map(String::from));
+            args.extend([String::from("-b:v"), format!("{}k", kbps), String::from("-maxrate"), format!("{}k", kbps), String::from("-bufsize"), format!("{}k", kbps * 2)]);
+            args.extend([String::from("-c:a"), String::from("aac"), String::from("-b:a"), format!("{}k", TARGET_AUDIO_KBPS), String::from("-movflags"), String::from("+faststart")]);
+        },
+        ExportPreset::ArchiveLossless => {
+            args.extend(["-c:v", "ffv1", "-level", "3", "-g", "1", "-slicecrc", "1", "-c:a", "flac"].into_iter().map(String::from));
+        }
+    }
+    args.extend([String::from("-y"), output.to_string()]);
+    args
+}
 
-            let cq = match quality {
-                EncodingQuality::High => "19",
-                EncodingQuality::Med => "23",
-                EncodingQuality::Low => "28"
-            };
+/// Joins clips through the concat filter, which rebuilds timestamps from the decoded frames.
+pub fn build_reencode_concat_cmd(clips: &[ClipInfo], style: &LowerThirdStyle, output: &str) -> Vec<String> {
+    let size = timeline_size(clips);
+    let normalize = normalize_filter(size);
+    let mut args = Vec::new();
+    let mut graph = String::new();
+    let mut inputs = String::new();
+    for (i, clip) in clips.iter().enumerate() {
+        args.extend(clip_input(clip));
+        let captions = lower_thirds_filter(clip, style, size);
+        graph.push_str(&format!("[{i}:v]{normalize}{captions}[n{i}];"));
+        inputs.push_str(&format!("[n{}][{}:a]", i, i));
+    }
+    args.extend([
+        String::from("-filter_complex"), format!("{}{}concat=n={}:v=1:a=1[v][a]", graph, inputs, clips.len()),
+        String::from("-map"), String::from("[v]"), String::from("-map"), String::from("[a]"),
+        String::from("-c:v"), String::from("libx264"), String::from("-preset"), String::from("medium"), String::from("-crf"), String::from("18"),
+        String::from("-pix_fmt"), String::from("yuv420p"), String::from("-c:a"), String::from("aac"), String::from("-b:a"), String::from("192k"),
+        String::from("-y"), output.to_string()
+    ]);
+    args
+}
+
+/// A re-encoded timeline takes the first clip's frame size.
+fn timeline_size(clips: &[ClipInfo]) -> (u32, u32) {
+    clips.first().and_then(|c| get_video_resolution(&c.video_path)).unwrap_or((1920, 1080))
+}
+
+/// Brings every clip to the timeline's frame size (letterboxed, never stretched), 4:2:0 and
+/// limited range, so webcam, screen and imported footage don't come out washed out or crushed
+/// next to each other.
+fn normalize_filter((width, height): (u32, u32)) -> String {
+    format!("scale={width}:{height}:force_original_aspect_ratio=decrease:out_range=tv,pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,setsar=1,format=yuv420p,setparams=range=tv")
+}
+
+/// Where lower thirds go in a frame, in pixels. The plates' outer edges sit on the title-safe margin.
+struct LowerThirdLayout {
+    /// Distance of the text from the frame's side.
+    inset: f64,
+    pad: f64,
+    /// Font size and top of the name line, then of the title line.
+    lines: [(f64, f64); 2]
+}
+
+impl LowerThirdLayout {
+    fn new(style: &LowerThirdStyle, (width, height): (u32, u32)) -> Self {
+        let h = height as f64;
+        let pad = (h * 0.012).round();
+        let inset = (width as f64 * (1.0 - TITLE_SAFE) / 2.0 + pad).ceil();
+        let (name_size, title_size) = ((h * 0.045).round(), (h * 0.032).round());
+        let top = if style.position.is_top() { h * 0.08 } else { h * 0.74 };
+        Self { inset, pad, lines: [(name_size, top.round()), (title_size, (top + name_size + 3.0 * pad).round())] }
+    }
+}
+
+/// A line of burned-in text with its box, as fractions of the frame: left, top, right, bottom.
+type TextBox = [f64; 4];
+
+fn text_width(text: &str, size: f64) -> f64 {
+    text.trim().chars().count() as f64 * size * GLYPH_WIDTH
+}
+
+/// Text widths are only estimated, so a box a hair over the edge still counts as inside.
+fn in_title_safe(text_box: TextBox) -> bool {
+    let margin = (1.0 - TITLE_SAFE) / 2.0 - 0.002;
+    text_box.iter().all(|edge| (margin..=1.0 - margin).contains(edge))
+}
+
+/// Burned-in text on the timeline that reaches outside title-safe, for the export note: lower
+/// thirds as this export draws them, and each take's caption and timestamp as they were burned in.
+pub fn title_safe_overflows(clips: &[ClipInfo], style: &LowerThirdStyle) -> Vec<String> {
+    let mut found = Vec::new();
+    for (i, clip) in clips.iter().enumerate() {
+        if clip.lower_thirds.is_empty() && clip.burned_caption.is_none() && !clip.burned_timestamp { continue; }
+        let (width, height) = get_video_resolution(&clip.video_path).unwrap_or((1920, 1080));
+        let (w, h) = (width as f64, height as f64);
+        let layout = LowerThirdLayout::new(style, (width, height));
+        for caption in &clip.lower_thirds {
+            for (text, (size, y)) in [&caption.name, &caption.title].into_iter().zip(layout.lines) {
+                if text.trim().is_empty() { continue; }
+                let tw = text_width(text, size);
+                let x = if style.position.is_left() { layout.inset } else { w - tw - layout.inset };
+                if !in_title_safe([(x - layout.pad) / w, (y - layout.pad) / h, (x + tw + layout.pad) / w, (y + size + layout.pad) / h]) {
+                    found.push(format!("\"{}\" on clip {}", text.trim(), i + 1));
+                }
+            }
+        }
+        // Laid out as build_merge_cmd draws the caption and TIMESTAMP_FILTER the time.
+        if let Some(text) = &clip.burned_caption {
+            let (size, tw) = (h / 16.0, text_width(text, h / 16.0));
+            if !in_title_safe([((w - tw) / 2.0 - 12.0) / w, (h / 12.0 - 12.0) / h, ((w + tw) / 2.0 + 12.0) / w, (h / 12.0 + size + 12.0) / h]) {
+                found.push(format!("the question on clip {}", i + 1));
+            }
+        }
+        if clip.burned_timestamp {
+            let (size, tw) = (h / 30.0, text_width("0000-00-00 00:00:00", h / 30.0));
+            if !in_title_safe([(w * 0.95 - tw - 12.0) / w, (h * 0.95 - size - 12.0) / h, 0.95, 0.95]) {
+                found.push(format!("the timestamp on clip {}", i + 1));
+            }
+        }
+    }
+    found
+}
+
+/// Slides each of the clip's lower thirds in from the style's side and back out, as a name plate
+/// in the accent colour over a darker title plate. Inputs start at zero after the trim, so times
+/// are shifted by the clip's in point. Empty when the clip has none.
+fn lower_thirds_filter(clip: &ClipInfo, style: &LowerThirdStyle, (width, height): (u32, u32)) -> String {
+    let LowerThirdLayout { inset: left, pad, lines: [(name_size, name_y), (title_size, title_y)] } = LowerThirdLayout::new(style, (width, height));
+    let [r, g, b] = style.accent;
+    let slide = style.slide_secs.max(0.01);
+    let font = style.font.as_deref().map_or(String::new(), |path| format!("fontfile={}:", escape_filter_path(path)));
+    let mut filter = String::new();
+    for caption in &clip.lower_thirds {
+        let (start, end) = (caption.start - clip.trim_in, caption.end - clip.trim_in);
+        if end <= 0.0 || end <= start { continue; }
+        let shown = format!("min(clip((t-({start:.3}))/{slide:.3}\\,0\\,1)\\,clip(({end:.3}-t)/{slide:.3}\\,0\\,1))");
+        let x = if style.position.is_left() {
+            format!("{left}-(1-{shown})*(tw+{left}+{pad})")
+        } else {
+            format!("{width}-tw-{left}+(1-{shown})*(tw+{left}+{pad})")
+        };
+        let enable = format!("between(t\\,({start:.3})\\,{end:.3})");
+        let lines = [(&caption.name, name_size, name_y, format!("0x{r:02x}{g:02x}{b:02x}@0.9")), (&caption.title, title_size, title_y, String::from("black@0.6"))];
+        for (text, size, y, plate) in lines {
+            if text.trim().is_empty() { continue; }
+            filter.push_str(&format!(",drawtext={font}text={}:fontcolor=white:fontsize={size}:box=1:boxcolor={plate}:boxborderw={pad}:x={x}:y={y}:enable={enable}", escape_drawtext(text)));
+        }
+    }
+    filter
+}
+
+/// Escapes caption text for drawtext inside `-filter_complex`, which unescapes it twice before
+/// drawtext expands `%` sequences. Backslashes are dropped and straight quotes curled rather than
+/// escaped three levels deep.
+fn escape_drawtext(text: &str) -> String {
+    text.replace('\\', "").replace('\'', "\u{2019}").replace('%', "\\\\\\\\%").replace(':', "\\\\:")
+        .replace(',', "\\,").replace(';', "\\;").replace('[', "\\[").replace(']', "\\]")
+}
+
+/// The transition out of `prev` into `next`, if any, shortened to half of the shorter clip so
+/// neighbouring transitions never overlap.
+fn boundary(prev: &ClipInfo, next: &ClipInfo) -> Option<(&'static str, f64)> {
+    let t = prev.transition?;
+    Some((t.kind.xfade_name(), t.secs.min(prev.trimmed_duration() / 2.0).min(next.trimmed_duration() / 2.0)))
+}
+
+pub fn has_transitions(clips: &[ClipInfo]) -> bool {
+    clips.windows(2).any(|pair| boundary(&pair[0], &pair[1]).is_some())
+}
+
+/// Running time of the timeline once every transition's overlap is taken out.
+pub fn transition_timeline_secs(clips: &[ClipInfo]) -> f64 {
+    let overlap: f64 = clips.windows(2).filter_map(|pair| boundary(&pair[0], &pair[1])).map(|(_, secs)| secs).sum();
+    clips.iter().map(ClipInfo::trimmed_duration).sum::<f64>() - overlap
+}
+
+/// Joins clips with `xfade`/`acrossfade` wherever a clip asks for a transition into the next, and
+/// a plain concat elsewhere. Inputs are re-timed from zero and brought to the first clip's frame
+/// rate first, as `xfade` needs matching rates and timebases.
+pub fn build_transition_cmd(clips: &[ClipInfo], style: &LowerThirdStyle, output: &str) -> Vec<String> {
+    let size = timeline_size(clips);
+    let normalize = normalize_filter(size);
+    let rate = clips.first().and_then(|c| get_frame_format(&c.video_path)).map_or(String::from("30"), |(_, _, rate)| rate);
+    let mut args = Vec::new();
+    let mut graph = String::new();
+    for (i, clip) in clips.iter().enumerate() {
+        args.extend(clip_input(clip));
+        let captions = lower_thirds_filter(clip, style, size);
+        graph.push_str(&format!("[{i}:v]setpts=PTS-STARTPTS,fps={rate},settb=AVTB,{normalize}{captions}[v{i}];"));
+        graph.push_str(&audio_chain(i, clip));
+    }
+
+    let mut length = clips.first().map(ClipInfo::trimmed_duration).unwrap_or(0.0);
+    let (mut v, mut a) = (String::from("[v0]"), String::from("[a0]"));
+    for i in 1..clips.len() {
+        let (next_v, next_a) = (format!("[xv{i}]"), format!("[xa{i}]"));
+        match boundary(&clips[i - 1], &clips[i]) {
+            Some((name, secs)) => {
+                graph.push_str(&format!("{v}[v{i}]xfade=transition={name}:duration={secs:.3}:offset={:.3}{next_v};{a}[a{i}]acrossfade=d={secs:.3}{next_a};", length - secs));
+                length += clips[i].trimmed_duration() - secs;
+            },
+            None => {
+                graph.push_str(&format!("{v}{a}[v{i}][a{i}]concat=n=2:v=1:a=1{next_v}{next_a};"));
+                length += clips[i].trimmed_duration();
+            }
+        }
+        (v, a) = (next_v, next_a);
+    }
+    graph.pop();
+
+    args.extend([
+        String::from("-filter_complex"), graph,
+        String::from("-map"), v, String::from("-map"), a,
+        String::from("-c:v"), String::from("libx264"), String::from("-preset"), String::from("medium"), String::from("-crf"), String::from("18"),
+        String::from("-pix_fmt"), String::from("yuv420p"), String::from("-c:a"), String::from("aac"), String::from("-b:a"), String::from("192k"),
+        String::from("-y"), output.to_string()
+    ]);
+    args
+}
+
+/// Input `i`'s audio as `[a{i}]`, started from zero at 48 kHz stereo so clips can be joined and
+/// crossfaded, or silence as long as the clip when it has no audio track.
+fn audio_chain(i: usize, clip: &ClipInfo) -> String {
+    if has_audio(&clip.video_path) {
+        format!("[{i}:a]asetpts=PTS-STARTPTS,aresample=48000,aformat=channel_layouts=stereo[a{i}];")
+    } else {
+        format!("anullsrc=r=48000:cl=stereo,atrim=duration={:.3}[a{i}];", clip.trimmed_duration())
+    }
+}
+
+/// Describes the first clip whose streams differ from the first clip's, which the concat demuxer
+/// can't stream-copy across. Clips that can't be probed are given the benefit of the doubt.
+pub fn concat_mismatch(clips: &[ClipInfo]) -> Option<String> {
+    let first = stream_summary(&clips.first()?.video_path)?;
+    clips.iter().enumerate().skip(1).find_map(|(i, clip)| match stream_summary(&clip.video_path) {
+        Some(summary) if summary != first => Some(format!("clip {} is {} but clip 1 is {}", i + 1, summary, first)),
+        _ => None
+    })
+}
 
-            vec!["-c:v", "h264_nvenc", "-vf", "format=yuv420p",
-                "-preset", preset, "-rc:v", "vbr", "-cq", cq]
+/// Describes the first clip whose streams differ from an earlier export's, which keeps the clips
+/// from being stream-copied onto its end. Clips that can't be probed are given the benefit of the doubt.
+pub fn append_mismatch(base: &Path, clips: &[ClipInfo]) -> Option<String> {
+    let base_summary = stream_summary(base)?;
+    clips.iter().enumerate().find_map(|(i, clip)| match stream_summary(&clip.video_path) {
+        Some(summary) if summary != base_summary => Some(format!("clip {} is {} but the export is {}", i + 1, summary, base_summary)),
+        _ => None
+    })
+}
+
+/// Re-encodes the clips, trimmed and captioned, to an earlier export's codecs, frame size, frame
+/// rate and audio format so they can be stream-copied onto its end; `frame` overrides the size and
+/// rate. `None` if the export can't be probed or uses a codec there's no encoder here for.
+pub fn build_conform_cmd(base: &Path, frame: Option<FrameFormat>, clips: &[ClipInfo], style: &LowerThirdStyle, output: &str) -> Option<Vec<String>> {
+    let probe = Command::new("ffprobe").args([
+        "-v", "error",
+        "-show_entries", "stream=codec_type,codec_name,width,height,pix_fmt,r_frame_rate,time_base,sample_rate,channels",
+        "-of", "json"
+    ]).arg(base).output().ok()?;
+    let json: serde_json::Value = serde_json::from_slice(&probe.stdout).ok()?;
+    let streams = json["streams"].as_array()?;
+    let video = streams.iter().find(|s| s["codec_type"] == "video")?;
+    let audio = streams.iter().find(|s| s["codec_type"] == "audio")?;
+    let video_encoder = match video["codec_name"].as_str()? {
+        "h264" => "libx264",
+        "hevc" => "libx265",
+        _ => return None
+    };
+    let audio_encoder = match audio["codec_name"].as_str()? {
+        "aac" => "aac",
+        "opus" => "libopus",
+        "mp3" => "libmp3lame",
+        _ => return None
+    };
+    let (size, rate) = match frame {
+        Some(f) => ((f.width, f.height), f.fps.to_string()),
+        None => ((video["width"].as_u64()? as u32, video["height"].as_u64()? as u32), video["r_frame_rate"].as_str()?.to_string())
+    };
+    let pix_fmt = video["pix_fmt"].as_str().unwrap_or("yuv420p");
+    // The concat demuxer copes best when every file counts time in the same units.
+    let timescale = video["time_base"].as_str().and_then(|t| t.split_once('/')).map(|(_, d)| d.to_string());
+
+    let normalize = normalize_filter(size);
+    let mut args = Vec::new();
+    let mut graph = String::new();
+    let mut inputs = String::new();
+    for (i, clip) in clips.iter().enumerate() {
+        args.extend(clip_input(clip));
+        let captions = lower_thirds_filter(clip, style, size);
+        graph.push_str(&format!("[{i}:v]{normalize},fps={rate},format={pix_fmt}{captions}[n{i}];"));
+        graph.push_str(&audio_chain(i, clip));
+        inputs.push_str(&format!("[n{i}][a{i}]"));
+    }
+    args.extend([
+        String::from("-filter_complex"), format!("{}{}concat=n={}:v=1:a=1[v][a]", graph, inputs, clips.len()),
+        String::from("-map"), String::from("[v]"), String::from("-map"), String::from("[a]"),
+        String::from("-c:v"), video_encoder.to_string(), String::from("-preset"), String::from("medium"), String::from("-crf"), String::from("18"),
+        String::from("-c:a"), audio_encoder.to_string(), String::from("-b:a"), String::from("192k")
+    ]);
+    if let Some(rate) = audio["sample_rate"].as_str() {
+        args.extend([String::from("-ar"), rate.to_string()]);
+    }
+    if let Some(channels) = audio["channels"].as_u64() {
+        args.extend([String::from("-ac"), channels.to_string()]);
+    }
+    if let Some(timescale) = timescale {
+        args.extend([String::from("-video_track_timescale"), timescale]);
+    }
+    args.extend([String::from("-y"), output.to_string()]);
+    Some(args)
+}
+
+/// Re-encodes a whole take, untrimmed and uncaptioned, to another take's codecs and the timeline's
+/// frame size and rate, so a session recorded in more than one format still joins up. The take's
+/// trims and lower thirds stay on its `ClipInfo` for the export to apply.
+pub fn build_take_conform_cmd(base: &Path, frame: FrameFormat, clip: &ClipInfo, output: &str) -> Option<Vec<String>> {
+    let whole = ClipInfo { trim_in: 0.0, trim_out: None, lower_thirds: Vec::new(), ..clip.clone() };
+    build_conform_cmd(base, Some(frame), &[whole], &LowerThirdStyle::default(), output)
+}
+
+/// A short description of a file's streams, e.g. "h264 1920x1080 yuv420p tv range 30/1 fps, aac 48000 Hz 2ch".
+/// The colour range is part of it because mixing full- and limited-range clips in one stream-copied
+/// file makes some of them look washed out.
+fn stream_summary(path: &Path) -> Option<String> {
+    let output = Command::new("ffprobe").args([
+        "-v", "error",
+        "-show_entries", "stream=codec_type,codec_name,width,height,pix_fmt,color_range,r_frame_rate,sample_rate,channels",
+        "-of", "json"
+    ]).arg(path).output().ok()?;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    let field = |s: &serde_json::Value, key: &str| s[key].as_str().map(String::from).or_else(|| s[key].as_u64().map(|n| n.to_string())).unwrap_or_default();
+
+    let streams: Vec<String> = json["streams"].as_array()?.iter().map(|s| match s["codec_type"].as_str() {
+        Some("video") => format!("{} {}x{} {} {} range {} fps", field(s, "codec_name"), field(s, "width"), field(s, "height"), field(s, "pix_fmt"),
+            if s["color_range"].is_string() { field(s, "color_range") } else { String::from("unknown") }, field(s, "r_frame_rate")),
+        Some("audio") => format!("{} {} Hz {}ch", field(s, "codec_name"), field(s, "sample_rate"), field(s, "channels")),
+        _ => field(s, "codec_name")
+    }).collect();
+    Some(streams.join(", "))
+}
+
+/// Crops the concat list to 16:9 for `output` and to 9:16 for `vertical_output(output)`, decoding
+/// the timeline once. Both are centred unless a `reframe` sendcmd script moves the vertical window.
+pub fn build_dual_cmd(list_file: &str, output: &Path, reframe: Option<&Path>) -> Vec<String> {
+    let mut args: Vec<String> = vec!["-f", "concat", "-safe", "0", "-i", list_file].into_iter().map(String::from).collect();
+    let vertical = match reframe {
+        Some(script) => format!("sendcmd=f={},crop@reframe={}:x=(iw-ow)/2", escape_filter_path(script), crop_size(9, 16)),
+        None => format!("crop={}", crop_size(9, 16))
+    };
+    args.push(String::from("-filter_complex"));
+    args.push(format!("[0:v]split=2[h][v];[h]crop={}[hv];[v]{}[vv]", crop_size(16, 9), vertical));
+    for (label, path) in [("[hv]", output.to_path_buf()), ("[vv]", vertical_output(output))] {
+        args.extend([String::from("-map"), String::from(label), String::from("-map"), String::from("0:a?")]);
+        args.extend(["-c:v", "libx264", "-preset", "veryfast", "-crf", "18", "-pix_fmt", "yuv420p", "-c:a", "aac", "-b:a", "128k", "-y"].into_iter().map(String::from));
+        args.push(path.to_string_lossy().to_string());
+    }
+    args
+}
+
+/// Re-encodes a concat list to 1080x1920, cropping a 9:16 window at the anchor or padding the
+/// whole picture with black bars placed by it.
+pub fn build_vertical_cmd(list_file: &str, frame: &VerticalFrame, encoding: AudioEncoding, output: &str) -> Vec<String> {
+    let anchor = frame.anchor.clamp(0.0, 1.0);
+    let fit = match frame.fit {
+        VerticalFit::Crop => format!("crop={}:x=(iw-ow)*{anchor:.3}:y=(ih-oh)/2,scale=1080:1920", crop_size(9, 16)),
+        VerticalFit::Pad => format!("scale=1080:1920:force_original_aspect_ratio=decrease,pad=1080:1920:(ow-iw)/2:trunc((oh-ih)*{anchor:.3}/2)*2")
+    };
+    let mut args: Vec<String> = vec!["-f", "concat", "-safe", "0", "-i", list_file].into_iter().map(String::from).collect();
+    args.extend([String::from("-vf"), format!("{},setsar=1,format=yuv420p", fit)]);
+    args.extend(["-c:v", "libx264", "-preset", "medium", "-crf", "18"].into_iter().map(String::from));
+    args.extend(audio_codec_args(encoding));
+    args.extend([String::from("-y"), output.to_string()]);
+    args
+}
+
+/// The largest `w:h` window, rounded down to even dimensions for 4:2:0.
+fn crop_size(w: u32, h: u32) -> String {
+    format!("trunc(min(iw\\,ih*{w}/{h})/2)*2:trunc(min(ih\\,iw*{h}/{w})/2)*2")
+}
+
+/// Escapes a path for use as a filter option inside `-filter_complex`, where both the graph and
+/// the option parser treat `:` and `\` specially.
+pub fn escape_filter_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/").replace(':', "\\\\:")
+}
+
+pub fn vertical_output(output: &Path) -> PathBuf {
+    let stem = output.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| String::from("vid"));
+    output.with_file_name(format!("{}_vertical.mp4", stem))
+}
+
+/// Per-rendition files written for a non-HLS ladder export, highest quality first.
+pub fn ladder_outputs(output: &Path) -> Vec<PathBuf> {
+    let stem = output.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| String::from("vid"));
+    let dir = output.parent().map(Path::to_path_buf).unwrap_or_default();
+    LADDER.iter().map(|(name, _, _)| dir.join(format!("{}_{}.mp4", stem, name))).collect()
+}
+
+/// Directory the HLS ladder is written into for a given export target.
+pub fn hls_dir(output: &Path) -> PathBuf {
+    let stem = output.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| String::from("vid"));
+    output.parent().map(Path::to_path_buf).unwrap_or_default().join(format!("{}_hls", stem))
+}
+
+/// Encodes interleaved little-endian f32 samples from stdin straight into a compressed mp4 track.
+pub fn build_audio_cmd(sample_rate: u32, channels: u16, encoding: AudioEncoding, filename: &str) -> Vec<String> {
+    let mut args = vec![
+        String::from("-f"), String::from("f32le"),
+        String::from("-ar"), sample_rate.to_string(),
+        String::from("-ac"), channels.to_string(),
+        String::from("-i"), String::from("-")
+    ];
+    if let Some(pan) = build_pan_filter(channels, encoding) {
+        args.push(String::from("-af"));
+        args.push(pan);
+    }
+    args.extend(audio_codec_args(encoding));
+    args.extend([
+        String::from("-f"), String::from("mp4"),
+        String::from("-y"), filename.to_string()
+    ]);
+    args
+}
+
+/// Picks the requested input channels and folds them down. Stereo sends alternating picks left and
+/// right so e.g. inputs 3+4 of an interface land as L/R; a single pick is duplicated to both sides.
+fn build_pan_filter(channels: u16, encoding: AudioEncoding) -> Option<String> {
+    if encoding.channel_mask == 0 && encoding.downmix == Downmix::Keep { return None; }
+
+    let picked: Vec<u16> = (0..channels.min(32)).filter(|c| encoding.channel_mask == 0 || encoding.channel_mask & (1 << c) != 0).collect();
+    if picked.is_empty() { return None; }
+
+    let average = |sources: &[u16]| -> String {
+        let gain = 1.0 / sources.len() as f64;
+        sources.iter().map(|c| format!("{:.4}*c{}", gain, c)).collect::<Vec<_>>().join("+")
+    };
+
+    let pan = match encoding.downmix {
+        Downmix::Keep => {
+            let outs: Vec<String> = picked.iter().enumerate().map(|(o, c)| format!("c{}=c{}", o, c)).collect();
+            format!("pan={}c|{}", picked.len(), outs.join("|"))
         },
-        EncoderPreset::AMD => vec!["-c:v", "h264_amf", "-vf", "format=yuv420p", "-usage", "transcoding"],
-        EncoderPreset::INTEL => vec!["-c:v", "h264_qsv", "-vf", "format=nv12", "-preset", "medium"]
+        Downmix::Mono => format!("pan=mono|c0={}", average(&picked)),
+        Downmix::Stereo => {
+            let left: Vec<u16> = picked.iter().step_by(2).copied().collect();
+            let right: Vec<u16> = if picked.len() > 1 { picked.iter().skip(1).step_by(2).copied().collect() } else { left.clone() };
+            format!("pan=stereo|c0={}|c1={}", average(&left), average(&right))
+        }
     };
+    Some(pan)
+}
 
-    for arg in enc_args { args.push(arg.to_string()); }
-    args.push(String::from("-y"));
-    args.push(filename.to_string());
+/// Muxes the raw segment video with its audio, if it was recorded with any. The config's audio offset is how late the audio
+/// arrives, so a positive value pulls the audio track earlier. A `caption` drawn over the first
+/// seconds of the picture or the config's watermark costs a libx264 re-encode of the video;
+/// otherwise both tracks are copied. Audio whose measured `clock` drifted past
+/// [`DRIFT_TOLERANCE_PPM`] is retimed to the wall clock and re-encoded, so long takes end in sync.
+/// A take split across files is tagged with its take number and which part of how many this is.
+pub fn build_merge_cmd(config: &RecorderConfig, video: &str, audio: Option<&str>, clock: Option<AudioClock>, caption: Option<&str>, part: Option<(u32, usize, usize)>, output: &str) -> Vec<String> {
+    let audio_offset_ms = config.audio_offset_ms;
+    let retime = clock.filter(|clock| clock.drift_ppm().abs() >= DRIFT_TOLERANCE_PPM);
+    let mut args = vec![String::from("-i"), video.to_string()];
+    if let Some(audio) = audio {
+        // Retimed audio gets its timestamps rewritten, so the offset goes into the new ones instead.
+        if audio_offset_ms != 0 && retime.is_none() {
+            args.push(String::from("-itsoffset"));
+            args.push(format!("{:.3}", -(audio_offset_ms as f64) / 1000.0));
+        }
+        args.extend([String::from("-i"), audio.to_string()]);
+    }
+    let logo_input = if audio.is_some() { 2 } else { 1 };
+    let caption = caption.map(|text| format!("drawtext=text={}:fontcolor=white:fontsize=h/16:box=1:boxcolor=black@0.6:boxborderw=12:x=(w-tw)/2:y=h/12:enable=lt(t\\,{})", escape_drawtext(text), CAPTION_SECS));
+    let video_map = match (caption, config.watermark.as_ref()) {
+        (None, None) => None,
+        (Some(caption), None) => {
+            args.extend([String::from("-vf"), caption]);
+            Some(String::from("0:v"))
+        },
+        (caption, Some(mark)) => {
+            args.extend([String::from("-i"), mark.image.to_string_lossy().to_string()]);
+            args.extend([String::from("-filter_complex"), format!("[0:v]{}[base];{}", caption.as_deref().unwrap_or("null"), watermark_graph(mark, logo_input))]);
+            Some(String::from("[v]"))
+        }
+    };
+    let reencode = video_map.is_some();
+    args.extend([String::from("-map"), video_map.unwrap_or_else(|| String::from("0:v"))]);
+    if reencode {
+        args.extend(["-c:v", "libx264", "-preset", "veryfast", "-crf", "18", "-pix_fmt", "yuv420p"].into_iter().map(String::from));
+    } else {
+        args.extend(["-c:v", "copy"].into_iter().map(String::from));
+    }
+    match retime {
+        _ if audio.is_none() => {},
+        // Stamping each sample at the measured rate and letting aresample stretch to those
+        // timestamps spreads the correction over the whole take instead of dropping chunks. The
+        // offset shifts every stamp, and aresample pads or trims the start to line up with zero.
+        Some(clock) => {
+            let shift = -(audio_offset_ms as f64) / 1000.0;
+            args.extend([String::from("-map"), String::from("1:a"), String::from("-af"), format!("asetpts=N/{:.3}/TB{:+.3}/TB,aresample=async=1000:first_pts=0", clock.measured, shift)]);
+            args.extend(audio_codec_args(config.audio));
+        },
+        None => args.extend(["-map", "1:a", "-c:a", "copy"].into_iter().map(String::from))
+    }
+    if let Some((take, index, count)) = part {
+        args.extend([String::from("-metadata"), format!("comment=clipper:take={};part={}/{}", take, index, count)]);
+    }
+    args.extend(["-metadata", "creation_time=now", "-y"].into_iter().map(String::from));
+    args.push(output.to_string());
+    args
+}
+
+/// Overlays input `input`'s logo on `[base]` as `[v]`. scale2ref sizes it against the frame,
+/// keeping the logo's aspect ratio.
+fn watermark_graph(mark: &Watermark, input: usize) -> String {
+    format!("[{input}:v][base]scale2ref=w=oh*mdar:h=ih*{:.3}[logo][frame];[logo]format=rgba,colorchannelmixer=aa={:.2}[faded];[frame][faded]overlay=x=(W-w)*{:.3}:y=(H-h)*{:.3},format=yuv420p[v]",
+        mark.height.clamp(0.01, 1.0), mark.opacity.clamp(0.0, 1.0), mark.x.clamp(0.0, 1.0), mark.y.clamp(0.0, 1.0))
+}
+
+/// Burns a logo into a finished export, copying its audio.
+pub fn build_watermark_cmd(input: &str, mark: &Watermark, output: &str) -> Vec<String> {
+    let mut args = vec![String::from("-i"), input.to_string(), String::from("-i"), mark.image.to_string_lossy().to_string()];
+    args.extend([String::from("-filter_complex"), format!("[0:v]null[base];{}", watermark_graph(mark, 1))]);
+    args.extend(["-map", "[v]", "-map", "0:a?", "-c:v", "libx264", "-preset", "medium", "-crf", "18", "-c:a", "copy", "-y"].into_iter().map(String::from));
+    args.push(output.to_string());
+    args
+}
+
+/// Estimates how late the audio is in a clip of a single hand clap, in milliseconds.
+/// The clap is taken as the loudest audio instant; the hands meet on the first frame after the
+/// motion peak where frame-to-frame difference falls below half of that peak.
+pub fn measure_clap_offset(path: &Path) -> Option<f64> {
+    let audio = metadata_series(path, &["-vn", "-af"], "asetnsamples=n=480,astats=metadata=1:reset=1,ametadata=print:key=lavfi.astats.Overall.Peak_level:file=-", "lavfi.astats.Overall.Peak_level");
+    let video = metadata_series(path, &["-an", "-vf"], "signalstats,metadata=print:key=lavfi.signalstats.YDIF:file=-", "lavfi.signalstats.YDIF");
+
+    let (clap_audio, _) = audio.iter().cloned().fold(None, |best: Option<(f64, f64)>, (t, v)| match best {
+        Some((_, bv)) if bv >= v => best,
+        _ => Some((t, v))
+    })?;
+
+    let peak_index = video.iter().enumerate().max_by(|a, b| a.1.1.total_cmp(&b.1.1))?.0;
+    let peak = video[peak_index].1;
+    let (clap_video, _) = video[peak_index..].iter().find(|(_, v)| *v < peak / 2.0)?;
+
+    Some((clap_audio - clap_video) * 1000.0)
+}
+
+/// Runs a metadata-printing filter over `path` and collects `(pts_time, value)` pairs for `key`.
+fn metadata_series(path: &Path, filter_flags: &[&str], filter: &str, key: &str) -> Vec<(f64, f64)> {
+    let output = Command::new("ffmpeg").arg("-i").arg(path).args(filter_flags).arg(filter)
+        .args(["-f", "null", "-"]).output();
+    let Ok(output) = output else { return Vec::new(); };
+
+    let mut series = Vec::new();
+    let mut pts_time = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(t) = line.split_whitespace().find_map(|tok| tok.strip_prefix("pts_time:")) {
+            pts_time = t.parse::<f64>().ok();
+        } else if let Some(v) = line.strip_prefix(key).and_then(|rest| rest.strip_prefix('='))
+            && let (Some(t), Ok(v)) = (pts_time, v.trim().parse::<f64>()) {
+            series.push((t, v));
+        }
+    }
+    series
+}
+
+/// Swaps the audio track of `clip` for `audio`, padding with silence so the video keeps its full length.
+pub fn build_voiceover_cmd(clip: &Path, audio: &str, encoding: AudioEncoding, output: &Path) -> Vec<String> {
+    let mut args = vec![
+        String::from("-i"), clip.to_string_lossy().to_string(),
+        String::from("-i"), audio.to_string(),
+        String::from("-map"), String::from("0:v"),
+        String::from("-map"), String::from("1:a"),
+        String::from("-c:v"), String::from("copy")
+    ];
+    args.extend(audio_codec_args(encoding));
+    args.extend([
+        String::from("-af"), String::from("apad"),
+        String::from("-shortest"),
+        String::from("-y"), output.to_string_lossy().to_string()
+    ]);
+    args
+}
+
+/// Copies a room tone take, tagging it so it's recognisable outside Clipper.
+pub fn build_room_tone_tag_cmd(input: &str, output: &Path) -> Vec<String> {
+    vec![
+        String::from("-i"), input.to_string(),
+        String::from("-c"), String::from("copy"),
+        String::from("-metadata"), String::from("title=Room tone"),
+        String::from("-metadata"), String::from("comment=clipper:room_tone"),
+        String::from("-y"), output.to_string_lossy().to_string()
+    ]
+}
+
+/// Lays looped room tone under a finished export wherever its audio drops out. The tone is ducked
+/// hard by the programme audio, so it only comes through in gaps and the picture is copied.
+pub fn build_room_tone_mix_cmd(input: &str, room_tone: &Path, output: &str) -> Vec<String> {
+    vec![
+        String::from("-i"), input.to_string(),
+        String::from("-stream_loop"), String::from("-1"),
+        String::from("-i"), room_tone.to_string_lossy().to_string(),
+        String::from("-filter_complex"),
+        String::from("[0:a]asplit=2[prog][key];[1:a][key]sidechaincompress=threshold=0.01:ratio=20:attack=5:release=250[tone];[prog][tone]amix=inputs=2:duration=first:normalize=0[a]"),
+        String::from("-map"), String::from("0:v"),
+        String::from("-map"), String::from("[a]"),
+        String::from("-c:v"), String::from("copy"),
+        String::from("-c:a"), String::from("aac"),
+        String::from("-b:a"), String::from("192k"),
+        String::from("-y"), output.to_string()
+    ]
+}
+
+/// An ffmetadata file with one chapter per clip, titled by its label or "Clip N". With
+/// `transitions`, each chapter starts where the transition into it begins.
+pub fn build_chapter_metadata(clips: &[ClipInfo], transitions: bool) -> String {
+    let mut metadata = String::from(";FFMETADATA1\n");
+    let mut start = 0.0;
+    for (i, clip) in clips.iter().enumerate() {
+        let overlap = match clips.get(i + 1) {
+            Some(next) if transitions => boundary(clip, next).map(|(_, secs)| secs).unwrap_or(0.0),
+            _ => 0.0
+        };
+        let end = start + clip.trimmed_duration() - overlap;
+        let title = if clip.label.trim().is_empty() { format!("Clip {}", i + 1) } else { clip.label.trim().to_string() };
+        metadata.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (start * 1000.0).round() as u64, (end * 1000.0).round() as u64, escape_metadata(&title)
+        ));
+        start = end;
+    }
+    metadata
+}
+
+/// Escapes the characters ffmetadata treats as syntax.
+fn escape_metadata(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Copies `input` with the export's tags and, if given, the chapters from an ffmetadata file and
+/// a poster image as cover art. `date` is a `YYYY-MM-DD` day stamped alongside the creation time.
+pub fn build_tag_cmd(input: &str, chapters: Option<&str>, poster: Option<&str>, metadata: &ExportMetadata, date: &str, output: &str) -> Vec<String> {
+    let mut args = vec![String::from("-i"), input.to_string()];
+    for extra in [chapters, poster].into_iter().flatten() {
+        args.extend([String::from("-i"), extra.to_string()]);
+    }
+    args.extend(["-map", "0", "-map_metadata", "0"].into_iter().map(String::from));
+    if chapters.is_some() {
+        args.extend([String::from("-map_chapters"), String::from("1")]);
+    }
+    if poster.is_some() {
+        let index = if chapters.is_some() { 2 } else { 1 };
+        args.extend([String::from("-map"), format!("{}:v", index), String::from("-disposition:v:1"), String::from("attached_pic")]);
+    }
+    for (key, value) in [("title", &metadata.title), ("artist", &metadata.author), ("comment", &metadata.comment)] {
+        if !value.trim().is_empty() {
+            args.extend([String::from("-metadata"), format!("{}={}", key, value.trim())]);
+        }
+    }
+    args.extend([String::from("-metadata"), format!("date={}", date)]);
+    args.extend(["-metadata", "creation_time=now", "-movflags", "+use_metadata_tags", "-c", "copy", "-y"].into_iter().map(String::from));
+    args.push(output.to_string());
+    args
+}
+
+/// Grabs a full-size frame to embed as the export's cover art.
+pub fn build_poster_cmd(input: &Path, at: f64, output: &str) -> Vec<String> {
+    vec![
+        String::from("-ss"), format!("{:.3}", at),
+        String::from("-i"), input.to_string_lossy().to_string(),
+        String::from("-vframes"), String::from("1"),
+        String::from("-q:v"), String::from("2"),
+        String::from("-y"), output.to_string()
+    ]
+}
+
+/// Cuts a clip in two at `at` seconds in one decode. Both halves are re-encoded so the cut lands on
+/// the exact frame rather than the nearest keyframe.
+pub fn build_split_cmd(config: &RecorderConfig, input: &Path, at: f64, first: &str, second: &str) -> Vec<String> {
+    let mut encode = video_codec_args(config);
+    encode.extend(audio_codec_args(config.audio));
+    let mut args = vec![String::from("-i"), input.to_string_lossy().to_string()];
+    args.extend([String::from("-t"), format!("{:.3}", at)]);
+    args.extend(encode.iter().cloned());
+    args.extend([String::from("-y"), first.to_string()]);
+    args.extend([String::from("-ss"), format!("{:.3}", at)]);
+    args.extend(encode);
+    args.extend([String::from("-y"), second.to_string()]);
+    args
+}
+
+pub fn build_thumb_cmd(input: &str, at: f64, output: &Path) -> Vec<String> {
+    vec![
+        String::from("-ss"), format!("{:.3}", at),
+        String::from("-i"), input.to_string(),
+        String::from("-vframes"), String::from("1"),
+        String::from("-vf"), String::from("scale=200:-1"),
+        String::from("-y"), output.to_string_lossy().to_string()
+    ]
+}
+
+pub fn build_preview_cmd(input: &str, output: &Path) -> Vec<String> {
+    vec![
+        String::from("-i"), input.to_string(),
+        String::from("-vf"), String::from("fps=5,scale=160:-1:flags=lanczos"),
+        String::from("-f"), String::from("gif"),
+        String::from("-y"), output.to_string_lossy().to_string()
+    ]
+}
+
+/// GIFs go through palettegen/paletteuse so the 256 colours are picked for this footage rather
+/// than a fixed web palette. WebP is lossy and needs no palette.
+pub fn build_animation_cmd(input: &Path, options: &AnimationOptions, output: &str) -> Vec<String> {
+    let mut args = vec![String::from("-ss"), format!("{:.3}", options.start)];
+    if let Some(end) = options.end {
+        args.extend([String::from("-to"), format!("{:.3}", end)]);
+    }
+    args.extend([String::from("-i"), input.to_string_lossy().to_string(), String::from("-an")]);
+    let scale = format!("fps={},scale={}:-2:flags=lanczos", options.fps, options.width);
+    match options.format {
+        AnimationFormat::Gif => {
+            args.extend([
+                String::from("-filter_complex"),
+                format!("{scale},split[a][b];[a]palettegen=stats_mode=diff[p];[b][p]paletteuse=dither=bayer:bayer_scale=5:diff_mode=rectangle"),
+                // GIF counts extra plays: 0 repeats forever, -1 plays once.
+                String::from("-loop"), String::from(if options.loop_forever { "0" } else { "-1" })
+            ]);
+        },
+        AnimationFormat::WebP => {
+            args.extend([
+                String::from("-vf"), scale,
+                String::from("-c:v"), String::from("libwebp"),
+                String::from("-q:v"), String::from("75"),
+                // WebP counts total plays: 0 is forever.
+                String::from("-loop"), String::from(if options.loop_forever { "0" } else { "1" })
+            ]);
+        }
+    }
+    args.extend([String::from("-y"), output.to_string()]);
+    args
+}
+
+/// Renders a clip-sized visualizer of `input`'s audio between `start` and `end`, keeping the
+/// audio, so it can sit on the timeline like any other clip.
+pub fn build_visualizer_cmd(config: &RecorderConfig, input: &Path, style: VisualizerStyle, start: f64, end: Option<f64>, output: &str) -> Vec<String> {
+    let mut args = vec![String::from("-ss"), format!("{:.3}", start)];
+    if let Some(end) = end {
+        args.extend([String::from("-to"), format!("{:.3}", end)]);
+    }
+    args.extend([String::from("-i"), input.to_string_lossy().to_string()]);
+    let size = format!("{}x{}", config.width, config.height);
+    let visual = match style {
+        VisualizerStyle::Waves => format!("showwaves=s={}:mode=cline:rate={}:colors=0x9fd3ff", size, config.fps),
+        VisualizerStyle::Spectrum => format!("showspectrum=s={}:mode=combined:color=intensity:slide=scroll,fps={}", size, config.fps)
+    };
+    let mut video = video_codec_args(config);
+    // The encoder's pixel format goes on the end of the graph, since -vf can't follow -filter_complex.
+    let format = match video.iter().position(|a| a == "-vf") {
+        Some(vf) => format!(",{}", video.drain(vf..vf + 2).nth(1).unwrap_or_default()),
+        None => String::new()
+    };
+    args.extend([
+        String::from("-filter_complex"), format!("[0:a]{}{}[v]", visual, format),
+        String::from("-map"), String::from("[v]"),
+        String::from("-map"), String::from("0:a")
+    ]);
+    args.extend(video);
+    args.extend(audio_codec_args(config.audio));
+    args.extend([String::from("-shortest"), String::from("-y"), output.to_string()]);
     args
 }
 
+pub fn build_waveform_cmd(input: &str, output: &Path) -> Vec<String> {
+    vec![
+        String::from("-i"), input.to_string(),
+        String::from("-filter_complex"), String::from("aformat=channel_layouts=mono,showwavespic=s=240x48:colors=0x9fd3ff"),
+        String::from("-frames:v"), String::from("1"),
+        String::from("-y"), output.to_string_lossy().to_string()
+    ]
+}
+
+/// Runs blackdetect and freezedetect over a clip, discarding the output. Only stretches of at
+/// least half a second black or one second frozen are reported.
+pub fn build_defect_scan_cmd(input: &str) -> Vec<String> {
+    vec![
+        String::from("-hide_banner"),
+        String::from("-i"), input.to_string(),
+        String::from("-vf"), String::from("blackdetect=d=0.5:pix_th=0.10,freezedetect=n=-60dB:d=1"),
+        String::from("-an"),
+        String::from("-f"), String::from("null"), String::from("-")
+    ]
+}
+
+/// Totals the black and frozen durations from the stderr of `build_defect_scan_cmd`.
+pub fn parse_defect_scan(stderr: &str) -> (f64, f64) {
+    let mut black = 0.0;
+    let mut frozen = 0.0;
+    for line in stderr.lines() {
+        // "[blackdetect @ 0x..] black_start:0 black_end:1.2 black_duration:1.2"
+        if let Some(v) = line.split("black_duration:").nth(1).and_then(|v| v.split_whitespace().next()) {
+            black += v.parse::<f64>().unwrap_or(0.0);
+        }
+        // "[freezedetect @ 0x..] lavfi.freezedetect.freeze_duration: 2.5"
+        if let Some(v) = line.split("freeze_duration:").nth(1) {
+            frozen += v.trim().parse::<f64>().unwrap_or(0.0);
+        }
+    }
+    (black, frozen)
+}
+
+/// Remuxes a damaged clip while skipping whatever packets the demuxer can't make sense of.
+pub fn build_recover_cmd(input: &str, output: &str) -> Vec<String> {
+    vec![
+        String::from("-err_detect"), String::from("ignore_err"),
+        String::from("-fflags"), String::from("+genpts+discardcorrupt"),
+        String::from("-i"), input.to_string(),
+        String::from("-c"), String::from("copy"),
+        String::from("-y"), output.to_string()
+    ]
+}
+
+/// Renders an argument list as a copy-pasteable shell command line.
+pub fn command_line(args: &[String]) -> String {
+    let mut line = String::from("ffmpeg");
+    for arg in args {
+        line.push(' ');
+        if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_.,:/=+%@".contains(c)) {
+            line.push_str(arg);
+        } else {
+            line.push('"');
+            line.push_str(&arg.replace('\\', "\\\\").replace('"', "\\\""));
+            line.push('"');
+        }
+    }
+    line
+}
+
+/// Encoder presets the installed ffmpeg was built with. `None` if ffmpeg could not be run at all.
+pub fn available_encoders() -> Option<Vec<EncoderPreset>> {
+    let output = Command::new("ffmpeg").args(["-hide_banner", "-encoders"]).output().ok()?;
+    let listing = String::from_utf8_lossy(&output.stdout);
+    // Lines look like " V....D libx264              libx264 H.264 / AVC ...".
+    let names: Vec<&str> = listing.lines().filter_map(|l| l.split_whitespace().nth(1)).collect();
+    Some(EncoderPreset::ALL.into_iter().filter(|p| names.contains(&p.ffmpeg_name())).collect())
+}
+
+pub fn get_video_resolution(path: &Path) -> Option<(u32, u32)> {
+    let output = Command::new("ffprobe").args([
+        "-v", "error",
+        "-select_streams", "v:0",
+        "-show_entries", "stream=width,height",
+        "-of", "csv=p=0"
+    ]).arg(path).output().ok()?;
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut parts = text.trim().split(',');
+    let width = parts.next()?.trim().parse().ok()?;
+    let height = parts.next()?.trim().parse().ok()?;
+    Some((width, height))
+}
+
+/// Frame size and rate of a file's first video stream, the rate as ffprobe gives it, e.g. "30/1".
+pub fn get_frame_format(path: &Path) -> Option<(u32, u32, String)> {
+    let output = Command::new("ffprobe").args([
+        "-v", "error",
+        "-select_streams", "v:0",
+        "-show_entries", "stream=width,height,r_frame_rate",
+        "-of", "csv=p=0"
+    ]).arg(path).output().ok()?;
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut parts = text.trim().split(',');
+    let width = parts.next()?.trim().parse().ok()?;
+    let height = parts.next()?.trim().parse().ok()?;
+    let rate = parts.next()?.trim().to_string();
+    Some((width, height, rate))
+}
+
+/// Frames per second from a rate as ffprobe gives it, e.g. "30000/1001"; 0 if it can't be read.
+pub fn rate_fps(rate: &str) -> f64 {
+    match rate.split_once('/') {
+        Some((num, den)) => num.parse::<f64>().unwrap_or(0.0) / den.parse::<f64>().unwrap_or(1.0).max(1.0),
+        None => rate.parse().unwrap_or(0.0)
+    }
+}
+
+/// Demuxes every video packet of a file and reports whether ffprobe got through it without errors.
+pub fn is_valid_media(path: &Path) -> bool {
+    let output = Command::new("ffprobe").args([
+        "-v", "error",
+        "-count_packets",
+        "-select_streams", "v:0",
+        "-show_entries", "stream=nb_read_packets",
+        "-of", "csv=p=0"
+    ]).arg(path).output();
+
+    match output {
+        Ok(o) if o.status.success() => {
+            let packets = String::from_utf8_lossy(&o.stdout).trim().parse::<u64>().unwrap_or(0);
+            packets > 0 && o.stderr.iter().all(u8::is_ascii_whitespace)
+        },
+        _ => false
+    }
+}
+
+/// How far a saved take's length may stray from how long it was recorded for, on top of
+/// `TAKE_LENGTH_SLACK` of that length.
+const TAKE_LENGTH_GRACE_SECS: f64 = 1.0;
+const TAKE_LENGTH_SLACK: f64 = 0.05;
+
+/// Checks a freshly saved take has a video stream, and an audio stream when `with_audio`, runs
+/// about as long as it was recorded for, and decodes cleanly at both ends. Returns what's wrong
+/// with it, if anything.
+pub fn check_take(path: &Path, expected_secs: f64, with_audio: bool) -> Option<String> {
+    let output = match Command::new("ffprobe").args([
+        "-v", "error",
+        "-show_entries", "stream=codec_type:format=duration",
+        "-of", "json"
+    ]).arg(path).output() {
+        Ok(o) => o,
+        Err(e) => return Some(format!("couldn't run ffprobe: {}", e))
+    };
+    let Ok(json) = serde_json::from_slice::<serde_json::Value>(&output.stdout) else {
+        return Some(String::from("ffprobe couldn't read it"));
+    };
+    let has = |kind: &str| json["streams"].as_array().is_some_and(|s| s.iter().any(|s| s["codec_type"] == kind));
+    if !has("video") {
+        return Some(String::from("no video stream"));
+    }
+    if with_audio && !has("audio") {
+        return Some(String::from("no audio stream"));
+    }
+    let duration = json["format"]["duration"].as_str().and_then(|d| d.parse::<f64>().ok()).unwrap_or(0.0);
+    if (duration - expected_secs).abs() > TAKE_LENGTH_GRACE_SECS + expected_secs * TAKE_LENGTH_SLACK {
+        return Some(format!("{:.1}s long, recorded for {:.1}s", duration, expected_secs));
+    }
+
+    // Two seconds from each end are decoded, where an interrupted write would show.
+    for (seek, seek_to, end) in [("-ss", "0", "start"), ("-sseof", "-2", "end")] {
+        let decoded = Command::new("ffmpeg").args(["-v", "error", "-xerror", seek, seek_to, "-i"]).arg(path)
+            .args(["-t", "2", "-f", "null", "-"]).output();
+        match decoded {
+            Ok(o) if o.status.success() && o.stderr.iter().all(u8::is_ascii_whitespace) => {},
+            _ => return Some(format!("doesn't decode cleanly at the {}", end))
+        }
+    }
+    None
+}
+
 pub fn get_video_duration(path: &PathBuf) -> f64 {
     let output = Command::new("ffprobe").args(&[
         "-v", "error",
@@ -0,0 +1,98 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! Streams the camera to an RTMP server alongside takes. The stream takes its frames straight
+//! from the camera queue on its own thread, so merges and exports on the recorder thread never
+//! stall it, and a slow connection costs stream frames instead of holding up the take.
+
+use crate::{budget::{FrameQueue, QueuedFrame}, crash};
+use crossbeam_channel::{RecvTimeoutError, bounded};
+use std::{io::{self, Write}, process::{Child, Command, Stdio}, sync::Arc, thread::{self, JoinHandle}, time::{Duration, Instant}};
+
+/// Frames waiting for the stream's encoder, about a second's worth.
+const LIVE_QUEUE_LEN: usize = 30;
+
+pub struct LiveStream {
+    frames: Arc<FrameQueue>,
+    writer: JoinHandle<()>
+}
+
+impl LiveStream {
+    /// Starts the encoder and taps the camera queue for it. The encoder's raw input is timed by
+    /// frame count, so the writer sends exactly `fps` frames a second from the first one on,
+    /// repeating the newest frame when the camera is late and skipping ones that arrive early.
+    /// That keeps the picture on the wall clock the audio is timed by.
+    pub fn start(args: &[String], fps: u32, frames: Arc<FrameQueue>) -> io::Result<Self> {
+        let mut encoder: Child = Command::new("ffmpeg").args(args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().map(crash::log_stderr)?;
+        let (tap, frame_rx) = bounded::<QueuedFrame>(LIVE_QUEUE_LEN);
+        frames.set_tap(Some(tap));
+        let budget = frames.budget().clone();
+        let interval = Duration::from_secs_f64(1.0 / fps.max(1) as f64);
+        let writer = thread::spawn(move || {
+            // The newest frame holds its budget reservation until a newer one replaces it.
+            let mut latest: Option<Arc<Vec<u8>>> = None;
+            let mut started: Option<Instant> = None;
+            let mut written: u64 = 0;
+            if let Some(mut stdin) = encoder.stdin.take() {
+                loop {
+                    if let (Some(start), Some(frame)) = (started, &latest) {
+                        let due = start + interval.mul_f64(written as f64);
+                        let now = Instant::now();
+                        if now >= due {
+                            if stdin.write_all(frame).is_err() { break; }
+                            written += 1;
+                            continue;
+                        }
+                        match frame_rx.recv_timeout(due - now) {
+                            Ok((frame, _)) => if let Some(old) = latest.replace(frame) { budget.release(old.len()); },
+                            Err(RecvTimeoutError::Timeout) => {},
+                            Err(RecvTimeoutError::Disconnected) => break
+                        }
+                    } else {
+                        match frame_rx.recv() {
+                            Ok((frame, _)) => {
+                                latest = Some(frame);
+                                started = Some(Instant::now());
+                            },
+                            Err(_) => break
+                        }
+                    }
+                }
+            }
+            if let Some(frame) = latest {
+                budget.release(frame.len());
+            }
+            for (frame, _) in frame_rx.try_iter() {
+                budget.release(frame.len());
+            }
+            let _ = encoder.wait();
+        });
+        Ok(Self { frames, writer })
+    }
+
+    /// The encoder has exited, from a refused connection or a dropped one.
+    pub fn has_ended(&self) -> bool {
+        self.writer.is_finished()
+    }
+
+    /// Unhooks the stream from the camera queue, which closes the encoder's input so it flushes
+    /// and disconnects, then waits for it.
+    pub fn stop(self) {
+        self.frames.set_tap(None);
+        let _ = self.writer.join();
+    }
+}
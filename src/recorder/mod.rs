@@ -14,51 +14,230 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 pub mod types;
-mod ffmpeg;
+pub mod ffmpeg;
+pub mod naming;
+pub mod reframe;
+mod assets;
+mod backup;
+mod live;
 
-use crate::{messages::{audio::AudioCommand, recorder::{RecorderCommand, RecorderStatus}}, recorder::ffmpeg::get_video_duration};
-use types::{EncoderPreset, EncodingQuality, EncodingSpeed};
+pub use ffmpeg::{LIVE_HLS_DIR, LIVE_HLS_PLAYLIST};
+
+const TIMELAPSE_FPS: u32 = 24;
+/// Stream-copy concat drifting by more than this points at broken timestamps.
+const DURATION_TOLERANCE_SECS: f64 = 0.5;
+/// How often free space is checked while a take is being written.
+const DISK_CHECK_EVERY: Duration = Duration::from_secs(5);
+const DROP_REPORT_EVERY: Duration = Duration::from_secs(1);
+/// A hardware encoder that exits this soon after a take's first frame never got going.
+const ENCODER_START_WINDOW: Duration = Duration::from_secs(3);
+/// A folder with less room than this isn't worth starting a take part in.
+const MIN_PART_BYTES: u64 = 200_000_000;
+/// How long going live waits on the audio thread for the microphone.
+const LIVE_AUDIO_REPLY: Duration = Duration::from_secs(2);
+
+use crate::{budget::{FrameBudget, FrameQueue}, crash::{self, log_line}, recovery, storage, messages::{audio::{AudioClock, AudioCommand}, recorder::{ClipInfo, ProgressStage, RecorderCommand, RecorderStatus}}, recorder::ffmpeg::get_video_duration};
+use assets::AssetPool;
+use backup::BackupPool;
+use live::LiveStream;
+use types::{EncoderPreset, ExportOptions, FrameFormat, Lane, RecorderConfig};
+use chrono::Local;
 use crossbeam_channel::{Receiver, Sender};
-use std::{fs::{self, File}, io::Write, path::PathBuf, process::{Child, Command, Stdio}, thread, time::Instant};
+use std::{collections::VecDeque, fs::{self, File}, io::{BufRead, BufReader, Write}, path::{Path, PathBuf}, process::{Child, Command, Stdio}, sync::{Arc, atomic::{AtomicBool, Ordering}}, thread::{self, JoinHandle}, time::{Duration, Instant}};
 
-pub fn start_thread(cmd_rx: Receiver<RecorderCommand>, status_tx: Sender<RecorderStatus>, aud_tx: Sender<AudioCommand>) {
+pub fn start_thread(cmd_rx: Receiver<RecorderCommand>, frames: Arc<FrameQueue>, status_tx: Sender<RecorderStatus>, aud_tx: Sender<AudioCommand>, frame_budget: Arc<FrameBudget>) {
     thread::spawn(move || {
         let mut video_process: Option<Child> = None;
         let mut segments: Vec<PathBuf> = Vec::new();
         let mut counter = 0;
-        let mut width = 640;
-        let mut height = 480;
-        let mut fps = 30;
-        let mut format = String::from("MJPEG");
-        let mut encoder = EncoderPreset::CPU;
-        let mut quality = EncodingQuality::Med;
-        let mut speed = EncodingSpeed::Balanced;
-        let temp_vid: &str = "tmp_vid.mp4";
-        let temp_aud: &str = "tmp_aud.mp4";
+        let mut config = RecorderConfig::default();
+        let assets = AssetPool::start(status_tx.clone());
+        let backups = BackupPool::start(status_tx.clone());
+        // The take's raw files, next to where it's saved so it fills the volume the free space checks watch.
+        let (mut temp_vid, mut temp_aud, mut temp_proxy) = (String::new(), String::new(), String::new());
+        // Folder the take's current part is recorded in and will be saved to.
+        let mut recording_dir = PathBuf::new();
+        let temp_vo = temp_file("vo.mp4");
+        let mut voiceover: Option<(ClipInfo, Child)> = None;
+        let mut recording_room_tone = false;
+        let mut replay: Option<(u32, Child)> = None;
+        // The RTMP address being streamed to, with its encoder.
+        let mut live: Option<(String, LiveStream)> = None;
+        let mut interval: Option<IntervalCapture> = None;
 
         let mut clip_start_time = Instant::now();
         let mut waiting_for_first_frame = false;
         let mut frames_written: u64 = 0;
         let mut last_frame_data: Option<Vec<u8>> = None;
+        let mut last_disk_check = Instant::now();
+        let mut disk_warned = false;
+        // Queue drops as of the start of the take, and how many of the take's drops were reported.
+        let mut drops_at_start = frames.dropped();
+        let mut drops_reported = 0;
+        let mut last_drop_report = Instant::now();
+        // Set once the chosen hardware encoder fails to start, until a different one is picked.
+        let mut encoder_fallback = false;
+        // Set once a take has been saved without sound because no audio came through, so the
+        // notice is only given once.
+        let mut audio_missing_noted = false;
+        // Earlier parts of the take in progress, when it's been split to fit where it's saved.
+        let mut take_parts: Vec<TakePart> = Vec::new();
 
-        while let Ok(cmd) = cmd_rx.recv() {
+        // Commands that arrived while an ffmpeg pass was running, oldest first.
+        let mut deferred: VecDeque<RecorderCommand> = VecDeque::new();
+
+        while let Some(cmd) = deferred.pop_front().or_else(|| next_command(&cmd_rx, &frames)) {
             match cmd {
-                RecorderCommand::UpdateConfig {width: w, height: h, fps: f, format: fmt, encoder: enc, quality: qty, speed: spd } => {
-                    width = w; height = h; fps = f; format = fmt; encoder = enc; quality = qty; speed = spd;
-                    println!("Recorder config updated: {}x{}@{} fps ({})", width, height, fps, format);
+                RecorderCommand::UpdateConfig(c) => {
+                    // Clip numbers belong to the session folder.
+                    if c.workspace != config.workspace {
+                        counter = 0;
+                    }
+                    if c.encoder != config.encoder || c.encoder_device != config.encoder_device {
+                        encoder_fallback = false;
+                    }
+                    // The stream only restarts for a change in the picture it's sent.
+                    let restart_live = (c.width, c.height, c.fps, &c.format) != (config.width, config.height, config.fps, &config.format) || c.bitrate_kbps != config.bitrate_kbps;
+                    config = c;
+                    if restart_live && let Some((url, stream)) = live.take() {
+                        stream.stop();
+                        live = start_live(&config, &url, &frames, &aud_tx, &status_tx).map(|s| (url, s));
+                    }
+                    log_line!("Recorder config updated: {}x{}@{} fps ({})", config.width, config.height, config.fps, config.format);
+                    if let Err(e) = fs::create_dir_all(&config.workspace) {
+                        let _ = status_tx.send(RecorderStatus::Error(format!("Cannot create workspace {}: {}", config.workspace.display(), e)));
+                    }
+                    if let Some((seconds, proc)) = replay.take() {
+                        stop_replay(proc);
+                        replay = start_replay(&config, seconds, &status_tx).map(|p| (seconds, p));
+                    }
+                },
+                RecorderCommand::SetReplay(seconds) => {
+                    if let Some((_, proc)) = replay.take() {
+                        stop_replay(proc);
+                    }
+                    if let Some(seconds) = seconds {
+                        replay = start_replay(&config, seconds, &status_tx).map(|p| (seconds, p));
+                    }
+                },
+                RecorderCommand::SetLiveStream(url) => {
+                    if let Some((_, stream)) = live.take() {
+                        stream.stop();
+                        let _ = aud_tx.send(AudioCommand::StopLiveAudio);
+                    }
+                    if let Some(url) = url {
+                        live = start_live(&config, &url, &frames, &aud_tx, &status_tx).map(|s| (url, s));
+                    }
+                },
+                RecorderCommand::StartInterval(every_secs, timelapse) => {
+                    let dir = config.workspace.join(format!("stills_{}", Local::now().format("%H%M%S")));
+                    if let Err(e) = fs::create_dir_all(&dir) {
+                        let _ = status_tx.send(RecorderStatus::Error(format!("Cannot create {}: {}", dir.display(), e)));
+                        continue;
+                    }
+                    interval = Some(IntervalCapture {
+                        every: Duration::from_secs(every_secs.max(1) as u64),
+                        next: Instant::now(),
+                        dir,
+                        count: 0,
+                        timelapse,
+                        writers: Vec::new()
+                    });
+                },
+                RecorderCommand::StopInterval => {
+                    let Some(capture) = interval.take() else { continue; };
+                    for writer in capture.writers {
+                        let _ = writer.join();
+                    }
+                    if !capture.timelapse || capture.count == 0 {
+                        let _ = status_tx.send(RecorderStatus::IntervalFinished(capture.dir));
+                        continue;
+                    }
+                    let output = capture.dir.with_extension("mp4");
+                    let args = ffmpeg::build_timelapse_cmd(&capture.dir, TIMELAPSE_FPS, &output);
+                    if config.dry_run {
+                        report_dry_run(&status_tx, &args);
+                        continue;
+                    }
+                    match Command::new("ffmpeg").args(&args).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().and_then(|c| crash::log_stderr(c).wait()) {
+                        Ok(s) if s.success() => { let _ = status_tx.send(RecorderStatus::IntervalFinished(output)); },
+                        _ => { let _ = status_tx.send(RecorderStatus::Error("Time-lapse assembly failed".into())); }
+                    }
+                },
+                RecorderCommand::SaveReplay => {
+                    let number = next_free_counter(&config, counter) + 1;
+                    let finfile = workspace_file(&config, &naming::clip_file_name(&config.clip_template, &config.project, number));
+                    let save_args = ffmpeg::build_replay_save_cmd(&config, &finfile);
+                    if config.dry_run {
+                        report_dry_run(&status_tx, &save_args);
+                        continue;
+                    }
+                    let Some((seconds, proc)) = replay.take() else {
+                        let _ = status_tx.send(RecorderStatus::Error("Replay buffer is off".into()));
+                        continue;
+                    };
+                    // Closing the encoder finishes the segment in progress and the list with it.
+                    stop_replay(proc);
+
+                    let saved = Command::new("ffmpeg").args(&save_args).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().and_then(|c| crash::log_stderr(c).wait());
+                    match saved {
+                        Ok(s) if s.success() => {
+                            counter = number;
+                            segments.push(PathBuf::from(&finfile));
+                            back_up(&config, &backups, &finfile);
+                            // The replay encoder burns the timestamp in like the take encoder does.
+                            let info = ClipInfo { burned_timestamp: config.burn_timestamp, ..build_clip_info(&config, &finfile, counter, &assets) };
+                            let _ = status_tx.send(RecorderStatus::SegmentSaved(Box::new(info)));
+                        },
+                        _ => { let _ = status_tx.send(RecorderStatus::Error("Saving the replay failed".into())); }
+                    }
+                    replay = start_replay(&config, seconds, &status_tx).map(|p| (seconds, p));
                 },
                 RecorderCommand::SetAudioDevice(index) => {
                     if let Err(e) = aud_tx.send(AudioCommand::SelectDevice(index)) {
                         let _ = status_tx.send(RecorderStatus::Error(format!("Audio thread lost: {}", e)));
                     }
                 },
+                RecorderCommand::SetAudioApp(app) => {
+                    if let Err(e) = aud_tx.send(AudioCommand::SelectApp(app)) {
+                        let _ = status_tx.send(RecorderStatus::Error(format!("Audio thread lost: {}", e)));
+                    }
+                },
+                RecorderCommand::SetTuning(t) => {
+                    if let Err(e) = crate::tuning::apply(t) {
+                        let _ = status_tx.send(RecorderStatus::Error(e));
+                    }
+                },
+                RecorderCommand::RefreshAudioApps => {
+                    let _ = aud_tx.send(AudioCommand::RefreshApps);
+                },
                 RecorderCommand::StartSegment => {
-                    counter += 1;
+                    if !config.dry_run
+                        && let Some(free_gb) = most_free_gb(&config)
+                        && free_gb < config.min_free_gb {
+                        log_line!("Not starting a take, {:.1} GB free in {}", free_gb, config.workspace.display());
+                        let _ = status_tx.send(RecorderStatus::LowDiskSpace { free_gb, refused: true });
+                        continue;
+                    }
+                    last_disk_check = Instant::now();
+                    disk_warned = false;
+                    drops_at_start = frames.dropped();
+                    drops_reported = 0;
+                    counter = next_free_counter(&config, counter) + 1;
                     frames_written = 0;
                     last_frame_data = None;
-                    let args = ffmpeg::build_cmd(width, height, fps, &format, encoder, quality, speed, temp_vid);
-                    let child = Command::new("ffmpeg").args(&args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::inherit()).spawn();
-                    match child {
+                    take_parts.clear();
+                    if config.live_hls {
+                        let _ = fs::create_dir_all(config.workspace.join(ffmpeg::LIVE_HLS_DIR));
+                    }
+                    recording_dir = part_dir(&config, &take_parts);
+                    (temp_vid, temp_aud, temp_proxy) = take_files(&recording_dir);
+                    let args = take_cmd(&config, encoder_fallback, &temp_vid, &temp_proxy);
+                    if config.dry_run {
+                        report_dry_run(&status_tx, &args);
+                        continue;
+                    }
+                    match spawn_encoder(&args) {
                         Ok(c) => {
                             video_process = Some(c);
                             clip_start_time = Instant::now();
@@ -67,13 +246,63 @@ pub fn start_thread(cmd_rx: Receiver<RecorderCommand>, status_tx: Sender<Recorde
                         Err(e) => { let _ = status_tx.send(RecorderStatus::Error(format!("Failed to spawn ffmpeg: {}", e))); }
                     }
 
-                    let _ = aud_tx.send(AudioCommand::StartRecording(String::from(temp_aud)));
+                    if config.record_audio {
+                        let _ = aud_tx.send(AudioCommand::StartRecording(temp_aud.clone(), config.audio));
+                    }
                 },
                 RecorderCommand::WriteFrame(data, capture_time) => {
+                    frame_budget.release(data.len());
+                    if let Some((_, proc)) = &mut replay
+                        && let Some(stdin) = &mut proc.stdin {
+                        let _ = stdin.write_all(&data);
+                    }
+                    if live.as_ref().is_some_and(|(_, stream)| stream.has_ended()) {
+                        if let Some((_, stream)) = live.take() {
+                            stream.stop();
+                        }
+                        let _ = aud_tx.send(AudioCommand::StopLiveAudio);
+                        log_line!("Live stream encoder exited");
+                        let _ = status_tx.send(RecorderStatus::LiveStreamEnded);
+                    }
+                    if let Some(capture) = &mut interval
+                        && capture_time >= capture.next {
+                        capture.next += capture.every;
+                        capture.count += 1;
+                        let args = ffmpeg::build_still_cmd(&config, &ffmpeg::still_path(&capture.dir, capture.count));
+                        if config.dry_run {
+                            report_dry_run(&status_tx, &args);
+                        } else {
+                            // Encoding a full-resolution still takes longer than a frame interval, so it runs off this thread.
+                            let frame = data.clone();
+                            capture.writers.retain(|w| !w.is_finished());
+                            capture.writers.push(thread::spawn(move || write_still(&args, &frame)));
+                        }
+                        let _ = status_tx.send(RecorderStatus::StillCaptured(capture.count));
+                    }
                     if capture_time < clip_start_time { continue; }
+                    let mut split_take = false;
+                    if video_process.is_some() && last_disk_check.elapsed() >= DISK_CHECK_EVERY {
+                        last_disk_check = Instant::now();
+                        if !disk_warned
+                            && let Some(free_gb) = most_free_gb(&config)
+                            && free_gb < config.min_free_gb {
+                            log_line!("Low disk space while recording: {:.1} GB free", free_gb);
+                            let _ = status_tx.send(RecorderStatus::LowDiskSpace { free_gb, refused: false });
+                            disk_warned = true;
+                        }
+                        split_take = part_full(&config, &recording_dir, &take_parts, file_len(&temp_vid) + file_len(&temp_aud));
+                    }
+                    let take_drops = frames.dropped() - drops_at_start;
+                    if video_process.is_some() && take_drops > drops_reported && last_drop_report.elapsed() >= DROP_REPORT_EVERY {
+                        drops_reported = take_drops;
+                        last_drop_report = Instant::now();
+                        let _ = status_tx.send(RecorderStatus::FramesDropped(take_drops));
+                    }
                     if let Some(proc) = &mut video_process {
                         if waiting_for_first_frame {
-                            let _ = aud_tx.send(AudioCommand::StartRecording(temp_aud.to_string()));
+                            if config.record_audio {
+                                let _ = aud_tx.send(AudioCommand::StartRecording(temp_aud.to_string(), config.audio));
+                            }
                             clip_start_time = Instant::now();
                             waiting_for_first_frame = false;
                         }
@@ -84,17 +313,118 @@ pub fn start_thread(cmd_rx: Receiver<RecorderCommand>, status_tx: Sender<Recorde
                             }
                         }
                     }
+                    if !encoder_fallback && config.encoder.is_hardware() && clip_start_time.elapsed() < ENCODER_START_WINDOW
+                        && let Some(proc) = &mut video_process
+                        && let Ok(Some(exit)) = proc.try_wait() {
+                        log_line!("{} stopped at the start of take {} ({}), retrying with libx264", config.encoder.ffmpeg_name(), counter, exit);
+                        encoder_fallback = true;
+                        let _ = status_tx.send(RecorderStatus::EncoderFallback(config.encoder));
+                        video_process = None;
+                        match spawn_encoder(&take_cmd(&config, true, &temp_vid, &temp_proxy)) {
+                            Ok(mut c) => {
+                                // The frames the failed encoder swallowed are redone as copies of this one,
+                                // so the video still lines up with audio that started at the first frame.
+                                frames_written = frames_written.max(1);
+                                if let Some(stdin) = &mut c.stdin {
+                                    for _ in 0..frames_written {
+                                        if stdin.write_all(&data).is_err() { break; }
+                                    }
+                                }
+                                last_frame_data = Some((*data).clone());
+                                video_process = Some(c);
+                            },
+                            Err(e) => { let _ = status_tx.send(RecorderStatus::Error(format!("Failed to spawn ffmpeg: {}", e))); }
+                        }
+                    }
+                    // The take so far is closed off as a part and a new encoder picks up from the next
+                    // frame. Frames queue up meanwhile, and audio restarts with that frame as on a new take.
+                    if split_take && let Some(mut proc) = video_process.take() {
+                        let bytes = file_len(&temp_vid) + file_len(&temp_aud);
+                        if let Err(e) = proc.wait() {
+                            log_line!("Video process wait error: {}", e);
+                        }
+                        let (ack_tx, ack_rx) = crossbeam_channel::bounded(1);
+                        let clock = if config.record_audio {
+                            aud_tx.send(AudioCommand::StopRecording(ack_tx)).ok().and_then(|_| ack_rx.recv().ok()).flatten()
+                        } else {
+                            None
+                        };
+                        // The closed part stays on the volume it was recorded to, renamed out of the way
+                        // of the next part's files.
+                        let n = take_parts.len() + 1;
+                        let scratch = recording_dir.join(ffmpeg::RECORDING_DIR);
+                        let closed = |name: &str| scratch.join(format!("{}_part{}.mp4", name, n)).to_string_lossy().to_string();
+                        let part = TakePart {
+                            video: closed("vid"),
+                            audio: closed("aud"),
+                            proxy: closed("vid_proxy"),
+                            secs: clip_start_time.elapsed().as_secs_f64(),
+                            bytes,
+                            dir: recording_dir.clone(),
+                            clock
+                        };
+                        let _ = fs::rename(&temp_vid, &part.video);
+                        let _ = fs::rename(&temp_aud, &part.audio);
+                        let _ = fs::rename(&temp_proxy, &part.proxy);
+                        log_line!("Take {} split at {:.2} GB to fit {}", counter, bytes as f64 / 1e9, part.dir.display());
+                        take_parts.push(part);
+                        recording_dir = part_dir(&config, &take_parts);
+                        (temp_vid, temp_aud, temp_proxy) = take_files(&recording_dir);
+                        frames_written = 0;
+                        last_frame_data = None;
+                        match spawn_encoder(&take_cmd(&config, encoder_fallback, &temp_vid, &temp_proxy)) {
+                            Ok(c) => {
+                                video_process = Some(c);
+                                waiting_for_first_frame = true;
+                            },
+                            Err(e) => { let _ = status_tx.send(RecorderStatus::Error(format!("Failed to spawn ffmpeg: {}", e))); }
+                        }
+                    }
                 },
-                RecorderCommand::EndSegment => {
+                RecorderCommand::EndSegment(stopped_at) => {
+                    // The command and frame channels are read in no set order, so frames from before
+                    // the stop can still be queued. They go into the take; the first later one is
+                    // put back for after it.
+                    while video_process.is_some() && let Ok((data, captured)) = frames.receiver().try_recv() {
+                        if captured > stopped_at {
+                            deferred.push_back(RecorderCommand::WriteFrame(data, captured));
+                            break;
+                        }
+                        frame_budget.release(data.len());
+                        if let Some((_, proc)) = &mut replay
+                            && let Some(stdin) = &mut proc.stdin {
+                            let _ = stdin.write_all(&data);
+                        }
+                        if captured < clip_start_time || waiting_for_first_frame { continue; }
+                        if let Some(stdin) = video_process.as_mut().and_then(|p| p.stdin.as_mut())
+                            && stdin.write_all(&data).is_ok() {
+                            frames_written += 1;
+                            last_frame_data = Some((*data).clone());
+                        }
+                    }
                     waiting_for_first_frame = false;
-                    let duration_secs = clip_start_time.elapsed().as_secs_f64();
-                    let expected_frames  = (duration_secs * fps as f64).round() as u64;
+                    let take_drops = frames.dropped() - drops_at_start;
+                    if take_drops > drops_reported {
+                        log_line!("Take {} dropped {} frames, the encoder fell behind", counter, take_drops);
+                        let _ = status_tx.send(RecorderStatus::FramesDropped(take_drops));
+                    }
+                    drops_reported = take_drops;
+                    if config.dry_run {
+                        let finfile = workspace_file(&config, &naming::clip_file_name(&config.clip_template, &config.project, counter));
+                        report_dry_run(&status_tx, &ffmpeg::build_merge_cmd(&config, &temp_vid, config.record_audio.then_some(temp_aud.as_str()), None, config.burn_caption.as_deref(), None, &finfile));
+                        report_dry_run(&status_tx, &ffmpeg::build_thumb_cmd(&finfile, 0.0, &config.workspace.join(format!("thumb_{:03}.jpg", counter))));
+                        report_dry_run(&status_tx, &ffmpeg::build_preview_cmd(&finfile, &config.workspace.join(format!("preview_{:03}.gif", counter))));
+                        report_dry_run(&status_tx, &ffmpeg::build_waveform_cmd(&finfile, &config.workspace.join(format!("wave_{:03}.png", counter))));
+                        continue;
+                    }
+                    let duration_secs = stopped_at.saturating_duration_since(clip_start_time).as_secs_f64();
+                    let expected_frames  = (duration_secs * config.fps as f64).round() as u64;
                     if let Some(proc) = &mut video_process {
                         if let Some(stdin) = &mut proc.stdin {
                             if frames_written < expected_frames {
                                 let missing = expected_frames - frames_written;
                                 if missing > 0 {
-                                    println!("Sync: padding");
+                                    log_line!("Sync: padding");
                                     if let Some(last_data) = &last_frame_data {
                                         for _ in 0..missing {
                                             let _ = stdin.write_all(last_data);
@@ -107,107 +437,1093 @@ pub fn start_thread(cmd_rx: Receiver<RecorderCommand>, status_tx: Sender<Recorde
 
                     if let Some(mut proc) = video_process.take() {
                         if let Err(e) = proc.wait() {
-                            eprintln!("Video process wait error: {}", e);
+                            log_line!("Video process wait error: {}", e);
                         }
                     }
 
                     let (ack_tx, ack_rx) = crossbeam_channel::bounded(1);
-                    if let Err(e) = aud_tx.send(AudioCommand::StopRecording(ack_tx)) {
-                        eprintln!("Audio thread unavailable: {}", e);
-                    } else if let Err(_) = ack_rx.recv() {
-                        eprintln!("Audio thread disconnected unexpectedly during flush");
+                    let mut clock = None;
+                    if !config.record_audio {
+                        // Video-only takes never started the audio thread.
+                    } else if let Err(e) = aud_tx.send(AudioCommand::StopRecording(ack_tx)) {
+                        log_line!("Audio thread unavailable: {}", e);
+                    } else {
+                        match ack_rx.recv() {
+                            Ok(measured) => clock = measured,
+                            Err(_) => log_line!("Audio thread disconnected unexpectedly during flush")
+                        }
                     }
 
-                    if !std::path::Path::new(temp_vid).exists() || !std::path::Path::new(temp_aud).exists() {
-                        let _ = status_tx.send(RecorderStatus::Error("Temp files missing, recording failed".into()));
-                        let _ = fs::remove_file(temp_vid);
-                        let _ = fs::remove_file(temp_aud);
-                        continue;
-                    }
+                    let mut parts = std::mem::take(&mut take_parts);
+                    parts.push(TakePart { video: temp_vid.clone(), audio: temp_aud.clone(), proxy: temp_proxy.clone(), secs: duration_secs, bytes: 0, dir: recording_dir.clone(), clock });
+                    let (take, total) = (counter, parts.len());
+                    for (i, part) in parts.iter().enumerate() {
+                        let mut audio = config.record_audio.then_some(part.audio.as_str());
+                        // No microphone, or one that failed, costs the take its sound rather than the take.
+                        if audio.is_some_and(|a| !std::path::Path::new(a).exists()) {
+                            audio = None;
+                            log_line!("No audio for take {}, saving it without sound", take);
+                            if !audio_missing_noted {
+                                audio_missing_noted = true;
+                                let _ = status_tx.send(RecorderStatus::AudioMissing);
+                            }
+                        }
+                        if !std::path::Path::new(&part.video).exists() {
+                            let _ = status_tx.send(RecorderStatus::Error("Temp files missing, recording failed".into()));
+                            let _ = fs::remove_file(&part.video);
+                            let _ = fs::remove_file(&part.audio);
+                            let _ = fs::remove_file(&part.proxy);
+                            continue;
+                        }
 
-                    let finfile = format!("clip_{:03}.mp4", counter);
-                    println!("Merging to {}", finfile);
+                        if i > 0 {
+                            counter = next_free_counter(&config, counter) + 1;
+                        }
+                        let _ = fs::create_dir_all(&part.dir);
+                        let finfile = part.dir.join(naming::clip_file_name(&config.clip_template, &config.project, counter)).to_string_lossy().to_string();
+                        log_line!("Merging to {}", finfile);
 
-                    let merge = Command::new("ffmpeg").args(&[
-                        "-i", temp_vid,
-                        "-i", temp_aud,
-                        "-c:v", "copy",
-                        "-c:a", "aac",
-                        "-y", &finfile
-                    ]).stdout(Stdio::null()).stderr(Stdio::inherit()).status();
-                    match merge {
-                        Ok(s) if s.success() => {
-                            segments.push(PathBuf::from(&finfile));
-                            let final_path = PathBuf::from(&finfile);
-                            let thumb_path = PathBuf::from(format!("thumb_{:03}.jpg", counter));
-                            let preview_path = PathBuf::from(format!("preview_{:03}.gif", counter));
-
-                            let _ = Command::new("ffmpeg").args(&[
-                                "-i", &finfile,
-                                "-ss", "00:00:00.000",
-                                "-vframes", "1",
-                                "-vf", "scale=200:-1",
-                                "-y", thumb_path.to_str().unwrap()
-                            ]).output();
-
-                            let _ = Command::new("ffmpeg").args(&[
-                                "-i", &finfile,
-                                "-vf", "fps=5,scale=160:-1:flags=lanczos",
-                                "-f", "gif",
-                                "-y", preview_path.to_str().unwrap()
-                            ]).output();
-
-                            let clip = crate::messages::recorder::ClipInfo {
-                                video_path: final_path.clone(),
-                                thumb_path,
-                                preview_path,
-                                duration: get_video_duration(&final_path)
-                            };
+                        let caption = config.burn_caption.as_deref().filter(|_| i == 0);
+                        let continuity = (total > 1).then_some((take, i + 1, total));
+                        if let Some(clock) = part.clock {
+                            log_line!("Audio clock ran at {:.1} Hz against {} Hz ({:+.0} ppm)", clock.measured, clock.nominal, clock.drift_ppm());
+                        }
+                        let merge = ffmpeg::build_merge_cmd(&config, &part.video, audio, part.clock, caption, continuity, &finfile);
+                        if run_with_progress(&merge, part.secs, ProgressStage::Merge, (i, total), &status_tx, &cmd_rx, &mut deferred) != PassResult::Finished {
+                            let _ = status_tx.send(RecorderStatus::Error("Merge failed".into()));
+                            let _ = fs::remove_file(&part.proxy);
+                            continue;
+                        }
+                        let _ = fs::remove_file(&part.video);
+                        let _ = fs::remove_file(&part.audio);
+                        if config.proxy && std::path::Path::new(&part.proxy).exists() {
+                            let proxy = ffmpeg::proxy_path(std::path::Path::new(&finfile));
+                            let moved = fs::create_dir_all(part.dir.join(ffmpeg::PROXY_DIR)).and_then(|_| storage::move_file(std::path::Path::new(&part.proxy), &proxy));
+                            if let Err(e) = moved {
+                                log_line!("Couldn't keep the proxy for {}: {}", finfile, e);
+                                let _ = fs::remove_file(&part.proxy);
+                            }
+                        }
+                        if !ffmpeg::is_valid_media(std::path::Path::new(&finfile)) {
+                            let (quarantined, recovered) = quarantine_segment(&config, &finfile);
+                            let _ = status_tx.send(RecorderStatus::SegmentQuarantined(quarantined, recovered));
+                            if !recovered {
+                                continue;
+                            }
+                        }
+                        let damaged = ffmpeg::check_take(std::path::Path::new(&finfile), part.secs, audio.is_some());
+                        if let Some(problem) = &damaged {
+                            log_line!("{} is damaged: {}", finfile, problem);
+                        }
+                        segments.push(PathBuf::from(&finfile));
+                        back_up(&config, &backups, &finfile);
+                        let burned = (caption.map(String::from), config.burn_timestamp);
+                        let _ = status_tx.send(RecorderStatus::SegmentSaved(Box::new(ClipInfo {
+                            damaged, burned_caption: burned.0, burned_timestamp: burned.1, ..build_clip_info(&config, &finfile, counter, &assets)
+                        })));
+                    }
+                    // Only goes once the merges have emptied it; a failed one leaves its files behind.
+                    for part in &parts {
+                        if let Some(scratch) = Path::new(&part.video).parent() {
+                            let _ = fs::remove_dir(scratch);
+                        }
+                    }
+                },
+                // Only meaningful while an export pass is running, where it is picked up directly.
+                RecorderCommand::CancelExport => {},
+                RecorderCommand::ClearSegments => {
+                    for seg in &segments {
+                        let _ = fs::remove_file(seg);
+                        let _ = fs::remove_file(ffmpeg::proxy_path(seg));
+                    }
+                    segments.clear();
+                },
+                RecorderCommand::DeleteClips(clips) => {
+                    for clip in &clips {
+                        let _ = fs::remove_file(clip);
+                        let _ = fs::remove_file(ffmpeg::proxy_path(clip));
+                    }
+                    segments.retain(|seg| !clips.contains(seg));
+                },
+                RecorderCommand::RecoverSession(orphans) => {
+                    if video_process.is_some() { continue; }
+                    let mut lost = 0;
+                    let mut clips = recovery::session_clips(&config.workspace);
+                    // A take cut off by the crash comes after every clip finished before it.
+                    for dir in orphans {
+                        let (vid, aud) = (dir.join("vid.mp4").to_string_lossy().to_string(), dir.join("aud.mp4").to_string_lossy().to_string());
+                        let finfile = workspace_file(&config, &naming::clip_file_name(&config.clip_template, &config.project, next_free_counter(&config, clips.len() as u32) + 1));
+                        let args = if std::path::Path::new(&aud).exists() {
+                            ffmpeg::build_merge_cmd(&config, &vid, Some(&aud), None, None, None, &finfile)
+                        } else {
+                            ffmpeg::build_recover_cmd(&vid, &finfile)
+                        };
+                        let merged = Command::new("ffmpeg").args(&args).stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok_and(|s| s.success());
+                        if merged && ffmpeg::is_valid_media(std::path::Path::new(&finfile)) {
+                            log_line!("Salvaged an unfinished take into {}", finfile);
+                            clips.push(PathBuf::from(finfile));
+                        } else {
+                            log_line!("Unfinished take in {} couldn't be salvaged", dir.display());
+                            let _ = fs::remove_file(&finfile);
+                            lost += 1;
+                        }
+                        let _ = fs::remove_dir_all(&dir);
+                    }
 
-                            let _ = status_tx.send(RecorderStatus::SegmentSaved(clip));
-                            let _ = fs::remove_file(temp_vid);
-                            let _ = fs::remove_file(temp_aud);
-                        },
-                        Ok(_) | Err(_) => { let _ = status_tx.send(RecorderStatus::Error("Merge failed".into())); }
+                    segments.clear();
+                    // Assets are named for each clip's own number, so they can't clash with a clip
+                    // whose number was freed by one lost before it.
+                    let mut highest = 0;
+                    for clip in clips {
+                        let finfile = clip.to_string_lossy().to_string();
+                        if !ffmpeg::is_valid_media(&clip) && !quarantine_segment(&config, &finfile).1 {
+                            lost += 1;
+                            continue;
+                        }
+                        let number = clip.file_name().and_then(|name| naming::clip_index(&config.clip_template, &config.project, &name.to_string_lossy()))
+                            .filter(|&n| n > highest).unwrap_or(highest + 1);
+                        highest = number;
+                        segments.push(clip);
+                        back_up(&config, &backups, &finfile);
+                        let _ = status_tx.send(RecorderStatus::SegmentSaved(Box::new(build_clip_info(&config, &finfile, number, &assets))));
                     }
+                    counter = next_free_counter(&config, highest);
+                    log_line!("Recovered {} clips from {}, {} lost", segments.len(), config.workspace.display(), lost);
+                    let _ = status_tx.send(RecorderStatus::SessionRecovered { restored: segments.len(), lost });
                 },
                 RecorderCommand::Undo => {
                     if let Some(path) = segments.pop() {
                         if let Err(e) = fs::remove_file(&path) {
-                            eprintln!("Failed to delete file: {}", e);
+                            log_line!("Failed to delete file: {}", e);
                         }
+                        let _ = fs::remove_file(ffmpeg::proxy_path(&path));
 
                         let _ = status_tx.send(RecorderStatus::SegmentDeleted);
                     }
                 },
-                RecorderCommand::FinalizeVideo(ordered_files, output_filename) => {
-                    if ordered_files.is_empty() { continue; }
-                    let list_file = "concat_list.txt";
-                    if let Ok(mut f) = fs::File::create(list_file) {
-                        for seg in &ordered_files {
-                            let _ = writeln!(f, "file '{}'", seg.to_string_lossy());
+                RecorderCommand::MeasureSync(clip) => {
+                    let _ = status_tx.send(RecorderStatus::SyncMeasured(ffmpeg::measure_clap_offset(&clip)));
+                },
+                RecorderCommand::StartVoiceover(clip) => {
+                    if video_process.is_some() || voiceover.is_some() || recording_room_tone { continue; }
+                    let player = Command::new("ffplay").args(["-autoexit", "-an", "-window_title", "Voiceover"]).arg(&clip.video_path)
+                        .stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+                    match player {
+                        Ok(p) => {
+                            let _ = aud_tx.send(AudioCommand::StartMicRecording(temp_vo.to_string(), config.audio));
+                            voiceover = Some((*clip, p));
+                        },
+                        Err(e) => { let _ = status_tx.send(RecorderStatus::Error(format!("Failed to spawn ffplay: {}", e))); }
+                    }
+                },
+                RecorderCommand::StopVoiceover => {
+                    let Some((info, mut player)) = voiceover.take() else { continue; };
+                    let clip = info.video_path.clone();
+                    let _ = player.kill();
+                    let _ = player.wait();
+
+                    let (ack_tx, ack_rx) = crossbeam_channel::bounded(1);
+                    if aud_tx.send(AudioCommand::StopRecording(ack_tx)).is_ok() {
+                        let _ = ack_rx.recv();
+                    }
+
+                    let remuxed = clip.with_extension("vo.mp4");
+                    let status = Command::new("ffmpeg").args(ffmpeg::build_voiceover_cmd(&clip, &temp_vo, config.audio, &remuxed))
+                        .stdout(Stdio::null()).stderr(Stdio::piped()).spawn().and_then(|c| crash::log_stderr(c).wait());
+                    match status {
+                        Ok(s) if s.success() && fs::rename(&remuxed, &clip).is_ok() => {
+                            // The backup still holds the clip as it was before the voiceover.
+                            back_up(&config, &backups, &clip.to_string_lossy());
+                            // The waveform is of the old audio; the rest is redrawn along with it, the
+                            // thumbnail from wherever it was picked.
+                            let picture = info.proxy_path.clone().unwrap_or_else(|| clip.clone());
+                            assets.queue(clip.clone(), picture, (info.thumb_path, info.thumb_secs.unwrap_or(0.0)), info.preview_path, info.waveform_path);
+                            let _ = status_tx.send(RecorderStatus::VoiceoverReplaced(clip));
+                        },
+                        _ => {
+                            let _ = fs::remove_file(&remuxed);
+                            let _ = status_tx.send(RecorderStatus::Error("Voiceover remux failed".into()));
                         }
                     }
+                    let _ = fs::remove_file(&temp_vo);
+                },
+                RecorderCommand::SplitClip(clip, at) => {
+                    // The take in progress owns the next clip number.
+                    if video_process.is_some() { continue; }
+                    let first_number = next_free_counter(&config, counter) + 1;
+                    let second_number = next_free_counter(&config, first_number) + 1;
+                    let first = workspace_file(&config, &naming::clip_file_name(&config.clip_template, &config.project, first_number));
+                    let second = workspace_file(&config, &naming::clip_file_name(&config.clip_template, &config.project, second_number));
+                    let args = ffmpeg::build_split_cmd(&config, &clip, at, &first, &second);
+                    if config.dry_run {
+                        report_dry_run(&status_tx, &args);
+                        continue;
+                    }
+                    let status = Command::new("ffmpeg").args(&args).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().and_then(|c| crash::log_stderr(c).wait());
+                    if !matches!(status, Ok(s) if s.success()) {
+                        let _ = fs::remove_file(&first);
+                        let _ = fs::remove_file(&second);
+                        let _ = status_tx.send(RecorderStatus::Error(format!("Splitting {} failed", clip.display())));
+                        continue;
+                    }
+                    let first_info = build_clip_info(&config, &first, first_number, &assets);
+                    let second_info = build_clip_info(&config, &second, second_number, &assets);
+                    counter = second_number;
+                    match segments.iter().position(|s| *s == clip) {
+                        Some(i) => { segments.splice(i..=i, [PathBuf::from(&first), PathBuf::from(&second)]); },
+                        None => segments.extend([PathBuf::from(&first), PathBuf::from(&second)])
+                    }
+                    let _ = fs::remove_file(&clip);
+                    back_up(&config, &backups, &first);
+                    back_up(&config, &backups, &second);
+                    let _ = status_tx.send(RecorderStatus::ClipSplit(clip, at, Box::new(first_info), Box::new(second_info)));
+                },
+                RecorderCommand::ExportAnimation(input, output, options) => {
+                    let args = ffmpeg::build_animation_cmd(&input, &options, &output);
+                    if config.dry_run {
+                        report_dry_run(&status_tx, &args);
+                        continue;
+                    }
+                    let total_secs = options.end.unwrap_or_else(|| get_video_duration(&input)) - options.start;
+                    match run_with_progress(&args, total_secs, ProgressStage::Export, (0, 1), &status_tx, &cmd_rx, &mut deferred) {
+                        PassResult::Finished => { let _ = status_tx.send(RecorderStatus::AnimationFinished(Some(PathBuf::from(output)))); },
+                        PassResult::Cancelled => {
+                            let _ = fs::remove_file(&output);
+                            let _ = status_tx.send(RecorderStatus::AnimationFinished(None));
+                        },
+                        PassResult::Failed => { let _ = status_tx.send(RecorderStatus::Error(format!("Couldn't make {}", output))); }
+                    }
+                },
+                RecorderCommand::GenerateVisualizer(source, style, start, end) => {
+                    // The take in progress owns the next clip number.
+                    if video_process.is_some() { continue; }
+                    let number = next_free_counter(&config, counter) + 1;
+                    let finfile = workspace_file(&config, &naming::clip_file_name(&config.clip_template, &config.project, number));
+                    let args = ffmpeg::build_visualizer_cmd(&config, &source, style, start, end, &finfile);
+                    if config.dry_run {
+                        report_dry_run(&status_tx, &args);
+                        continue;
+                    }
+                    let total_secs = end.unwrap_or_else(|| get_video_duration(&source)) - start;
+                    if run_with_progress(&args, total_secs, ProgressStage::Merge, (0, 1), &status_tx, &cmd_rx, &mut deferred) != PassResult::Finished {
+                        let _ = fs::remove_file(&finfile);
+                        let _ = status_tx.send(RecorderStatus::Error(format!("Couldn't make a visualizer from {}", source.display())));
+                        continue;
+                    }
+                    counter = number;
+                    segments.push(PathBuf::from(&finfile));
+                    back_up(&config, &backups, &finfile);
+                    let info = ClipInfo { lane: Lane::BRoll, ..build_clip_info(&config, &finfile, counter, &assets) };
+                    let _ = status_tx.send(RecorderStatus::SegmentSaved(Box::new(info)));
+                },
+                RecorderCommand::SetThumbnail(clip, thumb, at) => {
+                    let args = ffmpeg::build_thumb_cmd(&clip.to_string_lossy(), at, &thumb);
+                    if config.dry_run {
+                        report_dry_run(&status_tx, &args);
+                        continue;
+                    }
+                    match Command::new("ffmpeg").args(&args).stdout(Stdio::null()).stderr(Stdio::null()).status() {
+                        Ok(s) if s.success() => { let _ = status_tx.send(RecorderStatus::ThumbnailSet(clip, at)); },
+                        _ => { let _ = status_tx.send(RecorderStatus::Error(format!("Couldn't grab a thumbnail from {}", clip.display()))); }
+                    }
+                },
+                RecorderCommand::StartRoomTone => {
+                    if video_process.is_some() || voiceover.is_some() || recording_room_tone { continue; }
+                    let _ = aud_tx.send(AudioCommand::StartRecording(temp_file("tone.mp4"), config.audio));
+                    recording_room_tone = true;
+                },
+                RecorderCommand::StopRoomTone => {
+                    if !recording_room_tone { continue; }
+                    recording_room_tone = false;
+                    let (ack_tx, ack_rx) = crossbeam_channel::bounded(1);
+                    if aud_tx.send(AudioCommand::StopRecording(ack_tx)).is_ok() {
+                        let _ = ack_rx.recv();
+                    }
 
-                    let status = Command::new("ffmpeg").args(&[
-                        "-f", "concat", 
-                        "-safe", "0", 
-                        "-i", list_file, 
-                        "-c", "copy", 
-                        "-y", &output_filename
-                        ]).stdout(Stdio::null()).stderr(Stdio::inherit()).status();
+                    let temp_tone = temp_file("tone.mp4");
+                    let room_tone = config.workspace.join(ffmpeg::ROOM_TONE);
+                    let status = Command::new("ffmpeg").args(ffmpeg::build_room_tone_tag_cmd(&temp_tone, &room_tone))
+                        .stdout(Stdio::null()).stderr(Stdio::piped()).spawn().and_then(|c| crash::log_stderr(c).wait());
                     match status {
-                        Ok(s) if s.success() => {
-                            let _ = status_tx.send(RecorderStatus::VideoFinalized(PathBuf::from(&output_filename)));
+                        Ok(s) if s.success() => { let _ = status_tx.send(RecorderStatus::RoomToneRecorded(room_tone)); },
+                        _ => { let _ = status_tx.send(RecorderStatus::Error("Saving the room tone failed".into())); }
+                    }
+                    let _ = fs::remove_file(&temp_tone);
+                },
+                RecorderCommand::FinalizeVideo(ordered_clips, output_filename, options) => {
+                    // The app deletes the clips once no other queued export needs them.
+                    'export: {
+                        if ordered_clips.is_empty() {
+                            let _ = status_tx.send(RecorderStatus::ExportFailed("Nothing to export".into()));
+                            break 'export;
+                        }
+                        let unsafe_text = ffmpeg::title_safe_overflows(&ordered_clips, &options.lower_third_style);
+                        if !unsafe_text.is_empty() {
+                            let _ = status_tx.send(RecorderStatus::ConcatReencoded(format!("outside title-safe: {}", unsafe_text.join(", "))));
+                        }
+                        if let Some(preset) = options.preset.filter(|p| p.target_bytes().is_some()) {
+                            let secs = ordered_clips.iter().map(ClipInfo::trimmed_duration).sum();
+                            if ffmpeg::target_video_kbps(preset, secs).is_none() {
+                                let _ = status_tx.send(RecorderStatus::ExportFailed(format!("{:.0} minutes is too long to fit {}, trim the timeline or pick another preset", secs / 60.0, preset)));
+                                break 'export;
+                            }
+                        }
+                        // Conforming swaps in copies, and takes recorded since are none of this export's.
+                        let exported: Vec<PathBuf> = ordered_clips.iter().map(|c| c.video_path.clone()).collect();
+                        let mut ordered_clips = ordered_clips;
+                        let mut silent_copies = Vec::new();
+                        // Appending conforms the clips to the earlier export instead.
+                        let conformed = give_silent_tracks(&config, &mut ordered_clips, &mut silent_copies, &status_tx, &cmd_rx, &mut deferred)
+                            && (options.append_to.is_some()
+                                || conform_formats(&config, options.frame_format, &mut ordered_clips, &mut silent_copies, (&status_tx, &cmd_rx, &mut deferred)));
+                        if !conformed {
+                            for copy in &silent_copies { let _ = fs::remove_file(copy); }
+                            let _ = status_tx.send(RecorderStatus::ExportCancelled);
+                            break 'export;
+                        }
+                        let list_file = &temp_file("concat_list.txt");
+                        if let Some(base) = &options.append_to {
+                            // The clips are conformed to the earlier export first unless they already
+                            // match it, then copied onto its end next to it and moved over it.
+                            let conformed = temp_file("conformed.mp4");
+                            let appended = base.with_extension("appending.mp4");
+                            let reason = if ordered_clips.iter().any(|c| c.is_trimmed() || !c.lower_thirds.is_empty()) {
+                                Some(String::from("trims and lower thirds have to be encoded"))
+                            } else {
+                                ffmpeg::append_mismatch(base, &ordered_clips)
+                            };
+                            let mut passes = Vec::new();
+                            let mut parts = vec![base.clone()];
+                            if let Some(reason) = reason {
+                                let Some(conform) = ffmpeg::build_conform_cmd(base, None, &ordered_clips, &options.lower_third_style, &conformed) else {
+                                    let _ = status_tx.send(RecorderStatus::ExportFailed(format!("Can't match the clips to {}", base.display())));
+                                    break 'export;
+                                };
+                                log_line!("Encoding the new clips to match {} ({})", base.display(), reason);
+                                let _ = status_tx.send(RecorderStatus::ConcatReencoded(format!("new clips re-encoded, {}", reason)));
+                                passes.push(conform);
+                                parts.push(PathBuf::from(&conformed));
+                            } else {
+                                parts.extend(ordered_clips.iter().map(|c| c.video_path.clone()));
+                            }
+                            if let Ok(mut f) = fs::File::create(list_file) {
+                                for part in &parts {
+                                    let _ = writeln!(f, "file '{}'", part.to_string_lossy());
+                                }
+                            }
+                            let appended_name = appended.to_string_lossy().to_string();
+                            passes.push(["-f", "concat", "-safe", "0", "-i", list_file, "-c", "copy", "-y", &appended_name].into_iter().map(String::from).collect());
+                            if config.dry_run {
+                                for args in &passes {
+                                    report_dry_run(&status_tx, args);
+                                }
+                                break 'export;
+                            }
+
+                            let expected = get_video_duration(base) + ordered_clips.iter().map(ClipInfo::trimmed_duration).sum::<f64>();
+                            let count = passes.len();
+                            let mut result = PassResult::Finished;
+                            for (i, args) in passes.iter().enumerate() {
+                                result = run_with_progress(args, expected, ProgressStage::Export, (i, count), &status_tx, &cmd_rx, &mut deferred);
+                                if result != PassResult::Finished {
+                                    break;
+                                }
+                            }
+                            let _ = fs::remove_file(&conformed);
                             let _ = fs::remove_file(list_file);
-                            for seg in &segments { let _ = fs::remove_file(seg); }
-                            segments.clear();
-                            counter = 0;
-                        },
-                        _ => { let _ = status_tx.send(RecorderStatus::Error("Final concat fialed".into())); }
+                            for copy in &silent_copies { let _ = fs::remove_file(copy); }
+                            let actual = get_video_duration(&appended);
+                            match result {
+                                PassResult::Cancelled => {
+                                    let _ = fs::remove_file(&appended);
+                                    let _ = status_tx.send(RecorderStatus::ExportCancelled);
+                                },
+                                PassResult::Failed => {
+                                    let _ = fs::remove_file(&appended);
+                                    let _ = status_tx.send(RecorderStatus::ExportFailed("Appending failed".into()));
+                                },
+                                // The earlier export is only replaced by a result of the right length.
+                                PassResult::Finished if (actual - expected).abs() > DURATION_TOLERANCE_SECS => {
+                                    log_line!("Appended {} is {:.2}s, expected {:.2}s", appended.display(), actual, expected);
+                                    let _ = fs::remove_file(&appended);
+                                    let _ = status_tx.send(RecorderStatus::ExportFailed(format!("Appended file came out {:.1}s long instead of {:.1}s, {} is unchanged", actual, expected, base.display())));
+                                },
+                                PassResult::Finished => match fs::rename(&appended, base) {
+                                    Ok(()) => {
+                                        let _ = status_tx.send(RecorderStatus::VideoFinalized(base.clone()));
+                                        segments.retain(|seg| !exported.contains(seg));
+                                    },
+                                    Err(e) => {
+                                        let _ = status_tx.send(RecorderStatus::ExportFailed(format!("Couldn't replace {}: {}", base.display(), e)));
+                                    }
+                                }
+                            }
+                            break 'export;
+                        }
+                        if let Ok(mut f) = fs::File::create(list_file) {
+                            for clip in &ordered_clips {
+                                let _ = writeln!(f, "file '{}'", clip.video_path.to_string_lossy());
+                                if clip.trim_in > 0.0 {
+                                    let _ = writeln!(f, "inpoint {:.3}", clip.trim_in);
+                                }
+                                if let Some(out) = clip.trim_out {
+                                    let _ = writeln!(f, "outpoint {:.3}", out);
+                                }
+                            }
+                        }
+
+                        let output_path = PathBuf::from(&output_filename);
+                        let passlog = temp_file("twopass");
+                        let mut stream_copy = false;
+                        let list_based = options.dual || (!options.reencode && options.two_pass_kbps.is_some());
+                        // Vertical and preset exports join the clips with plain cuts from the concat list.
+                        let cuts_only = options.vertical.is_some() || options.preset.is_some();
+                        let skips_captions = options.cutaways || options.ladder || cuts_only || (list_based && !ffmpeg::has_transitions(&ordered_clips));
+                        if skips_captions && ordered_clips.iter().any(|c| !c.lower_thirds.is_empty()) {
+                            let _ = status_tx.send(RecorderStatus::ConcatReencoded(String::from("lower thirds left out, only single-file exports draw them")));
+                        }
+                        if cuts_only && ffmpeg::has_transitions(&ordered_clips) {
+                            log_line!("Transitions are skipped in vertical and preset exports, clips are joined with cuts");
+                        }
+                        // Transitions take the single-file encode, which has no vertical copy or second pass.
+                        let overridden: Vec<&str> = [(options.dual, "the 9:16 copy"), (options.reframe && options.dual, "subject following"), (options.two_pass_kbps.is_some(), "two-pass")]
+                            .into_iter().filter(|(on, _)| *on).map(|(_, name)| name).collect();
+                        if !options.cutaways && !options.ladder && !cuts_only && ffmpeg::has_transitions(&ordered_clips) && !overridden.is_empty() {
+                            let _ = status_tx.send(RecorderStatus::ConcatReencoded(format!("{} left out to draw the transitions", overridden.join(" and "))));
+                        }
+                        let (mut passes, finished_path) = if options.cutaways {
+                            match ffmpeg::build_cutaway_cmd(&ordered_clips, &output_filename) {
+                                Some(args) => (vec![args], output_path.clone()),
+                                None => {
+                                    let _ = status_tx.send(RecorderStatus::ExportFailed("Cutaway export needs at least one A-roll clip".into()));
+                                    break 'export;
+                                }
+                            }
+                        } else if options.ladder {
+                            if options.hls {
+                                let _ = fs::create_dir_all(ffmpeg::hls_dir(&output_path));
+                            }
+                            let finished = if options.hls {
+                                ffmpeg::hls_dir(&output_path).join("master.m3u8")
+                            } else {
+                                ffmpeg::ladder_outputs(&output_path).remove(0)
+                            };
+                            (vec![ffmpeg::build_ladder_cmd(list_file, &output_path, options.hls, ordered_clips.first().is_some_and(|c| ffmpeg::has_audio(&c.video_path)))], finished)
+                        } else if let Some(preset) = options.preset {
+                            let secs = ordered_clips.iter().map(ClipInfo::trimmed_duration).sum();
+                            (vec![ffmpeg::build_preset_cmd(list_file, preset, secs, &output_filename)], output_path.clone())
+                        } else if let Some(frame) = &options.vertical {
+                            (vec![ffmpeg::build_vertical_cmd(list_file, frame, config.audio, &output_filename)], output_path.clone())
+                        } else if ffmpeg::has_transitions(&ordered_clips) {
+                            (vec![ffmpeg::build_transition_cmd(&ordered_clips, &options.lower_third_style, &output_filename)], output_path.clone())
+                        } else if options.dual {
+                            let script = config.workspace.join("reframe.cmd");
+                            let model = config.face_model.as_deref().filter(|_| options.reframe && !config.dry_run);
+                            let reframe = match model {
+                                Some(model) => {
+                                    log_line!("Tracking the subject across {} clips", ordered_clips.len());
+                                    let Some(track) = track_subject(&ordered_clips, model, &status_tx, &cmd_rx, &mut deferred) else {
+                                        let _ = status_tx.send(RecorderStatus::ExportCancelled);
+                                        break 'export;
+                                    };
+                                    let written = track.and_then(|track| match track.is_empty() {
+                                        true => Ok(false),
+                                        false => reframe::write_commands(&track, &script).map(|_| true).map_err(|e| e.to_string())
+                                    });
+                                    match written {
+                                        Ok(written) => written,
+                                        Err(e) => {
+                                            log_line!("Subject tracking failed: {}", e);
+                                            let _ = status_tx.send(RecorderStatus::ConcatReencoded(format!("subject tracking failed, the 9:16 copy is centred ({})", e)));
+                                            false
+                                        }
+                                    }
+                                },
+                                None => false
+                            };
+                            (vec![ffmpeg::build_dual_cmd(list_file, &output_path, reframe.then_some(script.as_path()))], output_path.clone())
+                        } else if options.reencode {
+                            (vec![ffmpeg::build_reencode_concat_cmd(&ordered_clips, &options.lower_third_style, &output_filename)], output_path.clone())
+                        } else if let Some(kbps) = options.two_pass_kbps {
+                            let passes = (1..=2).map(|pass| ffmpeg::build_two_pass_cmd(list_file, &output_filename, kbps, pass, &passlog)).collect();
+                            (passes, output_path.clone())
+                        } else if ordered_clips.iter().any(|c| !c.lower_thirds.is_empty()) {
+                            let _ = status_tx.send(RecorderStatus::ConcatReencoded(String::from("re-encoded to draw lower thirds")));
+                            (vec![ffmpeg::build_reencode_concat_cmd(&ordered_clips, &options.lower_third_style, &output_filename)], output_path.clone())
+                        } else if ordered_clips.iter().any(ClipInfo::is_trimmed) {
+                            // Stream copy can only cut on keyframes, so trims would land up to a GOP early.
+                            let _ = status_tx.send(RecorderStatus::ConcatReencoded(String::from("re-encoded to cut trimmed clips exactly")));
+                            (vec![ffmpeg::build_reencode_concat_cmd(&ordered_clips, &options.lower_third_style, &output_filename)], output_path.clone())
+                        } else if let Some(reason) = ffmpeg::concat_mismatch(&ordered_clips) {
+                            log_line!("Clips can't be stream-copied ({}), re-encoding", reason);
+                            let _ = status_tx.send(RecorderStatus::ConcatReencoded(format!("re-encoded, {}", reason)));
+                            (vec![ffmpeg::build_reencode_concat_cmd(&ordered_clips, &options.lower_third_style, &output_filename)], output_path.clone())
+                        } else {
+                            stream_copy = true;
+                            let args = vec!["-f", "concat", "-safe", "0", "-i", list_file, "-c", "copy", "-y", &output_filename];
+                            (vec![args.into_iter().map(String::from).collect()], output_path.clone())
+                        };
+
+                        // Room tone goes under the single-file export as a last pass, written aside and
+                        // moved over the output once it succeeds.
+                        let room_tone = config.workspace.join(ffmpeg::ROOM_TONE);
+                        let toned = temp_file("toned.mp4");
+                        // The remuxes after the encode write MP4, which can't hold the archive preset's FFV1.
+                        let mp4 = options.preset.is_none_or(|p| p.container() == "mp4");
+                        let add_room_tone = options.room_tone && !options.ladder && mp4 && room_tone.exists();
+                        if add_room_tone {
+                            passes.push(ffmpeg::build_room_tone_mix_cmd(&output_filename, &room_tone, &toned));
+                        }
+                        let watermarked = temp_file("watermarked.mp4");
+                        let watermark = options.watermark.as_ref().filter(|_| !options.ladder && mp4);
+                        if options.watermark.is_some() {
+                            let dual_copy = options.dual && !options.cutaways && !options.ladder && !cuts_only && !ffmpeg::has_transitions(&ordered_clips);
+                            let skipped = if options.ladder { Some("the ladder renditions") } else if !mp4 { Some("this MKV") } else if dual_copy { Some("the 9:16 copy") } else { None };
+                            if let Some(skipped) = skipped {
+                                let _ = status_tx.send(RecorderStatus::ConcatReencoded(format!("watermark left off {}", skipped)));
+                            }
+                        }
+                        if let Some(mark) = watermark {
+                            let input = if add_room_tone { &toned } else { &output_filename };
+                            passes.push(ffmpeg::build_watermark_cmd(input, mark, &watermarked));
+                        }
+
+                        // A single-file export gets a last remux for its tags, and chapters marking each
+                        // clip of the timeline; a cutaway export's timeline is its A-roll.
+                        let chapter_clips: Vec<ClipInfo> = ordered_clips.iter().filter(|c| !options.cutaways || c.lane == Lane::ARoll).cloned().collect();
+                        let chapter_file = temp_file("chapters.txt");
+                        let tagged = temp_file("tagged.mp4");
+                        let transitions = !options.cutaways && !cuts_only && ffmpeg::has_transitions(&ordered_clips);
+                        let add_chapters = chapter_clips.len() > 1
+                            && fs::write(&chapter_file, ffmpeg::build_chapter_metadata(&chapter_clips, transitions)).is_ok();
+                        let add_tags = !options.ladder && mp4;
+                        // The first clip with a hand-picked thumbnail supplies the cover art.
+                        let poster_file = temp_file("poster.jpg");
+                        let add_poster = add_tags && match ordered_clips.iter().find_map(|c| c.thumb_secs.map(|at| (c, at))) {
+                            Some((clip, at)) => config.dry_run || Command::new("ffmpeg").args(ffmpeg::build_poster_cmd(&clip.video_path, at, &poster_file))
+                                .stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok_and(|s| s.success()),
+                            None => false
+                        };
+                        if add_tags {
+                            let input = if watermark.is_some() { &watermarked } else if add_room_tone { &toned } else { &output_filename };
+                            let date = Local::now().format("%Y-%m-%d").to_string();
+                            passes.push(ffmpeg::build_tag_cmd(input, add_chapters.then_some(chapter_file.as_str()), add_poster.then_some(poster_file.as_str()),
+                                &options.metadata, &date, &tagged));
+                        }
+
+                        if config.dry_run {
+                            for args in &passes {
+                                report_dry_run(&status_tx, args);
+                            }
+                            break 'export;
+                        }
+
+                        let total_secs: f64 = ordered_clips.iter().map(ClipInfo::trimmed_duration).sum();
+                        let count = passes.len();
+                        let mut result = PassResult::Finished;
+                        let mut i = 0;
+                        while i < passes.len() {
+                            result = run_with_progress(&passes[i], total_secs, ProgressStage::Export, (i, count), &status_tx, &cmd_rx, &mut deferred);
+                            if result == PassResult::Failed && i == 0 && stream_copy {
+                                log_line!("Stream-copy concat failed, retrying with a re-encode");
+                                let _ = status_tx.send(RecorderStatus::ConcatReencoded(String::from("re-encoded after stream copy failed")));
+                                passes[0] = ffmpeg::build_reencode_concat_cmd(&ordered_clips, &options.lower_third_style, &output_filename);
+                                stream_copy = false;
+                                continue;
+                            }
+                            if result != PassResult::Finished {
+                                break;
+                            }
+                            i += 1;
+                        }
+                        let last_pass = if add_tags { Some(&tagged) } else if watermark.is_some() { Some(&watermarked) } else if add_room_tone { Some(&toned) } else { None };
+                        if let Some(last) = last_pass
+                            && result == PassResult::Finished && let Err(e) = storage::move_file(std::path::Path::new(last), &output_path) {
+                            log_line!("Failed to move {} to {}: {}", last, output_path.display(), e);
+                            result = PassResult::Failed;
+                        }
+                        let _ = fs::remove_file(&toned);
+                        let _ = fs::remove_file(&watermarked);
+                        let _ = fs::remove_file(&tagged);
+                        let _ = fs::remove_file(&chapter_file);
+                        let _ = fs::remove_file(&poster_file);
+                        for log in [format!("{}-0.log", passlog), format!("{}-0.log.mbtree", passlog)] {
+                            let _ = fs::remove_file(log);
+                        }
+                        let _ = fs::remove_file(list_file);
+                        for copy in &silent_copies { let _ = fs::remove_file(copy); }
+                        if result == PassResult::Cancelled {
+                            remove_partial_export(&output_path, &options);
+                            let _ = status_tx.send(RecorderStatus::ExportCancelled);
+                        } else if result == PassResult::Finished {
+                            // The clips are kept on a mismatch so the UI can offer a re-encoded export.
+                            let expected: f64 = if !options.cutaways && !options.ladder && !cuts_only && ffmpeg::has_transitions(&ordered_clips) {
+                                ffmpeg::transition_timeline_secs(&ordered_clips)
+                            } else {
+                                ordered_clips.iter().filter(|c| !options.cutaways || c.lane == Lane::ARoll).map(ClipInfo::trimmed_duration).sum()
+                            };
+                            let actual = if options.hls { expected } else { get_video_duration(&finished_path) };
+                            if (actual - expected).abs() > DURATION_TOLERANCE_SECS {
+                                log_line!("Export {} is {:.2}s, clips add up to {:.2}s", finished_path.display(), actual, expected);
+                                let _ = status_tx.send(RecorderStatus::DurationMismatch(finished_path, expected, actual));
+                                break 'export;
+                            }
+                            let _ = status_tx.send(RecorderStatus::VideoFinalized(finished_path));
+                            segments.retain(|seg| !exported.contains(seg));
+                        } else {
+                            let _ = status_tx.send(RecorderStatus::ExportFailed("Final concat failed".into()));
+                        }
                     }
+                    let _ = status_tx.send(RecorderStatus::ExportEnded(output_filename));
                 }
             }
         }
     });
-}
\ No newline at end of file
+}
+
+/// Points clips saved without audio at temp copies with a silent track in the session codec, so
+/// they join up with clips that have one. The copies go into `copies` for removal once the export
+/// is done. False if the export was cancelled meanwhile.
+fn give_silent_tracks(config: &RecorderConfig, clips: &mut [ClipInfo], copies: &mut Vec<String>, status_tx: &Sender<RecorderStatus>,
+    cmd_rx: &Receiver<RecorderCommand>, deferred: &mut VecDeque<RecorderCommand>) -> bool {
+    let silent: Vec<usize> = (0..clips.len()).filter(|&i| !ffmpeg::has_audio(&clips[i].video_path)).collect();
+    for (n, &i) in silent.iter().enumerate() {
+        let clip = &mut clips[i];
+        let copy = temp_file(&format!("silent_{}.mp4", i));
+        let args = ffmpeg::build_silent_track_cmd(&clip.video_path, config.audio, &copy);
+        if config.dry_run {
+            report_dry_run(status_tx, &args);
+        } else {
+            match run_with_progress(&args, clip.duration, ProgressStage::Conform, (n, silent.len()), status_tx, cmd_rx, deferred) {
+                PassResult::Finished => {},
+                PassResult::Cancelled => return false,
+                PassResult::Failed => {
+                    log_line!("Couldn't add a silent track to {}", clip.video_path.display());
+                    continue;
+                }
+            }
+        }
+        clip.video_path = PathBuf::from(&copy);
+        copies.push(copy);
+    }
+    true
+}
+
+/// Points takes recorded in another frame size or rate than the timeline's at temp copies
+/// re-encoded to match it, so every export path sees one format. The timeline's format is
+/// `target`, or the first clip's without one. The copies go into `copies` for removal once the
+/// export is done. False if the export was cancelled meanwhile.
+fn conform_formats(config: &RecorderConfig, target: Option<FrameFormat>, clips: &mut [ClipInfo], copies: &mut Vec<String>,
+    (status_tx, cmd_rx, deferred): (&Sender<RecorderStatus>, &Receiver<RecorderCommand>, &mut VecDeque<RecorderCommand>)) -> bool {
+    let Some(base) = clips.first().map(|c| c.video_path.clone()) else { return true };
+    let Some(frame) = target.or_else(|| ffmpeg::get_frame_format(&base).map(|(width, height, rate)| FrameFormat { width, height, fps: ffmpeg::rate_fps(&rate).round() as u32 })) else { return true };
+    let differs = |clip: &ClipInfo| ffmpeg::get_frame_format(&clip.video_path)
+        .is_some_and(|(w, h, r)| (w, h) != (frame.width, frame.height) || (ffmpeg::rate_fps(&r) - frame.fps as f64).abs() > 0.01);
+    let mismatched: Vec<usize> = (0..clips.len()).filter(|&i| differs(&clips[i])).collect();
+    let mut upscaled = false;
+    let mut conformed = 0;
+    for (n, &i) in mismatched.iter().enumerate() {
+        let clip = &mut clips[i];
+        let Some((w, h, r)) = ffmpeg::get_frame_format(&clip.video_path) else { continue };
+        let copy = temp_file(&format!("conformed_{}.mp4", i));
+        let Some(args) = ffmpeg::build_take_conform_cmd(&base, frame, clip, &copy) else {
+            log_line!("Can't conform {} to {}", clip.video_path.display(), base.display());
+            continue;
+        };
+        log_line!("Conforming clip {} from {}x{} at {} fps to {}x{} at {} fps", i + 1, w, h, r, frame.width, frame.height, frame.fps);
+        if config.dry_run {
+            report_dry_run(status_tx, &args);
+        } else {
+            match run_with_progress(&args, clip.duration, ProgressStage::Conform, (n, mismatched.len()), status_tx, cmd_rx, deferred) {
+                PassResult::Finished => {},
+                PassResult::Cancelled => return false,
+                PassResult::Failed => {
+                    log_line!("Couldn't conform {}", clip.video_path.display());
+                    continue;
+                }
+            }
+        }
+        upscaled |= w < frame.width || h < frame.height;
+        clip.video_path = PathBuf::from(&copy);
+        copies.push(copy);
+        conformed += 1;
+    }
+    if conformed > 0 {
+        let quality = if upscaled { "smaller takes were scaled up and look softer" } else { "takes were rescaled and retimed, which costs some quality" };
+        let _ = status_tx.send(RecorderStatus::ConcatReencoded(format!("{} takes conformed to {}x{} at {} fps, {}", conformed, frame.width, frame.height, frame.fps, quality)));
+    }
+    true
+}
+
+/// Describes a finished clip and queues its thumbnail, hover preview, waveform and defect scan,
+/// which arrive later as `ClipAssetsReady`.
+fn build_clip_info(config: &RecorderConfig, finfile: &str, counter: u32, assets: &AssetPool) -> ClipInfo {
+    let final_path = PathBuf::from(finfile);
+    let thumb_path = config.workspace.join(format!("thumb_{:03}.jpg", counter));
+    let preview_path = config.workspace.join(format!("preview_{:03}.gif", counter));
+    let waveform_path = config.workspace.join(format!("wave_{:03}.png", counter));
+    let proxy = ffmpeg::proxy_path(&final_path);
+    let proxy_path = proxy.exists().then_some(proxy);
+    let picture = proxy_path.clone().unwrap_or_else(|| final_path.clone());
+    assets.queue(final_path.clone(), picture, (thumb_path.clone(), 0.0), preview_path.clone(), waveform_path.clone());
+
+    ClipInfo { thumb_path, preview_path, waveform_path, proxy_path, ..plain_clip_info(&final_path) }
+}
+
+/// Describes a clip file as it was saved, with no edits and no generated assets.
+pub fn plain_clip_info(path: &Path) -> ClipInfo {
+    let video_path = path.to_path_buf();
+    ClipInfo {
+        duration: get_video_duration(&video_path),
+        video_path,
+        thumb_path: PathBuf::new(),
+        preview_path: PathBuf::new(),
+        waveform_path: PathBuf::new(),
+        lane: Lane::default(),
+        black_secs: 0.0,
+        frozen_secs: 0.0,
+        transition: None,
+        trim_in: 0.0,
+        trim_out: None,
+        label: String::new(),
+        thumb_secs: None,
+        lower_thirds: Vec::new(),
+        excluded: false,
+        proxy_path: None,
+        damaged: None,
+        burned_caption: None,
+        burned_timestamp: false
+    }
+}
+
+#[derive(PartialEq)]
+enum PassResult {
+    Finished,
+    Failed,
+    Cancelled
+}
+
+/// Runs pass `index` of `count` ffmpeg passes with `-progress pipe:1`, forwarding the overall percentage
+/// done to the UI. Commands arriving meanwhile are set aside in `deferred`, except for
+/// `CancelExport` during an export, which kills the pass.
+fn run_with_progress(args: &[String], total_secs: f64, stage: ProgressStage, (index, count): (usize, usize), status_tx: &Sender<RecorderStatus>,
+    cmd_rx: &Receiver<RecorderCommand>, deferred: &mut VecDeque<RecorderCommand>) -> PassResult {
+    let mut child = match Command::new("ffmpeg").args(["-progress", "pipe:1", "-nostats"]).args(args).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map(crash::log_stderr) {
+        Ok(c) => c,
+        Err(e) => {
+            log_line!("Failed to spawn ffmpeg: {}", e);
+            return PassResult::Failed;
+        }
+    };
+    let mut cancelled = false;
+    let started = Instant::now();
+    let mut media_secs = 0.0;
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(us) = line.strip_prefix("out_time_us=").and_then(|v| v.parse::<f64>().ok())
+                && total_secs > 0.0 {
+                media_secs = us / 1_000_000.0;
+                let pass_done = (us / 1_000_000.0 / total_secs).clamp(0.0, 1.0);
+                let percent = ((index as f64 + pass_done) / count as f64 * 100.0) as f32;
+                let _ = status_tx.send(RecorderStatus::Progress { stage, percent });
+            }
+            while let Ok(cmd) = cmd_rx.try_recv() {
+                match cmd {
+                    RecorderCommand::CancelExport if stage != ProgressStage::Merge && !cancelled => {
+                        log_line!("Export cancelled, stopping ffmpeg");
+                        let _ = child.kill();
+                        cancelled = true;
+                    },
+                    RecorderCommand::CancelExport => {},
+                    other => deferred.push_back(other)
+                }
+            }
+        }
+    }
+    let success = child.wait().map(|s| s.success()).unwrap_or(false);
+    if cancelled {
+        PassResult::Cancelled
+    } else if success {
+        // Very short passes are mostly startup time, which would skew the export estimate.
+        let wall = started.elapsed().as_secs_f64();
+        if wall > 0.5 && media_secs > 0.0 {
+            let _ = status_tx.send(RecorderStatus::Throughput { encode: !ffmpeg::copies_video(args), speed: media_secs / wall });
+        }
+        PassResult::Finished
+    } else {
+        PassResult::Failed
+    }
+}
+
+/// Runs the face tracking on its own thread, forwarding its progress and watching for
+/// `CancelExport` like `run_with_progress` does. `None` if the export was cancelled.
+fn track_subject(clips: &[ClipInfo], model: &Path, status_tx: &Sender<RecorderStatus>, cmd_rx: &Receiver<RecorderCommand>,
+    deferred: &mut VecDeque<RecorderCommand>) -> Option<Result<Vec<reframe::Keyframe>, String>> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (progress_tx, progress_rx) = crossbeam_channel::unbounded();
+    let worker = {
+        let (clips, model, cancel) = (clips.to_vec(), model.to_path_buf(), cancel.clone());
+        thread::spawn(move || reframe::track_timeline(&clips, &model, &cancel, |done| { let _ = progress_tx.send(done); }))
+    };
+    loop {
+        crossbeam_channel::select! {
+            recv(progress_rx) -> done => match done {
+                Ok(done) => { let _ = status_tx.send(RecorderStatus::Progress { stage: ProgressStage::Reframe, percent: done * 100.0 }); },
+                Err(_) => break
+            },
+            recv(cmd_rx) -> cmd => match cmd {
+                Ok(RecorderCommand::CancelExport) => {
+                    log_line!("Export cancelled while tracking the subject");
+                    cancel.store(true, Ordering::Relaxed);
+                },
+                Ok(other) => deferred.push_back(other),
+                Err(_) => {
+                    cancel.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+        }
+    }
+    worker.join().ok().flatten()
+}
+
+/// Deletes whatever a cancelled export managed to write.
+fn remove_partial_export(output: &std::path::Path, options: &ExportOptions) {
+    let _ = fs::remove_file(output);
+    if options.dual {
+        let _ = fs::remove_file(ffmpeg::vertical_output(output));
+    }
+    if options.ladder {
+        for rung in ffmpeg::ladder_outputs(output) {
+            let _ = fs::remove_file(rung);
+        }
+        if options.hls {
+            let _ = fs::remove_dir_all(ffmpeg::hls_dir(output));
+        }
+    }
+}
+
+/// Interval photo mode state. Stills are numbered from 1 in their own folder.
+struct IntervalCapture {
+    every: Duration,
+    next: Instant,
+    dir: PathBuf,
+    count: u32,
+    timelapse: bool,
+    writers: Vec<JoinHandle<()>>
+}
+
+fn write_still(args: &[String], frame: &[u8]) {
+    let child = Command::new("ffmpeg").args(args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+    match child {
+        Ok(mut c) => {
+            if let Some(mut stdin) = c.stdin.take() {
+                let _ = stdin.write_all(frame);
+            }
+            let _ = c.wait();
+        },
+        Err(e) => log_line!("Failed to spawn ffmpeg for still: {}", e)
+    }
+}
+
+/// Moves a clip that failed validation into the quarantine folder and tries to salvage it back
+/// under its original name. Returns the quarantined copy and whether the salvage is playable.
+fn quarantine_segment(config: &RecorderConfig, finfile: &str) -> (PathBuf, bool) {
+    let source = PathBuf::from(finfile);
+    let dir = config.workspace.join(ffmpeg::QUARANTINE_DIR);
+    let _ = fs::create_dir_all(&dir);
+    let quarantined = dir.join(source.file_name().unwrap_or_default());
+    if let Err(e) = fs::rename(&source, &quarantined) {
+        log_line!("Failed to quarantine {}: {}", finfile, e);
+        return (source, false);
+    }
+    log_line!("{} failed validation, moved to {}", finfile, quarantined.display());
+
+    let remuxed = Command::new("ffmpeg").args(ffmpeg::build_recover_cmd(&quarantined.to_string_lossy(), finfile))
+        .stdout(Stdio::null()).stderr(Stdio::piped()).spawn().and_then(|c| crash::log_stderr(c).wait());
+    let recovered = matches!(remuxed, Ok(s) if s.success()) && ffmpeg::is_valid_media(&source);
+    if !recovered {
+        let _ = fs::remove_file(&source);
+    }
+    (quarantined, recovered)
+}
+
+/// Queues a copy of a saved clip into this session's folder in the backup location, if one is set.
+fn back_up(config: &RecorderConfig, backups: &BackupPool, clip: &str) {
+    if let Some(root) = &config.backup_dir {
+        backups.queue(PathBuf::from(clip), root.join(config.workspace.file_name().unwrap_or_default()));
+    }
+}
+
+fn workspace_file(config: &RecorderConfig, name: &str) -> String {
+    config.workspace.join(name).to_string_lossy().to_string()
+}
+
+/// The first counter from `from` whose next clip name isn't taken yet. Numbers only ever go up
+/// within a session, and skipping names already on disk keeps a restarted or restored session
+/// from overwriting clips it didn't record.
+fn next_free_counter(config: &RecorderConfig, from: u32) -> u32 {
+    let dirs = take_dirs(config);
+    let mut counter = from;
+    while dirs.iter().any(|d| d.join(naming::clip_file_name(&config.clip_template, &config.project, counter + 1)).exists()) {
+        counter += 1;
+    }
+    counter
+}
+
+/// A finished stretch of a take that was split to fit where it's saved, merged with the rest
+/// when the take ends.
+struct TakePart {
+    video: String,
+    audio: String,
+    proxy: String,
+    secs: f64,
+    bytes: u64,
+    dir: PathBuf,
+    clock: Option<AudioClock>
+}
+
+/// Folders a take can be saved in, in order: the workspace, then the same session's folder under
+/// the overflow location.
+fn take_dirs(config: &RecorderConfig) -> Vec<PathBuf> {
+    let mut dirs = vec![config.workspace.clone()];
+    if let Some(overflow) = &config.overflow_dir {
+        dirs.push(overflow.join(config.workspace.file_name().unwrap_or_default()));
+    }
+    dirs
+}
+
+fn most_free_gb(config: &RecorderConfig) -> Option<f64> {
+    take_dirs(config).iter().filter_map(|d| storage::free_space_gb(d)).reduce(f64::max)
+}
+
+/// Bytes `dir` can still take above the free space floor, less what earlier parts of the take
+/// will need there. `None` when free space can't be read.
+fn room(config: &RecorderConfig, dir: &Path, parts: &[TakePart]) -> Option<u64> {
+    let promised: u64 = parts.iter().filter(|p| p.dir == dir).map(|p| p.bytes).sum();
+    let free = storage::free_space_gb(dir)?;
+    Some((((free - config.min_free_gb).max(0.0) * 1e9) as u64).saturating_sub(promised))
+}
+
+/// Where the take's current part will be saved: the first folder with room for more than a
+/// sliver of video.
+fn part_dir(config: &RecorderConfig, parts: &[TakePart]) -> PathBuf {
+    let dirs = take_dirs(config);
+    dirs.iter().find(|d| room(config, d, parts).is_none_or(|r| r > MIN_PART_BYTES)).unwrap_or(&dirs[0]).clone()
+}
+
+/// Whether the part being recorded in `dir` has to be closed off: it's nearing the file size limit
+/// there (4 GB on FAT32), or the room left there while a later folder has more.
+fn part_full(config: &RecorderConfig, dir: &Path, parts: &[TakePart], bytes: u64) -> bool {
+    let near = |limit: u64| bytes >= limit / 10 * 9;
+    if storage::max_file_size(dir).is_some_and(near) {
+        return true;
+    }
+    let dirs = take_dirs(config);
+    let later_room = dirs.iter().skip_while(|d| d.as_path() != dir).skip(1).any(|d| room(config, d, parts).is_none_or(|r| r > MIN_PART_BYTES));
+    later_room && room(config, dir, parts).is_some_and(near)
+}
+
+/// A take's raw video, audio and proxy files in the recording folder under `dir`.
+fn take_files(dir: &Path) -> (String, String, String) {
+    let scratch = dir.join(ffmpeg::RECORDING_DIR);
+    if let Err(e) = fs::create_dir_all(&scratch) {
+        log_line!("Couldn't create {}: {}", scratch.display(), e);
+    }
+    let file = |name: &str| scratch.join(name).to_string_lossy().to_string();
+    (file("vid.mp4"), file("aud.mp4"), file("vid_proxy.mp4"))
+}
+
+fn file_len(path: &str) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Blocks for the next command or camera frame, whichever comes first.
+fn next_command(cmd_rx: &Receiver<RecorderCommand>, frames: &FrameQueue) -> Option<RecorderCommand> {
+    crossbeam_channel::select! {
+        recv(cmd_rx) -> cmd => cmd.ok(),
+        recv(frames.receiver()) -> frame => match frame {
+            Ok((data, captured)) => Some(RecorderCommand::WriteFrame(data, captured)),
+            Err(_) => cmd_rx.recv().ok()
+        }
+    }
+}
+
+/// The command for a take's encoder, on libx264 when the chosen hardware encoder wouldn't start.
+fn take_cmd(config: &RecorderConfig, software: bool, video: &str, proxy: &str) -> Vec<String> {
+    let proxy = config.proxy.then_some(proxy);
+    if software {
+        ffmpeg::build_cmd(&software_encoding(config), video, proxy)
+    } else {
+        ffmpeg::build_cmd(config, video, proxy)
+    }
+}
+
+fn spawn_encoder(args: &[String]) -> std::io::Result<Child> {
+    Command::new("ffmpeg").args(args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().map(crash::log_stderr)
+}
+
+/// The take settings with libx264 in place of a hardware encoder that won't start. Device and
+/// custom encoder flags are dropped since they were meant for the hardware encoder.
+fn software_encoding(config: &RecorderConfig) -> RecorderConfig {
+    RecorderConfig { encoder: EncoderPreset::CPU, encoder_device: None, extra_args: Vec::new(), ..config.clone() }
+}
+
+fn temp_file(name: &str) -> String {
+    storage::temp_file(name).to_string_lossy().to_string()
+}
+
+fn start_replay(config: &RecorderConfig, seconds: u32, status_tx: &Sender<RecorderStatus>) -> Option<Child> {
+    let args = ffmpeg::build_replay_cmd(config, seconds);
+    if config.dry_run {
+        report_dry_run(status_tx, &args);
+        return None;
+    }
+    let replay_dir = config.workspace.join(ffmpeg::REPLAY_DIR);
+    let _ = fs::remove_dir_all(&replay_dir);
+    let _ = fs::create_dir_all(&replay_dir);
+    match Command::new("ffmpeg").args(&args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().map(crash::log_stderr) {
+        Ok(c) => Some(c),
+        Err(e) => {
+            let _ = status_tx.send(RecorderStatus::Error(format!("Failed to spawn replay encoder: {}", e)));
+            None
+        }
+    }
+}
+
+/// Starts streaming to `url`, with the microphone if the audio thread can serve it. The address
+/// usually carries the stream key, so it's kept out of the log.
+fn start_live(config: &RecorderConfig, url: &str, frames: &Arc<FrameQueue>, aud_tx: &Sender<AudioCommand>, status_tx: &Sender<RecorderStatus>) -> Option<LiveStream> {
+    if config.dry_run {
+        report_dry_run(status_tx, &ffmpeg::build_live_cmd(config, None, url));
+        return None;
+    }
+    let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+    let audio = aud_tx.send(AudioCommand::StartLiveAudio(reply_tx)).ok().and_then(|_| reply_rx.recv_timeout(LIVE_AUDIO_REPLY).ok()).flatten();
+    if audio.is_none() {
+        log_line!("Going live without sound, no microphone to stream");
+    }
+    match LiveStream::start(&ffmpeg::build_live_cmd(config, audio, url), config.fps, frames.clone()) {
+        Ok(stream) => {
+            log_line!("Live stream started");
+            Some(stream)
+        },
+        Err(e) => {
+            let _ = aud_tx.send(AudioCommand::StopLiveAudio);
+            let _ = status_tx.send(RecorderStatus::Error(format!("Failed to spawn live stream encoder: {}", e)));
+            let _ = status_tx.send(RecorderStatus::LiveStreamEnded);
+            None
+        }
+    }
+}
+
+fn stop_replay(mut proc: Child) {
+    drop(proc.stdin.take());
+    let _ = proc.wait();
+}
+
+fn report_dry_run(status_tx: &Sender<RecorderStatus>, args: &[String]) {
+    let line = ffmpeg::command_line(args);
+    log_line!("[dry run] {}", line);
+    let _ = status_tx.send(RecorderStatus::DryRun(line));
+}
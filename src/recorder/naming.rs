@@ -0,0 +1,77 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use chrono::Local;
+
+pub const DEFAULT_CLIP_TEMPLATE: &str = "clip_{index}";
+pub const DEFAULT_EXPORT_TEMPLATE: &str = "{project}_{date}";
+/// Guestbook takes are never curated, so they're named for when they were left.
+pub const GUESTBOOK_CLIP_TEMPLATE: &str = "guestbook_{date}_{time}";
+pub const VARIABLES: &str = "{project} {date} {time} {index}";
+
+/// Expands `{project}`, `{date}`, `{time}` and `{index}` in `template`. Unknown placeholders are
+/// kept as written, and characters that are not safe in file names are replaced with `_`.
+pub fn expand(template: &str, project: &str, index: u32) -> String {
+    let now = Local::now();
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}') else { break; };
+        out.push_str(&rest[..open]);
+        let value = match &rest[open + 1..open + close] {
+            "project" => project.to_string(),
+            "date" => now.format("%Y-%m-%d").to_string(),
+            "time" => now.format("%H%M%S").to_string(),
+            "index" => format!("{:03}", index),
+            _ => rest[open..=open + close].to_string()
+        };
+        out.push_str(&value);
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+
+    let name: String = out.chars().map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c }).collect();
+    let name = name.trim();
+    if name.is_empty() { String::from("untitled") } else { name.to_string() }
+}
+
+/// File name for segment `index`. The index is appended when the template leaves it out, since
+/// every segment of a session would otherwise overwrite the previous one.
+pub fn clip_file_name(template: &str, project: &str, index: u32) -> String {
+    let stem = expand(template, project, index);
+    if template.contains("{index}") {
+        format!("{}.mp4", stem)
+    } else {
+        format!("{}_{:03}.mp4", stem, index)
+    }
+}
+
+/// The index a file named by `clip_file_name` was given, read back from where the template puts
+/// it. `None` when the name doesn't look like it came from this template.
+pub fn clip_index(template: &str, project: &str, file_name: &str) -> Option<u32> {
+    let stem = file_name.strip_suffix(".mp4")?;
+    let digits = match template.split_once("{index}") {
+        // Dates, times and the project expand to the same width every time, so the index starts
+        // where the expanded text before it ends.
+        Some((before, _)) => {
+            let offset = if before.trim().is_empty() { 0 } else { expand(before, project, 0).len() };
+            stem.get(offset..)?
+        },
+        None => stem.rsplit_once('_')?.1
+    };
+    let end = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+    digits[..end].parse().ok()
+}
@@ -0,0 +1,139 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+//! Subject tracking for the vertical export. Frames are sampled from each clip and faces are
+//! found in them by ffmpeg's `dnn_detect` with the face model set in settings; without one the
+//! crop stays centred. The smoothed track drives the 9:16 crop window through `sendcmd`.
+
+use std::{fmt::Write as _, fs, io::{self, BufRead, BufReader}, path::Path, process::{Command, Stdio}, sync::atomic::{AtomicBool, Ordering}};
+
+use crate::messages::recorder::ClipInfo;
+
+const SAMPLE_FPS: f64 = 2.0;
+const SAMPLE_W: u32 = 640;
+/// Weight of each new sample in the exponential smoothing, run forwards and backwards.
+const SMOOTHING: f32 = 0.3;
+/// Detections the model is less sure of than this are ignored.
+const MIN_CONFIDENCE: f32 = 0.5;
+
+/// A point on the timeline and the horizontal centre of the subject, 0.0 (left) to 1.0 (right).
+pub type Keyframe = (f64, f32);
+
+/// Tracks every clip in timeline order and returns one smoothed track for the whole export,
+/// reporting the share of it done as it goes. `None` once `cancel` is set, and an error naming
+/// the clip when ffmpeg couldn't run the model over it.
+pub fn track_timeline(clips: &[ClipInfo], model: &Path, cancel: &AtomicBool, progress: impl Fn(f32)) -> Option<Result<Vec<Keyframe>, String>> {
+    let total: f64 = clips.iter().map(ClipInfo::trimmed_duration).sum::<f64>().max(0.001);
+    let mut track = Vec::new();
+    let mut offset = 0.0;
+    for clip in clips {
+        let centres = match sample_clip(clip, model, cancel, |secs| progress(((offset + secs) / total) as f32))? {
+            Ok(centres) => centres,
+            Err(e) => return Some(Err(format!("{}: {}", clip.video_path.display(), e)))
+        };
+        let mut last = track.last().map(|(_, c)| *c).unwrap_or(0.5);
+        for (i, centre) in centres.into_iter().enumerate() {
+            last = centre.unwrap_or(last);
+            track.push((offset + i as f64 / SAMPLE_FPS, last));
+        }
+        offset += clip.trimmed_duration();
+    }
+    smooth(&mut track);
+    Some(Ok(track))
+}
+
+/// Runs the face model over a clip at a low rate and reads the boxes `showinfo` prints for each
+/// sampled frame, keeping the centre of the largest face. A failed run gives ffmpeg's last
+/// complaint, since a wrong model or a build without `dnn_detect` otherwise looks like no faces.
+fn sample_clip(clip: &ClipInfo, model: &Path, cancel: &AtomicBool, progress: impl Fn(f64)) -> Option<Result<Vec<Option<f32>>, String>> {
+    let filter = format!("fps={},scale={}:-2,dnn_detect=dnn_backend=openvino:model={}:confidence={},showinfo",
+        SAMPLE_FPS, SAMPLE_W, super::ffmpeg::escape_filter_path(model), MIN_CONFIDENCE);
+    let child = Command::new("ffmpeg").args(["-hide_banner", "-nostats"]).args(super::ffmpeg::clip_input(clip))
+        .args(["-vf", &filter, "-an", "-f", "null", "-"])
+        .stdout(Stdio::null()).stderr(Stdio::piped()).spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => return Some(Err(format!("couldn't start ffmpeg: {}", e)))
+    };
+
+    let mut centres: Vec<Option<f32>> = Vec::new();
+    let mut largest = 0;
+    let mut complaint = None;
+    if let Some(stderr) = child.stderr.take() {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            if cancel.load(Ordering::Relaxed) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            if line.contains(" n:") && line.contains("pts_time:") {
+                centres.push(None);
+                largest = 0;
+                progress(centres.len() as f64 / SAMPLE_FPS);
+            } else if let Some((x0, x1, area)) = face_box(&line)
+                && area > largest && let Some(centre) = centres.last_mut() {
+                largest = area;
+                *centre = Some(((x0 + x1) as f32 / 2.0 / SAMPLE_W as f32).clamp(0.0, 1.0));
+            } else if !line.contains("Parsed_showinfo") && !line.trim().is_empty() {
+                complaint = Some(line.trim().to_string());
+            }
+        }
+    }
+    match child.wait() {
+        Ok(status) if status.success() => Some(Ok(centres)),
+        Ok(status) => Some(Err(complaint.unwrap_or_else(|| format!("ffmpeg exited with {}", status)))),
+        Err(e) => Some(Err(e.to_string()))
+    }
+}
+
+/// Left and right edges and the area of a box from a `showinfo` line like
+/// `index: 0, region: (x0, y0) -> (x1, y1), label: face, confidence: 9000/10000.`
+fn face_box(line: &str) -> Option<(i32, i32, i32)> {
+    let region = line.split("region: (").nth(1)?;
+    let (top_left, rest) = region.split_once(") -> (")?;
+    let bottom_right = rest.split(')').next()?;
+    let point = |s: &str| -> Option<(i32, i32)> {
+        let (x, y) = s.split_once(',')?;
+        Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+    };
+    let ((x0, y0), (x1, y1)) = (point(top_left)?, point(bottom_right)?);
+    Some((x0, x1, (x1 - x0).max(0) * (y1 - y0).max(0)))
+}
+
+/// Exponential smoothing forwards then backwards, so the crop glides without lagging the subject.
+fn smooth(track: &mut [Keyframe]) {
+    for i in 1..track.len() {
+        track[i].1 = track[i - 1].1 + SMOOTHING * (track[i].1 - track[i - 1].1);
+    }
+    for i in (0..track.len().saturating_sub(1)).rev() {
+        track[i].1 = track[i + 1].1 + SMOOTHING * (track[i].1 - track[i + 1].1);
+    }
+}
+
+/// Writes a `sendcmd` script that retargets `crop@reframe`'s x at every keyframe with a linear ramp
+/// to the next one, keeping the window inside the frame.
+pub fn write_commands(track: &[Keyframe], path: &Path) -> io::Result<()> {
+    let mut script = String::new();
+    for pair in track.windows(2) {
+        let ((t0, c0), (t1, c1)) = (pair[0], pair[1]);
+        let _ = writeln!(script, "{:.3} crop@reframe x 'clip(({:.4}+({:.4})*(t-{:.3})/{:.3})*iw-ow/2,0,iw-ow)';",
+            t0, c0, c1 - c0, t0, (t1 - t0).max(0.001));
+    }
+    if let Some((t, c)) = track.last() {
+        let _ = writeln!(script, "{:.3} crop@reframe x 'clip({:.4}*iw-ow/2,0,iw-ow)';", t, c);
+    }
+    fs::write(path, script)
+}
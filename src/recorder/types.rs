@@ -13,14 +13,84 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use std::fmt;
+use crate::messages::recorder::ClipInfo;
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf};
 
 #[derive(Debug, Clone, PartialEq, Copy)]
 pub enum EncoderPreset {
     CPU, 
     NVIDIA,
     AMD,
-    INTEL
+    INTEL,
+    CPUHEVC,
+    NVIDIAHEVC,
+    AMDHEVC,
+    INTELHEVC,
+    CPUAV1,
+    NVIDIAAV1,
+    INTELAV1,
+    /// Lossless intra-only H.264 for clips that will be re-edited elsewhere.
+    MASTERING
+}
+
+impl EncoderPreset {
+    pub const ALL: [EncoderPreset; 12] = [
+        EncoderPreset::CPU, EncoderPreset::NVIDIA, EncoderPreset::AMD, EncoderPreset::INTEL,
+        EncoderPreset::CPUHEVC, EncoderPreset::NVIDIAHEVC, EncoderPreset::AMDHEVC, EncoderPreset::INTELHEVC,
+        EncoderPreset::CPUAV1, EncoderPreset::NVIDIAAV1, EncoderPreset::INTELAV1, EncoderPreset::MASTERING
+    ];
+
+    /// The ffmpeg encoder this preset drives.
+    pub fn ffmpeg_name(self) -> &'static str {
+        match self {
+            EncoderPreset::CPU => "libx264",
+            EncoderPreset::NVIDIA => "h264_nvenc",
+            EncoderPreset::AMD => "h264_amf",
+            EncoderPreset::INTEL => "h264_qsv",
+            EncoderPreset::CPUHEVC => "libx265",
+            EncoderPreset::NVIDIAHEVC => "hevc_nvenc",
+            EncoderPreset::AMDHEVC => "hevc_amf",
+            EncoderPreset::INTELHEVC => "hevc_qsv",
+            EncoderPreset::CPUAV1 => "libsvtav1",
+            EncoderPreset::NVIDIAAV1 => "av1_nvenc",
+            EncoderPreset::INTELAV1 => "av1_qsv",
+            EncoderPreset::MASTERING => "libx264"
+        }
+    }
+
+    pub fn is_hardware(self) -> bool {
+        !matches!(self, EncoderPreset::CPU | EncoderPreset::CPUHEVC | EncoderPreset::CPUAV1 | EncoderPreset::MASTERING)
+    }
+
+    pub fn is_hevc(self) -> bool {
+        matches!(self, EncoderPreset::CPUHEVC | EncoderPreset::NVIDIAHEVC | EncoderPreset::AMDHEVC | EncoderPreset::INTELHEVC)
+    }
+
+    /// MPEG-TS can't carry AV1, so segmented outputs switch container for it.
+    pub fn is_av1(self) -> bool {
+        matches!(self, EncoderPreset::CPUAV1 | EncoderPreset::NVIDIAAV1 | EncoderPreset::INTELAV1)
+    }
+
+    /// Whose GPU runs this encoder, for picking a device on multi-GPU machines.
+    pub fn vendor(self) -> Option<GpuVendor> {
+        match self {
+            EncoderPreset::NVIDIA | EncoderPreset::NVIDIAHEVC | EncoderPreset::NVIDIAAV1 => Some(GpuVendor::Nvidia),
+            EncoderPreset::INTEL | EncoderPreset::INTELHEVC | EncoderPreset::INTELAV1 => Some(GpuVendor::Intel),
+            EncoderPreset::AMD | EncoderPreset::AMDHEVC => Some(GpuVendor::Amd),
+            EncoderPreset::CPU | EncoderPreset::CPUHEVC | EncoderPreset::CPUAV1 | EncoderPreset::MASTERING => None
+        }
+    }
+
+    /// The hardware encoder of the same codec, for profiles that want the CPU left alone.
+    pub fn hardware_equivalent(self) -> Self {
+        match self {
+            EncoderPreset::CPU => EncoderPreset::NVIDIA,
+            EncoderPreset::CPUHEVC => EncoderPreset::NVIDIAHEVC,
+            EncoderPreset::CPUAV1 => EncoderPreset::NVIDIAAV1,
+            other => other
+        }
+    }
 }
 
 impl fmt::Display for EncoderPreset {
@@ -29,7 +99,32 @@ impl fmt::Display for EncoderPreset {
             EncoderPreset::CPU => write!(f, "CPU (Universal / libx264)"),
             EncoderPreset::NVIDIA => write!(f, "NVIDIA (NVENC)"),
             EncoderPreset::AMD => write!(f, "AMD (AMF)"),
-            EncoderPreset::INTEL => write!(f, "Intel (QuickSync)")
+            EncoderPreset::INTEL => write!(f, "Intel (QuickSync)"),
+            EncoderPreset::CPUHEVC => write!(f, "CPU HEVC (libx265)"),
+            EncoderPreset::NVIDIAHEVC => write!(f, "NVIDIA HEVC (NVENC)"),
+            EncoderPreset::AMDHEVC => write!(f, "AMD HEVC (AMF)"),
+            EncoderPreset::INTELHEVC => write!(f, "Intel HEVC (QuickSync)"),
+            EncoderPreset::CPUAV1 => write!(f, "CPU AV1 (SVT-AV1)"),
+            EncoderPreset::NVIDIAAV1 => write!(f, "NVIDIA AV1 (NVENC, RTX 40+)"),
+            EncoderPreset::INTELAV1 => write!(f, "Intel AV1 (QuickSync, Arc)"),
+            EncoderPreset::MASTERING => write!(f, "Mastering (Lossless libx264, very large files)")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy, Serialize, Deserialize)]
+pub enum GpuVendor {
+    Nvidia,
+    Intel,
+    Amd
+}
+
+impl fmt::Display for GpuVendor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuVendor::Nvidia => write!(f, "NVENC"),
+            GpuVendor::Intel => write!(f, "QuickSync"),
+            GpuVendor::Amd => write!(f, "AMF")
         }
     }
 }
@@ -66,4 +161,535 @@ impl fmt::Display for EncodingSpeed {
             EncodingSpeed::Compact => write!(f, "Compact (High CPU, Smaller file)")
         }
     }
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ExportOptions {
+    pub ladder: bool,
+    pub hls: bool,
+    pub cutaways: bool,
+    /// Re-encode the joined timeline in two passes at this video bitrate instead of stream-copying.
+    #[serde(default)]
+    pub two_pass_kbps: Option<u32>,
+    /// Join with the concat filter and re-encode, for clips whose timestamps don't stream-copy cleanly.
+    #[serde(default)]
+    pub reencode: bool,
+    /// Render a 16:9 and a 9:16 deliverable of the timeline in the same pass.
+    #[serde(default)]
+    pub dual: bool,
+    /// Let the 9:16 crop follow the subject instead of staying centred.
+    #[serde(default)]
+    pub reframe: bool,
+    /// Fill audio gaps with the session's room tone instead of digital silence.
+    #[serde(default)]
+    pub room_tone: bool,
+    #[serde(default)]
+    pub metadata: ExportMetadata,
+    #[serde(default)]
+    pub lower_third_style: LowerThirdStyle,
+    /// Add the clips onto the end of this earlier export instead of writing a new file. Its
+    /// content is copied as it is; only the new clips are encoded, if they have to be.
+    #[serde(default)]
+    pub append_to: Option<PathBuf>,
+    /// Export the timeline as a single 1080x1920 video for Shorts and Reels.
+    #[serde(default)]
+    pub vertical: Option<VerticalFrame>,
+    /// Encode the timeline to a named destination's size, frame rate and codecs.
+    #[serde(default)]
+    pub preset: Option<ExportPreset>,
+    /// Logo burned into the finished export.
+    #[serde(default)]
+    pub watermark: Option<Watermark>,
+    /// Takes in another frame size or rate are conformed to this, the format the timeline started
+    /// in. `None` conforms them to the first clip.
+    #[serde(default)]
+    pub frame_format: Option<FrameFormat>
+}
+
+/// A frame size and rate.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FrameFormat {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32
+}
+
+/// A logo composited over the picture, sized and placed relative to the frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Watermark {
+    /// A PNG, whose transparency is kept.
+    pub image: PathBuf,
+    /// Where the logo sits in the room the frame leaves around it, from 0 (left or top) to 1
+    /// (right or bottom).
+    pub x: f32,
+    pub y: f32,
+    pub opacity: f32,
+    /// Logo height as a fraction of the frame's.
+    pub height: f32,
+    /// Burn it into the export once instead of into every take as it's saved.
+    pub at_export: bool
+}
+
+impl Watermark {
+    pub fn new(image: PathBuf) -> Self {
+        Self { image, x: 0.95, y: 0.05, opacity: 0.8, height: 0.1, at_export: true }
+    }
+}
+
+/// A bundle of export settings for a common destination.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ExportPreset {
+    /// 1080p at 60 fps in high-quality H.264, streamable from the start of the file.
+    YouTube1080p60,
+    /// 720p at 30 fps squeezed under Discord's 25 MB upload limit.
+    Discord25Mb,
+    /// Lossless FFV1 and FLAC in Matroska at the source size and rate, for keeping.
+    ArchiveLossless
+}
+
+impl ExportPreset {
+    pub const ALL: [ExportPreset; 3] = [ExportPreset::YouTube1080p60, ExportPreset::Discord25Mb, ExportPreset::ArchiveLossless];
+
+    /// Output height; never scaled up. `None` keeps the source size.
+    pub fn height(self) -> Option<u32> {
+        match self {
+            ExportPreset::YouTube1080p60 => Some(1080),
+            ExportPreset::Discord25Mb => Some(720),
+            ExportPreset::ArchiveLossless => None
+        }
+    }
+
+    /// Output frame rate. `None` keeps the source rate.
+    pub fn fps(self) -> Option<u32> {
+        match self {
+            ExportPreset::YouTube1080p60 => Some(60),
+            ExportPreset::Discord25Mb => Some(30),
+            ExportPreset::ArchiveLossless => None
+        }
+    }
+
+    /// The file size the bitrate is worked out from, for presets aiming at one.
+    pub fn target_bytes(self) -> Option<u64> {
+        match self {
+            ExportPreset::Discord25Mb => Some(25_000_000),
+            _ => None
+        }
+    }
+
+    pub fn container(self) -> &'static str {
+        match self {
+            ExportPreset::ArchiveLossless => "mkv",
+            _ => "mp4"
+        }
+    }
+}
+
+impl fmt::Display for ExportPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportPreset::YouTube1080p60 => write!(f, "YouTube 1080p60"),
+            ExportPreset::Discord25Mb => write!(f, "Discord <25MB"),
+            ExportPreset::ArchiveLossless => write!(f, "Archive lossless")
+        }
+    }
+}
+
+/// How the picture is brought to 9:16 for a vertical export.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VerticalFrame {
+    pub fit: VerticalFit,
+    /// Where the window sits along the axis with room to spare, from 0 (left or top) to 1 (right
+    /// or bottom).
+    pub anchor: f32
+}
+
+impl Default for VerticalFrame {
+    fn default() -> Self {
+        Self { fit: VerticalFit::Crop, anchor: 0.5 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum VerticalFit {
+    /// Cut a full-height 9:16 window out of the picture.
+    Crop,
+    /// Shrink the whole picture to the frame's width and fill above and below with black.
+    Pad
+}
+
+impl fmt::Display for VerticalFit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerticalFit::Crop => write!(f, "Crop"),
+            VerticalFit::Pad => write!(f, "Pad")
+        }
+    }
+}
+
+/// A name and title caption over part of a clip. Times are seconds into the clip as recorded,
+/// before any trim.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LowerThird {
+    pub name: String,
+    pub title: String,
+    pub start: f64,
+    pub end: f64
+}
+
+/// How every lower third in an export looks: the name plate's colour, the corner captions sit in,
+/// how long they take to slide in and back out, and an optional font file in place of ffmpeg's default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LowerThirdStyle {
+    pub accent: [u8; 3],
+    pub slide_secs: f64,
+    pub position: CaptionPosition,
+    pub font: Option<PathBuf>
+}
+
+impl Default for LowerThirdStyle {
+    fn default() -> Self {
+        Self { accent: [230, 120, 20], slide_secs: 0.5, position: CaptionPosition::BottomLeft, font: None }
+    }
+}
+
+/// Corner of the frame lower thirds are drawn in. Captions slide in from that side.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CaptionPosition {
+    BottomLeft,
+    BottomRight,
+    TopLeft,
+    TopRight
+}
+
+impl CaptionPosition {
+    pub const ALL: [CaptionPosition; 4] = [CaptionPosition::BottomLeft, CaptionPosition::BottomRight, CaptionPosition::TopLeft, CaptionPosition::TopRight];
+
+    pub fn is_left(self) -> bool {
+        matches!(self, CaptionPosition::BottomLeft | CaptionPosition::TopLeft)
+    }
+
+    pub fn is_top(self) -> bool {
+        matches!(self, CaptionPosition::TopLeft | CaptionPosition::TopRight)
+    }
+}
+
+impl fmt::Display for CaptionPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptionPosition::BottomLeft => write!(f, "Bottom left"),
+            CaptionPosition::BottomRight => write!(f, "Bottom right"),
+            CaptionPosition::TopLeft => write!(f, "Top left"),
+            CaptionPosition::TopRight => write!(f, "Top right")
+        }
+    }
+}
+
+/// Container tags written into single-file exports. Empty fields are left out.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportMetadata {
+    pub title: String,
+    pub author: String,
+    pub comment: String
+}
+
+/// A finalize request as queued by the UI, persisted until the recorder reports it done.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportJob {
+    pub clips: Vec<ClipInfo>,
+    pub output: String,
+    pub options: ExportOptions
+}
+
+/// A finished export, kept so it can be found, opened or redone later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportRecord {
+    pub job: ExportJob,
+    /// The playable result: the export itself, the top ladder rung or the HLS master playlist.
+    pub path: PathBuf,
+    pub finished_at: String
+}
+
+/// Timeline category of a clip. B-roll and cutaways are laid over the A-roll clip before them
+/// when exporting with cutaways.
+#[derive(Debug, Clone, PartialEq, Copy, Default, Serialize, Deserialize)]
+pub enum Lane {
+    #[default]
+    ARoll,
+    BRoll,
+    Cutaway
+}
+
+impl fmt::Display for Lane {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lane::ARoll => write!(f, "A-roll"),
+            Lane::BRoll => write!(f, "B-roll"),
+            Lane::Cutaway => write!(f, "Cutaway")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum AnimationFormat {
+    Gif,
+    WebP
+}
+
+impl AnimationFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::WebP => "webp"
+        }
+    }
+}
+
+impl fmt::Display for AnimationFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationFormat::Gif => write!(f, "GIF"),
+            AnimationFormat::WebP => write!(f, "WebP")
+        }
+    }
+}
+
+/// Look of a generated audio visualizer clip.
+#[derive(Debug, Clone, PartialEq, Copy, Default)]
+pub enum VisualizerStyle {
+    #[default]
+    Waves,
+    Spectrum
+}
+
+impl VisualizerStyle {
+    pub const ALL: [VisualizerStyle; 2] = [VisualizerStyle::Waves, VisualizerStyle::Spectrum];
+}
+
+impl fmt::Display for VisualizerStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VisualizerStyle::Waves => write!(f, "Waves"),
+            VisualizerStyle::Spectrum => write!(f, "Spectrum")
+        }
+    }
+}
+
+/// Settings for an animated GIF/WebP made from a clip or an export.
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct AnimationOptions {
+    pub format: AnimationFormat,
+    /// Output width; the height follows the aspect ratio.
+    pub width: u32,
+    pub fps: u32,
+    pub loop_forever: bool,
+    /// Part of the source to use: start, and end if not the whole rest of it.
+    pub start: f64,
+    pub end: Option<f64>
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self { format: AnimationFormat::Gif, width: 480, fps: 12, loop_forever: true, start: 0.0, end: None }
+    }
+}
+
+pub const DEFAULT_TRANSITION_SECS: f64 = 0.5;
+
+/// How a clip hands over to the next one on export.
+#[derive(Debug, Clone, PartialEq, Copy, Serialize, Deserialize)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub secs: f64
+}
+
+#[derive(Debug, Clone, PartialEq, Copy, Serialize, Deserialize)]
+pub enum TransitionKind {
+    Fade,
+    Dissolve,
+    FadeBlack,
+    WipeLeft,
+    SlideLeft
+}
+
+impl TransitionKind {
+    pub const ALL: [TransitionKind; 5] = [TransitionKind::Fade, TransitionKind::Dissolve, TransitionKind::FadeBlack, TransitionKind::WipeLeft, TransitionKind::SlideLeft];
+
+    pub fn xfade_name(self) -> &'static str {
+        match self {
+            TransitionKind::Fade => "fade",
+            TransitionKind::Dissolve => "dissolve",
+            TransitionKind::FadeBlack => "fadeblack",
+            TransitionKind::WipeLeft => "wipeleft",
+            TransitionKind::SlideLeft => "slideleft"
+        }
+    }
+}
+
+impl fmt::Display for TransitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionKind::Fade => write!(f, "Crossfade"),
+            TransitionKind::Dissolve => write!(f, "Dissolve"),
+            TransitionKind::FadeBlack => write!(f, "Dip to black"),
+            TransitionKind::WipeLeft => write!(f, "Wipe"),
+            TransitionKind::SlideLeft => write!(f, "Slide")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum AudioCodec {
+    Aac,
+    Opus
+}
+
+impl AudioCodec {
+    pub fn ffmpeg_name(self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "libopus"
+        }
+    }
+}
+
+impl fmt::Display for AudioCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioCodec::Aac => write!(f, "AAC"),
+            AudioCodec::Opus => write!(f, "Opus")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Downmix {
+    Keep,
+    Mono,
+    Stereo
+}
+
+impl fmt::Display for Downmix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Downmix::Keep => write!(f, "Keep channels"),
+            Downmix::Mono => write!(f, "Mono"),
+            Downmix::Stereo => write!(f, "Stereo")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct AudioEncoding {
+    pub codec: AudioCodec,
+    pub bitrate_kbps: u32,
+    /// Bit per input channel to record; 0 records every channel.
+    pub channel_mask: u32,
+    pub downmix: Downmix
+}
+
+impl Default for AudioEncoding {
+    fn default() -> Self {
+        Self { codec: AudioCodec::Aac, bitrate_kbps: 192, channel_mask: 0, downmix: Downmix::Keep }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Copy, Default)]
+pub enum RateControl {
+    #[default]
+    Crf,
+    Vbr,
+    Cbr
+}
+
+impl fmt::Display for RateControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateControl::Crf => write!(f, "Constant quality"),
+            RateControl::Vbr => write!(f, "VBR (target bitrate)"),
+            RateControl::Cbr => write!(f, "CBR (fixed bitrate)")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecorderConfig {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub format: String,
+    pub encoder: EncoderPreset,
+    pub quality: EncodingQuality,
+    pub speed: EncodingSpeed,
+    pub rate_control: RateControl,
+    /// Video target for `RateControl::Vbr` and `RateControl::Cbr`.
+    pub bitrate_kbps: u32,
+    pub live_hls: bool,
+    /// Also encode a low-res proxy of each take for the timeline's thumbnails and previews.
+    pub proxy: bool,
+    pub audio_offset_ms: i32,
+    pub audio: AudioEncoding,
+    pub dry_run: bool,
+    /// Session folder every clip, thumbnail and temp file is written into.
+    pub workspace: PathBuf,
+    pub project: String,
+    /// See `naming::expand` for the placeholders.
+    pub clip_template: String,
+    /// User-supplied encoder flags, already split into arguments.
+    pub extra_args: Vec<String>,
+    /// Which GPU a hardware encoder runs on, in the form its device option takes. `None` leaves
+    /// the choice to the driver.
+    pub encoder_device: Option<String>,
+    /// Takes won't start, and a running take warns, below this much free space in the workspace.
+    pub min_free_gb: f64,
+    /// Burned into the start of each take as it's merged, such as the guestbook question a guest
+    /// is answering.
+    pub burn_caption: Option<String>,
+    /// Burn the wall-clock time into every recorded frame.
+    pub burn_timestamp: bool,
+    /// Record sound alongside the picture. Off, the audio thread is left alone and takes are saved
+    /// with no audio track at all.
+    pub record_audio: bool,
+    /// Where takes continue once the workspace's volume is full.
+    pub overflow_dir: Option<PathBuf>,
+    /// Every saved clip is also copied here, into a folder named after the session.
+    pub backup_dir: Option<PathBuf>,
+    /// Logo burned into each take as it's saved, at the cost of re-encoding it.
+    pub watermark: Option<Watermark>,
+    /// OpenVINO face detection model that vertical exports follow the subject with.
+    pub face_model: Option<PathBuf>
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 480,
+            fps: 30,
+            format: String::from("MJPEG"),
+            encoder: EncoderPreset::CPU,
+            quality: EncodingQuality::Med,
+            speed: EncodingSpeed::Balanced,
+            rate_control: RateControl::Crf,
+            bitrate_kbps: 8000,
+            live_hls: false,
+            proxy: false,
+            audio_offset_ms: 0,
+            audio: AudioEncoding::default(),
+            dry_run: false,
+            workspace: PathBuf::from("."),
+            project: String::from("clipper"),
+            clip_template: String::from(super::naming::DEFAULT_CLIP_TEMPLATE),
+            extra_args: Vec::new(),
+            encoder_device: None,
+            min_free_gb: 2.0,
+            burn_caption: None,
+            burn_timestamp: false,
+            record_audio: true,
+            overflow_dir: None,
+            backup_dir: None,
+            watermark: None,
+            face_model: None
+        }
+    }
+}
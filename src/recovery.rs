@@ -0,0 +1,84 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+//! Picking a session back up after Clipper died mid-session. The workspace in use is recorded
+//! while Clipper runs and cleared on a clean exit, so finding it on startup means the last session
+//! crashed or was killed.
+
+use crate::{messages::recorder::ClipInfo, recorder::ffmpeg, storage};
+use std::{fs, io, path::{Path, PathBuf}};
+
+const ACTIVE_SESSION: &str = "active_session.json";
+/// The session's timeline as the app last had it: order, trims, lanes and exclusions.
+const TIMELINE_FILE: &str = "timeline.json";
+
+/// What a crashed session left behind.
+pub struct CrashedSession {
+    pub workspace: PathBuf,
+    pub clips: usize,
+    /// Recording folders holding a take that was still recording.
+    pub orphaned_takes: Vec<PathBuf>
+}
+
+pub fn mark_active(workspace: &Path) {
+    storage::save_json(ACTIVE_SESSION, &workspace);
+}
+
+pub fn mark_clean_exit() {
+    let _ = fs::remove_file(storage::data_dir().join(ACTIVE_SESSION));
+}
+
+pub fn save_timeline(workspace: &Path, clips: &[ClipInfo]) -> io::Result<()> {
+    fs::write(workspace.join(TIMELINE_FILE), serde_json::to_vec_pretty(clips)?)
+}
+
+pub fn load_timeline(workspace: &Path) -> Option<Vec<ClipInfo>> {
+    let data = fs::read_to_string(workspace.join(TIMELINE_FILE)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+/// The clips in a session workspace, oldest first. Time-lapses and room tone share the folder but
+/// aren't takes.
+pub fn session_clips(workspace: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(workspace) else { return Vec::new() };
+    let mut clips: Vec<(PathBuf, Option<std::time::SystemTime>)> = entries.flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "mp4"))
+        .filter(|p| p.file_name().is_some_and(|n| n != ffmpeg::ROOM_TONE && !n.to_string_lossy().starts_with("stills_")))
+        .map(|p| { let modified = fs::metadata(&p).and_then(|m| m.modified()).ok(); (p, modified) })
+        .collect();
+    clips.sort_by_key(|(_, modified)| *modified);
+    clips.into_iter().map(|(p, _)| p).collect()
+}
+
+/// Deletes the takes a crashed session was still recording, for a fresh start instead of a restore.
+pub fn discard_unfinished(session: &CrashedSession) {
+    for dir in &session.orphaned_takes {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+/// Looks for a session that didn't close cleanly and still has something worth restoring.
+pub fn find_crashed() -> Option<CrashedSession> {
+    let workspace: PathBuf = storage::load_json(ACTIVE_SESSION)?;
+    let clips = session_clips(&workspace).len();
+    let orphaned_takes: Vec<PathBuf> = [workspace.join(ffmpeg::RECORDING_DIR)].into_iter().filter(|d| d.join("vid.mp4").exists()).collect();
+    if clips == 0 && orphaned_takes.is_empty() {
+        mark_clean_exit();
+        return None;
+    }
+    Some(CrashedSession { workspace, clips, orphaned_takes })
+}
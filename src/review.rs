@@ -0,0 +1,66 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{fmt::Write as _, fs, io, path::{Path, PathBuf}};
+
+use crate::messages::recorder::ClipInfo;
+use serde::{Deserialize, Serialize};
+
+pub const NOTES_FILE: &str = "review_notes.md";
+/// The flags themselves, which the markdown only describes, so a reopened session gets them back.
+const FLAGS_FILE: &str = "review_flags.json";
+
+/// A moment in a clip to come back to, e.g. "check this at 01:23 of clip 7".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReviewFlag {
+    pub clip: PathBuf,
+    pub at_secs: f64,
+    pub note: String
+}
+
+pub fn timestamp(secs: f64) -> String {
+    let whole = secs.max(0.0) as u64;
+    format!("{:02}:{:02}", whole / 60, whole % 60)
+}
+
+/// Rewrites the session's notes file as a markdown checklist, in timeline order, and saves the
+/// flags for `load_flags`. Clips that are no longer on the timeline keep their flags under their
+/// file name.
+pub fn write_notes(workspace: &Path, flags: &[ReviewFlag], playlist: &[ClipInfo]) -> io::Result<PathBuf> {
+    let position = |flag: &ReviewFlag| playlist.iter().position(|c| c.video_path == flag.clip);
+    let mut ordered: Vec<&ReviewFlag> = flags.iter().collect();
+    ordered.sort_by(|a, b| position(a).unwrap_or(usize::MAX).cmp(&position(b).unwrap_or(usize::MAX)).then(a.at_secs.total_cmp(&b.at_secs)));
+
+    let mut text = String::from("# Review notes\n\n");
+    for flag in ordered {
+        let file = flag.clip.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let clip = match position(flag) {
+            Some(i) => format!("clip {} ({})", i + 1, file),
+            None => format!("{} (removed)", file)
+        };
+        let _ = writeln!(text, "- [ ] {} at {}: {}", clip, timestamp(flag.at_secs), flag.note.trim());
+    }
+
+    fs::write(workspace.join(FLAGS_FILE), serde_json::to_vec_pretty(flags)?)?;
+    let path = workspace.join(NOTES_FILE);
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// The flags last saved in a session folder, none for a new one.
+pub fn load_flags(workspace: &Path) -> Vec<ReviewFlag> {
+    fs::read_to_string(workspace.join(FLAGS_FILE)).ok().and_then(|data| serde_json::from_str(&data).ok()).unwrap_or_default()
+}
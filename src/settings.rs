@@ -0,0 +1,169 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use crate::{budget::{self, DropPolicy}, keymap::Action, messages::video::VideoConfig, recorder::types::{GpuVendor, Watermark}, storage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, path::PathBuf};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Preferences that carry over between sessions. Missing fields fall back to their defaults so
+/// older settings files keep loading.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Appended verbatim to the video encoder arguments.
+    pub extra_ffmpeg_args: String,
+    /// Slide timeline cards apart while dragging instead of jumping.
+    pub animate_timeline: bool,
+    /// Lay timeline cards out in wrapping rows instead of one scrolling strip.
+    pub timeline_wrap: bool,
+    /// Device each hardware encoder family runs on; missing entries use the driver's default.
+    pub encoder_devices: BTreeMap<GpuVendor, String>,
+    /// A take ends by itself after this long, as if the record key were released.
+    pub max_take_secs: Option<u32>,
+    /// Free space in GB the workspace volume must keep for a take to start.
+    pub min_free_gb: f64,
+    /// Freeze auto white balance and exposure for the length of each take.
+    pub lock_exposure: bool,
+    /// Kiosk mode: settings, removing clips and quitting ask for the PIN this was made from. It
+    /// keeps guests at the screen out, not anyone with access to this file.
+    pub kiosk_pin: Option<PinHash>,
+    /// Every take is saved as its own file named by time, and an attract screen replaces the
+    /// timeline.
+    pub guestbook: bool,
+    /// Questions the guestbook puts to guests, one per line, moving to the next after each take.
+    pub guestbook_prompts: String,
+    /// Also draw the question over the first seconds of the take it was answered in.
+    pub burn_prompts: bool,
+    /// Draw the date and time into every frame as it's recorded. The time is when the encoder gets
+    /// the frame, which trails the camera by however many frames are queued.
+    pub burn_timestamp: bool,
+    /// Off for silent screen captures, saved without an audio track.
+    pub record_audio: bool,
+    /// RTMP address to go live to, stream key included.
+    pub live_url: String,
+    /// Serve the live HLS playlist to browsers on the local network.
+    pub serve_hls: bool,
+    /// JSON file the recording state is written to once a second, for dashboards.
+    pub status_file: Option<PathBuf>,
+    /// Folder each session's dated folder is made in; the platform default until one is picked.
+    pub workspace_root: Option<PathBuf>,
+    /// Camera format last started, picked again while the camera still offers it.
+    pub video_format: Option<VideoConfig>,
+    /// Takes carry on in a session folder here once the workspace volume runs low.
+    pub overflow_dir: Option<PathBuf>,
+    /// Second location every saved clip is copied to.
+    pub backup_dir: Option<PathBuf>,
+    /// Logo burned into each take or into the finished export, as its `at_export` says. Exports
+    /// only carry it on a single MP4 file: ladders, MKV presets and the 9:16 copy go without.
+    pub watermark: Option<Watermark>,
+    /// OpenVINO face detection model (.xml) for following the subject in vertical exports.
+    pub face_model: Option<PathBuf>,
+    /// How late the microphone arrives relative to the camera, made up for as takes are merged.
+    pub audio_offset_ms: i32,
+    /// Grab the save-replay hotkey from the whole desktop while recording, so it works while a
+    /// game has focus.
+    pub global_replay_hotkey: bool,
+    /// Hotkeys moved off their defaults, by egui key name.
+    pub hotkeys: BTreeMap<Action, String>,
+    /// Memory queued frames may hold before new ones are dropped instead of buffered.
+    pub frame_cap_mb: usize,
+    /// Frames that can wait for the recorder before the drop policy kicks in.
+    pub frame_queue_len: usize,
+    pub drop_policy: DropPolicy,
+    /// The preview is decoded from every nth captured frame.
+    pub preview_every: u32
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            extra_ffmpeg_args: String::new(),
+            animate_timeline: true,
+            timeline_wrap: false,
+            encoder_devices: BTreeMap::new(),
+            max_take_secs: None,
+            min_free_gb: 2.0,
+            lock_exposure: false,
+            kiosk_pin: None,
+            guestbook: false,
+            guestbook_prompts: String::new(),
+            burn_prompts: false,
+            burn_timestamp: false,
+            record_audio: true,
+            live_url: String::new(),
+            serve_hls: false,
+            status_file: None,
+            overflow_dir: None,
+            workspace_root: None,
+            video_format: None,
+            backup_dir: None,
+            watermark: None,
+            face_model: None,
+            audio_offset_ms: 0,
+            global_replay_hotkey: false,
+            hotkeys: BTreeMap::new(),
+            frame_cap_mb: budget::DEFAULT_CAP_MB,
+            frame_queue_len: budget::FRAME_QUEUE_LEN,
+            drop_policy: DropPolicy::DropOldest,
+            preview_every: 1
+        }
+    }
+}
+
+/// A kiosk PIN as it's saved: a random salt and the hash of the PIN with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinHash {
+    salt: String,
+    hash: String
+}
+
+impl PinHash {
+    /// Rounds of SHA-256, so guessing a short PIN from the hash takes a while.
+    const ROUNDS: u32 = 100_000;
+
+    pub fn new(pin: &str) -> Self {
+        let mut salt = [0u8; 16];
+        getrandom::fill(&mut salt).expect("the OS has no random source");
+        let salt: String = salt.iter().map(|b| format!("{:02x}", b)).collect();
+        let hash = Self::digest(&salt, pin);
+        Self { salt, hash }
+    }
+
+    pub fn matches(&self, pin: &str) -> bool {
+        Self::digest(&self.salt, pin) == self.hash
+    }
+
+    fn digest(salt: &str, pin: &str) -> String {
+        let mut digest = Sha256::digest(format!("{}{}", salt, pin));
+        for _ in 1..Self::ROUNDS {
+            digest = Sha256::digest(digest);
+        }
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        storage::load_json(SETTINGS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        storage::save_json(SETTINGS_FILE, self);
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! Machine-readable recording state for dashboards and OBS text sources: a small JSON object
+//! rewritten once a second to a file, or printed as one line a second to stdout.
+
+use crate::crash::log_line;
+use serde::Serialize;
+use std::{fs, io::Write, path::PathBuf, time::{Duration, Instant}};
+
+const REPORT_EVERY: Duration = Duration::from_secs(1);
+/// Command-line flag naming the status file, or `-` for stdout.
+const STATUS_FLAG: &str = "--status-json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedState {
+    Configuring,
+    Idle,
+    Recording,
+    Exporting
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusReport {
+    pub state: FeedState,
+    /// Length of the take being recorded, zero between takes.
+    pub elapsed_secs: u64,
+    pub clips: usize,
+    pub last_error: Option<String>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatusTarget {
+    Stdout,
+    File(PathBuf)
+}
+
+impl StatusTarget {
+    /// `--status-json <file>`, or `--status-json -` for stdout.
+    pub fn from_args() -> Option<Self> {
+        let mut args = std::env::args().skip_while(|a| a != STATUS_FLAG).skip(1);
+        match args.next()?.as_str() {
+            "-" => Some(Self::Stdout),
+            path => Some(Self::File(PathBuf::from(path)))
+        }
+    }
+}
+
+pub struct StatusFeed {
+    target: StatusTarget,
+    last_report: Option<Instant>,
+    /// Set after a failed write so a missing folder is logged once, not every second.
+    failing: bool
+}
+
+impl StatusFeed {
+    pub fn new(target: StatusTarget) -> Self {
+        Self { target, last_report: None, failing: false }
+    }
+
+    pub fn target(&self) -> &StatusTarget {
+        &self.target
+    }
+
+    /// Writes the report unless one went out less than `REPORT_EVERY` ago.
+    pub fn offer(&mut self, report: &StatusReport) {
+        if self.last_report.is_some_and(|at| at.elapsed() < REPORT_EVERY) { return; }
+        self.last_report = Some(Instant::now());
+        let Ok(json) = serde_json::to_string(report) else { return };
+        let result = match &self.target {
+            StatusTarget::Stdout => {
+                let mut out = std::io::stdout().lock();
+                writeln!(out, "{}", json).and_then(|_| out.flush())
+            },
+            // Written aside and moved over, so a reader never sees half a file.
+            StatusTarget::File(path) => {
+                let mut temp = path.clone().into_os_string();
+                temp.push(".tmp");
+                fs::write(&temp, &json).and_then(|_| fs::rename(&temp, path))
+            }
+        };
+        match result {
+            Err(e) if !self.failing => {
+                log_line!("Couldn't write the status feed: {}", e);
+                self.failing = true;
+            },
+            Err(_) => {},
+            Ok(()) => self.failing = false
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::crash::log_line;
+use chrono::Local;
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, io, path::{Path, PathBuf}};
+
+/// This session's scratch folder. The pid and start time keep a crashed session's leftovers from
+/// colliding with a new one.
+static SESSION_TEMP: Lazy<PathBuf> = Lazy::new(|| temp_root().join(format!("{}-{}", std::process::id(), Local::now().format("%Y%m%d%H%M%S"))));
+
+/// Held for as long as this process runs, so an instance clearing out stale scratch folders
+/// leaves this one alone. `None` if the folder couldn't be made or locked.
+static SESSION_LOCK: Lazy<Option<fs::File>> = Lazy::new(|| {
+    create_private_dir(&SESSION_TEMP).ok()?;
+    let file = fs::File::create(SESSION_TEMP.join(TEMP_LOCK)).ok()?;
+    file.try_lock().ok()?;
+    Some(file)
+});
+
+/// Lock file in each session's scratch folder.
+const TEMP_LOCK: &str = ".lock";
+
+/// Per-user directory for state that has to survive a restart. Falls back to the CWD when the
+/// platform has no data dir.
+pub fn data_dir() -> PathBuf {
+    let dir = dirs::data_dir().map(|d| d.join("clipper")).unwrap_or_else(|| PathBuf::from("."));
+    let _ = fs::create_dir_all(&dir);
+    dir
+}
+
+/// Where recording sessions go unless the user picks another folder.
+pub fn default_workspace_root() -> PathBuf {
+    dirs::video_dir().or_else(dirs::home_dir).unwrap_or_default().join("Clipper")
+}
+
+/// Scratch space for exports and other short-lived files. It's kept in the user's cache dir rather
+/// than the shared OS temp dir, so other users can neither read it nor clear it out.
+fn temp_root() -> PathBuf {
+    dirs::cache_dir().map(|d| d.join("clipper").join("tmp")).unwrap_or_else(|| std::env::temp_dir().join("clipper"))
+}
+
+/// Creates `dir` and any missing parents readable by this user only.
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+    }
+    #[cfg(not(unix))]
+    fs::create_dir_all(dir)
+}
+
+/// A scratch file in this user's temp space, private to this session.
+pub fn temp_file(name: &str) -> PathBuf {
+    hold_session_temp();
+    SESSION_TEMP.join(name)
+}
+
+/// Creates and locks this session's scratch folder ahead of its first use.
+pub fn hold_session_temp() {
+    if SESSION_LOCK.is_none() {
+        let _ = create_private_dir(&SESSION_TEMP);
+    }
+}
+
+/// Whether another running process holds the lock in a scratch folder.
+fn in_use(dir: &Path) -> bool {
+    fs::File::open(dir.join(TEMP_LOCK)).is_ok_and(|f| f.try_lock().is_err())
+}
+
+/// Scratch folders left behind by sessions that crashed. Folders still locked by a running
+/// process, like a `clipper merge`, are skipped.
+fn stale_temp_dirs() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(temp_root()) else { return Vec::new() };
+    entries.flatten().map(|e| e.path()).filter(|p| p.is_dir() && *p != *SESSION_TEMP && !in_use(p)).collect()
+}
+
+pub fn clean_stale_temp() {
+    for dir in stale_temp_dirs() {
+        log_line!("Removing stale temp files in {}", dir.display());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+pub fn remove_session_temp() {
+    let _ = fs::remove_dir_all(&*SESSION_TEMP);
+}
+
+/// Renames, falling back to copying for a destination on another volume, which the temp dir
+/// often is.
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+pub fn load_json<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let data = fs::read_to_string(data_dir().join(name)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+pub fn save_json<T: Serialize>(name: &str, value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(data) => {
+            if let Err(e) = fs::write(data_dir().join(name), data) {
+                log_line!("Failed to write {}: {}", name, e);
+            }
+        },
+        Err(e) => log_line!("Failed to serialize {}: {}", name, e)
+    }
+}
+
+/// The workspace may not exist yet, so walk up to the nearest existing ancestor.
+#[cfg(any(target_os = "linux", windows))]
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|p| p.exists())
+}
+
+/// Space left for this user on the volume holding `path`, in GB.
+#[cfg(target_os = "linux")]
+pub fn free_space_gb(path: &Path) -> Option<f64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+    let c_path = CString::new(existing_ancestor(path)?.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 { return None; }
+    Some(stats.f_bavail as f64 * stats.f_frsize as f64 / 1e9)
+}
+
+#[cfg(windows)]
+pub fn free_space_gb(path: &Path) -> Option<f64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+    let wide: Vec<u16> = existing_ancestor(path)?.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available: u64 = 0;
+    let ok = unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) };
+    if ok != 0 { Some(available as f64 / 1e9) } else { None }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn free_space_gb(_path: &Path) -> Option<f64> {
+    None
+}
+
+/// The largest file FAT32 can hold, one byte short of 4 GiB.
+#[cfg(any(target_os = "linux", windows))]
+const FAT_MAX_FILE: u64 = u32::MAX as u64;
+
+/// The largest file the volume holding `path` can store, when its filesystem has a limit that
+/// matters for video: FAT32 USB drives and SD cards. exFAT and NTFS have none in practice.
+#[cfg(target_os = "linux")]
+pub fn max_file_size(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+    let c_path = CString::new(existing_ancestor(path)?.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 { return None; }
+    (stats.f_type as u64 == libc::MSDOS_SUPER_MAGIC as u64).then_some(FAT_MAX_FILE)
+}
+
+#[cfg(windows)]
+pub fn max_file_size(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{GetVolumeInformationW, GetVolumePathNameW};
+    let wide: Vec<u16> = existing_ancestor(path)?.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut root = [0u16; 261];
+    if unsafe { GetVolumePathNameW(wide.as_ptr(), root.as_mut_ptr(), root.len() as u32) } == 0 { return None; }
+    let mut fs_name = [0u16; 32];
+    let ok = unsafe {
+        GetVolumeInformationW(root.as_ptr(), std::ptr::null_mut(), 0, std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut(), fs_name.as_mut_ptr(), fs_name.len() as u32)
+    };
+    if ok == 0 { return None; }
+    let len = fs_name.iter().position(|&c| c == 0).unwrap_or(fs_name.len());
+    let fs_name = String::from_utf16_lossy(&fs_name[..len]);
+    matches!(fs_name.as_str(), "FAT" | "FAT32").then_some(FAT_MAX_FILE)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn max_file_size(_path: &Path) -> Option<u64> {
+    None
+}
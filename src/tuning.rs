@@ -0,0 +1,71 @@
+// Copyright (C) 2025 Joshua Kesler
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::crash::log_line;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once raising a thread's priority has been refused, so it isn't tried, and reported, on
+/// every Confirm.
+static PRIORITY_DENIED: AtomicBool = AtomicBool::new(false);
+
+/// Scheduling hints for one of the pipeline threads.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ThreadTuning {
+    pub raise_priority: bool,
+    pub core: Option<usize>
+}
+
+pub fn core_count() -> usize {
+    core_affinity::get_core_ids().map(|ids| ids.len()).unwrap_or(0)
+}
+
+/// Applies `tuning` to the calling thread. A priority the system won't grant is logged the first
+/// time and then left at normal.
+pub fn apply(tuning: ThreadTuning) -> Result<(), String> {
+    if let Some(core) = tuning.core {
+        let id = core_affinity::get_core_ids().and_then(|ids| ids.into_iter().find(|id| id.id == core));
+        match id {
+            Some(id) if core_affinity::set_for_current(id) => {},
+            _ => return Err(format!("Could not pin thread to core {}", core))
+        }
+    }
+
+    if tuning.raise_priority && !PRIORITY_DENIED.load(Ordering::Relaxed)
+        && let Err(e) = raise_priority() {
+        PRIORITY_DENIED.store(true, Ordering::Relaxed);
+        log_line!("{}, running at normal priority", e);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn raise_priority() -> Result<(), String> {
+    // Linux applies nice values per thread when targeted by tid. Going below 0 needs CAP_SYS_NICE
+    // or a matching RLIMIT_NICE.
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, -10) };
+    if result == 0 { Ok(()) } else { Err(format!("Could not raise thread priority: {}", std::io::Error::last_os_error())) }
+}
+
+#[cfg(windows)]
+fn raise_priority() -> Result<(), String> {
+    use windows_sys::Win32::System::Threading::{GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_HIGHEST};
+    let ok = unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_HIGHEST) };
+    if ok != 0 { Ok(()) } else { Err(format!("Could not raise thread priority: {}", std::io::Error::last_os_error())) }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn raise_priority() -> Result<(), String> {
+    Err(String::from("Raising thread priority is not supported on this platform"))
+}